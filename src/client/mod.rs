@@ -10,7 +10,7 @@ fn parse_frame(frame: Frame) -> Result<Option<Vec<u8>>> {
         Frame::Null => Ok(None),
         Frame::Error(s) => Err(s.into()),
         Frame::Integer(s) => Ok(Some(s.to_le_bytes().to_vec())),
-        Frame::Array(arr) => {
+        Frame::Array(arr) | Frame::Push(arr) => {
             let mut bytes = Vec::new();
             for frame in arr {
                 let b = parse_frame(frame)?;
@@ -20,5 +20,8 @@ fn parse_frame(frame: Frame) -> Result<Option<Vec<u8>>> {
             }
             Ok(Some(bytes))
         }
+        Frame::Map(pairs) => parse_frame(Frame::Array(
+            pairs.into_iter().flat_map(|(k, v)| [k, v]).collect(),
+        )),
     }
 }
@@ -0,0 +1,265 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{BloomFilter, Class, Database, FilterFull, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// the filter created by BF.ADD and BF.MADD when the key doesn't exist
+const DEFAULT_ERROR_RATE: f64 = 0.01;
+const DEFAULT_CAPACITY: u64 = 100;
+const DEFAULT_EXPANSION: u32 = 2;
+
+#[derive(Debug)]
+pub struct BfReserve {
+    key: String,
+    error_rate: f64,
+    capacity: u64,
+    expansion: Option<u32>,
+}
+
+/// BF.ADD and BF.MADD
+#[derive(Debug)]
+pub struct BfAdd {
+    key: String,
+    items: Vec<Bytes>,
+    multi: bool,
+}
+
+/// BF.EXISTS and BF.MEXISTS
+#[derive(Debug)]
+pub struct BfExists {
+    key: String,
+    items: Vec<Bytes>,
+    multi: bool,
+}
+
+#[derive(Debug)]
+pub struct BfInfo {
+    key: String,
+    field: Option<InfoField>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InfoField {
+    Capacity,
+    Size,
+    Filters,
+    Items,
+    Expansion,
+}
+
+impl BfReserve {
+    pub fn parse_frames(parser: &mut Parser) -> Result<BfReserve> {
+        let key = parser.next_string()?;
+        let error_rate = parser.next_float()?;
+        if !(error_rate > 0.0 && error_rate < 1.0) {
+            return Err("(0 < error rate range < 1)".into());
+        }
+        let capacity = parser.next_int()?;
+        if capacity <= 0 {
+            return Err("(capacity should be larger than 0)".into());
+        }
+
+        let (mut expansion, mut non_scaling) = (None, false);
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match &token[..] {
+                "expansion" => {
+                    let value = parser.next_int()?;
+                    if value < 1 || value > u32::MAX as i64 {
+                        return Err("expansion should be greater or equal to 1".into());
+                    }
+                    expansion = Some(value as u32);
+                }
+                "nonscaling" => non_scaling = true,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        if non_scaling && expansion.is_some() {
+            return Err("Nonscaling filters cannot expand".into());
+        }
+        let expansion = if non_scaling {
+            None
+        } else {
+            Some(expansion.unwrap_or(DEFAULT_EXPANSION))
+        };
+        Ok(BfReserve {
+            key,
+            error_rate,
+            capacity: capacity as u64,
+            expansion,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if db.get(&self.key).is_some() {
+            return Frame::Error("ERR item exists".to_string());
+        }
+        let filter = BloomFilter::new(self.error_rate, self.capacity, self.expansion);
+        db.insert(self.key.clone(), Value::Bloom(filter));
+        db.notify(Class::Module, "bf.reserve", &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl BfAdd {
+    /// `multi` tells whether several items are given and replied to (BF.MADD)
+    pub fn parse_frames(parser: &mut Parser, multi: bool) -> Result<BfAdd> {
+        let key = parser.next_string()?;
+        let items = if multi {
+            parser.remaining_bytes()?
+        } else {
+            vec![parser.next_bytes()?]
+        };
+        Ok(BfAdd { key, items, multi })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_or_insert_bloom(&self.key, || {
+            BloomFilter::new(
+                DEFAULT_ERROR_RATE,
+                DEFAULT_CAPACITY,
+                Some(DEFAULT_EXPANSION),
+            )
+        }) {
+            Ok(filter) => filter,
+            Err(e) => return e.into(),
+        };
+        let mut changed = false;
+        let mut replies: Vec<Frame> = self
+            .items
+            .iter()
+            .map(|item| match filter.add(item) {
+                Ok(added) => {
+                    changed |= added;
+                    Frame::Integer(added as i64)
+                }
+                Err(FilterFull) => Frame::Error("ERR non scaling filter is full".to_string()),
+            })
+            .collect();
+        if changed {
+            db.notify(Class::Module, "bf.add", &self.key);
+        }
+        if self.multi {
+            Frame::Array(replies)
+        } else {
+            replies.remove(0)
+        }
+    }
+}
+
+impl BfExists {
+    /// `multi` tells whether several items are given and replied to (BF.MEXISTS)
+    pub fn parse_frames(parser: &mut Parser, multi: bool) -> Result<BfExists> {
+        let key = parser.next_string()?;
+        let items = if multi {
+            parser.remaining_bytes()?
+        } else {
+            vec![parser.next_bytes()?]
+        };
+        Ok(BfExists { key, items, multi })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_bloom(&self.key) {
+            Ok(filter) => filter,
+            Err(e) => return e.into(),
+        };
+        let mut replies: Vec<Frame> = self
+            .items
+            .iter()
+            .map(|item| Frame::Integer(filter.is_some_and(|filter| filter.contains(item)) as i64))
+            .collect();
+        if self.multi {
+            Frame::Array(replies)
+        } else {
+            replies.remove(0)
+        }
+    }
+}
+
+impl BfInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<BfInfo> {
+        let key = parser.next_string()?;
+        let field = match parser.next_string() {
+            Ok(field) => Some(match &field.to_lowercase()[..] {
+                "capacity" => InfoField::Capacity,
+                "size" => InfoField::Size,
+                "filters" => InfoField::Filters,
+                "items" => InfoField::Items,
+                "expansion" => InfoField::Expansion,
+                _ => return Err(format!("syntax error near '{}'", field).into()),
+            }),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(BfInfo { key, field })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_bloom(&self.key) {
+            Ok(Some(filter)) => filter,
+            Ok(None) => return Frame::Error("ERR not found".to_string()),
+            Err(e) => return e.into(),
+        };
+        let value = |field| match field {
+            InfoField::Capacity => Frame::Integer(filter.capacity() as i64),
+            InfoField::Size => Frame::Integer(filter.size() as i64),
+            InfoField::Filters => Frame::Integer(filter.filters() as i64),
+            InfoField::Items => Frame::Integer(filter.items() as i64),
+            InfoField::Expansion => match filter.expansion() {
+                Some(expansion) => Frame::Integer(expansion as i64),
+                None => Frame::Null,
+            },
+        };
+        match self.field {
+            Some(field) => Frame::Array(vec![value(field)]),
+            None => {
+                let fields = [
+                    ("Capacity", InfoField::Capacity),
+                    ("Size", InfoField::Size),
+                    ("Number of filters", InfoField::Filters),
+                    ("Number of items inserted", InfoField::Items),
+                    ("Expansion rate", InfoField::Expansion),
+                ];
+                let mut frames = Vec::with_capacity(fields.len() * 2);
+                for (name, field) in fields {
+                    frames.push(Frame::Bulk(Bytes::from_static(name.as_bytes())));
+                    frames.push(value(field));
+                }
+                Frame::Array(frames)
+            }
+        }
+    }
+}
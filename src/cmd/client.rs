@@ -0,0 +1,201 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Database, Tracking},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// CLIENT ID, TRACKING, CACHING and GETREDIR, about the connection issuing them
+#[derive(Debug)]
+pub struct Client {
+    subcommand: Subcommand,
+}
+
+/// HELLO, switch the protocol of the connection to RESP2 or RESP3. only RESP3
+/// clients receive push messages, such as the invalidations of tracked keys
+#[derive(Debug)]
+pub struct Hello {
+    protocol: Option<i64>,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    Id,
+    /// tracking is turned off when there are no options
+    Tracking(Option<Tracking>),
+    Caching(bool),
+    GetRedir,
+}
+
+impl Client {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Client> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let subcommand = match &subcommand[..] {
+            "id" => Subcommand::Id,
+            "tracking" => Subcommand::Tracking(parse_tracking(parser)?),
+            "caching" => match &parser.next_string()?.to_lowercase()[..] {
+                "yes" => Subcommand::Caching(true),
+                "no" => Subcommand::Caching(false),
+                _ => return Err("syntax error".into()),
+            },
+            "getredir" => Subcommand::GetRedir,
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(Client { subcommand })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match self.subcommand {
+            Subcommand::Id => Frame::Integer(db.client() as i64),
+            Subcommand::Tracking(Some(tracking)) => match db.start_tracking(tracking) {
+                Ok(()) => Frame::into_simple("OK"),
+                Err(e) => Frame::Error(e),
+            },
+            Subcommand::Tracking(None) => {
+                db.stop_tracking(db.client());
+                Frame::into_simple("OK")
+            }
+            Subcommand::Caching(yes) => match db.set_caching(yes) {
+                Ok(()) => Frame::into_simple("OK"),
+                Err(e) => Frame::Error(e.to_string()),
+            },
+            // -1 when not tracking, 0 when not redirecting
+            Subcommand::GetRedir => match db.tracking() {
+                Some(tracking) => Frame::Integer(tracking.redirect.unwrap_or(0) as i64),
+                None => Frame::Integer(-1),
+            },
+        }
+    }
+
+    /// CLIENT CACHING only applies to the command following it
+    pub(crate) fn is_caching(&self) -> bool {
+        matches!(self.subcommand, Subcommand::Caching(_))
+    }
+}
+
+impl Hello {
+    /// AUTH and SETNAME aren't supported, the server has neither users nor client names
+    pub fn parse_frames(parser: &mut Parser) -> Result<Hello> {
+        let protocol = match parser.next_int() {
+            Ok(protocol) => Some(protocol),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        parser.check_finished()?;
+        Ok(Hello { protocol })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the properties of the server and the connection, a map in RESP3
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match self.protocol {
+            Some(protocol @ (2 | 3)) => db.set_protocol(protocol as u8),
+            Some(_) => return Frame::Error("NOPROTO unsupported protocol version".to_string()),
+            None => {}
+        }
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from_static(s.as_bytes()));
+        let properties = vec![
+            (bulk("server"), bulk("redis")),
+            (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+            (bulk("proto"), Frame::Integer(db.protocol() as i64)),
+            (bulk("id"), Frame::Integer(db.client() as i64)),
+            (bulk("mode"), bulk("standalone")),
+            (bulk("role"), bulk("master")),
+            (bulk("modules"), Frame::Array(vec![])),
+        ];
+        match db.protocol() {
+            3 => Frame::Map(properties),
+            _ => Frame::Array(
+                properties
+                    .into_iter()
+                    .flat_map(|(name, value)| [name, value])
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// `ON` followed by its options, or `OFF`
+fn parse_tracking(parser: &mut Parser) -> Result<Option<Tracking>> {
+    match &parser.next_string()?.to_lowercase()[..] {
+        "on" => {}
+        "off" => return Ok(None),
+        _ => return Err("syntax error".into()),
+    }
+    let mut tracking = Tracking::default();
+    loop {
+        let option = match parser.next_string() {
+            Ok(option) => option.to_lowercase(),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e.into()),
+        };
+        match &option[..] {
+            "redirect" => match parser.next_int()? {
+                id if id < 0 => return Err("Invalid client ID".into()),
+                id => tracking.redirect = Some(id as u64),
+            },
+            "prefix" => tracking.prefixes.push(parser.next_string()?),
+            "bcast" => tracking.bcast = true,
+            "optin" => tracking.optin = true,
+            "optout" => tracking.optout = true,
+            "noloop" => tracking.noloop = true,
+            _ => return Err("syntax error".into()),
+        }
+    }
+
+    if !tracking.prefixes.is_empty() && !tracking.bcast {
+        return Err("PREFIX option requires BCAST mode to be enabled".into());
+    }
+    if tracking.optin && tracking.optout {
+        return Err("You can't use both OPTIN and OPTOUT".into());
+    }
+    if tracking.bcast && (tracking.optin || tracking.optout) {
+        return Err("OPTIN and OPTOUT are not compatible with BCAST".into());
+    }
+    Ok(Some(tracking))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::*;
+    use crate::Command;
+
+    #[tokio::test]
+    async fn hello_test() {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        let server = DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx);
+        let (db, _pushes) = server.connect();
+        let mut db = db.lock();
+
+        assert!(matches!(
+            Command::parse(&["HELLO"]).apply(&mut db),
+            Frame::Array(_)
+        ));
+        assert!(matches!(
+            Command::parse(&["HELLO", "3"]).apply(&mut db),
+            Frame::Map(_)
+        ));
+        assert_eq!(db.protocol(), 3);
+        let reply = Command::parse(&["HELLO", "4"]).apply(&mut db);
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("NOPROTO")));
+        assert_eq!(db.protocol(), 3);
+        Command::parse(&["HELLO", "2"]).apply(&mut db);
+        assert_eq!(db.protocol(), 2);
+    }
+}
@@ -0,0 +1,238 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, CountMinSketch, Database, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// CMS.INITBYDIM and CMS.INITBYPROB
+#[derive(Debug)]
+pub struct CmsInit {
+    key: String,
+    width: usize,
+    depth: usize,
+}
+
+#[derive(Debug)]
+pub struct CmsIncrBy {
+    key: String,
+    increments: Vec<(Bytes, u32)>,
+}
+
+#[derive(Debug)]
+pub struct CmsQuery {
+    key: String,
+    items: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct CmsMerge {
+    destination: String,
+    sources: Vec<(String, i64)>,
+}
+
+impl CmsInit {
+    /// `by_prob` tells whether the dimensions derive from an error and a probability
+    pub fn parse_frames(parser: &mut Parser, by_prob: bool) -> Result<CmsInit> {
+        let key = parser.next_string()?;
+        let (width, depth) = if by_prob {
+            let error = parser.next_float()?;
+            if !(error > 0.0 && error < 1.0) {
+                return Err("CMS: invalid overestimation value".into());
+            }
+            let probability = parser.next_float()?;
+            if !(probability > 0.0 && probability < 1.0) {
+                return Err("CMS: invalid prob value".into());
+            }
+            CountMinSketch::dimensions(error, probability)
+        } else {
+            let width = parser.next_int()?;
+            if width <= 0 {
+                return Err("CMS: invalid width".into());
+            }
+            let depth = parser.next_int()?;
+            if depth <= 0 {
+                return Err("CMS: invalid depth".into());
+            }
+            (width as usize, depth as usize)
+        };
+        Ok(CmsInit { key, width, depth })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if db.get(&self.key).is_some() {
+            return Frame::Error("ERR CMS: key already exists".to_string());
+        }
+        let sketch = match CountMinSketch::new(self.width, self.depth) {
+            Ok(sketch) => sketch,
+            Err(e) => return Frame::Error(format!("ERR {}", e)),
+        };
+        db.insert(self.key.clone(), Value::Cms(sketch));
+        db.notify(Class::Module, "cms.init", &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl CmsIncrBy {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CmsIncrBy> {
+        let key = parser.next_string()?;
+        let mut increments = vec![];
+        loop {
+            let item = match parser.next_bytes() {
+                Ok(item) => item,
+                Err(ParseError::EndOfStream) if !increments.is_empty() => break,
+                Err(e) => return Err(e.into()),
+            };
+            let increment = parser.next_int()?;
+            if !(0..=u32::MAX as i64).contains(&increment) {
+                return Err("CMS: Cannot parse number".into());
+            }
+            increments.push((item, increment as u32));
+        }
+        Ok(CmsIncrBy { key, increments })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let sketch = match db.get_cms_mut(&self.key) {
+            Ok(Some(sketch)) => sketch,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let counts = self
+            .increments
+            .iter()
+            .map(|(item, increment)| Frame::Integer(sketch.increment(item, *increment) as i64))
+            .collect();
+        db.notify(Class::Module, "cms.incrby", &self.key);
+        Frame::Array(counts)
+    }
+}
+
+impl CmsQuery {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CmsQuery> {
+        let key = parser.next_string()?;
+        let items = parser.remaining_bytes()?;
+        Ok(CmsQuery { key, items })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let sketch = match db.get_cms(&self.key) {
+            Ok(Some(sketch)) => sketch,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let counts = self
+            .items
+            .iter()
+            .map(|item| Frame::Integer(sketch.query(item) as i64))
+            .collect();
+        Frame::Array(counts)
+    }
+}
+
+impl CmsMerge {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CmsMerge> {
+        let destination = parser.next_string()?;
+        let numkeys = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err("CMS: invalid numkeys".into());
+        }
+        if numkeys as u64 > parser.remaining() as u64 {
+            return Err("syntax error".into());
+        }
+        let keys: Vec<_> = (0..numkeys)
+            .map(|_| parser.next_string())
+            .collect::<std::result::Result<_, _>>()?;
+        let mut weights = vec![1; keys.len()];
+        match parser.next_string() {
+            Ok(token) if token.to_lowercase() == "weights" => {
+                for weight in weights.iter_mut() {
+                    *weight = parser.next_int()?;
+                }
+            }
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(CmsMerge {
+            destination,
+            sources: keys.into_iter().zip(weights).collect(),
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the destination must already exist, with the dimensions of every source
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let (width, depth) = match db.get_cms(&self.destination) {
+            Ok(Some(sketch)) => (sketch.width(), sketch.depth()),
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        // the destination may be one of the sources, which are read before it changes
+        let mut sources = Vec::with_capacity(self.sources.len());
+        for (key, weight) in &self.sources {
+            match db.get_cms(key) {
+                Ok(Some(sketch)) if sketch.width() == width && sketch.depth() == depth => {
+                    sources.push((sketch.clone(), *weight))
+                }
+                Ok(Some(_)) => {
+                    return Frame::Error("ERR CMS: width/depth is not equal".to_string())
+                }
+                Ok(None) => return no_such_key(),
+                Err(e) => return e.into(),
+            }
+        }
+        let sources: Vec<(&CountMinSketch, i64)> = sources
+            .iter()
+            .map(|(sketch, weight)| (sketch, *weight))
+            .collect();
+        if let Ok(Some(destination)) = db.get_cms_mut(&self.destination) {
+            destination.merge(&sources);
+        }
+        db.notify(Class::Module, "cms.merge", &self.destination);
+        Frame::into_simple("OK")
+    }
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR CMS: key does not exist".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Command;
+
+    #[test]
+    fn cms_merge_numkeys_test() {
+        // the count isn't trusted to allocate the keys
+        let words = ["CMS.MERGE", "dest", "100000000000", "a"];
+        let e = Command::try_parse(&words).map(|_| ()).unwrap_err();
+        assert_eq!(e.to_string(), "syntax error");
+        assert!(Command::try_parse(&["CMS.MERGE", "dest", "2", "a", "b"]).is_ok());
+    }
+}
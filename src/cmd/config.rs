@@ -0,0 +1,104 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    config::{self, PARAMETERS},
+    glob::glob_match,
+    parser::Parser,
+    Connection, Frame, Result,
+};
+
+/// CONFIG GET and CONFIG SET over the tunable parameters
+#[derive(Debug)]
+pub struct Config {
+    action: Action,
+}
+
+#[derive(Debug)]
+enum Action {
+    /// glob-style patterns of parameter names
+    Get(Vec<String>),
+    Set(Vec<(String, String)>),
+}
+
+impl Config {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Config> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let action = match &subcommand[..] {
+            "get" => {
+                let patterns = parser.remaining_strings()?;
+                if patterns.is_empty() {
+                    return Err("wrong number of arguments for 'config|get' command".into());
+                }
+                Action::Get(patterns)
+            }
+            "set" => {
+                let args = parser.remaining_strings()?;
+                if args.is_empty() || args.len() % 2 != 0 {
+                    return Err("wrong number of arguments for 'config|set' command".into());
+                }
+                let pairs = args
+                    .chunks(2)
+                    .map(|pair| (pair[0].to_lowercase(), pair[1].clone()))
+                    .collect();
+                Action::Set(pairs)
+            }
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(Config { action })
+    }
+
+    #[instrument(skip(connection))]
+    pub async fn execute(self, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply();
+        connection.write_frame(frame).await
+    }
+
+    /// GET replies the matching parameters and their values as a flat array.
+    /// SET changes nothing unless every parameter and value is valid
+    pub(crate) fn apply(self) -> Frame {
+        match self.action {
+            Action::Get(patterns) => {
+                let mut reply = vec![];
+                for (name, parameter, format) in &PARAMETERS {
+                    let matched = patterns.iter().any(|pattern| {
+                        glob_match(pattern.to_lowercase().as_bytes(), name.as_bytes())
+                    });
+                    if matched {
+                        reply.push(Frame::Bulk(Bytes::from_static(name.as_bytes())));
+                        let value = format.write(config::get(parameter));
+                        reply.push(Frame::Bulk(Bytes::from(value)));
+                    }
+                }
+                Frame::Array(reply)
+            }
+            Action::Set(pairs) => {
+                let mut changes = vec![];
+                for (name, value) in &pairs {
+                    let Some((_, parameter, format)) =
+                        PARAMETERS.iter().find(|(known, _, _)| known == name)
+                    else {
+                        return Frame::Error(format!(
+                            "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                            name
+                        ));
+                    };
+                    let value = match format.read(value) {
+                        Ok(value) => value,
+                        Err(reason) => {
+                            return Frame::Error(format!(
+                                "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                                name, reason
+                            ))
+                        }
+                    };
+                    changes.push((*parameter, value));
+                }
+                for (parameter, value) in changes {
+                    config::set(parameter, value);
+                }
+                Frame::into_simple("OK")
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, CuckooFilter, Database, FilterFull, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// the filter created by CF.ADD and CF.ADDNX when the key doesn't exist
+const DEFAULT_CAPACITY: u64 = 1024;
+const DEFAULT_BUCKET_SIZE: usize = 2;
+const DEFAULT_MAX_ITERATIONS: u32 = 20;
+const DEFAULT_EXPANSION: u32 = 1;
+
+#[derive(Debug)]
+pub struct CfReserve {
+    key: String,
+    capacity: u64,
+    bucket_size: usize,
+    max_iterations: u32,
+    expansion: u32,
+}
+
+/// CF.ADD and CF.ADDNX
+#[derive(Debug)]
+pub struct CfAdd {
+    key: String,
+    item: Bytes,
+    nx: bool,
+}
+
+/// CF.EXISTS and CF.MEXISTS
+#[derive(Debug)]
+pub struct CfExists {
+    key: String,
+    items: Vec<Bytes>,
+    multi: bool,
+}
+
+#[derive(Debug)]
+pub struct CfDel {
+    key: String,
+    item: Bytes,
+}
+
+#[derive(Debug)]
+pub struct CfCount {
+    key: String,
+    item: Bytes,
+}
+
+#[derive(Debug)]
+pub struct CfInfo {
+    key: String,
+}
+
+impl CfReserve {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CfReserve> {
+        let key = parser.next_string()?;
+        let capacity = parser.next_int()?;
+        if capacity <= 0 {
+            return Err("(capacity should be larger than 0)".into());
+        }
+        let mut reserve = CfReserve {
+            key,
+            capacity: capacity as u64,
+            bucket_size: DEFAULT_BUCKET_SIZE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            expansion: DEFAULT_EXPANSION,
+        };
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let value = match &token[..] {
+                "bucketsize" | "maxiterations" | "expansion" => parser.next_int()?,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            };
+            match &token[..] {
+                "bucketsize" if (1..=255).contains(&value) => reserve.bucket_size = value as usize,
+                "bucketsize" => return Err("Bucket size must be between 1 and 255".into()),
+                "maxiterations" if (1..=65535).contains(&value) => {
+                    reserve.max_iterations = value as u32
+                }
+                "maxiterations" => return Err("MAXITERATIONS must be between 1 and 65535".into()),
+                _ if (0..=32768).contains(&value) => reserve.expansion = value as u32,
+                _ => return Err("EXPANSION must be between 0 and 32768".into()),
+            }
+        }
+        Ok(reserve)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if db.get(&self.key).is_some() {
+            return Frame::Error("ERR item exists".to_string());
+        }
+        let filter = CuckooFilter::new(
+            self.capacity,
+            self.bucket_size,
+            self.max_iterations,
+            self.expansion,
+        );
+        db.insert(self.key.clone(), Value::Cuckoo(filter));
+        db.notify(Class::Module, "cf.reserve", &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl CfAdd {
+    /// `nx` only adds items which aren't in the filter yet (CF.ADDNX)
+    pub fn parse_frames(parser: &mut Parser, nx: bool) -> Result<CfAdd> {
+        let key = parser.next_string()?;
+        let item = parser.next_bytes()?;
+        Ok(CfAdd { key, item, nx })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_or_insert_cuckoo(&self.key, || {
+            CuckooFilter::new(
+                DEFAULT_CAPACITY,
+                DEFAULT_BUCKET_SIZE,
+                DEFAULT_MAX_ITERATIONS,
+                DEFAULT_EXPANSION,
+            )
+        }) {
+            Ok(filter) => filter,
+            Err(e) => return e.into(),
+        };
+        if self.nx && filter.contains(&self.item) {
+            return Frame::Integer(0);
+        }
+        match filter.add(&self.item) {
+            Ok(()) => {
+                db.notify(Class::Module, "cf.add", &self.key);
+                Frame::Integer(1)
+            }
+            Err(FilterFull) => Frame::Error("ERR Filter is full".to_string()),
+        }
+    }
+}
+
+impl CfExists {
+    /// `multi` tells whether several items are given and replied to (CF.MEXISTS)
+    pub fn parse_frames(parser: &mut Parser, multi: bool) -> Result<CfExists> {
+        let key = parser.next_string()?;
+        let items = if multi {
+            parser.remaining_bytes()?
+        } else {
+            vec![parser.next_bytes()?]
+        };
+        Ok(CfExists { key, items, multi })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_cuckoo(&self.key) {
+            Ok(filter) => filter,
+            Err(e) => return e.into(),
+        };
+        let mut replies: Vec<Frame> = self
+            .items
+            .iter()
+            .map(|item| Frame::Integer(filter.is_some_and(|filter| filter.contains(item)) as i64))
+            .collect();
+        if self.multi {
+            Frame::Array(replies)
+        } else {
+            replies.remove(0)
+        }
+    }
+}
+
+impl CfDel {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CfDel> {
+        let key = parser.next_string()?;
+        let item = parser.next_bytes()?;
+        Ok(CfDel { key, item })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_cuckoo_mut(&self.key) {
+            Ok(Some(filter)) => {
+                let deleted = filter.delete(&self.item);
+                if deleted {
+                    db.notify(Class::Module, "cf.del", &self.key);
+                }
+                Frame::Integer(deleted as i64)
+            }
+            Ok(None) => Frame::Error("ERR Not found".to_string()),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CfCount {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CfCount> {
+        let key = parser.next_string()?;
+        let item = parser.next_bytes()?;
+        Ok(CfCount { key, item })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_cuckoo(&self.key) {
+            Ok(filter) => {
+                Frame::Integer(filter.map_or(0, |filter| filter.count(&self.item)) as i64)
+            }
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CfInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<CfInfo> {
+        let key = parser.next_string()?;
+        Ok(CfInfo { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let filter = match db.get_cuckoo(&self.key) {
+            Ok(Some(filter)) => filter,
+            Ok(None) => return Frame::Error("ERR not found".to_string()),
+            Err(e) => return e.into(),
+        };
+        let fields = [
+            ("Size", filter.size() as i64),
+            ("Number of buckets", filter.buckets() as i64),
+            ("Number of filters", filter.filters() as i64),
+            ("Number of items inserted", filter.inserted() as i64),
+            ("Number of items deleted", filter.deleted() as i64),
+            ("Bucket size", filter.bucket_size() as i64),
+            ("Expansion rate", filter.expansion() as i64),
+            ("Max iterations", filter.max_iterations() as i64),
+        ];
+        let mut frames = Vec::with_capacity(fields.len() * 2);
+        for (name, value) in fields {
+            frames.push(Frame::Bulk(Bytes::from_static(name.as_bytes())));
+            frames.push(Frame::Integer(value));
+        }
+        Frame::Array(frames)
+    }
+}
@@ -0,0 +1,507 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use super::zset::store;
+use crate::{
+    db::{Class, Database, ScoreBound, ZSet},
+    geo::{self, Shape},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: String,
+    nx: bool,
+    xx: bool,
+    ch: bool,
+    positions: Vec<(f64, f64, Bytes)>,
+}
+
+#[derive(Debug)]
+pub struct GeoPos {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct GeoDist {
+    key: String,
+    first: Bytes,
+    second: Bytes,
+    unit: f64,
+}
+
+#[derive(Debug)]
+pub struct GeoHash {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+/// GEOSEARCH and GEOSEARCHSTORE
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: String,
+    destination: Option<String>,
+    origin: Origin,
+    shape: Shape,
+    /// meters per unit of the shape, also used for the returned distances
+    unit: f64,
+    order: Option<Order>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+#[derive(Debug)]
+enum Origin {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Order {
+    Asc,
+    Desc,
+}
+
+/// a member found by a search
+struct Found {
+    member: Bytes,
+    distance: f64,
+    hash: u64,
+    position: (f64, f64),
+}
+
+impl GeoAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<GeoAdd> {
+        let key = parser.next_string()?;
+        let mut geoadd = GeoAdd {
+            key,
+            nx: false,
+            xx: false,
+            ch: false,
+            positions: vec![],
+        };
+        // flags come first, the first token which isn't a flag is a longitude
+        let mut lon = loop {
+            let token = parser.next_string()?;
+            match &token.to_lowercase()[..] {
+                "nx" => geoadd.nx = true,
+                "xx" => geoadd.xx = true,
+                "ch" => geoadd.ch = true,
+                _ => break token,
+            }
+        };
+        loop {
+            let lon_value = lon
+                .parse::<f64>()
+                .map_err(|_| "value is not a valid float")?;
+            let lat = parser.next_float()?;
+            let member = parser.next_bytes()?;
+            geoadd.positions.push((lon_value, lat, member));
+            lon = match parser.next_string() {
+                Ok(lon) => lon,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+        }
+        if geoadd.nx && geoadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        Ok(geoadd)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if let Some((lon, lat, _)) = self
+            .positions
+            .iter()
+            .find(|(lon, lat, _)| !geo::is_valid(*lon, *lat))
+        {
+            return Frame::Error(format!(
+                "ERR invalid longitude,latitude pair {:.6},{:.6}",
+                lon, lat
+            ));
+        }
+        let zset = match db.get_or_insert_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let (mut added, mut changed) = (0, 0);
+        for (lon, lat, member) in self.positions {
+            let score = geo::encode(lon, lat) as f64;
+            match zset.score(&member) {
+                None if self.xx => {}
+                None => {
+                    zset.insert(member, score);
+                    added += 1;
+                }
+                Some(current) if current != score && !self.nx => {
+                    zset.insert(member, score);
+                    changed += 1;
+                }
+                Some(_) => {}
+            }
+        }
+        if added + changed > 0 {
+            db.notify(Class::ZSet, "zadd", &self.key);
+        }
+        // XX may have left the newly created set empty
+        db.remove_if_empty(&self.key);
+        db.serve_blocked(&self.key);
+        Frame::Integer(if self.ch { added + changed } else { added })
+    }
+}
+
+impl GeoPos {
+    pub fn parse_frames(parser: &mut Parser) -> Result<GeoPos> {
+        let key = parser.next_string()?;
+        let members = match parser.remaining_bytes() {
+            Ok(members) => members,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(GeoPos { key, members })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let positions = self
+            .members
+            .iter()
+            .map(|member| match position(zset, member) {
+                Some((lon, lat)) => {
+                    Frame::Array(vec![Frame::into_double(lon), Frame::into_double(lat)])
+                }
+                None => Frame::Null,
+            })
+            .collect();
+        Frame::Array(positions)
+    }
+}
+
+impl GeoDist {
+    pub fn parse_frames(parser: &mut Parser) -> Result<GeoDist> {
+        let key = parser.next_string()?;
+        let first = parser.next_bytes()?;
+        let second = parser.next_bytes()?;
+        let unit = match parser.next_string() {
+            Ok(unit) => parse_unit(&unit)?,
+            Err(ParseError::EndOfStream) => 1.0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(GeoDist {
+            key,
+            first,
+            second,
+            unit,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        match (position(zset, &self.first), position(zset, &self.second)) {
+            (Some((lon1, lat1)), Some((lon2, lat2))) => {
+                distance_frame(geo::distance(lon1, lat1, lon2, lat2) / self.unit)
+            }
+            _ => Frame::Null,
+        }
+    }
+}
+
+impl GeoHash {
+    pub fn parse_frames(parser: &mut Parser) -> Result<GeoHash> {
+        let key = parser.next_string()?;
+        let members = match parser.remaining_bytes() {
+            Ok(members) => members,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(GeoHash { key, members })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let hashes = self
+            .members
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) => Frame::Bulk(Bytes::from(geo::to_geohash_string(score as u64))),
+                None => Frame::Null,
+            })
+            .collect();
+        Frame::Array(hashes)
+    }
+}
+
+impl GeoSearch {
+    /// the variant storing its result at a destination key
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    /// `store` tells whether a destination key leads the arguments (GEOSEARCHSTORE)
+    pub fn parse_frames(parser: &mut Parser, store: bool) -> Result<GeoSearch> {
+        let destination = if store {
+            Some(parser.next_string()?)
+        } else {
+            None
+        };
+        let key = parser.next_string()?;
+
+        let (mut origin, mut shape, mut unit) = (None, None, 1.0);
+        let (mut order, mut count, mut any) = (None, None, false);
+        let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+            (false, false, false, false);
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match &token[..] {
+                "frommember" if origin.is_none() => {
+                    origin = Some(Origin::Member(parser.next_bytes()?))
+                }
+                "fromlonlat" if origin.is_none() => {
+                    let (lon, lat) = (parser.next_float()?, parser.next_float()?);
+                    if !geo::is_valid(lon, lat) {
+                        return Err(format!(
+                            "invalid longitude,latitude pair {:.6},{:.6}",
+                            lon, lat
+                        )
+                        .into());
+                    }
+                    origin = Some(Origin::LonLat(lon, lat))
+                }
+                "byradius" if shape.is_none() => {
+                    let radius = parser.next_float()?;
+                    unit = parse_unit(&parser.next_string()?)?;
+                    if radius < 0.0 {
+                        return Err("radius cannot be negative".into());
+                    }
+                    shape = Some(Shape::Radius(radius * unit));
+                }
+                "bybox" if shape.is_none() => {
+                    let (width, height) = (parser.next_float()?, parser.next_float()?);
+                    unit = parse_unit(&parser.next_string()?)?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err("height or width cannot be negative".into());
+                    }
+                    shape = Some(Shape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                    });
+                }
+                "asc" => order = Some(Order::Asc),
+                "desc" => order = Some(Order::Desc),
+                "count" => {
+                    let value = parser.next_int()?;
+                    if value <= 0 {
+                        return Err("COUNT must be > 0".into());
+                    }
+                    count = Some(value as usize);
+                }
+                "any" => any = true,
+                "withcoord" if !store => with_coord = true,
+                "withdist" if !store => with_dist = true,
+                "withhash" if !store => with_hash = true,
+                "storedist" if store => store_dist = true,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        let Some(origin) = origin else {
+            return Err("exactly one of FROMMEMBER or FROMLONLAT can be specified".into());
+        };
+        let Some(shape) = shape else {
+            return Err("exactly one of BYRADIUS and BYBOX can be specified".into());
+        };
+        if any && count.is_none() {
+            return Err("the ANY argument requires COUNT argument".into());
+        }
+        // the closest members are the ones kept by a plain COUNT
+        if count.is_some() && !any && order.is_none() {
+            order = Some(Order::Asc);
+        }
+        Ok(GeoSearch {
+            key,
+            destination,
+            origin,
+            shape,
+            unit,
+            order,
+            count,
+            any,
+            with_coord,
+            with_dist,
+            with_hash,
+            store_dist,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let found = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => match self.search(zset) {
+                Some(found) => found,
+                None => {
+                    return Frame::Error("ERR could not decode requested zset member".to_string())
+                }
+            },
+            Ok(None) => vec![],
+            Err(e) => return e.into(),
+        };
+
+        match self.destination {
+            Some(destination) => {
+                let len = found.len();
+                let entries = found
+                    .into_iter()
+                    .map(|found| {
+                        let score = if self.store_dist {
+                            found.distance / self.unit
+                        } else {
+                            found.hash as f64
+                        };
+                        (found.member, score)
+                    })
+                    .collect();
+                store(db, destination, entries, "geosearchstore");
+                Frame::Integer(len as i64)
+            }
+            None => Frame::Array(
+                found
+                    .into_iter()
+                    .map(|found| self.found_frame(found))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// the matching members in the requested order, `None` when the origin member is missing
+    fn search(&self, zset: &ZSet) -> Option<Vec<Found>> {
+        let center = match &self.origin {
+            Origin::Member(member) => position(Some(zset), member)?,
+            Origin::LonLat(lon, lat) => (*lon, *lat),
+        };
+        let limit = if self.any { self.count } else { None };
+
+        let mut found = vec![];
+        'ranges: for (min, max) in geo::search_ranges(center.0, center.1, &self.shape) {
+            let min = ScoreBound {
+                value: min as f64,
+                exclusive: false,
+            };
+            let max = ScoreBound {
+                value: max as f64,
+                exclusive: true,
+            };
+            for (member, score) in zset.range_by_score(min, max, false, 0, None) {
+                let hash = score as u64;
+                let position = geo::decode(hash);
+                if let Some(distance) = self.shape.distance_if_within(center, position) {
+                    found.push(Found {
+                        member,
+                        distance,
+                        hash,
+                        position,
+                    });
+                    if limit.is_some_and(|limit| found.len() >= limit) {
+                        break 'ranges;
+                    }
+                }
+            }
+        }
+
+        match self.order {
+            Some(Order::Asc) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(Order::Desc) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        found.truncate(self.count.unwrap_or(usize::MAX));
+        Some(found)
+    }
+
+    fn found_frame(&self, found: Found) -> Frame {
+        if !self.with_coord && !self.with_dist && !self.with_hash {
+            return Frame::Bulk(found.member);
+        }
+        let mut frames = vec![Frame::Bulk(found.member)];
+        if self.with_dist {
+            frames.push(distance_frame(found.distance / self.unit));
+        }
+        if self.with_hash {
+            frames.push(Frame::Integer(found.hash as i64));
+        }
+        if self.with_coord {
+            let (lon, lat) = found.position;
+            frames.push(Frame::Array(vec![
+                Frame::into_double(lon),
+                Frame::into_double(lat),
+            ]));
+        }
+        Frame::Array(frames)
+    }
+}
+
+/// the decoded position of a member, the center of its geohash cell
+fn position(zset: Option<&ZSet>, member: &[u8]) -> Option<(f64, f64)> {
+    let score = zset?.score(member)?;
+    Some(geo::decode(score as u64))
+}
+
+fn distance_frame(distance: f64) -> Frame {
+    Frame::Bulk(Bytes::from(format!("{:.4}", distance)))
+}
+
+/// meters per unit
+fn parse_unit(unit: &str) -> Result<f64> {
+    match &unit.to_lowercase()[..] {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "mi" => Ok(1609.34),
+        "ft" => Ok(0.3048),
+        _ => Err("unsupported unit provided. please use M, KM, FT, MI".into()),
+    }
+}
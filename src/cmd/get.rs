@@ -1,7 +1,7 @@
 use bytes::Bytes;
 use tracing::instrument;
 
-use crate::{Connection, DbHolder, Frame, Result};
+use crate::{db::Database, Connection, DbHolder, Frame, Result};
 
 #[derive(Debug)]
 pub struct Get {
@@ -17,11 +17,15 @@ impl Get {
 
     #[instrument(skip(db, connection))]
     pub async fn execute(&self, connection: &mut Connection, db: &DbHolder) -> Result<()> {
-        let value = db.get(&self.key);
-        if let Some(value) = value {
-            Self::write_response(connection, value).await
-        } else {
-            connection.write_frame(Frame::Null).await
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(&self, db: &mut Database) -> Frame {
+        match db.read_string(&self.key) {
+            Ok(Some(value)) => Frame::Array(vec![Frame::Bulk(value)]),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
         }
     }
 
@@ -29,11 +33,4 @@ impl Get {
         let data = Bytes::copy_from_slice(key.as_bytes());
         Frame::Array(vec![Frame::into_simple("get"), Frame::Bulk(data)])
     }
-
-    async fn write_response(connection: &mut Connection, value: Bytes) -> Result<()> {
-        let data = Frame::Bulk(value);
-        let resp = Frame::Array(vec![data]);
-        connection.write_frame(resp).await?;
-        Ok(())
-    }
 }
@@ -0,0 +1,377 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, ExpireCondition},
+    parser::Parser,
+    Connection, DbHolder, Frame, Result,
+};
+
+#[derive(Debug)]
+pub struct HSet {
+    key: String,
+    pairs: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug)]
+pub struct HGet {
+    key: String,
+    field: Bytes,
+}
+
+#[derive(Debug)]
+pub struct HDel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct HLen {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct HExists {
+    key: String,
+    field: Bytes,
+}
+
+#[derive(Debug)]
+pub struct HGetAll {
+    key: String,
+}
+
+/// HEXPIRE and HPEXPIRE
+#[derive(Debug)]
+pub struct HExpire {
+    key: String,
+    ttl: Duration,
+    condition: ExpireCondition,
+    fields: Vec<Bytes>,
+}
+
+/// HTTL and HPTTL
+#[derive(Debug)]
+pub struct HTtl {
+    key: String,
+    millis: bool,
+    fields: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct HPersist {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl HSet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HSet> {
+        let key = parser.next_string()?;
+        let args = parser.remaining_bytes()?;
+        if args.len() % 2 != 0 {
+            return Err("wrong number of arguments for 'hset' command".into());
+        }
+        let pairs = args
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Ok(HSet { key, pairs })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let hash = match db.get_or_insert_hash(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let mut added = 0;
+        for (field, value) in self.pairs {
+            if hash.insert(field, value) {
+                added += 1;
+            }
+        }
+        db.notify(Class::Hash, "hset", &self.key);
+        db.sync_hash(&self.key);
+        Frame::Integer(added)
+    }
+}
+
+impl HGet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HGet> {
+        Ok(HGet {
+            key: parser.next_string()?,
+            field: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(Some(hash)) => hash
+                .get(&self.field)
+                .map_or(Frame::Null, |value| Frame::Bulk(value.clone())),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl HDel {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HDel> {
+        let key = parser.next_string()?;
+        let fields = parser.remaining_bytes()?;
+        Ok(HDel { key, fields })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let removed = match db.get_hash(&self.key) {
+            Ok(Some(hash)) => self.fields.iter().filter(|f| hash.remove(f)).count(),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if removed > 0 {
+            db.notify(Class::Hash, "hdel", &self.key);
+        }
+        db.sync_hash(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl HLen {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HLen> {
+        Ok(HLen {
+            key: parser.next_string()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(hash) => Frame::Integer(hash.map_or(0, |hash| hash.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl HExists {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HExists> {
+        Ok(HExists {
+            key: parser.next_string()?,
+            field: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(hash) => Frame::Integer(hash.map_or(0, |hash| hash.contains(&self.field) as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl HGetAll {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HGetAll> {
+        Ok(HGetAll {
+            key: parser.next_string()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_hash(&self.key) {
+            Ok(Some(hash)) => Frame::Array(
+                hash.iter()
+                    .flat_map(|(field, value)| {
+                        [Frame::Bulk(field.clone()), Frame::Bulk(value.clone())]
+                    })
+                    .collect(),
+            ),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl HExpire {
+    /// `millis` tells whether the ttl is given in milliseconds (HPEXPIRE) or seconds (HEXPIRE)
+    pub fn parse_frames(parser: &mut Parser, millis: bool) -> Result<HExpire> {
+        let key = parser.next_string()?;
+        let ttl = parser.next_int()?;
+        if ttl < 0 {
+            return Err("invalid expire time".into());
+        }
+        let ttl = if millis {
+            Duration::from_millis(ttl as u64)
+        } else {
+            Duration::from_secs(ttl as u64)
+        };
+
+        let mut token = parser.next_string()?.to_lowercase();
+        let condition = match &token[..] {
+            "nx" => ExpireCondition::Nx,
+            "xx" => ExpireCondition::Xx,
+            "gt" => ExpireCondition::Gt,
+            "lt" => ExpireCondition::Lt,
+            _ => ExpireCondition::Always,
+        };
+        if condition != ExpireCondition::Always {
+            token = parser.next_string()?.to_lowercase();
+        }
+        let fields = parse_fields(parser, &token)?;
+        Ok(HExpire {
+            key,
+            ttl,
+            condition,
+            fields,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let now = Instant::now();
+        let deadline = now + self.ttl;
+        let codes = match db.get_hash(&self.key) {
+            Ok(Some(hash)) => self
+                .fields
+                .iter()
+                .map(|field| hash.expire(field, deadline, self.condition, now))
+                .collect(),
+            Ok(None) => vec![-2; self.fields.len()],
+            Err(e) => return e.into(),
+        };
+        // fields given a deadline which has already passed are deleted at once
+        if codes.contains(&1) {
+            db.notify(Class::Hash, "hexpire", &self.key);
+        }
+        if codes.contains(&2) {
+            db.notify(Class::Hash, "hdel", &self.key);
+        }
+        db.sync_hash(&self.key);
+        Frame::Array(codes.into_iter().map(Frame::Integer).collect())
+    }
+}
+
+impl HTtl {
+    /// `millis` tells whether the ttl is replied in milliseconds (HPTTL) or seconds (HTTL)
+    pub fn parse_frames(parser: &mut Parser, millis: bool) -> Result<HTtl> {
+        let key = parser.next_string()?;
+        let token = parser.next_string()?.to_lowercase();
+        let fields = parse_fields(parser, &token)?;
+        Ok(HTtl {
+            key,
+            millis,
+            fields,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let now = Instant::now();
+        let hash = match db.get_hash(&self.key) {
+            Ok(hash) => hash,
+            Err(e) => return e.into(),
+        };
+        let ttls = self.fields.iter().map(|field| {
+            match hash.as_ref().and_then(|hash| hash.ttl(field, now)) {
+                None => -2,
+                Some(None) => -1,
+                Some(Some(ttl)) if self.millis => ttl.as_millis() as i64,
+                // round up like redis does, a field about to expire still has 1 second
+                Some(Some(ttl)) => ttl.as_millis().div_ceil(1000) as i64,
+            }
+        });
+        Frame::Array(ttls.map(Frame::Integer).collect())
+    }
+}
+
+impl HPersist {
+    pub fn parse_frames(parser: &mut Parser) -> Result<HPersist> {
+        let key = parser.next_string()?;
+        let token = parser.next_string()?.to_lowercase();
+        let fields = parse_fields(parser, &token)?;
+        Ok(HPersist { key, fields })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let codes = match db.get_hash(&self.key) {
+            Ok(Some(hash)) => self
+                .fields
+                .iter()
+                .map(|field| hash.persist(field))
+                .collect(),
+            Ok(None) => vec![-2; self.fields.len()],
+            Err(e) => return e.into(),
+        };
+        if codes.contains(&1) {
+            db.notify(Class::Hash, "hpersist", &self.key);
+        }
+        db.sync_hash(&self.key);
+        Frame::Array(codes.into_iter().map(Frame::Integer).collect())
+    }
+}
+
+/// parse `FIELDS numfields field [field ...]`, `token` is the already consumed keyword
+fn parse_fields(parser: &mut Parser, token: &str) -> Result<Vec<Bytes>> {
+    if token != "fields" {
+        return Err(format!("unexpected argument '{}', expected FIELDS", token).into());
+    }
+    let count = parser.next_int()?;
+    if count <= 0 {
+        return Err("numfields should be greater than 0".into());
+    }
+    let fields = (0..count)
+        .map(|_| parser.next_bytes())
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(fields)
+}
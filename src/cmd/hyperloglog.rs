@@ -0,0 +1,168 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, Value},
+    hyperloglog::{HyperLogLog, InvalidHll},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+#[derive(Debug)]
+pub struct PfAdd {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct PfMerge {
+    destination: String,
+    sources: Vec<String>,
+}
+
+impl PfAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<PfAdd> {
+        let key = parser.next_string()?;
+        let elements = match parser.remaining_bytes() {
+            Ok(elements) => elements,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(PfAdd { key, elements })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let (mut hll, created) = match load(db, &self.key) {
+            Ok(Some(hll)) => (hll, false),
+            Ok(None) => (HyperLogLog::new(), true),
+            Err(frame) => return frame,
+        };
+        let mut updated = created;
+        for element in &self.elements {
+            updated |= hll.add(element);
+        }
+        if updated {
+            store(db, &self.key, &mut hll);
+            db.notify(Class::String, "pfadd", &self.key);
+        }
+        Frame::Integer(updated as i64)
+    }
+}
+
+impl PfCount {
+    pub fn parse_frames(parser: &mut Parser) -> Result<PfCount> {
+        let keys = parser.remaining_strings()?;
+        Ok(PfCount { keys })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the cardinality of a single key is cached into its header,
+    /// the one of a union is computed on a temporary merge
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if let [key] = &self.keys[..] {
+            return match load(db, key) {
+                Ok(Some(mut hll)) => {
+                    let cached = hll.is_cached();
+                    let cardinality = hll.count();
+                    if !cached {
+                        store(db, key, &mut hll);
+                    }
+                    Frame::Integer(cardinality as i64)
+                }
+                Ok(None) => Frame::Integer(0),
+                Err(frame) => frame,
+            };
+        }
+
+        let mut union = HyperLogLog::new();
+        for key in &self.keys {
+            match load(db, key) {
+                Ok(Some(hll)) => union.merge(&hll),
+                Ok(None) => {}
+                Err(frame) => return frame,
+            }
+        }
+        Frame::Integer(union.count() as i64)
+    }
+}
+
+impl PfMerge {
+    pub fn parse_frames(parser: &mut Parser) -> Result<PfMerge> {
+        let destination = parser.next_string()?;
+        let sources = match parser.remaining_strings() {
+            Ok(sources) => sources,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(PfMerge {
+            destination,
+            sources,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let mut merged = match load(db, &self.destination) {
+            Ok(hll) => hll.unwrap_or_else(HyperLogLog::new),
+            Err(frame) => return frame,
+        };
+        for key in &self.sources {
+            match load(db, key) {
+                Ok(Some(hll)) => merged.merge(&hll),
+                Ok(None) => {}
+                Err(frame) => return frame,
+            }
+        }
+        store(db, &self.destination, &mut merged);
+        db.notify(Class::String, "pfadd", &self.destination);
+        Frame::into_simple("OK")
+    }
+}
+
+/// the HyperLogLog stored at key, any other value is an error
+fn load(db: &Database, key: &str) -> std::result::Result<Option<HyperLogLog>, Frame> {
+    let bytes = match db.get_string(key) {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match HyperLogLog::decode(&bytes) {
+        Ok(hll) => Ok(Some(hll)),
+        Err(InvalidHll::NotHll) => Err(Frame::Error(
+            "WRONGTYPE Key is not a valid HyperLogLog string value.".to_string(),
+        )),
+        Err(InvalidHll::Corrupted) => Err(Frame::Error(
+            "INVALIDOBJ Corrupted HLL object detected".to_string(),
+        )),
+    }
+}
+
+/// write the HyperLogLog back as a string, the deadline of an existing key is kept
+fn store(db: &mut Database, key: &str, hll: &mut HyperLogLog) {
+    let encoded = hll.encode();
+    match db.get_string_mut(key) {
+        Ok(Some(bytes)) => *bytes = encoded,
+        _ => db.insert(key.to_string(), Value::String(encoded)),
+    }
+}
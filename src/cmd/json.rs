@@ -0,0 +1,708 @@
+use bytes::Bytes;
+use serde_json::{Number, Value as Json};
+use tracing::instrument;
+
+use crate::{
+    db::{lookup_mut, normalize, remove, type_name, Class, Database, Format, JsonPath, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+#[derive(Debug)]
+pub struct JsonSet {
+    key: String,
+    path: JsonPath,
+    value: Json,
+    nx: bool,
+    xx: bool,
+}
+
+#[derive(Debug)]
+pub struct JsonGet {
+    key: String,
+    format: Format,
+    paths: Vec<JsonPath>,
+}
+
+#[derive(Debug)]
+pub struct JsonMGet {
+    keys: Vec<String>,
+    path: JsonPath,
+}
+
+/// JSON.DEL and JSON.FORGET
+#[derive(Debug)]
+pub struct JsonDel {
+    key: String,
+    path: JsonPath,
+}
+
+#[derive(Debug)]
+pub struct JsonType {
+    key: String,
+    path: JsonPath,
+}
+
+#[derive(Debug)]
+pub struct JsonResp {
+    key: String,
+    path: JsonPath,
+}
+
+/// JSON.NUMINCRBY and JSON.NUMMULTBY
+#[derive(Debug)]
+pub struct JsonNumOp {
+    key: String,
+    path: JsonPath,
+    operand: Number,
+    multiply: bool,
+}
+
+#[derive(Debug)]
+pub struct JsonStrAppend {
+    key: String,
+    path: JsonPath,
+    suffix: String,
+}
+
+/// JSON.STRLEN, JSON.ARRLEN and JSON.OBJLEN
+#[derive(Debug)]
+pub struct JsonLen {
+    key: String,
+    path: JsonPath,
+    of: &'static str,
+}
+
+/// JSON.ARRAPPEND and JSON.ARRINSERT
+#[derive(Debug)]
+pub struct JsonArrInsert {
+    key: String,
+    path: JsonPath,
+    index: Option<i64>,
+    values: Vec<Json>,
+}
+
+#[derive(Debug)]
+pub struct JsonArrPop {
+    key: String,
+    path: JsonPath,
+    index: i64,
+}
+
+impl JsonSet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonSet> {
+        let key = parser.next_string()?;
+        let path = JsonPath::parse(&parser.next_string()?)?;
+        let value = parse_json(parser)?;
+        let (mut nx, mut xx) = (false, false);
+        loop {
+            match parser.next_string() {
+                Ok(token) => match &token.to_lowercase()[..] {
+                    "nx" => nx = true,
+                    "xx" => xx = true,
+                    _ => return Err(format!("syntax error near '{}'", token).into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if nx && xx {
+            return Err("syntax error".into());
+        }
+        Ok(JsonSet {
+            key,
+            path,
+            value,
+            nx,
+            xx,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let key = self.key.clone();
+        let frame = self.write(db);
+        if let Frame::Simple(_) = frame {
+            db.notify(Class::Module, "json.set", &key);
+        }
+        frame
+    }
+
+    /// the selected values are replaced, when there are none the last member of the path
+    /// is added to the objects holding it. nothing is written when NX or XX can't be met
+    fn write(self, db: &mut Database) -> Frame {
+        let document = match db.get_json_mut(&self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) if self.xx => return Frame::Null,
+            Ok(None) if !self.path.is_root() => {
+                return Frame::Error("ERR new objects must be created at the root".to_string())
+            }
+            Ok(None) => {
+                db.insert(self.key, Value::Json(self.value));
+                return Frame::into_simple("OK");
+            }
+            Err(e) => return e.into(),
+        };
+
+        let mut locations = self.path.locations(document);
+        if !locations.is_empty() {
+            if self.nx {
+                return Frame::Null;
+            }
+            // nested values first, so that their location is still valid
+            locations.sort_unstable_by(|a, b| b.cmp(a));
+            for location in locations {
+                if let Some(value) = lookup_mut(document, &location) {
+                    *value = self.value.clone();
+                }
+            }
+            return Frame::into_simple("OK");
+        }
+        let points = self.path.insertion_points(document);
+        if self.xx || points.is_empty() {
+            return Frame::Null;
+        }
+        for (location, name) in points {
+            if let Some(Json::Object(map)) = lookup_mut(document, &location) {
+                map.insert(name, self.value.clone());
+            }
+        }
+        Frame::into_simple("OK")
+    }
+}
+
+impl JsonGet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonGet> {
+        let key = parser.next_string()?;
+        let mut format = Format::default();
+        let mut paths = vec![];
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match &token.to_lowercase()[..] {
+                "indent" => format.indent = parser.next_string()?,
+                "newline" => format.newline = parser.next_string()?,
+                "space" => format.space = parser.next_string()?,
+                _ => paths.push(JsonPath::parse(&token)?),
+            }
+        }
+        if paths.is_empty() {
+            paths.push(root());
+        }
+        Ok(JsonGet { key, format, paths })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// a single path replies with its value, several with an object keyed by path
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let document = match db.get_json(&self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let all_legacy = self.paths.iter().all(JsonPath::is_legacy);
+        let mut values = vec![];
+        for path in &self.paths {
+            let selected = path.select(document);
+            let value = if path.is_legacy() && (self.paths.len() == 1 || all_legacy) {
+                match selected.first() {
+                    Some(value) => (*value).clone(),
+                    None => return no_such_path(path),
+                }
+            } else {
+                Json::Array(selected.into_iter().cloned().collect())
+            };
+            values.push((path.to_string(), value));
+        }
+        let reply = match values.len() {
+            1 => values.remove(0).1,
+            _ => Json::Object(values.into_iter().collect()),
+        };
+        Frame::Bulk(Bytes::from(self.format.serialize(&reply)))
+    }
+}
+
+impl JsonMGet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonMGet> {
+        let mut keys = parser.remaining_strings()?;
+        if keys.len() < 2 {
+            return Err("wrong number of arguments for 'json.mget' command".into());
+        }
+        let path = JsonPath::parse(&keys.pop().unwrap_or_default())?;
+        Ok(JsonMGet { keys, path })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// keys which are missing or don't hold a document reply with nil
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let format = Format::default();
+        let values = self
+            .keys
+            .iter()
+            .map(|key| {
+                let Ok(Some(document)) = db.get_json(key) else {
+                    return Frame::Null;
+                };
+                let selected = self.path.select(document);
+                let value = if self.path.is_legacy() {
+                    match selected.first() {
+                        Some(value) => (*value).clone(),
+                        None => return Frame::Null,
+                    }
+                } else {
+                    Json::Array(selected.into_iter().cloned().collect())
+                };
+                Frame::Bulk(Bytes::from(format.serialize(&value)))
+            })
+            .collect();
+        Frame::Array(values)
+    }
+}
+
+impl JsonDel {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonDel> {
+        let key = parser.next_string()?;
+        let path = parse_optional_path(parser)?;
+        Ok(JsonDel { key, path })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// deleting the root deletes the key
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let document = match db.get_json_mut(&self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        if self.path.is_root() {
+            db.remove(&self.key);
+            db.notify(Class::Module, "json.del", &self.key);
+            return Frame::Integer(1);
+        }
+        let locations = self.path.locations(document);
+        let removed = remove(document, locations);
+        if removed > 0 {
+            db.notify(Class::Module, "json.del", &self.key);
+        }
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl JsonType {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonType> {
+        let key = parser.next_string()?;
+        let path = parse_optional_path(parser)?;
+        Ok(JsonType { key, path })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        inspect(db, &self.key, &self.path, |value| {
+            Ok(Frame::into_simple(type_name(value)))
+        })
+    }
+}
+
+impl JsonResp {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonResp> {
+        let key = parser.next_string()?;
+        let path = parse_optional_path(parser)?;
+        Ok(JsonResp { key, path })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        inspect(db, &self.key, &self.path, |value| Ok(to_resp(value)))
+    }
+}
+
+impl JsonNumOp {
+    /// `multiply` tells whether the values are multiplied (JSON.NUMMULTBY) rather than
+    /// incremented
+    pub fn parse_frames(parser: &mut Parser, multiply: bool) -> Result<JsonNumOp> {
+        let key = parser.next_string()?;
+        let path = JsonPath::parse(&parser.next_string()?)?;
+        let Json::Number(operand) = parse_json(parser)? else {
+            return Err("expected value is not a number".into());
+        };
+        Ok(JsonNumOp {
+            key,
+            path,
+            operand,
+            multiply,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the reply is the json of the new value, or of an array of them for a JSONPath where
+    /// the values which aren't numbers are null
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let document = match db.get_json_mut(&self.key) {
+            Ok(Some(document)) => document,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let locations = self.path.locations(document);
+        // every result is computed before any is stored, an overflow changes nothing
+        let mut results = Vec::with_capacity(locations.len());
+        for location in &locations {
+            let result = match lookup_mut(document, location) {
+                Some(Json::Number(current)) => match self.compute(current) {
+                    Some(number) => Json::Number(number),
+                    None => {
+                        return Frame::Error("ERR result is not a number or overflows".to_string())
+                    }
+                },
+                Some(value) if self.path.is_legacy() => {
+                    return wrong_type("number", value);
+                }
+                _ => Json::Null,
+            };
+            results.push(result);
+        }
+        for (location, result) in locations.iter().zip(&results) {
+            match lookup_mut(document, location) {
+                Some(value) if !result.is_null() => *value = result.clone(),
+                _ => {}
+            }
+        }
+        if results.iter().any(|result| !result.is_null()) {
+            let event = if self.multiply {
+                "json.nummultby"
+            } else {
+                "json.numincrby"
+            };
+            db.notify(Class::Module, event, &self.key);
+        }
+
+        let reply = if self.path.is_legacy() {
+            match results.pop() {
+                Some(result) => result,
+                None => return no_such_path(&self.path),
+            }
+        } else {
+            Json::Array(results)
+        };
+        Frame::Bulk(Bytes::from(reply.to_string()))
+    }
+
+    /// integers stay integers unless the result doesn't fit
+    fn compute(&self, current: &Number) -> Option<Number> {
+        if let (Some(a), Some(b)) = (current.as_i64(), self.operand.as_i64()) {
+            let result = if self.multiply {
+                a.checked_mul(b)
+            } else {
+                a.checked_add(b)
+            };
+            if let Some(result) = result {
+                return Some(result.into());
+            }
+        }
+        let (a, b) = (current.as_f64()?, self.operand.as_f64()?);
+        Number::from_f64(if self.multiply { a * b } else { a + b })
+    }
+}
+
+impl JsonStrAppend {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonStrAppend> {
+        let key = parser.next_string()?;
+        let mut args = parser.remaining_strings()?;
+        let (path, value) = match (args.pop(), args.pop(), args.is_empty()) {
+            (Some(value), None, _) => (root(), value),
+            (Some(value), Some(path), true) => (JsonPath::parse(&path)?, value),
+            _ => return Err("wrong number of arguments for 'json.strappend' command".into()),
+        };
+        let Ok(Json::String(suffix)) = serde_json::from_str(&value) else {
+            return Err("expected a json string".into());
+        };
+        Ok(JsonStrAppend { key, path, suffix })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let append = |value: &mut Json| match value {
+            Json::String(string) => {
+                string.push_str(&self.suffix);
+                Ok(Frame::Integer(string.len() as i64))
+            }
+            value => Err(wrong_type("string", value)),
+        };
+        update(db, &self.key, &self.path, "json.strappend", append)
+    }
+}
+
+impl JsonLen {
+    /// `of` is the type of the values which are measured
+    pub fn parse_frames(parser: &mut Parser, of: &'static str) -> Result<JsonLen> {
+        let key = parser.next_string()?;
+        let path = parse_optional_path(parser)?;
+        Ok(JsonLen { key, path, of })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        inspect(db, &self.key, &self.path, |value| {
+            let len = match value {
+                Json::String(string) if self.of == "string" => string.len(),
+                Json::Array(array) if self.of == "array" => array.len(),
+                Json::Object(map) if self.of == "object" => map.len(),
+                value => return Err(wrong_type(self.of, value)),
+            };
+            Ok(Frame::Integer(len as i64))
+        })
+    }
+}
+
+impl JsonArrInsert {
+    /// `insert` tells whether the values are preceded by the index where they are inserted
+    /// (JSON.ARRINSERT) rather than appended
+    pub fn parse_frames(parser: &mut Parser, insert: bool) -> Result<JsonArrInsert> {
+        let key = parser.next_string()?;
+        let path = JsonPath::parse(&parser.next_string()?)?;
+        let index = if insert {
+            Some(parser.next_int()?)
+        } else {
+            None
+        };
+        let mut values = vec![parse_json(parser)?];
+        loop {
+            match parse_json(parser) {
+                Ok(value) => values.push(value),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(JsonArrInsert {
+            key,
+            path,
+            index,
+            values,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// a negative index counts from the end, the length of the array appends
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let event = match self.index {
+            Some(_) => "json.arrinsert",
+            None => "json.arrappend",
+        };
+        update(db, &self.key, &self.path, event, |value| {
+            let Json::Array(array) = value else {
+                return Err(wrong_type("array", value));
+            };
+            let index = match self.index {
+                None => array.len(),
+                Some(index) if index == array.len() as i64 => array.len(),
+                Some(index) => match normalize(index, array.len()) {
+                    Some(index) => index,
+                    None => return Err(Frame::Error("ERR index out of bounds".to_string())),
+                },
+            };
+            array.splice(index..index, self.values.iter().cloned());
+            Ok(Frame::Integer(array.len() as i64))
+        })
+    }
+}
+
+impl JsonArrPop {
+    pub fn parse_frames(parser: &mut Parser) -> Result<JsonArrPop> {
+        let key = parser.next_string()?;
+        let path = parse_optional_path(parser)?;
+        let index = match parser.next_int() {
+            Ok(index) => index,
+            Err(ParseError::EndOfStream) => -1,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(JsonArrPop { key, path, index })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// an index out of the array pops the nearest end, an empty array replies nil
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        update(db, &self.key, &self.path, "json.arrpop", |value| {
+            let Json::Array(array) = value else {
+                return Err(wrong_type("array", value));
+            };
+            // nothing is popped, which isn't a write
+            if array.is_empty() {
+                return Err(Frame::Null);
+            }
+            let len = array.len() as i64;
+            let index = if self.index < 0 {
+                self.index + len
+            } else {
+                self.index
+            };
+            let popped = array.remove(index.clamp(0, len - 1) as usize);
+            Ok(Frame::Bulk(Bytes::from(popped.to_string())))
+        })
+    }
+}
+
+/// the reply for every selected value: an array for a JSONPath where the values the command
+/// doesn't apply to are nil, the reply for the last value for a legacy path
+fn reply(path: &JsonPath, mut results: Vec<std::result::Result<Frame, Frame>>) -> Frame {
+    if !path.is_legacy() {
+        return Frame::Array(
+            results
+                .into_iter()
+                .map(|result| result.unwrap_or(Frame::Null))
+                .collect(),
+        );
+    }
+    match results.pop() {
+        Some(Ok(frame)) | Some(Err(frame)) => frame,
+        None => no_such_path(path),
+    }
+}
+
+/// apply `op` to the selected values of a document, a missing key replies nil
+fn inspect(
+    db: &Database,
+    key: &str,
+    path: &JsonPath,
+    op: impl Fn(&Json) -> std::result::Result<Frame, Frame>,
+) -> Frame {
+    match db.get_json(key) {
+        Ok(Some(document)) => reply(path, path.select(document).into_iter().map(op).collect()),
+        Ok(None) => Frame::Null,
+        Err(e) => e.into(),
+    }
+}
+
+/// modify the selected values of a document in place, the key must exist. the
+/// event is notified when `op` succeeds on at least one value
+fn update(
+    db: &mut Database,
+    key: &str,
+    path: &JsonPath,
+    event: &str,
+    mut op: impl FnMut(&mut Json) -> std::result::Result<Frame, Frame>,
+) -> Frame {
+    let document = match db.get_json_mut(key) {
+        Ok(Some(document)) => document,
+        Ok(None) => return no_such_key(),
+        Err(e) => return e.into(),
+    };
+    let results: Vec<_> = path
+        .locations(document)
+        .iter()
+        .filter_map(|location| lookup_mut(document, location).map(&mut op))
+        .collect();
+    if results.iter().any(std::result::Result::is_ok) {
+        db.notify(Class::Module, event, key);
+    }
+    reply(path, results)
+}
+
+/// objects are replied as RESP3 maps
+fn to_resp(value: &Json) -> Frame {
+    match value {
+        Json::Null => Frame::Null,
+        Json::Bool(boolean) => Frame::into_simple(&boolean.to_string()),
+        Json::Number(number) => match number.as_i64() {
+            Some(integer) => Frame::Integer(integer),
+            None => Frame::Bulk(Bytes::from(number.to_string())),
+        },
+        Json::String(string) => Frame::Bulk(Bytes::from(string.clone())),
+        Json::Array(array) => Frame::Array(array.iter().map(to_resp).collect()),
+        Json::Object(map) => Frame::Map(
+            map.iter()
+                .map(|(key, member)| (Frame::Bulk(Bytes::from(key.clone())), to_resp(member)))
+                .collect(),
+        ),
+    }
+}
+
+fn parse_json(parser: &mut Parser) -> std::result::Result<Json, ParseError> {
+    let text = parser.next_bytes()?;
+    serde_json::from_slice(&text).map_err(|e| format!("invalid json: {}", e).into())
+}
+
+/// the path defaults to the root in the legacy syntax
+fn parse_optional_path(parser: &mut Parser) -> Result<JsonPath> {
+    match parser.next_string() {
+        Ok(path) => Ok(JsonPath::parse(&path)?),
+        Err(ParseError::EndOfStream) => Ok(root()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn root() -> JsonPath {
+    JsonPath::parse(".").expect("the root is a valid path")
+}
+
+fn wrong_type(expected: &str, found: &Json) -> Frame {
+    Frame::Error(format!(
+        "ERR wrong type of path value - expected {} but found {}",
+        expected,
+        type_name(found)
+    ))
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR could not perform this operation on a key that doesn't exist".to_string())
+}
+
+fn no_such_path(path: &JsonPath) -> Frame {
+    Frame::Error(format!("ERR Path '{}' does not exist", path))
+}
@@ -1,19 +1,249 @@
 mod ping;
 use std::time::Duration;
 
+use tokio::sync::mpsc;
+
 pub use ping::Ping;
 
 mod get;
 pub use get::Get;
 
 mod set;
-use crate::{parser::ParseError, parser::Parser, Connection, DbHolder, Frame, Result};
+use crate::{
+    db::Database, parser::ParseError, parser::Parser, Connection, DbHolder, Frame, Result,
+};
 pub use set::Set;
 
+mod hash;
+pub use hash::{HDel, HExists, HExpire, HGet, HGetAll, HLen, HPersist, HSet, HTtl};
+
+mod sets;
+pub use sets::{
+    SAdd, SCard, SCombine, SInterCard, SIsMember, SMIsMember, SMembers, SMove, SPop, SRandMember,
+    SRem, SScan, SetOp,
+};
+
+mod zset;
+pub use zset::{
+    BZPop, ZAdd, ZCard, ZCombine, ZCount, ZIncrBy, ZMPop, ZMScore, ZPop, ZRange, ZRank, ZRem,
+    ZRemRange, ZScore, ZSetOp,
+};
+
+mod stream;
+pub use stream::{XAdd, XDel, XLen, XRange, XRead, XTrim};
+
+mod stream_group;
+pub use stream_group::{XAck, XAutoClaim, XClaim, XGroup, XInfo, XPending, XReadGroup};
+
+mod hyperloglog;
+pub use hyperloglog::{PfAdd, PfCount, PfMerge};
+
+mod geo;
+pub use geo::{GeoAdd, GeoDist, GeoHash, GeoPos, GeoSearch};
+
+mod bloom;
+pub use bloom::{BfAdd, BfExists, BfInfo, BfReserve};
+
+mod cuckoo;
+pub use cuckoo::{CfAdd, CfCount, CfDel, CfExists, CfInfo, CfReserve};
+
+mod cms;
+pub use cms::{CmsIncrBy, CmsInit, CmsMerge, CmsQuery};
+
+mod topk;
+pub use topk::{TopKAdd, TopKList, TopKQuery, TopKReserve};
+
+mod tdigest;
+pub use tdigest::{
+    TDigestAdd, TDigestCreate, TDigestExtreme, TDigestMerge, TDigestQuery, TDigestReset,
+    TDigestTrimmedMean,
+};
+
+mod json;
+pub use json::{
+    JsonArrInsert, JsonArrPop, JsonDel, JsonGet, JsonLen, JsonMGet, JsonNumOp, JsonResp, JsonSet,
+    JsonStrAppend, JsonType,
+};
+
+mod timeseries;
+pub use timeseries::{
+    TsAdd, TsCreate, TsCreateRule, TsDel, TsDeleteRule, TsGet, TsInfo, TsMAdd, TsMRange,
+    TsQueryIndex, TsRange,
+};
+
+mod search;
+pub use search::{FtAggregate, FtCreate, FtDropIndex, FtInfo, FtList, FtSearch};
+
+mod vectorset;
+pub use vectorset::{VAdd, VCard, VDim, VEmb, VGetAttr, VInfo, VLinks, VRem, VSetAttr, VSim};
+
+mod roaring;
+pub use roaring::{RAdd, RBitOp, RCard, RContains, RRange};
+
+mod object;
+pub use object::Object;
+
+mod config;
+pub use config::Config;
+
+mod pubsub;
+pub use pubsub::{PubSub, Publish, Reset, Subscribe, Unsubscribe};
+
+mod client;
+pub use client::{Client, Hello};
+
+mod script;
+pub(crate) use script::run_script;
+pub use script::{Eval, Script};
+
+mod transaction;
+pub(crate) use transaction::Transaction;
+pub use transaction::{Discard, Exec, Multi, Unwatch, Watch};
+
 pub enum Command {
     Ping(Ping),
     Get(Get),
     Set(Set),
+    HSet(HSet),
+    HGet(HGet),
+    HDel(HDel),
+    HLen(HLen),
+    HExists(HExists),
+    HGetAll(HGetAll),
+    HExpire(HExpire),
+    HTtl(HTtl),
+    HPersist(HPersist),
+    SAdd(SAdd),
+    SRem(SRem),
+    SIsMember(SIsMember),
+    SMIsMember(SMIsMember),
+    SMembers(SMembers),
+    SCard(SCard),
+    SPop(SPop),
+    SRandMember(SRandMember),
+    SMove(SMove),
+    SCombine(SCombine),
+    SInterCard(SInterCard),
+    SScan(SScan),
+    ZAdd(ZAdd),
+    ZRem(ZRem),
+    ZScore(ZScore),
+    ZMScore(ZMScore),
+    ZIncrBy(ZIncrBy),
+    ZCard(ZCard),
+    ZCount(ZCount),
+    ZRank(ZRank),
+    ZRange(ZRange),
+    ZPop(ZPop),
+    BZPop(BZPop),
+    ZMPop(ZMPop),
+    ZRemRange(ZRemRange),
+    ZCombine(ZCombine),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    XTrim(XTrim),
+    XDel(XDel),
+    XRead(XRead),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XAutoClaim(XAutoClaim),
+    XInfo(XInfo),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    GeoAdd(GeoAdd),
+    GeoPos(GeoPos),
+    GeoDist(GeoDist),
+    GeoHash(GeoHash),
+    GeoSearch(GeoSearch),
+    BfReserve(BfReserve),
+    BfAdd(BfAdd),
+    BfExists(BfExists),
+    BfInfo(BfInfo),
+    CfReserve(CfReserve),
+    CfAdd(CfAdd),
+    CfExists(CfExists),
+    CfDel(CfDel),
+    CfCount(CfCount),
+    CfInfo(CfInfo),
+    CmsInit(CmsInit),
+    CmsIncrBy(CmsIncrBy),
+    CmsQuery(CmsQuery),
+    CmsMerge(CmsMerge),
+    TopKReserve(TopKReserve),
+    TopKAdd(TopKAdd),
+    TopKQuery(TopKQuery),
+    TopKList(TopKList),
+    TDigestCreate(TDigestCreate),
+    TDigestAdd(TDigestAdd),
+    TDigestQuery(TDigestQuery),
+    TDigestExtreme(TDigestExtreme),
+    TDigestMerge(TDigestMerge),
+    TDigestReset(TDigestReset),
+    TDigestTrimmedMean(TDigestTrimmedMean),
+    JsonSet(JsonSet),
+    JsonGet(JsonGet),
+    JsonMGet(JsonMGet),
+    JsonDel(JsonDel),
+    JsonType(JsonType),
+    JsonResp(JsonResp),
+    JsonNumOp(JsonNumOp),
+    JsonStrAppend(JsonStrAppend),
+    JsonLen(JsonLen),
+    JsonArrInsert(JsonArrInsert),
+    JsonArrPop(JsonArrPop),
+    TsCreate(TsCreate),
+    TsAdd(TsAdd),
+    TsMAdd(TsMAdd),
+    TsGet(TsGet),
+    TsDel(TsDel),
+    TsCreateRule(TsCreateRule),
+    TsDeleteRule(TsDeleteRule),
+    TsRange(TsRange),
+    TsMRange(TsMRange),
+    TsQueryIndex(TsQueryIndex),
+    TsInfo(TsInfo),
+    FtCreate(FtCreate),
+    FtSearch(FtSearch),
+    FtAggregate(FtAggregate),
+    FtDropIndex(FtDropIndex),
+    FtInfo(FtInfo),
+    FtList(FtList),
+    VAdd(VAdd),
+    VSim(VSim),
+    VRem(VRem),
+    VCard(VCard),
+    VDim(VDim),
+    VEmb(VEmb),
+    VGetAttr(VGetAttr),
+    VSetAttr(VSetAttr),
+    VInfo(VInfo),
+    VLinks(VLinks),
+    RAdd(RAdd),
+    RContains(RContains),
+    RCard(RCard),
+    RRange(RRange),
+    RBitOp(RBitOp),
+    Object(Object),
+    Config(Config),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Publish(Publish),
+    PubSub(PubSub),
+    Reset(Reset),
+    Client(Client),
+    Hello(Hello),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    Script(Script),
 }
 
 impl Command {
@@ -21,7 +251,7 @@ impl Command {
         use ParseError::EndOfStream;
 
         let mut parser = Parser::new(frame)?;
-        let cmd_name = parser.next_string()?;
+        let cmd_name = parser.next_string()?.to_lowercase();
         let cmd = match &cmd_name[..] {
             "ping" => Command::Ping(Ping {}),
             "get" => {
@@ -39,17 +269,652 @@ impl Command {
                     Err(e) => return Err(e.into()),
                 }
             }
+            "hset" => Command::HSet(HSet::parse_frames(&mut parser)?),
+            "hget" => Command::HGet(HGet::parse_frames(&mut parser)?),
+            "hdel" => Command::HDel(HDel::parse_frames(&mut parser)?),
+            "hlen" => Command::HLen(HLen::parse_frames(&mut parser)?),
+            "hexists" => Command::HExists(HExists::parse_frames(&mut parser)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(&mut parser)?),
+            "hexpire" => Command::HExpire(HExpire::parse_frames(&mut parser, false)?),
+            "hpexpire" => Command::HExpire(HExpire::parse_frames(&mut parser, true)?),
+            "httl" => Command::HTtl(HTtl::parse_frames(&mut parser, false)?),
+            "hpttl" => Command::HTtl(HTtl::parse_frames(&mut parser, true)?),
+            "hpersist" => Command::HPersist(HPersist::parse_frames(&mut parser)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(&mut parser)?),
+            "srem" => Command::SRem(SRem::parse_frames(&mut parser)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(&mut parser)?),
+            "smismember" => Command::SMIsMember(SMIsMember::parse_frames(&mut parser)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(&mut parser)?),
+            "scard" => Command::SCard(SCard::parse_frames(&mut parser)?),
+            "spop" => Command::SPop(SPop::parse_frames(&mut parser)?),
+            "srandmember" => Command::SRandMember(SRandMember::parse_frames(&mut parser)?),
+            "smove" => Command::SMove(SMove::parse_frames(&mut parser)?),
+            "sinter" => {
+                Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Inter, false)?)
+            }
+            "sunion" => {
+                Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Union, false)?)
+            }
+            "sdiff" => Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Diff, false)?),
+            "sinterstore" => {
+                Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Inter, true)?)
+            }
+            "sunionstore" => {
+                Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Union, true)?)
+            }
+            "sdiffstore" => {
+                Command::SCombine(SCombine::parse_frames(&mut parser, SetOp::Diff, true)?)
+            }
+            "sintercard" => Command::SInterCard(SInterCard::parse_frames(&mut parser)?),
+            "sscan" => Command::SScan(SScan::parse_frames(&mut parser)?),
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(&mut parser)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(&mut parser)?),
+            "zscore" => Command::ZScore(ZScore::parse_frames(&mut parser)?),
+            "zmscore" => Command::ZMScore(ZMScore::parse_frames(&mut parser)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(&mut parser)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(&mut parser)?),
+            "zcount" => Command::ZCount(ZCount::parse_frames(&mut parser, false)?),
+            "zlexcount" => Command::ZCount(ZCount::parse_frames(&mut parser, true)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(&mut parser, false)?),
+            "zrevrank" => Command::ZRank(ZRank::parse_frames(&mut parser, true)?),
+            "zrange" => Command::ZRange(ZRange::parse_frames(&mut parser, false)?),
+            "zrangestore" => Command::ZRange(ZRange::parse_frames(&mut parser, true)?),
+            "zpopmin" => Command::ZPop(ZPop::parse_frames(&mut parser, false)?),
+            "zpopmax" => Command::ZPop(ZPop::parse_frames(&mut parser, true)?),
+            "bzpopmin" => Command::BZPop(BZPop::parse_frames(&mut parser, false)?),
+            "bzpopmax" => Command::BZPop(BZPop::parse_frames(&mut parser, true)?),
+            "zmpop" => Command::ZMPop(ZMPop::parse_frames(&mut parser, false)?),
+            "bzmpop" => Command::ZMPop(ZMPop::parse_frames(&mut parser, true)?),
+            "zremrangebyrank" => Command::ZRemRange(ZRemRange::parse_frames(&mut parser, "rank")?),
+            "zremrangebyscore" => {
+                Command::ZRemRange(ZRemRange::parse_frames(&mut parser, "score")?)
+            }
+            "zremrangebylex" => Command::ZRemRange(ZRemRange::parse_frames(&mut parser, "lex")?),
+            "zunion" => {
+                Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Union, false)?)
+            }
+            "zinter" => {
+                Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Inter, false)?)
+            }
+            "zdiff" => Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Diff, false)?),
+            "zunionstore" => {
+                Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Union, true)?)
+            }
+            "zinterstore" => {
+                Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Inter, true)?)
+            }
+            "zdiffstore" => {
+                Command::ZCombine(ZCombine::parse_frames(&mut parser, ZSetOp::Diff, true)?)
+            }
+            "xadd" => Command::XAdd(XAdd::parse_frames(&mut parser)?),
+            "xrange" => Command::XRange(XRange::parse_frames(&mut parser, false)?),
+            "xrevrange" => Command::XRange(XRange::parse_frames(&mut parser, true)?),
+            "xlen" => Command::XLen(XLen::parse_frames(&mut parser)?),
+            "xtrim" => Command::XTrim(XTrim::parse_frames(&mut parser)?),
+            "xdel" => Command::XDel(XDel::parse_frames(&mut parser)?),
+            "xread" => Command::XRead(XRead::parse_frames(&mut parser)?),
+            "xgroup" => Command::XGroup(XGroup::parse_frames(&mut parser)?),
+            "xreadgroup" => Command::XReadGroup(XReadGroup::parse_frames(&mut parser)?),
+            "xack" => Command::XAck(XAck::parse_frames(&mut parser)?),
+            "xpending" => Command::XPending(XPending::parse_frames(&mut parser)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(&mut parser)?),
+            "xautoclaim" => Command::XAutoClaim(XAutoClaim::parse_frames(&mut parser)?),
+            "xinfo" => Command::XInfo(XInfo::parse_frames(&mut parser)?),
+            "pfadd" => Command::PfAdd(PfAdd::parse_frames(&mut parser)?),
+            "pfcount" => Command::PfCount(PfCount::parse_frames(&mut parser)?),
+            "pfmerge" => Command::PfMerge(PfMerge::parse_frames(&mut parser)?),
+            "geoadd" => Command::GeoAdd(GeoAdd::parse_frames(&mut parser)?),
+            "geopos" => Command::GeoPos(GeoPos::parse_frames(&mut parser)?),
+            "geodist" => Command::GeoDist(GeoDist::parse_frames(&mut parser)?),
+            "geohash" => Command::GeoHash(GeoHash::parse_frames(&mut parser)?),
+            "geosearch" => Command::GeoSearch(GeoSearch::parse_frames(&mut parser, false)?),
+            "geosearchstore" => Command::GeoSearch(GeoSearch::parse_frames(&mut parser, true)?),
+            "bf.reserve" => Command::BfReserve(BfReserve::parse_frames(&mut parser)?),
+            "bf.add" => Command::BfAdd(BfAdd::parse_frames(&mut parser, false)?),
+            "bf.madd" => Command::BfAdd(BfAdd::parse_frames(&mut parser, true)?),
+            "bf.exists" => Command::BfExists(BfExists::parse_frames(&mut parser, false)?),
+            "bf.mexists" => Command::BfExists(BfExists::parse_frames(&mut parser, true)?),
+            "bf.info" => Command::BfInfo(BfInfo::parse_frames(&mut parser)?),
+            "cf.reserve" => Command::CfReserve(CfReserve::parse_frames(&mut parser)?),
+            "cf.add" => Command::CfAdd(CfAdd::parse_frames(&mut parser, false)?),
+            "cf.addnx" => Command::CfAdd(CfAdd::parse_frames(&mut parser, true)?),
+            "cf.exists" => Command::CfExists(CfExists::parse_frames(&mut parser, false)?),
+            "cf.mexists" => Command::CfExists(CfExists::parse_frames(&mut parser, true)?),
+            "cf.del" => Command::CfDel(CfDel::parse_frames(&mut parser)?),
+            "cf.count" => Command::CfCount(CfCount::parse_frames(&mut parser)?),
+            "cf.info" => Command::CfInfo(CfInfo::parse_frames(&mut parser)?),
+            "cms.initbydim" => Command::CmsInit(CmsInit::parse_frames(&mut parser, false)?),
+            "cms.initbyprob" => Command::CmsInit(CmsInit::parse_frames(&mut parser, true)?),
+            "cms.incrby" => Command::CmsIncrBy(CmsIncrBy::parse_frames(&mut parser)?),
+            "cms.query" => Command::CmsQuery(CmsQuery::parse_frames(&mut parser)?),
+            "cms.merge" => Command::CmsMerge(CmsMerge::parse_frames(&mut parser)?),
+            "topk.reserve" => Command::TopKReserve(TopKReserve::parse_frames(&mut parser)?),
+            "topk.add" => Command::TopKAdd(TopKAdd::parse_frames(&mut parser, false)?),
+            "topk.incrby" => Command::TopKAdd(TopKAdd::parse_frames(&mut parser, true)?),
+            "topk.query" => Command::TopKQuery(TopKQuery::parse_frames(&mut parser)?),
+            "topk.list" => Command::TopKList(TopKList::parse_frames(&mut parser)?),
+            "tdigest.create" => Command::TDigestCreate(TDigestCreate::parse_frames(&mut parser)?),
+            "tdigest.add" => Command::TDigestAdd(TDigestAdd::parse_frames(&mut parser)?),
+            "tdigest.quantile" => {
+                Command::TDigestQuery(TDigestQuery::parse_frames(&mut parser, false)?)
+            }
+            "tdigest.cdf" => Command::TDigestQuery(TDigestQuery::parse_frames(&mut parser, true)?),
+            "tdigest.min" => {
+                Command::TDigestExtreme(TDigestExtreme::parse_frames(&mut parser, false)?)
+            }
+            "tdigest.max" => {
+                Command::TDigestExtreme(TDigestExtreme::parse_frames(&mut parser, true)?)
+            }
+            "tdigest.merge" => Command::TDigestMerge(TDigestMerge::parse_frames(&mut parser)?),
+            "tdigest.reset" => Command::TDigestReset(TDigestReset::parse_frames(&mut parser)?),
+            "tdigest.trimmed_mean" => {
+                Command::TDigestTrimmedMean(TDigestTrimmedMean::parse_frames(&mut parser)?)
+            }
+            "json.set" => Command::JsonSet(JsonSet::parse_frames(&mut parser)?),
+            "json.get" => Command::JsonGet(JsonGet::parse_frames(&mut parser)?),
+            "json.mget" => Command::JsonMGet(JsonMGet::parse_frames(&mut parser)?),
+            "json.del" | "json.forget" => Command::JsonDel(JsonDel::parse_frames(&mut parser)?),
+            "json.type" => Command::JsonType(JsonType::parse_frames(&mut parser)?),
+            "json.resp" => Command::JsonResp(JsonResp::parse_frames(&mut parser)?),
+            "json.numincrby" => Command::JsonNumOp(JsonNumOp::parse_frames(&mut parser, false)?),
+            "json.nummultby" => Command::JsonNumOp(JsonNumOp::parse_frames(&mut parser, true)?),
+            "json.strappend" => Command::JsonStrAppend(JsonStrAppend::parse_frames(&mut parser)?),
+            "json.strlen" => Command::JsonLen(JsonLen::parse_frames(&mut parser, "string")?),
+            "json.arrlen" => Command::JsonLen(JsonLen::parse_frames(&mut parser, "array")?),
+            "json.objlen" => Command::JsonLen(JsonLen::parse_frames(&mut parser, "object")?),
+            "json.arrappend" => {
+                Command::JsonArrInsert(JsonArrInsert::parse_frames(&mut parser, false)?)
+            }
+            "json.arrinsert" => {
+                Command::JsonArrInsert(JsonArrInsert::parse_frames(&mut parser, true)?)
+            }
+            "json.arrpop" => Command::JsonArrPop(JsonArrPop::parse_frames(&mut parser)?),
+            "ts.create" => Command::TsCreate(TsCreate::parse_frames(&mut parser, false)?),
+            "ts.alter" => Command::TsCreate(TsCreate::parse_frames(&mut parser, true)?),
+            "ts.add" => Command::TsAdd(TsAdd::parse_frames(&mut parser)?),
+            "ts.madd" => Command::TsMAdd(TsMAdd::parse_frames(&mut parser)?),
+            "ts.get" => Command::TsGet(TsGet::parse_frames(&mut parser)?),
+            "ts.del" => Command::TsDel(TsDel::parse_frames(&mut parser)?),
+            "ts.createrule" => Command::TsCreateRule(TsCreateRule::parse_frames(&mut parser)?),
+            "ts.deleterule" => Command::TsDeleteRule(TsDeleteRule::parse_frames(&mut parser)?),
+            "ts.range" => Command::TsRange(TsRange::parse_frames(&mut parser, false)?),
+            "ts.revrange" => Command::TsRange(TsRange::parse_frames(&mut parser, true)?),
+            "ts.mrange" => Command::TsMRange(TsMRange::parse_frames(&mut parser, false)?),
+            "ts.mrevrange" => Command::TsMRange(TsMRange::parse_frames(&mut parser, true)?),
+            "ts.queryindex" => Command::TsQueryIndex(TsQueryIndex::parse_frames(&mut parser)?),
+            "ts.info" => Command::TsInfo(TsInfo::parse_frames(&mut parser)?),
+            "ft.create" => Command::FtCreate(FtCreate::parse_frames(&mut parser)?),
+            "ft.search" => Command::FtSearch(FtSearch::parse_frames(&mut parser)?),
+            "ft.aggregate" => Command::FtAggregate(FtAggregate::parse_frames(&mut parser)?),
+            "ft.dropindex" => Command::FtDropIndex(FtDropIndex::parse_frames(&mut parser)?),
+            "ft.info" => Command::FtInfo(FtInfo::parse_frames(&mut parser)?),
+            "ft._list" => Command::FtList(FtList::parse_frames(&mut parser)?),
+            "vadd" => Command::VAdd(VAdd::parse_frames(&mut parser)?),
+            "vsim" => Command::VSim(VSim::parse_frames(&mut parser)?),
+            "vrem" => Command::VRem(VRem::parse_frames(&mut parser)?),
+            "vcard" => Command::VCard(VCard::parse_frames(&mut parser)?),
+            "vdim" => Command::VDim(VDim::parse_frames(&mut parser)?),
+            "vemb" => Command::VEmb(VEmb::parse_frames(&mut parser)?),
+            "vgetattr" => Command::VGetAttr(VGetAttr::parse_frames(&mut parser)?),
+            "vsetattr" => Command::VSetAttr(VSetAttr::parse_frames(&mut parser)?),
+            "vinfo" => Command::VInfo(VInfo::parse_frames(&mut parser)?),
+            "vlinks" => Command::VLinks(VLinks::parse_frames(&mut parser)?),
+            "r.add" => Command::RAdd(RAdd::parse_frames(&mut parser, false)?),
+            "r.rem" => Command::RAdd(RAdd::parse_frames(&mut parser, true)?),
+            "r.contains" => Command::RContains(RContains::parse_frames(&mut parser)?),
+            "r.card" => Command::RCard(RCard::parse_frames(&mut parser)?),
+            "r.range" => Command::RRange(RRange::parse_frames(&mut parser)?),
+            "r.bitop" => Command::RBitOp(RBitOp::parse_frames(&mut parser)?),
+            "object" => Command::Object(Object::parse_frames(&mut parser)?),
+            "config" => Command::Config(Config::parse_frames(&mut parser)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parser, false)?),
+            "psubscribe" => Command::Subscribe(Subscribe::parse_frames(&mut parser, true)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parser, false)?),
+            "punsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(&mut parser, true)?),
+            "publish" => Command::Publish(Publish::parse_frames(&mut parser)?),
+            "pubsub" => Command::PubSub(PubSub::parse_frames(&mut parser)?),
+            "reset" => Command::Reset(Reset),
+            "client" => Command::Client(Client::parse_frames(&mut parser)?),
+            "hello" => Command::Hello(Hello::parse_frames(&mut parser)?),
+            "multi" => Command::Multi(Multi),
+            "exec" => Command::Exec(Exec),
+            "discard" => Command::Discard(Discard),
+            "watch" => Command::Watch(Watch::parse_frames(&mut parser)?),
+            "unwatch" => Command::Unwatch(Unwatch),
+            "eval" => Command::Eval(Eval::parse_frames(&mut parser, false, false)?),
+            "evalsha" => Command::Eval(Eval::parse_frames(&mut parser, true, false)?),
+            "eval_ro" => Command::Eval(Eval::parse_frames(&mut parser, false, true)?),
+            "evalsha_ro" => Command::Eval(Eval::parse_frames(&mut parser, true, true)?),
+            "script" => Command::Script(Script::parse_frames(&mut parser)?),
             _ => return Err(format!("unrecognized command '{}'", cmd_name).into()),
         };
         parser.check_finished()?;
         Ok(cmd)
     }
 
-    pub async fn execute(self, connection: &mut Connection, db: &DbHolder) -> Result<()> {
+    /// `pushes` are the push messages of the client, which are written as they
+    /// come by the commands holding the connection for long, such as SUBSCRIBE
+    pub async fn execute(
+        self,
+        connection: &mut Connection,
+        db: &DbHolder,
+        pushes: &mut mpsc::Receiver<Frame>,
+    ) -> Result<()> {
         match self {
             Command::Ping(cmd) => cmd.execute(connection).await,
             Command::Get(cmd) => cmd.execute(connection, db).await,
             Command::Set(cmd) => cmd.execute(db, connection).await,
+            Command::HSet(cmd) => cmd.execute(db, connection).await,
+            Command::HGet(cmd) => cmd.execute(db, connection).await,
+            Command::HDel(cmd) => cmd.execute(db, connection).await,
+            Command::HLen(cmd) => cmd.execute(db, connection).await,
+            Command::HExists(cmd) => cmd.execute(db, connection).await,
+            Command::HGetAll(cmd) => cmd.execute(db, connection).await,
+            Command::HExpire(cmd) => cmd.execute(db, connection).await,
+            Command::HTtl(cmd) => cmd.execute(db, connection).await,
+            Command::HPersist(cmd) => cmd.execute(db, connection).await,
+            Command::SAdd(cmd) => cmd.execute(db, connection).await,
+            Command::SRem(cmd) => cmd.execute(db, connection).await,
+            Command::SIsMember(cmd) => cmd.execute(db, connection).await,
+            Command::SMIsMember(cmd) => cmd.execute(db, connection).await,
+            Command::SMembers(cmd) => cmd.execute(db, connection).await,
+            Command::SCard(cmd) => cmd.execute(db, connection).await,
+            Command::SPop(cmd) => cmd.execute(db, connection).await,
+            Command::SRandMember(cmd) => cmd.execute(db, connection).await,
+            Command::SMove(cmd) => cmd.execute(db, connection).await,
+            Command::SCombine(cmd) => cmd.execute(db, connection).await,
+            Command::SInterCard(cmd) => cmd.execute(db, connection).await,
+            Command::SScan(cmd) => cmd.execute(db, connection).await,
+            Command::ZAdd(cmd) => cmd.execute(db, connection).await,
+            Command::ZRem(cmd) => cmd.execute(db, connection).await,
+            Command::ZScore(cmd) => cmd.execute(db, connection).await,
+            Command::ZMScore(cmd) => cmd.execute(db, connection).await,
+            Command::ZIncrBy(cmd) => cmd.execute(db, connection).await,
+            Command::ZCard(cmd) => cmd.execute(db, connection).await,
+            Command::ZCount(cmd) => cmd.execute(db, connection).await,
+            Command::ZRank(cmd) => cmd.execute(db, connection).await,
+            Command::ZRange(cmd) => cmd.execute(db, connection).await,
+            Command::ZPop(cmd) => cmd.execute(db, connection).await,
+            Command::BZPop(cmd) => cmd.execute(db, connection).await,
+            Command::ZMPop(cmd) => cmd.execute(db, connection).await,
+            Command::ZRemRange(cmd) => cmd.execute(db, connection).await,
+            Command::ZCombine(cmd) => cmd.execute(db, connection).await,
+            Command::XAdd(cmd) => cmd.execute(db, connection).await,
+            Command::XRange(cmd) => cmd.execute(db, connection).await,
+            Command::XLen(cmd) => cmd.execute(db, connection).await,
+            Command::XTrim(cmd) => cmd.execute(db, connection).await,
+            Command::XDel(cmd) => cmd.execute(db, connection).await,
+            Command::XRead(cmd) => cmd.execute(db, connection).await,
+            Command::XGroup(cmd) => cmd.execute(db, connection).await,
+            Command::XReadGroup(cmd) => cmd.execute(db, connection).await,
+            Command::XAck(cmd) => cmd.execute(db, connection).await,
+            Command::XPending(cmd) => cmd.execute(db, connection).await,
+            Command::XClaim(cmd) => cmd.execute(db, connection).await,
+            Command::XAutoClaim(cmd) => cmd.execute(db, connection).await,
+            Command::XInfo(cmd) => cmd.execute(db, connection).await,
+            Command::PfAdd(cmd) => cmd.execute(db, connection).await,
+            Command::PfCount(cmd) => cmd.execute(db, connection).await,
+            Command::PfMerge(cmd) => cmd.execute(db, connection).await,
+            Command::GeoAdd(cmd) => cmd.execute(db, connection).await,
+            Command::GeoPos(cmd) => cmd.execute(db, connection).await,
+            Command::GeoDist(cmd) => cmd.execute(db, connection).await,
+            Command::GeoHash(cmd) => cmd.execute(db, connection).await,
+            Command::GeoSearch(cmd) => cmd.execute(db, connection).await,
+            Command::BfReserve(cmd) => cmd.execute(db, connection).await,
+            Command::BfAdd(cmd) => cmd.execute(db, connection).await,
+            Command::BfExists(cmd) => cmd.execute(db, connection).await,
+            Command::BfInfo(cmd) => cmd.execute(db, connection).await,
+            Command::CfReserve(cmd) => cmd.execute(db, connection).await,
+            Command::CfAdd(cmd) => cmd.execute(db, connection).await,
+            Command::CfExists(cmd) => cmd.execute(db, connection).await,
+            Command::CfDel(cmd) => cmd.execute(db, connection).await,
+            Command::CfCount(cmd) => cmd.execute(db, connection).await,
+            Command::CfInfo(cmd) => cmd.execute(db, connection).await,
+            Command::CmsInit(cmd) => cmd.execute(db, connection).await,
+            Command::CmsIncrBy(cmd) => cmd.execute(db, connection).await,
+            Command::CmsQuery(cmd) => cmd.execute(db, connection).await,
+            Command::CmsMerge(cmd) => cmd.execute(db, connection).await,
+            Command::TopKReserve(cmd) => cmd.execute(db, connection).await,
+            Command::TopKAdd(cmd) => cmd.execute(db, connection).await,
+            Command::TopKQuery(cmd) => cmd.execute(db, connection).await,
+            Command::TopKList(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestCreate(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestAdd(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestQuery(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestExtreme(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestMerge(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestReset(cmd) => cmd.execute(db, connection).await,
+            Command::TDigestTrimmedMean(cmd) => cmd.execute(db, connection).await,
+            Command::JsonSet(cmd) => cmd.execute(db, connection).await,
+            Command::JsonGet(cmd) => cmd.execute(db, connection).await,
+            Command::JsonMGet(cmd) => cmd.execute(db, connection).await,
+            Command::JsonDel(cmd) => cmd.execute(db, connection).await,
+            Command::JsonType(cmd) => cmd.execute(db, connection).await,
+            Command::JsonResp(cmd) => cmd.execute(db, connection).await,
+            Command::JsonNumOp(cmd) => cmd.execute(db, connection).await,
+            Command::JsonStrAppend(cmd) => cmd.execute(db, connection).await,
+            Command::JsonLen(cmd) => cmd.execute(db, connection).await,
+            Command::JsonArrInsert(cmd) => cmd.execute(db, connection).await,
+            Command::JsonArrPop(cmd) => cmd.execute(db, connection).await,
+            Command::TsCreate(cmd) => cmd.execute(db, connection).await,
+            Command::TsAdd(cmd) => cmd.execute(db, connection).await,
+            Command::TsMAdd(cmd) => cmd.execute(db, connection).await,
+            Command::TsGet(cmd) => cmd.execute(db, connection).await,
+            Command::TsDel(cmd) => cmd.execute(db, connection).await,
+            Command::TsCreateRule(cmd) => cmd.execute(db, connection).await,
+            Command::TsDeleteRule(cmd) => cmd.execute(db, connection).await,
+            Command::TsRange(cmd) => cmd.execute(db, connection).await,
+            Command::TsMRange(cmd) => cmd.execute(db, connection).await,
+            Command::TsQueryIndex(cmd) => cmd.execute(db, connection).await,
+            Command::TsInfo(cmd) => cmd.execute(db, connection).await,
+            Command::FtCreate(cmd) => cmd.execute(db, connection).await,
+            Command::FtSearch(cmd) => cmd.execute(db, connection).await,
+            Command::FtAggregate(cmd) => cmd.execute(db, connection).await,
+            Command::FtDropIndex(cmd) => cmd.execute(db, connection).await,
+            Command::FtInfo(cmd) => cmd.execute(db, connection).await,
+            Command::FtList(cmd) => cmd.execute(db, connection).await,
+            Command::VAdd(cmd) => cmd.execute(db, connection).await,
+            Command::VSim(cmd) => cmd.execute(db, connection).await,
+            Command::VRem(cmd) => cmd.execute(db, connection).await,
+            Command::VCard(cmd) => cmd.execute(db, connection).await,
+            Command::VDim(cmd) => cmd.execute(db, connection).await,
+            Command::VEmb(cmd) => cmd.execute(db, connection).await,
+            Command::VGetAttr(cmd) => cmd.execute(db, connection).await,
+            Command::VSetAttr(cmd) => cmd.execute(db, connection).await,
+            Command::VInfo(cmd) => cmd.execute(db, connection).await,
+            Command::VLinks(cmd) => cmd.execute(db, connection).await,
+            Command::RAdd(cmd) => cmd.execute(db, connection).await,
+            Command::RContains(cmd) => cmd.execute(db, connection).await,
+            Command::RCard(cmd) => cmd.execute(db, connection).await,
+            Command::RRange(cmd) => cmd.execute(db, connection).await,
+            Command::RBitOp(cmd) => cmd.execute(db, connection).await,
+            Command::Object(cmd) => cmd.execute(db, connection).await,
+            Command::Config(cmd) => cmd.execute(connection).await,
+            Command::Subscribe(cmd) => cmd.execute(db, connection, pushes).await,
+            Command::Unsubscribe(cmd) => cmd.execute(connection).await,
+            Command::Publish(cmd) => cmd.execute(db, connection).await,
+            Command::PubSub(cmd) => cmd.execute(db, connection).await,
+            Command::Reset(cmd) => cmd.execute(db, connection).await,
+            Command::Client(cmd) => cmd.execute(db, connection).await,
+            Command::Hello(cmd) => cmd.execute(db, connection).await,
+            Command::Multi(cmd) => cmd.execute(connection).await,
+            Command::Exec(cmd) => cmd.execute(connection).await,
+            Command::Discard(cmd) => cmd.execute(connection).await,
+            Command::Watch(cmd) => cmd.execute(db, connection).await,
+            Command::Unwatch(cmd) => cmd.execute(db, connection).await,
+            Command::Eval(cmd) => cmd.execute(db, connection).await,
+            Command::Script(cmd) => cmd.execute(db, connection).await,
+        }
+    }
+
+    /// apply the command on an already locked database, such as within EXEC
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match self {
+            Command::Ping(cmd) => cmd.apply(),
+            Command::Get(cmd) => cmd.apply(db),
+            Command::Set(cmd) => cmd.apply(db),
+            Command::HSet(cmd) => cmd.apply(db),
+            Command::HGet(cmd) => cmd.apply(db),
+            Command::HDel(cmd) => cmd.apply(db),
+            Command::HLen(cmd) => cmd.apply(db),
+            Command::HExists(cmd) => cmd.apply(db),
+            Command::HGetAll(cmd) => cmd.apply(db),
+            Command::HExpire(cmd) => cmd.apply(db),
+            Command::HTtl(cmd) => cmd.apply(db),
+            Command::HPersist(cmd) => cmd.apply(db),
+            Command::SAdd(cmd) => cmd.apply(db),
+            Command::SRem(cmd) => cmd.apply(db),
+            Command::SIsMember(cmd) => cmd.apply(db),
+            Command::SMIsMember(cmd) => cmd.apply(db),
+            Command::SMembers(cmd) => cmd.apply(db),
+            Command::SCard(cmd) => cmd.apply(db),
+            Command::SPop(cmd) => cmd.apply(db),
+            Command::SRandMember(cmd) => cmd.apply(db),
+            Command::SMove(cmd) => cmd.apply(db),
+            Command::SCombine(cmd) => cmd.apply(db),
+            Command::SInterCard(cmd) => cmd.apply(db),
+            Command::SScan(cmd) => cmd.apply(db),
+            Command::ZAdd(cmd) => cmd.apply(db),
+            Command::ZRem(cmd) => cmd.apply(db),
+            Command::ZScore(cmd) => cmd.apply(db),
+            Command::ZMScore(cmd) => cmd.apply(db),
+            Command::ZIncrBy(cmd) => cmd.apply(db),
+            Command::ZCard(cmd) => cmd.apply(db),
+            Command::ZCount(cmd) => cmd.apply(db),
+            Command::ZRank(cmd) => cmd.apply(db),
+            Command::ZRange(cmd) => cmd.apply(db),
+            Command::ZPop(cmd) => cmd.apply(db),
+            Command::BZPop(cmd) => cmd.apply(db),
+            Command::ZMPop(cmd) => cmd.apply(db),
+            Command::ZRemRange(cmd) => cmd.apply(db),
+            Command::ZCombine(cmd) => cmd.apply(db),
+            Command::XAdd(cmd) => cmd.apply(db),
+            Command::XRange(cmd) => cmd.apply(db),
+            Command::XLen(cmd) => cmd.apply(db),
+            Command::XTrim(cmd) => cmd.apply(db),
+            Command::XDel(cmd) => cmd.apply(db),
+            Command::XRead(cmd) => cmd.apply(db),
+            Command::XGroup(cmd) => cmd.apply(db),
+            Command::XReadGroup(cmd) => cmd.apply(db),
+            Command::XAck(cmd) => cmd.apply(db),
+            Command::XPending(cmd) => cmd.apply(db),
+            Command::XClaim(cmd) => cmd.apply(db),
+            Command::XAutoClaim(cmd) => cmd.apply(db),
+            Command::XInfo(cmd) => cmd.apply(db),
+            Command::PfAdd(cmd) => cmd.apply(db),
+            Command::PfCount(cmd) => cmd.apply(db),
+            Command::PfMerge(cmd) => cmd.apply(db),
+            Command::GeoAdd(cmd) => cmd.apply(db),
+            Command::GeoPos(cmd) => cmd.apply(db),
+            Command::GeoDist(cmd) => cmd.apply(db),
+            Command::GeoHash(cmd) => cmd.apply(db),
+            Command::GeoSearch(cmd) => cmd.apply(db),
+            Command::BfReserve(cmd) => cmd.apply(db),
+            Command::BfAdd(cmd) => cmd.apply(db),
+            Command::BfExists(cmd) => cmd.apply(db),
+            Command::BfInfo(cmd) => cmd.apply(db),
+            Command::CfReserve(cmd) => cmd.apply(db),
+            Command::CfAdd(cmd) => cmd.apply(db),
+            Command::CfExists(cmd) => cmd.apply(db),
+            Command::CfDel(cmd) => cmd.apply(db),
+            Command::CfCount(cmd) => cmd.apply(db),
+            Command::CfInfo(cmd) => cmd.apply(db),
+            Command::CmsInit(cmd) => cmd.apply(db),
+            Command::CmsIncrBy(cmd) => cmd.apply(db),
+            Command::CmsQuery(cmd) => cmd.apply(db),
+            Command::CmsMerge(cmd) => cmd.apply(db),
+            Command::TopKReserve(cmd) => cmd.apply(db),
+            Command::TopKAdd(cmd) => cmd.apply(db),
+            Command::TopKQuery(cmd) => cmd.apply(db),
+            Command::TopKList(cmd) => cmd.apply(db),
+            Command::TDigestCreate(cmd) => cmd.apply(db),
+            Command::TDigestAdd(cmd) => cmd.apply(db),
+            Command::TDigestQuery(cmd) => cmd.apply(db),
+            Command::TDigestExtreme(cmd) => cmd.apply(db),
+            Command::TDigestMerge(cmd) => cmd.apply(db),
+            Command::TDigestReset(cmd) => cmd.apply(db),
+            Command::TDigestTrimmedMean(cmd) => cmd.apply(db),
+            Command::JsonSet(cmd) => cmd.apply(db),
+            Command::JsonGet(cmd) => cmd.apply(db),
+            Command::JsonMGet(cmd) => cmd.apply(db),
+            Command::JsonDel(cmd) => cmd.apply(db),
+            Command::JsonType(cmd) => cmd.apply(db),
+            Command::JsonResp(cmd) => cmd.apply(db),
+            Command::JsonNumOp(cmd) => cmd.apply(db),
+            Command::JsonStrAppend(cmd) => cmd.apply(db),
+            Command::JsonLen(cmd) => cmd.apply(db),
+            Command::JsonArrInsert(cmd) => cmd.apply(db),
+            Command::JsonArrPop(cmd) => cmd.apply(db),
+            Command::TsCreate(cmd) => cmd.apply(db),
+            Command::TsAdd(cmd) => cmd.apply(db),
+            Command::TsMAdd(cmd) => cmd.apply(db),
+            Command::TsGet(cmd) => cmd.apply(db),
+            Command::TsDel(cmd) => cmd.apply(db),
+            Command::TsCreateRule(cmd) => cmd.apply(db),
+            Command::TsDeleteRule(cmd) => cmd.apply(db),
+            Command::TsRange(cmd) => cmd.apply(db),
+            Command::TsMRange(cmd) => cmd.apply(db),
+            Command::TsQueryIndex(cmd) => cmd.apply(db),
+            Command::TsInfo(cmd) => cmd.apply(db),
+            Command::FtCreate(cmd) => cmd.apply(db),
+            Command::FtSearch(cmd) => cmd.apply(db),
+            Command::FtAggregate(cmd) => cmd.apply(db),
+            Command::FtDropIndex(cmd) => cmd.apply(db),
+            Command::FtInfo(cmd) => cmd.apply(db),
+            Command::FtList(cmd) => cmd.apply(db),
+            Command::VAdd(cmd) => cmd.apply(db),
+            Command::VSim(cmd) => cmd.apply(db),
+            Command::VRem(cmd) => cmd.apply(db),
+            Command::VCard(cmd) => cmd.apply(db),
+            Command::VDim(cmd) => cmd.apply(db),
+            Command::VEmb(cmd) => cmd.apply(db),
+            Command::VGetAttr(cmd) => cmd.apply(db),
+            Command::VSetAttr(cmd) => cmd.apply(db),
+            Command::VInfo(cmd) => cmd.apply(db),
+            Command::VLinks(cmd) => cmd.apply(db),
+            Command::RAdd(cmd) => cmd.apply(db),
+            Command::RContains(cmd) => cmd.apply(db),
+            Command::RCard(cmd) => cmd.apply(db),
+            Command::RRange(cmd) => cmd.apply(db),
+            Command::RBitOp(cmd) => cmd.apply(db),
+            Command::Object(cmd) => cmd.apply(db),
+            Command::Config(cmd) => cmd.apply(),
+            Command::Publish(cmd) => cmd.apply(db),
+            Command::PubSub(cmd) => cmd.apply(db),
+            Command::Client(cmd) => cmd.apply(db),
+            Command::Hello(cmd) => cmd.apply(db),
+            Command::Watch(cmd) => cmd.apply(db),
+            Command::Unwatch(cmd) => cmd.apply(db),
+            Command::Eval(cmd) => cmd.apply(db),
+            Command::Script(cmd) => cmd.apply(db),
+            cmd => {
+                debug_assert!(cmd.is_connection_bound());
+                Frame::Error("ERR Command not allowed inside a transaction".to_string())
+            }
+        }
+    }
+
+    /// the command written as its words, for the tests applying commands directly
+    #[cfg(test)]
+    pub(crate) fn parse(words: &[&str]) -> Command {
+        Command::try_parse(words).unwrap()
+    }
+
+    /// the command written as its words, for the tests of invalid commands
+    #[cfg(test)]
+    pub(crate) fn try_parse(words: &[&str]) -> Result<Command> {
+        let frames = words
+            .iter()
+            .map(|word| Frame::Bulk(bytes::Bytes::copy_from_slice(word.as_bytes())))
+            .collect();
+        Command::from_frame(Frame::Array(frames))
+    }
+
+    /// subscriber mode and transactions are states of the connection, such
+    /// commands can't be applied on their own
+    pub(crate) fn is_connection_bound(&self) -> bool {
+        matches!(
+            self,
+            Command::Subscribe(_)
+                | Command::Unsubscribe(_)
+                | Command::Reset(_)
+                | Command::Multi(_)
+                | Command::Exec(_)
+                | Command::Discard(_)
+        )
+    }
+
+    /// scripts may call any command but the ones about the connection, the
+    /// server, and scripts themselves
+    pub(crate) fn is_scriptable(&self) -> bool {
+        !self.is_connection_bound()
+            && !matches!(
+                self,
+                Command::Client(_)
+                    | Command::Hello(_)
+                    | Command::Config(_)
+                    | Command::Watch(_)
+                    | Command::Unwatch(_)
+                    | Command::Eval(_)
+                    | Command::Script(_)
+            )
+    }
+
+    /// the commands which never write, the only ones EVAL_RO may call
+    pub(crate) fn is_readonly(&self) -> bool {
+        match self {
+            Command::SCombine(cmd) => !cmd.is_store(),
+            Command::ZRange(cmd) => !cmd.is_store(),
+            Command::ZCombine(cmd) => !cmd.is_store(),
+            Command::GeoSearch(cmd) => !cmd.is_store(),
+            cmd => matches!(
+                cmd,
+                Command::Ping(_)
+                    | Command::Get(_)
+                    | Command::HGet(_)
+                    | Command::HLen(_)
+                    | Command::HExists(_)
+                    | Command::HGetAll(_)
+                    | Command::HTtl(_)
+                    | Command::SIsMember(_)
+                    | Command::SMIsMember(_)
+                    | Command::SMembers(_)
+                    | Command::SCard(_)
+                    | Command::SRandMember(_)
+                    | Command::SInterCard(_)
+                    | Command::SScan(_)
+                    | Command::ZScore(_)
+                    | Command::ZMScore(_)
+                    | Command::ZCard(_)
+                    | Command::ZCount(_)
+                    | Command::ZRank(_)
+                    | Command::XRange(_)
+                    | Command::XLen(_)
+                    | Command::XRead(_)
+                    | Command::XPending(_)
+                    | Command::XInfo(_)
+                    | Command::PfCount(_)
+                    | Command::GeoPos(_)
+                    | Command::GeoDist(_)
+                    | Command::GeoHash(_)
+                    | Command::BfExists(_)
+                    | Command::BfInfo(_)
+                    | Command::CfExists(_)
+                    | Command::CfCount(_)
+                    | Command::CfInfo(_)
+                    | Command::CmsQuery(_)
+                    | Command::TopKQuery(_)
+                    | Command::TopKList(_)
+                    | Command::TDigestQuery(_)
+                    | Command::TDigestExtreme(_)
+                    | Command::TDigestTrimmedMean(_)
+                    | Command::JsonGet(_)
+                    | Command::JsonMGet(_)
+                    | Command::JsonType(_)
+                    | Command::JsonResp(_)
+                    | Command::JsonLen(_)
+                    | Command::TsGet(_)
+                    | Command::TsRange(_)
+                    | Command::TsMRange(_)
+                    | Command::TsQueryIndex(_)
+                    | Command::TsInfo(_)
+                    | Command::FtSearch(_)
+                    | Command::FtAggregate(_)
+                    | Command::FtInfo(_)
+                    | Command::FtList(_)
+                    | Command::VSim(_)
+                    | Command::VCard(_)
+                    | Command::VDim(_)
+                    | Command::VEmb(_)
+                    | Command::VGetAttr(_)
+                    | Command::VInfo(_)
+                    | Command::VLinks(_)
+                    | Command::RContains(_)
+                    | Command::RCard(_)
+                    | Command::RRange(_)
+                    | Command::Object(_)
+                    | Command::PubSub(_)
+            ),
         }
     }
 }
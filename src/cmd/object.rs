@@ -0,0 +1,35 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{db::Database, parser::Parser, Connection, DbHolder, Frame, Result};
+
+/// OBJECT ENCODING, the representation of the value stored at a key
+#[derive(Debug)]
+pub struct Object {
+    key: String,
+}
+
+impl Object {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Object> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        if subcommand != "encoding" {
+            return Err(format!("unknown subcommand '{}'", subcommand).into());
+        }
+        let key = parser.next_string()?;
+        Ok(Object { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the name of the encoding, or null if the key doesn't exist
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get(&self.key) {
+            Some(value) => Frame::Bulk(Bytes::from_static(value.encoding().as_bytes())),
+            None => Frame::Null,
+        }
+    }
+}
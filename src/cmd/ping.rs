@@ -1,6 +1,6 @@
 use tracing::instrument;
 
-use crate::{Result, Connection, Frame};
+use crate::{Connection, Frame, Result};
 
 #[derive(Debug)]
 pub struct Ping;
@@ -8,10 +8,14 @@ pub struct Ping;
 impl Ping {
     #[instrument(skip(connection))]
     pub async fn execute(&self, connection: &mut Connection) -> Result<()> {
-        connection.write_frame(Frame::into_simple("pong")).await 
+        connection.write_frame(self.apply()).await
+    }
+
+    pub(crate) fn apply(&self) -> Frame {
+        Frame::into_simple("pong")
     }
 
     pub fn get_frame() -> Frame {
         Frame::Array(vec![Frame::into_simple("ping")])
     }
-}
\ No newline at end of file
+}
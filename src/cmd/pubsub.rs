@@ -0,0 +1,386 @@
+use bytes::Bytes;
+use tokio::{select, sync::mpsc};
+use tracing::instrument;
+
+use crate::{
+    cmd::Command,
+    db::{Database, BACKLOG},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// SUBSCRIBE and PSUBSCRIBE
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<Bytes>,
+    pattern: bool,
+}
+
+/// UNSUBSCRIBE and PUNSUBSCRIBE, every channel or pattern is left when none is given
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<Bytes>,
+    pattern: bool,
+}
+
+#[derive(Debug)]
+pub struct Publish {
+    channel: Bytes,
+    message: Bytes,
+}
+
+/// PUBSUB CHANNELS, NUMSUB, NUMPAT and SHARDCHANNELS
+#[derive(Debug)]
+pub struct PubSub {
+    query: Query,
+}
+
+#[derive(Debug)]
+enum Query {
+    Channels(Option<Bytes>),
+    NumSub(Vec<Bytes>),
+    NumPat,
+    /// the pattern is accepted, though there is nothing to filter
+    ShardChannels,
+}
+
+/// RESET, which also leaves subscriber mode
+#[derive(Debug)]
+pub struct Reset;
+
+/// the registration of a client in subscriber mode, dropped when the mode is
+/// left in any way, including a closed connection or a shutdown
+struct Subscription {
+    db: DbHolder,
+    id: u64,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.db.lock().remove_subscriber(self.id);
+    }
+}
+
+impl Subscribe {
+    /// `pattern` tells whether glob-style patterns are given instead of channels
+    pub fn parse_frames(parser: &mut Parser, pattern: bool) -> Result<Subscribe> {
+        let channels = parser.remaining_bytes()?;
+        Ok(Subscribe { channels, pattern })
+    }
+
+    /// enter subscriber mode. messages and the push messages of the client are
+    /// written as they come while the commands allowed in this mode are read,
+    /// until no subscription is left
+    #[instrument(skip(db, connection, pushes))]
+    pub async fn execute(
+        self,
+        db: &DbHolder,
+        connection: &mut Connection,
+        pushes: &mut mpsc::Receiver<Frame>,
+    ) -> Result<()> {
+        let (sender, mut receiver) = mpsc::channel(BACKLOG);
+        let id = db.client();
+        db.lock().add_subscriber(id, sender);
+        let _subscription = Subscription { db: db.clone(), id };
+        let mut cmd = Command::Subscribe(self);
+        let mut name = String::new();
+
+        loop {
+            match cmd {
+                Command::Subscribe(cmd) => {
+                    let frames = cmd.apply_subscribed(&mut db.lock(), id);
+                    for frame in frames {
+                        connection.write_frame(frame).await?;
+                    }
+                }
+                Command::Unsubscribe(cmd) => {
+                    let frames = cmd.apply_subscribed(&mut db.lock(), id);
+                    for frame in frames {
+                        connection.write_frame(frame).await?;
+                    }
+                }
+                Command::Ping(_) => {
+                    let pong = vec![
+                        Frame::Bulk(Bytes::from_static(b"pong")),
+                        Frame::Bulk(Bytes::new()),
+                    ];
+                    connection.write_frame(Frame::Array(pong)).await?;
+                }
+                Command::Reset(cmd) => return cmd.execute(db, connection).await,
+                _ => {
+                    let error = format!(
+                        "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                        name
+                    );
+                    connection.write_frame(Frame::Error(error)).await?;
+                }
+            }
+            if db.lock().subscriptions(id) == 0 {
+                return Ok(());
+            }
+
+            let frame = loop {
+                select! {
+                    Some(message) = receiver.recv() => connection.write_frame(message).await?,
+                    push = pushes.recv() => match push {
+                        Some(push) => connection.write_frame(push).await?,
+                        // the client has been disconnected by the server
+                        None => return Ok(()),
+                    },
+                    frame = connection.read_frame() => match frame? {
+                        Some(frame) => break frame,
+                        None => return Ok(()),
+                    },
+                }
+            };
+            name = command_name(&frame);
+            cmd = Command::from_frame(frame)?;
+        }
+    }
+
+    /// one confirmation per channel or pattern
+    fn apply_subscribed(self, db: &mut Database, id: u64) -> Vec<Frame> {
+        let kind = if self.pattern {
+            "psubscribe"
+        } else {
+            "subscribe"
+        };
+        self.channels
+            .into_iter()
+            .map(|channel| {
+                let count = match self.pattern {
+                    true => db.psubscribe(id, channel.clone()),
+                    false => db.subscribe(id, channel.clone()),
+                };
+                confirmation(kind, Frame::Bulk(channel), count)
+            })
+            .collect()
+    }
+}
+
+impl Unsubscribe {
+    pub fn parse_frames(parser: &mut Parser, pattern: bool) -> Result<Unsubscribe> {
+        let channels = match parser.remaining_bytes() {
+            Ok(channels) => channels,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Unsubscribe { channels, pattern })
+    }
+
+    /// outside of subscriber mode there is nothing to leave
+    #[instrument(skip(connection))]
+    pub async fn execute(self, connection: &mut Connection) -> Result<()> {
+        let kind = self.kind();
+        if self.channels.is_empty() {
+            return connection
+                .write_frame(confirmation(kind, Frame::Null, 0))
+                .await;
+        }
+        for channel in self.channels {
+            connection
+                .write_frame(confirmation(kind, Frame::Bulk(channel), 0))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// one confirmation per channel or pattern left
+    fn apply_subscribed(self, db: &mut Database, id: u64) -> Vec<Frame> {
+        let kind = self.kind();
+        let channels = match (self.channels.is_empty(), self.pattern) {
+            (true, false) => db.channels_of(id),
+            (true, true) => db.patterns_of(id),
+            (false, _) => self.channels,
+        };
+        if channels.is_empty() {
+            let count = db.subscriptions(id);
+            return vec![confirmation(kind, Frame::Null, count)];
+        }
+        channels
+            .into_iter()
+            .map(|channel| {
+                let count = match self.pattern {
+                    true => db.punsubscribe(id, &channel),
+                    false => db.unsubscribe(id, &channel),
+                };
+                confirmation(kind, Frame::Bulk(channel), count)
+            })
+            .collect()
+    }
+
+    fn kind(&self) -> &'static str {
+        if self.pattern {
+            "punsubscribe"
+        } else {
+            "unsubscribe"
+        }
+    }
+}
+
+impl Publish {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Publish> {
+        let channel = parser.next_bytes()?;
+        let message = parser.next_bytes()?;
+        Ok(Publish { channel, message })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the number of clients which have received the message
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        Frame::Integer(db.publish(&self.channel, &self.message) as i64)
+    }
+}
+
+impl PubSub {
+    pub fn parse_frames(parser: &mut Parser) -> Result<PubSub> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let pattern = |parser: &mut Parser| match parser.next_bytes() {
+            Ok(pattern) => Ok(Some(pattern)),
+            Err(ParseError::EndOfStream) => Ok(None),
+            Err(e) => Err(e),
+        };
+        let query = match &subcommand[..] {
+            "channels" => Query::Channels(pattern(parser)?),
+            "numsub" => match parser.remaining_bytes() {
+                Ok(channels) => Query::NumSub(channels),
+                Err(ParseError::EndOfStream) => Query::NumSub(vec![]),
+                Err(e) => return Err(e.into()),
+            },
+            "numpat" => Query::NumPat,
+            "shardchannels" => {
+                pattern(parser)?;
+                Query::ShardChannels
+            }
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(PubSub { query })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match self.query {
+            Query::Channels(pattern) => Frame::Array(
+                db.active_channels(pattern.as_deref())
+                    .into_iter()
+                    .map(Frame::Bulk)
+                    .collect(),
+            ),
+            // each channel followed by its number of subscribers
+            Query::NumSub(channels) => Frame::Array(
+                channels
+                    .into_iter()
+                    .flat_map(|channel| {
+                        let count = db.channel_subscribers(&channel);
+                        [Frame::Bulk(channel), Frame::Integer(count as i64)]
+                    })
+                    .collect(),
+            ),
+            Query::NumPat => Frame::Integer(db.active_patterns() as i64),
+            // there is no sharded pub/sub, so no shard channel is ever active
+            Query::ShardChannels => Frame::Array(vec![]),
+        }
+    }
+}
+
+impl Reset {
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the subscriptions end with the subscriber mode, the watched keys, the
+    /// tracking of the keys the client caches and RESP3 are dropped here
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let client = db.client();
+        db.unwatch(client);
+        db.stop_tracking(client);
+        db.set_protocol(2);
+        Frame::into_simple("RESET")
+    }
+}
+
+/// the reply to (P)(UN)SUBSCRIBE for each channel or pattern, with the number of
+/// subscriptions left
+fn confirmation(kind: &'static str, channel: Frame, count: usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(kind.as_bytes())),
+        channel,
+        Frame::Integer(count as i64),
+    ])
+}
+
+fn command_name(frame: &Frame) -> String {
+    match frame {
+        Frame::Array(parts) => match parts.first() {
+            Some(Frame::Bulk(name)) => String::from_utf8_lossy(name).to_lowercase(),
+            Some(Frame::Simple(name)) => name.to_lowercase(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::broadcast,
+        time::{timeout, Duration},
+    };
+
+    use super::*;
+    use crate::db::Tracking;
+
+    #[tokio::test]
+    async fn subscriber_pushes_test() {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        let server = DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx);
+        let (db, mut pushes) = server.connect();
+        {
+            let mut db = db.lock();
+            db.set_protocol(3);
+            db.start_tracking(Tracking::default()).unwrap();
+            db.track("k");
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).await;
+        let mut client = Connection::new(stream.unwrap());
+        let (socket, _) = listener.accept().await.unwrap();
+        let subscriber = tokio::spawn(async move {
+            let mut connection = Connection::new(socket);
+            let subscribe = Subscribe {
+                channels: vec![Bytes::from_static(b"news")],
+                pattern: false,
+            };
+            subscribe.execute(&db, &mut connection, &mut pushes).await
+        });
+        let news = Frame::Bulk(Bytes::from_static(b"news"));
+        let reply = client.read_frame().await.unwrap();
+        assert_eq!(reply, Some(confirmation("subscribe", news, 1)));
+
+        // the invalidation of a cached key reaches a client in subscriber mode
+        server.set("k".to_string(), Bytes::new(), None).unwrap();
+        let invalidate = Frame::Push(vec![
+            Frame::Bulk(Bytes::from_static(b"invalidate")),
+            Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"k"))]),
+        ]);
+        let push = timeout(Duration::from_secs(1), client.read_frame()).await;
+        assert_eq!(push.unwrap().unwrap(), Some(invalidate));
+        drop(client);
+        subscriber.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{BitOp, Class, Database, Roaring, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// R.ADD and R.REM
+#[derive(Debug)]
+pub struct RAdd {
+    key: String,
+    values: Vec<u64>,
+    remove: bool,
+}
+
+#[derive(Debug)]
+pub struct RContains {
+    key: String,
+    values: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub struct RCard {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct RRange {
+    key: String,
+    start: u64,
+    end: u64,
+    count: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct RBitOp {
+    op: BitOp,
+    destination: String,
+    keys: Vec<String>,
+}
+
+impl RAdd {
+    /// `remove` tells whether the values are removed (R.REM)
+    pub fn parse_frames(parser: &mut Parser, remove: bool) -> Result<RAdd> {
+        let key = parser.next_string()?;
+        let values = parse_values(parser)?;
+        Ok(RAdd {
+            key,
+            values,
+            remove,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the number of values added, or removed
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let bitmap = match db.get_roaring_mut(&self.key) {
+            Ok(Some(bitmap)) => bitmap,
+            Ok(None) if self.remove => return Frame::Integer(0),
+            Ok(None) => {
+                db.insert(self.key.clone(), Value::Roaring(Roaring::default()));
+                db.get_roaring_mut(&self.key).unwrap().unwrap()
+            }
+            Err(e) => return e.into(),
+        };
+        let changed = self
+            .values
+            .iter()
+            .filter(|value| match self.remove {
+                true => bitmap.remove(**value),
+                false => bitmap.insert(**value),
+            })
+            .count();
+        if changed > 0 {
+            let event = if self.remove { "r.rem" } else { "r.add" };
+            db.notify(Class::Module, event, &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(changed as i64)
+    }
+}
+
+impl RContains {
+    pub fn parse_frames(parser: &mut Parser) -> Result<RContains> {
+        let key = parser.next_string()?;
+        let values = parse_values(parser)?;
+        Ok(RContains { key, values })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// 1 or 0 for a single value, an array of them for several
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let bitmap = match db.get_roaring(&self.key) {
+            Ok(bitmap) => bitmap,
+            Err(e) => return e.into(),
+        };
+        let mut found: Vec<Frame> = self
+            .values
+            .iter()
+            .map(
+                |value| Frame::Integer(bitmap.is_some_and(|bitmap| bitmap.contains(*value)) as i64),
+            )
+            .collect();
+        match found.len() {
+            1 => found.remove(0),
+            _ => Frame::Array(found),
+        }
+    }
+}
+
+impl RCard {
+    pub fn parse_frames(parser: &mut Parser) -> Result<RCard> {
+        let key = parser.next_string()?;
+        Ok(RCard { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_roaring(&self.key) {
+            Ok(bitmap) => Frame::Integer(bitmap.map_or(0, Roaring::len) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl RRange {
+    /// `key start end [COUNT n]`, both ends are included
+    pub fn parse_frames(parser: &mut Parser) -> Result<RRange> {
+        let key = parser.next_string()?;
+        let start = parse_value(&parser.next_string()?)?;
+        let end = parse_value(&parser.next_string()?)?;
+        let count = match parser.next_string() {
+            Ok(token) if token.eq_ignore_ascii_case("count") => match parser.next_int()? {
+                count if count < 0 => return Err("COUNT can't be negative".into()),
+                count => Some(count as usize),
+            },
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(RRange {
+            key,
+            start,
+            end,
+            count,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the values in ascending order, as strings since they may not fit a signed integer
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let bitmap = match db.get_roaring(&self.key) {
+            Ok(Some(bitmap)) => bitmap,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        Frame::Array(
+            bitmap
+                .range(self.start..=self.end)
+                .take(self.count.unwrap_or(usize::MAX))
+                .map(|value| Frame::Bulk(Bytes::from(value.to_string())))
+                .collect(),
+        )
+    }
+}
+
+impl RBitOp {
+    /// `AND | OR | XOR | ANDNOT destination key [key ...]`
+    pub fn parse_frames(parser: &mut Parser) -> Result<RBitOp> {
+        let name = parser.next_string()?;
+        let Some(op) = BitOp::parse(&name) else {
+            return Err(format!("unknown operation '{}'", name).into());
+        };
+        let destination = parser.next_string()?;
+        let keys = parser.remaining_strings()?;
+        Ok(RBitOp {
+            op,
+            destination,
+            keys,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the keys are combined from left to right and the result is stored, the
+    /// destination is deleted when it is empty. a missing key is an empty bitmap
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let mut result: Option<Roaring> = None;
+        for key in &self.keys {
+            let empty = Roaring::default();
+            let bitmap = match db.get_roaring(key) {
+                Ok(bitmap) => bitmap.unwrap_or(&empty),
+                Err(e) => return e.into(),
+            };
+            result = Some(match result {
+                None => bitmap.clone(),
+                // the values of the first key which are in none of the others
+                Some(result) => result.combine(bitmap, self.op),
+            });
+        }
+        let result = result.unwrap_or_default();
+        let len = result.len();
+        if !result.is_empty() {
+            db.insert(self.destination.clone(), Value::Roaring(result));
+            db.notify(Class::Module, "r.bitop", &self.destination);
+        } else if db.remove(&self.destination).is_some() {
+            db.notify(Class::Generic, "del", &self.destination);
+        }
+        Frame::Integer(len as i64)
+    }
+}
+
+fn parse_values(parser: &mut Parser) -> Result<Vec<u64>> {
+    parser
+        .remaining_strings()?
+        .iter()
+        .map(|value| parse_value(value))
+        .collect()
+}
+
+fn parse_value(value: &str) -> Result<u64> {
+    value
+        .parse()
+        .map_err(|_| "value is not an unsigned 64 bit integer".into())
+}
@@ -0,0 +1,236 @@
+use bytes::Bytes;
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task::block_in_place,
+};
+use tracing::instrument;
+
+use crate::{
+    db::{Database, ScriptControl},
+    parser::{ParseError, Parser},
+    script, Connection, DbHolder, Frame, Result,
+};
+
+/// EVAL and EVALSHA, and their read-only variants EVAL_RO and EVALSHA_RO
+#[derive(Debug)]
+pub struct Eval {
+    source: Source,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    readonly: bool,
+}
+
+#[derive(Debug)]
+enum Source {
+    Body(String),
+    Sha(String),
+}
+
+/// SCRIPT LOAD, EXISTS, FLUSH and KILL
+#[derive(Debug)]
+pub struct Script {
+    subcommand: Subcommand,
+}
+
+#[derive(Debug)]
+enum Subcommand {
+    Load(String),
+    Exists(Vec<String>),
+    Flush,
+    Kill,
+}
+
+impl Eval {
+    pub fn parse_frames(parser: &mut Parser, sha: bool, readonly: bool) -> Result<Eval> {
+        let script = parser.next_string()?;
+        let source = match sha {
+            true => Source::Sha(script),
+            false => Source::Body(script),
+        };
+        let numkeys = parser.next_int()?;
+        let mut args = match parser.remaining_bytes() {
+            Ok(args) => args,
+            Err(ParseError::EndOfStream) => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        if numkeys < 0 {
+            return Err("Number of keys can't be negative".into());
+        }
+        if numkeys as usize > args.len() {
+            return Err("Number of keys can't be greater than number of args".into());
+        }
+        let keys = args.drain(..numkeys as usize).collect();
+        Ok(Eval {
+            source,
+            keys,
+            args,
+            readonly,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = run_script(|| self.apply(&mut db.lock()));
+        connection.write_frame(frame).await
+    }
+
+    /// the body given to EVAL is cached, so that EVALSHA can run it afterwards
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let body = match self.source {
+            Source::Body(body) => {
+                db.load_script(body.clone());
+                body
+            }
+            Source::Sha(sha) => match db.script(&sha) {
+                Some(body) => body,
+                None => {
+                    return Frame::Error(
+                        "NOSCRIPT No matching script. Please use EVAL.".to_string(),
+                    )
+                }
+            },
+        };
+        script::run(db, &body, self.keys, self.args, self.readonly)
+    }
+}
+
+impl Script {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Script> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let subcommand = match &subcommand[..] {
+            "load" => Subcommand::Load(parser.next_string()?),
+            "exists" => Subcommand::Exists(parser.remaining_strings()?),
+            "flush" => {
+                // scripts are always flushed at once
+                match parser.next_string() {
+                    Ok(mode) if ["async", "sync"].contains(&&mode.to_lowercase()[..]) => {}
+                    Ok(_) => return Err("syntax error".into()),
+                    Err(ParseError::EndOfStream) => {}
+                    Err(e) => return Err(e.into()),
+                }
+                Subcommand::Flush
+            }
+            "kill" => Subcommand::Kill,
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(Script { subcommand })
+    }
+
+    /// SCRIPT KILL doesn't lock the database, which the running script holds
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = match self.subcommand {
+            Subcommand::Kill => kill(db.script_control()),
+            _ => self.apply(&mut db.lock()),
+        };
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match self.subcommand {
+            Subcommand::Load(body) => Frame::Bulk(Bytes::from(db.load_script(body))),
+            Subcommand::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(db.script(sha).is_some() as i64))
+                    .collect(),
+            ),
+            Subcommand::Flush => {
+                db.flush_scripts();
+                Frame::into_simple("OK")
+            }
+            Subcommand::Kill => kill(&db.script_control()),
+        }
+    }
+
+    /// SCRIPT KILL may be called while a script keeps the server busy
+    pub(crate) fn is_kill(&self) -> bool {
+        matches!(self.subcommand, Subcommand::Kill)
+    }
+}
+
+/// apply commands which may run a script, and so hold the database for long. on
+/// a multi-thread runtime the other connections are served by another worker
+/// meanwhile, so that they can be told the server is busy. a current-thread
+/// runtime has no other worker, its clients wait for the script to end
+pub(crate) fn run_script<T>(apply: impl FnOnce() -> T) -> T {
+    match Handle::current().runtime_flavor() {
+        RuntimeFlavor::MultiThread => block_in_place(apply),
+        _ => apply(),
+    }
+}
+
+fn kill(control: &ScriptControl) -> Frame {
+    match control.kill() {
+        Ok(()) => Frame::into_simple("OK"),
+        Err(e) => Frame::Error(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        net::TcpStream,
+        sync::{broadcast, mpsc},
+        time::{sleep, timeout},
+    };
+
+    use crate::{config, Listener};
+
+    use super::*;
+
+    async fn send(connection: &mut Connection, words: &[&str]) {
+        let frames = words
+            .iter()
+            .map(|word| Frame::Bulk(Bytes::copy_from_slice(word.as_bytes())))
+            .collect();
+        connection.write_frame(Frame::Array(frames)).await.unwrap();
+    }
+
+    async fn request(connection: &mut Connection, words: &[&str]) -> Frame {
+        send(connection, words).await;
+        read_reply(connection).await
+    }
+
+    async fn read_reply(connection: &mut Connection) -> Frame {
+        let reply = timeout(Duration::from_secs(5), connection.read_frame());
+        reply.await.unwrap().unwrap().unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn busy_script_test() {
+        let _threshold = config::Override::new(&config::BUSY_REPLY_THRESHOLD, 100);
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        let mut listener = Listener::new("127.0.0.1:0", shutdown_tx.clone(), shutdown_completed_tx)
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { listener.run().await });
+        let connect = || async { Connection::new(TcpStream::connect(addr).await.unwrap()) };
+
+        let mut script = connect().await;
+        send(&mut script, &["EVAL", "while true do end", "0"]).await;
+        sleep(Duration::from_millis(200)).await;
+
+        // a client connecting while the script runs is served, though only to be
+        // told the server is busy or to stop the script
+        let mut other = connect().await;
+        let reply = request(&mut other, &["SET", "k", "v"]).await;
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("BUSY")));
+        let reply = request(&mut other, &["SCRIPT", "KILL"]).await;
+        assert_eq!(reply, Frame::into_simple("OK"));
+        let reply = read_reply(&mut script).await;
+        assert!(matches!(reply, Frame::Error(e) if e.contains("SCRIPT KILL")));
+
+        let reply = request(&mut other, &["SET", "k", "v"]).await;
+        assert_eq!(reply, Frame::into_simple("OK"));
+        let reply = request(&mut script, &["GET", "k"]).await;
+        assert_eq!(
+            reply,
+            Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"v"))])
+        );
+        shutdown_tx.send(()).unwrap();
+    }
+}
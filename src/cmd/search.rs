@@ -0,0 +1,784 @@
+use bytes::Bytes;
+use std::{cmp::Ordering, collections::BTreeMap, collections::HashSet, iter::Peekable, vec};
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, Document, FieldKind, FieldSpec, SearchIndex},
+    parser::{ParseError, Parser},
+    search::Query,
+    Connection, DbHolder, Frame, Result,
+};
+
+/// the arguments after the fixed ones, their options come in any order
+type Args = Peekable<vec::IntoIter<String>>;
+
+#[derive(Debug)]
+pub struct FtCreate {
+    name: String,
+    prefixes: Vec<String>,
+    fields: Vec<FieldSpec>,
+}
+
+#[derive(Debug)]
+pub struct FtSearch {
+    name: String,
+    query: String,
+    no_content: bool,
+    with_scores: bool,
+    returned: Option<Vec<String>>,
+    /// the attribute and whether the order is ascending
+    sort_by: Option<(String, bool)>,
+    offset: usize,
+    count: usize,
+}
+
+#[derive(Debug)]
+pub struct FtAggregate {
+    name: String,
+    query: String,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug)]
+pub struct FtDropIndex {
+    name: String,
+    delete_documents: bool,
+}
+
+#[derive(Debug)]
+pub struct FtInfo {
+    name: String,
+}
+
+/// FT._LIST
+#[derive(Debug)]
+pub struct FtList;
+
+/// the stages of FT.AGGREGATE, applied to the rows in order
+#[derive(Debug)]
+enum Step {
+    Load(Vec<String>),
+    GroupBy {
+        properties: Vec<String>,
+        reducers: Vec<Reducer>,
+    },
+    SortBy {
+        properties: Vec<(String, bool)>,
+        max: Option<usize>,
+    },
+    Limit(usize, usize),
+}
+
+#[derive(Debug)]
+struct Reducer {
+    function: Reduce,
+    property: Option<String>,
+    alias: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Reduce {
+    Count,
+    CountDistinct,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+/// a row of FT.AGGREGATE, the properties of a document until it is grouped
+struct Row<'a> {
+    document: Option<&'a Document>,
+    values: Vec<(String, String)>,
+}
+
+impl FtCreate {
+    pub fn parse_frames(parser: &mut Parser) -> Result<FtCreate> {
+        let name = parser.next_string()?;
+        let mut args = remaining(parser)?;
+        let mut prefixes = vec![];
+        loop {
+            let token = next(&mut args)?;
+            match &token.to_lowercase()[..] {
+                "on" => {
+                    if next(&mut args)?.to_lowercase() != "hash" {
+                        return Err("only hashes can be indexed".into());
+                    }
+                }
+                "prefix" => {
+                    let count = parse_count(&next(&mut args)?)?;
+                    for _ in 0..count {
+                        prefixes.push(next(&mut args)?);
+                    }
+                }
+                "schema" => break,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        let mut fields = vec![];
+        while let Some(name) = args.next() {
+            let mut alias = name.clone();
+            let mut kind = next(&mut args)?;
+            if kind.to_lowercase() == "as" {
+                alias = next(&mut args)?;
+                kind = next(&mut args)?;
+            }
+            let mut kind = match &kind.to_lowercase()[..] {
+                "text" => FieldKind::Text { weight: 1.0 },
+                "tag" => FieldKind::Tag {
+                    separator: ',',
+                    case_sensitive: false,
+                },
+                "numeric" => FieldKind::Numeric,
+                _ => return Err(format!("unknown field type '{}'", kind).into()),
+            };
+            while let Some(option) = args.peek() {
+                match (&option.to_lowercase()[..], &mut kind) {
+                    ("weight", FieldKind::Text { weight }) => {
+                        args.next();
+                        *weight = match next(&mut args)?.parse() {
+                            Ok(value) if value >= 0.0 => value,
+                            _ => return Err("invalid WEIGHT".into()),
+                        };
+                    }
+                    ("separator", FieldKind::Tag { separator, .. }) => {
+                        args.next();
+                        let value = next(&mut args)?;
+                        let mut chars = value.chars();
+                        *separator = match (chars.next(), chars.next()) {
+                            (Some(c), None) => c,
+                            _ => return Err("SEPARATOR must be a single character".into()),
+                        };
+                    }
+                    ("casesensitive", FieldKind::Tag { case_sensitive, .. }) => {
+                        args.next();
+                        *case_sensitive = true;
+                    }
+                    // every attribute can be sorted by and no stemming is done anyway
+                    ("sortable" | "nostem", _) => {
+                        args.next();
+                    }
+                    _ => break,
+                }
+            }
+            fields.push(FieldSpec { name, alias, kind });
+        }
+        if fields.is_empty() {
+            return Err("SCHEMA requires at least one field".into());
+        }
+        Ok(FtCreate {
+            name,
+            prefixes,
+            fields,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let index = SearchIndex::new(self.prefixes, self.fields);
+        match db.create_index(self.name, index) {
+            true => Frame::Simple("OK".to_string()),
+            false => Frame::Error("ERR Index already exists".to_string()),
+        }
+    }
+}
+
+impl FtSearch {
+    pub fn parse_frames(parser: &mut Parser) -> Result<FtSearch> {
+        let name = parser.next_string()?;
+        let query = parser.next_string()?;
+        let mut args = remaining(parser)?;
+        let mut search = FtSearch {
+            name,
+            query,
+            no_content: false,
+            with_scores: false,
+            returned: None,
+            sort_by: None,
+            offset: 0,
+            count: 10,
+        };
+        while let Some(token) = args.next() {
+            match &token.to_lowercase()[..] {
+                "nocontent" => search.no_content = true,
+                "withscores" => search.with_scores = true,
+                "return" => {
+                    let count = parse_count(&next(&mut args)?)?;
+                    let mut returned = vec![];
+                    for _ in 0..count {
+                        returned.push(next(&mut args)?);
+                    }
+                    search.returned = Some(returned);
+                }
+                "sortby" => {
+                    let property = next(&mut args)?;
+                    search.sort_by = Some((property, parse_order(&mut args)));
+                }
+                "limit" => {
+                    search.offset = parse_count(&next(&mut args)?)?;
+                    search.count = parse_count(&next(&mut args)?)?;
+                }
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        Ok(search)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the number of matches, then the requested page of keys, each followed by its
+    /// score and its fields
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let Some(index) = db.search_index(&self.name) else {
+            return no_such_index(&self.name);
+        };
+        let mut results = match Query::parse(&self.query).and_then(|query| index.search(&query)) {
+            Ok(results) => results,
+            Err(e) => return Frame::Error(format!("ERR {}", e)),
+        };
+        if let Some((property, ascending)) = &self.sort_by {
+            let Some(field) = schema_field(index.fields(), property) else {
+                return not_in_schema(property);
+            };
+            // the documents without the attribute come last, whatever the order
+            results.sort_by(|(a, _), (b, _)| {
+                let a = index.document(a).unwrap();
+                let b = index.document(b).unwrap();
+                match (sort_key(index, field, a), sort_key(index, field, b)) {
+                    (Some(a), Some(b)) if *ascending => a.compare(&b),
+                    (Some(a), Some(b)) => b.compare(&a),
+                    (a, b) => b.is_some().cmp(&a.is_some()).reverse(),
+                }
+            });
+        }
+
+        let mut frames = vec![Frame::Integer(results.len() as i64)];
+        for (key, score) in results.into_iter().skip(self.offset).take(self.count) {
+            frames.push(Frame::Bulk(Bytes::from(key.to_string())));
+            if self.with_scores {
+                frames.push(Frame::into_double(score));
+            }
+            if self.no_content {
+                continue;
+            }
+            let document = index.document(key).unwrap();
+            let fields = match &self.returned {
+                None => document
+                    .fields
+                    .iter()
+                    .flat_map(|(field, value)| {
+                        [Frame::Bulk(field.clone()), Frame::Bulk(value.clone())]
+                    })
+                    .collect(),
+                Some(returned) => returned
+                    .iter()
+                    .filter_map(|property| {
+                        let value = property_value(index, document, property)?;
+                        Some([
+                            Frame::Bulk(Bytes::from(property.clone())),
+                            Frame::Bulk(Bytes::from(value)),
+                        ])
+                    })
+                    .flatten()
+                    .collect(),
+            };
+            frames.push(Frame::Array(fields));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl FtAggregate {
+    pub fn parse_frames(parser: &mut Parser) -> Result<FtAggregate> {
+        let name = parser.next_string()?;
+        let query = parser.next_string()?;
+        let mut args = remaining(parser)?;
+        let mut steps = vec![];
+        while let Some(token) = args.next() {
+            let step = match &token.to_lowercase()[..] {
+                "load" => Step::Load(parse_properties(&mut args)?),
+                "groupby" => {
+                    let properties = parse_properties(&mut args)?;
+                    let mut reducers = vec![];
+                    while args
+                        .peek()
+                        .is_some_and(|arg| arg.to_lowercase() == "reduce")
+                    {
+                        args.next();
+                        reducers.push(Reducer::parse(&mut args)?);
+                    }
+                    Step::GroupBy {
+                        properties,
+                        reducers,
+                    }
+                }
+                "sortby" => {
+                    let count = parse_count(&next(&mut args)?)?;
+                    let mut sort_args: Args = (0..count)
+                        .map(|_| next(&mut args))
+                        .collect::<Result<Vec<_>>>()?
+                        .into_iter()
+                        .peekable();
+                    let mut properties = vec![];
+                    while let Some(property) = sort_args.next() {
+                        properties.push((parse_property(&property)?, parse_order(&mut sort_args)));
+                    }
+                    let mut max = None;
+                    if args.peek().is_some_and(|arg| arg.to_lowercase() == "max") {
+                        args.next();
+                        max = Some(parse_count(&next(&mut args)?)?);
+                    }
+                    Step::SortBy { properties, max }
+                }
+                "limit" => {
+                    let offset = parse_count(&next(&mut args)?)?;
+                    Step::Limit(offset, parse_count(&next(&mut args)?)?)
+                }
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            };
+            steps.push(step);
+        }
+        Ok(FtAggregate { name, query, steps })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the number of rows, then every row as its properties and their values
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let Some(index) = db.search_index(&self.name) else {
+            return no_such_index(&self.name);
+        };
+        let results = match Query::parse(&self.query).and_then(|query| index.search(&query)) {
+            Ok(results) => results,
+            Err(e) => return Frame::Error(format!("ERR {}", e)),
+        };
+        let mut rows: Vec<Row> = results
+            .into_iter()
+            .map(|(key, _)| Row {
+                document: index.document(key),
+                values: vec![],
+            })
+            .collect();
+
+        for step in &self.steps {
+            rows = match step {
+                Step::Load(properties) => {
+                    for row in &mut rows {
+                        for property in properties {
+                            if let Some(value) = row.get(index, property) {
+                                row.values.push((property.clone(), value));
+                            }
+                        }
+                    }
+                    rows
+                }
+                Step::GroupBy {
+                    properties,
+                    reducers,
+                } => group(index, rows, properties, reducers),
+                Step::SortBy { properties, max } => {
+                    let mut keyed: Vec<(Vec<Option<SortKey>>, Row)> = rows
+                        .into_iter()
+                        .map(|row| {
+                            let keys = properties
+                                .iter()
+                                .map(|(property, _)| row.get(index, property).map(SortKey::parse))
+                                .collect();
+                            (keys, row)
+                        })
+                        .collect();
+                    keyed.sort_by(|(a, _), (b, _)| {
+                        a.iter()
+                            .zip(b)
+                            .zip(properties)
+                            .map(|((a, b), (_, ascending))| match (a, b) {
+                                (Some(a), Some(b)) if *ascending => a.compare(b),
+                                (Some(a), Some(b)) => b.compare(a),
+                                (a, b) => b.is_some().cmp(&a.is_some()).reverse(),
+                            })
+                            .find(|ordering| ordering.is_ne())
+                            .unwrap_or(Ordering::Equal)
+                    });
+                    keyed.truncate(max.unwrap_or(usize::MAX));
+                    keyed.into_iter().map(|(_, row)| row).collect()
+                }
+                Step::Limit(offset, count) => rows.into_iter().skip(*offset).take(*count).collect(),
+            };
+        }
+
+        let mut frames = vec![Frame::Integer(rows.len() as i64)];
+        for row in rows {
+            frames.push(Frame::Array(
+                row.values
+                    .into_iter()
+                    .flat_map(|(property, value)| {
+                        [
+                            Frame::Bulk(Bytes::from(property)),
+                            Frame::Bulk(Bytes::from(value)),
+                        ]
+                    })
+                    .collect(),
+            ));
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl Reducer {
+    /// `function nargs args... [AS alias]`
+    fn parse(args: &mut Args) -> Result<Reducer> {
+        let name = next(args)?.to_lowercase();
+        let function = match &name[..] {
+            "count" => Reduce::Count,
+            "count_distinct" => Reduce::CountDistinct,
+            "sum" => Reduce::Sum,
+            "min" => Reduce::Min,
+            "max" => Reduce::Max,
+            "avg" => Reduce::Avg,
+            _ => return Err(format!("unknown reducer '{}'", name).into()),
+        };
+        let property = match (function, parse_count(&next(args)?)?) {
+            (Reduce::Count, 0) => None,
+            (Reduce::Count, _) => return Err("COUNT takes no arguments".into()),
+            (_, 1) => Some(parse_property(&next(args)?)?),
+            _ => return Err(format!("{} takes one argument", name.to_uppercase()).into()),
+        };
+        let mut alias = format!(
+            "__generated_alias{}{}",
+            name,
+            property.as_deref().unwrap_or("")
+        );
+        if args.peek().is_some_and(|arg| arg.to_lowercase() == "as") {
+            args.next();
+            alias = next(args)?;
+        }
+        Ok(Reducer {
+            function,
+            property,
+            alias,
+        })
+    }
+
+    fn reduce(&self, index: &SearchIndex, rows: &[Row]) -> String {
+        let values: Vec<String> = match &self.property {
+            Some(property) => rows
+                .iter()
+                .filter_map(|row| row.get(index, property))
+                .collect(),
+            None => vec![],
+        };
+        let numbers = || values.iter().filter_map(|value| value.parse::<f64>().ok());
+        // an empty sum of floats is negative zero
+        let sum = || numbers().fold(0.0, |sum, number| sum + number);
+        let number = match self.function {
+            Reduce::Count => rows.len() as f64,
+            Reduce::CountDistinct => values.iter().collect::<HashSet<_>>().len() as f64,
+            Reduce::Sum => sum(),
+            Reduce::Min => numbers().fold(f64::INFINITY, f64::min),
+            Reduce::Max => numbers().fold(f64::NEG_INFINITY, f64::max),
+            Reduce::Avg => {
+                let count = numbers().count();
+                match count {
+                    0 => 0.0,
+                    _ => sum() / count as f64,
+                }
+            }
+        };
+        number.to_string()
+    }
+}
+
+impl Row<'_> {
+    /// the value of a property loaded or computed by a previous step, or of the document
+    fn get(&self, index: &SearchIndex, property: &str) -> Option<String> {
+        if let Some((_, value)) = self.values.iter().find(|(name, _)| name == property) {
+            return Some(value.clone());
+        }
+        property_value(index, self.document?, property)
+    }
+}
+
+/// the rows with the same values of the properties become a single row, with the
+/// values of the reducers
+fn group<'a>(
+    index: &'a SearchIndex,
+    rows: Vec<Row<'a>>,
+    properties: &[String],
+    reducers: &[Reducer],
+) -> Vec<Row<'a>> {
+    let mut groups: BTreeMap<Vec<Option<String>>, Vec<Row>> = BTreeMap::new();
+    for row in rows {
+        let values = properties
+            .iter()
+            .map(|property| row.get(index, property))
+            .collect();
+        groups.entry(values).or_default().push(row);
+    }
+    groups
+        .into_iter()
+        .map(|(values, rows)| {
+            let mut row = Row {
+                document: None,
+                values: properties
+                    .iter()
+                    .zip(values)
+                    .filter_map(|(property, value)| Some((property.clone(), value?)))
+                    .collect(),
+            };
+            for reducer in reducers {
+                row.values
+                    .push((reducer.alias.clone(), reducer.reduce(index, &rows)));
+            }
+            row
+        })
+        .collect()
+}
+
+/// a value to sort by, numbers before strings
+#[derive(Debug)]
+enum SortKey {
+    Number(f64),
+    Text(String),
+}
+
+impl SortKey {
+    fn parse(value: String) -> SortKey {
+        match value.parse() {
+            Ok(number) => SortKey::Number(number),
+            Err(_) => SortKey::Text(value),
+        }
+    }
+
+    fn compare(&self, other: &SortKey) -> Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::Number(_), SortKey::Text(_)) => Ordering::Less,
+            (SortKey::Text(_), SortKey::Number(_)) => Ordering::Greater,
+            (SortKey::Text(a), SortKey::Text(b)) => a.cmp(b),
+        }
+    }
+}
+
+/// NUMERIC attributes sort as numbers, the others as strings
+fn sort_key(index: &SearchIndex, field: &FieldSpec, document: &Document) -> Option<SortKey> {
+    match field.kind {
+        FieldKind::Numeric => document
+            .number(index.fields(), &field.alias)
+            .map(SortKey::Number),
+        _ => document
+            .get(&field.name)
+            .map(|value| SortKey::Text(String::from_utf8_lossy(value).to_lowercase())),
+    }
+}
+
+/// an attribute of the schema by alias or field name
+fn schema_field<'a>(fields: &'a [FieldSpec], property: &str) -> Option<&'a FieldSpec> {
+    fields
+        .iter()
+        .find(|field| field.alias == property)
+        .or_else(|| fields.iter().find(|field| field.name == property))
+}
+
+/// the value of an attribute of the schema, or of any field of the hash
+fn property_value(index: &SearchIndex, document: &Document, property: &str) -> Option<String> {
+    let name = schema_field(index.fields(), property).map_or(property, |field| &field.name);
+    document
+        .get(name)
+        .map(|value| String::from_utf8_lossy(value).to_string())
+}
+
+impl FtDropIndex {
+    pub fn parse_frames(parser: &mut Parser) -> Result<FtDropIndex> {
+        let name = parser.next_string()?;
+        let delete_documents = match parser.next_string() {
+            Ok(option) if option.to_lowercase() == "dd" => true,
+            Ok(option) => return Err(format!("syntax error near '{}'", option).into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(FtDropIndex {
+            name,
+            delete_documents,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// DD deletes the indexed hashes along with the index
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let Some(index) = db.drop_index(&self.name) else {
+            return Frame::Error("ERR Unknown Index name".to_string());
+        };
+        if self.delete_documents {
+            for key in index.keys() {
+                if db.remove(key).is_some() {
+                    db.notify(Class::Generic, "del", key);
+                }
+            }
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
+
+impl FtInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<FtInfo> {
+        let name = parser.next_string()?;
+        Ok(FtInfo { name })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let Some(index) = db.search_index(&self.name) else {
+            return no_such_index(&self.name);
+        };
+        let bulk = |text: &str| Frame::Bulk(Bytes::from(text.to_string()));
+        let prefixes = index.prefixes().iter().map(|prefix| bulk(prefix)).collect();
+        let attributes = index
+            .fields()
+            .iter()
+            .map(|field| {
+                let mut frames = vec![
+                    bulk("identifier"),
+                    bulk(&field.name),
+                    bulk("attribute"),
+                    bulk(&field.alias),
+                    bulk("type"),
+                    bulk(field.kind.name()),
+                ];
+                match field.kind {
+                    FieldKind::Text { weight } => {
+                        frames.extend([bulk("WEIGHT"), Frame::into_double(weight)]);
+                    }
+                    FieldKind::Tag {
+                        separator,
+                        case_sensitive,
+                    } => {
+                        frames.extend([bulk("SEPARATOR"), bulk(&separator.to_string())]);
+                        if case_sensitive {
+                            frames.push(bulk("CASESENSITIVE"));
+                        }
+                    }
+                    FieldKind::Numeric => {}
+                }
+                Frame::Array(frames)
+            })
+            .collect();
+        Frame::Array(vec![
+            bulk("index_name"),
+            bulk(&self.name),
+            bulk("index_definition"),
+            Frame::Array(vec![
+                bulk("key_type"),
+                bulk("HASH"),
+                bulk("prefixes"),
+                Frame::Array(prefixes),
+            ]),
+            bulk("attributes"),
+            Frame::Array(attributes),
+            bulk("num_docs"),
+            Frame::Integer(index.len() as i64),
+        ])
+    }
+}
+
+impl FtList {
+    pub fn parse_frames(_parser: &mut Parser) -> Result<FtList> {
+        Ok(FtList)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        Frame::Array(
+            db.index_names()
+                .into_iter()
+                .map(|name| Frame::Bulk(Bytes::from(name)))
+                .collect(),
+        )
+    }
+}
+
+/// `n @a @b...`
+fn parse_properties(args: &mut Args) -> Result<Vec<String>> {
+    let count = parse_count(&next(args)?)?;
+    (0..count).map(|_| parse_property(&next(args)?)).collect()
+}
+
+/// the properties of FT.AGGREGATE are preceded by `@`
+fn parse_property(property: &str) -> Result<String> {
+    match property.strip_prefix('@') {
+        Some(name) if !name.is_empty() => Ok(name.to_string()),
+        _ => Err(format!("bad property '{}', it must start with '@'", property).into()),
+    }
+}
+
+/// an optional ASC or DESC, ascending by default
+fn parse_order(args: &mut Args) -> bool {
+    match args.peek().map(|arg| arg.to_lowercase()).as_deref() {
+        Some("asc") => {
+            args.next();
+            true
+        }
+        Some("desc") => {
+            args.next();
+            false
+        }
+        _ => true,
+    }
+}
+
+fn parse_count(count: &str) -> Result<usize> {
+    count
+        .parse()
+        .map_err(|_| format!("invalid count '{}'", count).into())
+}
+
+fn remaining(parser: &mut Parser) -> Result<Args> {
+    match parser.remaining_strings() {
+        Ok(args) => Ok(args.into_iter().peekable()),
+        Err(ParseError::EndOfStream) => Ok(vec![].into_iter().peekable()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn next(args: &mut Args) -> Result<String> {
+    args.next()
+        .ok_or_else(|| "wrong number of arguments".into())
+}
+
+fn no_such_index(name: &str) -> Frame {
+    Frame::Error(format!("ERR {}: no such index", name))
+}
+
+fn not_in_schema(property: &str) -> Frame {
+    Frame::Error(format!(
+        "ERR Property `{}` not loaded nor in schema",
+        property
+    ))
+}
@@ -3,7 +3,7 @@ use std::time::Duration;
 use bytes::Bytes;
 use tracing::instrument;
 
-use crate::{DbHolder, Connection, Result, Frame};
+use crate::{db::Database, Connection, DbHolder, Frame, Result};
 
 #[derive(Debug)]
 pub struct Set {
@@ -23,12 +23,21 @@ impl Set {
 
     #[instrument(skip(db, connection))]
     pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
-        db.set(self.key, self.value, self.expiration)?;
-        connection.write_frame(Frame::into_simple("OK")).await 
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        db.set(self.key, self.value, self.expiration);
+        Frame::into_simple("OK")
     }
 
     pub fn get_frame(key: &str, value: Bytes, expiration: Option<Duration>) -> Frame {
-        let mut frame = vec![Frame::into_simple("set"), Frame::into_simple(key), Frame::Bulk(value)]; 
+        let mut frame = vec![
+            Frame::into_simple("set"),
+            Frame::into_simple(key),
+            Frame::Bulk(value),
+        ];
         if let Some(exp) = expiration {
             frame.push(Frame::Integer(exp.as_secs() as i64))
         }
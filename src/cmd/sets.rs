@@ -0,0 +1,554 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, Set, Value, WrongType},
+    glob::glob_match,
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+#[derive(Debug)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct SIsMember {
+    key: String,
+    member: Bytes,
+}
+
+#[derive(Debug)]
+pub struct SMIsMember {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct SMembers {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct SCard {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct SPop {
+    key: String,
+    count: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct SRandMember {
+    key: String,
+    count: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct SMove {
+    source: String,
+    destination: String,
+    member: Bytes,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl SetOp {
+    /// the keyspace event of the STORE variant
+    fn store_event(self) -> &'static str {
+        match self {
+            SetOp::Inter => "sinterstore",
+            SetOp::Union => "sunionstore",
+            SetOp::Diff => "sdiffstore",
+        }
+    }
+}
+
+/// SINTER, SUNION, SDIFF and their STORE variants
+#[derive(Debug)]
+pub struct SCombine {
+    op: SetOp,
+    destination: Option<String>,
+    keys: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct SInterCard {
+    keys: Vec<String>,
+    limit: usize,
+}
+
+#[derive(Debug)]
+pub struct SScan {
+    key: String,
+    cursor: usize,
+    pattern: Option<Bytes>,
+    count: usize,
+}
+
+impl SAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SAdd> {
+        Ok(SAdd {
+            key: parser.next_string()?,
+            members: parser.remaining_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_or_insert_set(&self.key) {
+            Ok(set) => set,
+            Err(e) => return e.into(),
+        };
+        let added = self
+            .members
+            .into_iter()
+            .filter(|m| set.insert(m.clone()))
+            .count();
+        if added > 0 {
+            db.notify(Class::Set, "sadd", &self.key);
+        }
+        Frame::Integer(added as i64)
+    }
+}
+
+impl SRem {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SRem> {
+        Ok(SRem {
+            key: parser.next_string()?,
+            members: parser.remaining_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let removed = match db.get_set_mut(&self.key) {
+            Ok(Some(set)) => self.members.iter().filter(|m| set.remove(m)).count(),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if removed > 0 {
+            db.notify(Class::Set, "srem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl SIsMember {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SIsMember> {
+        Ok(SIsMember {
+            key: parser.next_string()?,
+            member: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(set) => Frame::Integer(set.is_some_and(|set| set.contains(&self.member)) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl SMIsMember {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SMIsMember> {
+        Ok(SMIsMember {
+            key: parser.next_string()?,
+            members: parser.remaining_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_set(&self.key) {
+            Ok(set) => set,
+            Err(e) => return e.into(),
+        };
+        let flags = self
+            .members
+            .iter()
+            .map(|m| Frame::Integer(set.is_some_and(|set| set.contains(m)) as i64));
+        Frame::Array(flags.collect())
+    }
+}
+
+impl SMembers {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SMembers> {
+        Ok(SMembers {
+            key: parser.next_string()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(Some(set)) => members_frame(set.iter()),
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl SCard {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SCard> {
+        Ok(SCard {
+            key: parser.next_string()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_set(&self.key) {
+            Ok(set) => Frame::Integer(set.map_or(0, |set| set.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl SPop {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SPop> {
+        let key = parser.next_string()?;
+        let count = match parser.next_int() {
+            Ok(count) if count < 0 => return Err("value is out of range, must be positive".into()),
+            Ok(count) => Some(count as usize),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(SPop { key, count })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let popped = match db.get_set_mut(&self.key) {
+            Ok(Some(set)) => set.pop_random(self.count.unwrap_or(1)),
+            Ok(None) => vec![],
+            Err(e) => return e.into(),
+        };
+        if !popped.is_empty() {
+            db.notify(Class::Set, "spop", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        match self.count {
+            Some(_) => members_frame(popped.into_iter()),
+            None => popped.into_iter().next().map_or(Frame::Null, Frame::Bulk),
+        }
+    }
+}
+
+impl SRandMember {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SRandMember> {
+        let key = parser.next_string()?;
+        let count = match parser.next_int() {
+            Ok(count) => Some(count),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(SRandMember { key, count })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let members = match db.get_set(&self.key) {
+            Ok(Some(set)) => set.random(self.count.unwrap_or(1)),
+            Ok(None) => vec![],
+            Err(e) => return e.into(),
+        };
+        match self.count {
+            Some(_) => members_frame(members.into_iter()),
+            None => members.into_iter().next().map_or(Frame::Null, Frame::Bulk),
+        }
+    }
+}
+
+impl SMove {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SMove> {
+        Ok(SMove {
+            source: parser.next_string()?,
+            destination: parser.next_string()?,
+            member: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        // check the destination first, so that nothing is moved if it has a wrong type
+        if let Err(e) = db.get_set(&self.destination) {
+            return e.into();
+        }
+        let moved = match db.get_set_mut(&self.source) {
+            Ok(Some(set)) => set.remove(&self.member),
+            Ok(None) => false,
+            Err(e) => return e.into(),
+        };
+        if !moved {
+            return Frame::Integer(0);
+        }
+        db.notify(Class::Set, "srem", &self.source);
+        db.remove_if_empty(&self.source);
+        if let Ok(set) = db.get_or_insert_set(&self.destination) {
+            set.insert(self.member);
+        }
+        db.notify(Class::Set, "sadd", &self.destination);
+        Frame::Integer(1)
+    }
+}
+
+impl SCombine {
+    /// the variant storing its result at a destination key
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    /// `store` tells whether a destination key leads the keys (SINTERSTORE, ...)
+    pub fn parse_frames(parser: &mut Parser, op: SetOp, store: bool) -> Result<SCombine> {
+        let destination = if store {
+            Some(parser.next_string()?)
+        } else {
+            None
+        };
+        let keys = parser.remaining_strings()?;
+        Ok(SCombine {
+            op,
+            destination,
+            keys,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let result = match combine(db, self.op, &self.keys) {
+            Ok(result) => result,
+            Err(e) => return e.into(),
+        };
+        match self.destination {
+            Some(destination) => {
+                let len = result.len();
+                if !result.is_empty() {
+                    db.insert(destination.clone(), Value::Set(result));
+                    db.notify(Class::Set, self.op.store_event(), &destination);
+                } else if db.remove(&destination).is_some() {
+                    db.notify(Class::Generic, "del", &destination);
+                }
+                Frame::Integer(len as i64)
+            }
+            None => members_frame(result.iter()),
+        }
+    }
+}
+
+impl SInterCard {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SInterCard> {
+        let numkeys = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err("numkeys should be greater than 0".into());
+        }
+        let keys = (0..numkeys)
+            .map(|_| parser.next_string())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let limit = match parser.next_string() {
+            Ok(token) if token.eq_ignore_ascii_case("limit") => match parser.next_int()? {
+                limit if limit < 0 => return Err("LIMIT can't be negative".into()),
+                limit => limit as usize,
+            },
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(SInterCard { keys, limit })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let sets = match lookup_sets(db, &self.keys) {
+            Ok(sets) => sets,
+            Err(e) => return e.into(),
+        };
+        let Some(sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return Frame::Integer(0);
+        };
+        let (smallest, others) = split_smallest(sets);
+        let common = smallest
+            .iter()
+            .filter(|m| others.iter().all(|set| set.contains(m)));
+        let count = if self.limit == 0 {
+            common.count()
+        } else {
+            common.take(self.limit).count()
+        };
+        Frame::Integer(count as i64)
+    }
+}
+
+impl SScan {
+    pub fn parse_frames(parser: &mut Parser) -> Result<SScan> {
+        let key = parser.next_string()?;
+        let cursor = parser.next_int()?;
+        if cursor < 0 {
+            return Err("invalid cursor".into());
+        }
+        let mut scan = SScan {
+            key,
+            cursor: cursor as usize,
+            pattern: None,
+            count: DEFAULT_SCAN_COUNT,
+        };
+        loop {
+            match parser.next_string() {
+                Ok(token) => match &token.to_lowercase()[..] {
+                    "match" => scan.pattern = Some(parser.next_bytes()?),
+                    "count" => match parser.next_int()? {
+                        count if count < 1 => return Err("syntax error".into()),
+                        count => scan.count = count as usize,
+                    },
+                    _ => return Err(format!("syntax error near '{}'", token).into()),
+                },
+                Err(ParseError::EndOfStream) => return Ok(scan),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let (cursor, members) = match db.get_set(&self.key) {
+            Ok(Some(set)) => set.scan(self.cursor, self.count),
+            Ok(None) => (0, vec![]),
+            Err(e) => return e.into(),
+        };
+        let members = members.into_iter().filter(|m| {
+            self.pattern
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, m))
+        });
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(cursor.to_string())),
+            members_frame(members),
+        ])
+    }
+}
+
+fn members_frame(members: impl Iterator<Item = Bytes>) -> Frame {
+    Frame::Array(members.map(Frame::Bulk).collect())
+}
+
+fn lookup_sets<'a>(
+    db: &'a Database,
+    keys: &[String],
+) -> std::result::Result<Vec<Option<&'a Set>>, WrongType> {
+    keys.iter().map(|key| db.get_set(key)).collect()
+}
+
+/// split off the smallest set, intersections iterate it and probe the others
+fn split_smallest(mut sets: Vec<&Set>) -> (&Set, Vec<&Set>) {
+    let smallest = (0..sets.len()).min_by_key(|i| sets[*i].len()).unwrap();
+    let set = sets.swap_remove(smallest);
+    (set, sets)
+}
+
+fn combine(db: &Database, op: SetOp, keys: &[String]) -> std::result::Result<Set, WrongType> {
+    let sets = lookup_sets(db, keys)?;
+    let result = match op {
+        SetOp::Inter => match sets.into_iter().collect::<Option<Vec<_>>>() {
+            Some(sets) => {
+                let (smallest, others) = split_smallest(sets);
+                smallest
+                    .iter()
+                    .filter(|m| others.iter().all(|set| set.contains(m)))
+                    .collect()
+            }
+            // a missing key is an empty set
+            None => Set::default(),
+        },
+        SetOp::Union => sets.into_iter().flatten().flat_map(Set::iter).collect(),
+        SetOp::Diff => {
+            let mut sets = sets.into_iter();
+            let first = sets.next().flatten();
+            let others: Vec<&Set> = sets.flatten().collect();
+            first
+                .into_iter()
+                .flat_map(Set::iter)
+                .filter(|m| !others.iter().any(|set| set.contains(m)))
+                .collect()
+        }
+    };
+    Ok(result)
+}
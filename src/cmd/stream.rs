@@ -0,0 +1,542 @@
+use std::{future::pending, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use tokio::{
+    select,
+    sync::Notify,
+    time::{sleep_until, Instant},
+};
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, Fields, NewId, StreamId, Trim, TrimStrategy, WrongType},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+pub(super) const INVALID_ID: &str = "Invalid stream ID specified as stream command argument";
+
+#[derive(Debug)]
+pub struct XAdd {
+    key: String,
+    no_mkstream: bool,
+    trim: Option<Trim>,
+    id: NewId,
+    fields: Fields,
+}
+
+/// XRANGE and XREVRANGE
+#[derive(Debug)]
+pub struct XRange {
+    key: String,
+    start: StreamId,
+    end: StreamId,
+    reverse: bool,
+    count: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct XLen {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct XTrim {
+    key: String,
+    trim: Trim,
+}
+
+#[derive(Debug)]
+pub struct XDel {
+    key: String,
+    ids: Vec<StreamId>,
+}
+
+#[derive(Debug)]
+pub struct XRead {
+    count: Option<usize>,
+    /// `None` doesn't block, `Some(None)` blocks forever
+    block: Option<Option<Duration>>,
+    keys: Vec<String>,
+    ids: Vec<ReadId>,
+}
+
+/// where XREAD starts reading a stream
+#[derive(Debug, Clone, Copy)]
+enum ReadId {
+    /// entries after this ID
+    After(StreamId),
+    /// `$`, entries added after the command was received
+    New,
+    /// `+`, the last entry
+    LastEntry,
+}
+
+impl XAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XAdd> {
+        let key = parser.next_string()?;
+        let mut no_mkstream = false;
+        let mut trim = TrimOptions::default();
+        // options come first, the first token which isn't an option is the ID
+        let id = loop {
+            let token = parser.next_string()?;
+            if !trim.parse(&token, parser)? {
+                match &token.to_lowercase()[..] {
+                    "nomkstream" => no_mkstream = true,
+                    _ => break parse_new_id(&token)?,
+                }
+            }
+        };
+        let trim = trim.finish()?;
+
+        let mut fields = vec![];
+        loop {
+            let field = match parser.next_bytes() {
+                Ok(field) => field,
+                Err(ParseError::EndOfStream) if !fields.is_empty() => break,
+                Err(ParseError::EndOfStream) => {
+                    return Err("wrong number of arguments for 'xadd' command".into())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let value = match parser.next_bytes() {
+                Err(ParseError::EndOfStream) => {
+                    return Err("wrong number of arguments for 'xadd' command".into())
+                }
+                value => value?,
+            };
+            fields.push((field, value));
+        }
+        Ok(XAdd {
+            key,
+            no_mkstream,
+            trim,
+            id,
+            fields,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let stream = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) if self.no_mkstream => return Frame::Null,
+            Ok(None) => match db.get_or_insert_stream(&self.key) {
+                Ok(stream) => stream,
+                Err(e) => return e.into(),
+            },
+            Err(e) => return e.into(),
+        };
+        let id = match stream.add(self.id, self.fields) {
+            Ok(id) => id,
+            Err(e) => {
+                // don't leave behind the stream created for a rejected entry
+                if stream.len() == 0 && stream.last_id() == StreamId::MIN {
+                    db.remove(&self.key);
+                }
+                return Frame::Error(e.to_string());
+            }
+        };
+        let trimmed = self.trim.map_or(0, |trim| stream.trim(trim));
+        db.notify(Class::Stream, "xadd", &self.key);
+        if trimmed > 0 {
+            db.notify(Class::Stream, "xtrim", &self.key);
+        }
+        db.signal_ready(&self.key);
+        Frame::Bulk(Bytes::from(id.to_string()))
+    }
+}
+
+impl XRange {
+    pub fn parse_frames(parser: &mut Parser, reverse: bool) -> Result<XRange> {
+        let key = parser.next_string()?;
+        let (first, second) = (parser.next_string()?, parser.next_string()?);
+        let (start, end) = if reverse {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let count = match parser.next_string() {
+            Ok(option) if option.to_lowercase() == "count" => {
+                Some(parser.next_int()?.max(0) as usize)
+            }
+            Ok(_) => return Err("syntax error".into()),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(XRange {
+            key,
+            start: parse_range_id(&start, false)?,
+            end: parse_range_id(&end, true)?,
+            reverse,
+            count,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_stream(&self.key) {
+            Ok(Some(stream)) => {
+                entries_frame(stream.range(self.start, self.end, self.reverse, self.count))
+            }
+            Ok(None) => Frame::Array(vec![]),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl XLen {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XLen> {
+        let key = parser.next_string()?;
+        Ok(XLen { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_stream(&self.key) {
+            Ok(stream) => Frame::Integer(stream.map_or(0, |stream| stream.len()) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl XTrim {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XTrim> {
+        let key = parser.next_string()?;
+        let mut trim = TrimOptions::default();
+        loop {
+            match parser.next_string() {
+                Ok(token) if trim.parse(&token, parser)? => {}
+                Ok(_) => return Err("syntax error".into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match trim.finish()? {
+            Some(trim) => Ok(XTrim { key, trim }),
+            None => Err("syntax error".into()),
+        }
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let trimmed = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => stream.trim(self.trim),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if trimmed > 0 {
+            db.notify(Class::Stream, "xtrim", &self.key);
+        }
+        Frame::Integer(trimmed as i64)
+    }
+}
+
+impl XDel {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XDel> {
+        let key = parser.next_string()?;
+        let ids = parser
+            .remaining_strings()?
+            .iter()
+            .map(|id| StreamId::parse(id, 0).ok_or_else(|| INVALID_ID.into()))
+            .collect::<Result<_>>()?;
+        Ok(XDel { key, ids })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let deleted = match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => self.ids.iter().filter(|id| stream.delete(**id)).count(),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if deleted > 0 {
+            db.notify(Class::Stream, "xdel", &self.key);
+        }
+        Frame::Integer(deleted as i64)
+    }
+}
+
+impl XRead {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XRead> {
+        let (mut count, mut block) = (None, None);
+        loop {
+            match &parser.next_string()?.to_lowercase()[..] {
+                "count" => count = Some(parser.next_int()?.max(0) as usize),
+                "block" => {
+                    block = match parser.next_int()? {
+                        timeout if timeout < 0 => return Err("timeout is negative".into()),
+                        0 => Some(None),
+                        timeout => Some(Some(Duration::from_millis(timeout as u64))),
+                    }
+                }
+                "streams" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        let mut keys = parser.remaining_strings()?;
+        if keys.len() % 2 != 0 {
+            return Err("Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.".into());
+        }
+        let ids = keys
+            .split_off(keys.len() / 2)
+            .iter()
+            .map(|id| match &id[..] {
+                "$" => Ok(ReadId::New),
+                "+" => Ok(ReadId::LastEntry),
+                id => StreamId::parse(id, 0)
+                    .map(ReadId::After)
+                    .ok_or_else(|| INVALID_ID.into()),
+            })
+            .collect::<Result<_>>()?;
+        Ok(XRead {
+            count,
+            block,
+            keys,
+            ids,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(mut self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let keys = self.keys.clone();
+        read_or_block(db, connection, &keys, self.block, |db| {
+            match self.read(db) {
+                Ok(Frame::Null) => None,
+                Ok(frame) => Some(frame),
+                Err(e) => Some(e.into()),
+            }
+        })
+        .await
+    }
+
+    /// never blocks, which is how XREAD behaves within a transaction
+    pub(crate) fn apply(mut self, db: &mut Database) -> Frame {
+        match self.read(db) {
+            Ok(frame) => frame,
+            Err(e) => e.into(),
+        }
+    }
+
+    /// read every stream with new entries. `$` is resolved on the first read so
+    /// that a blocked client gets the entries added after the command was received
+    fn read(&mut self, db: &mut Database) -> std::result::Result<Frame, WrongType> {
+        let mut frames = vec![];
+        for (key, id) in self.keys.iter().zip(self.ids.iter_mut()) {
+            let Some(stream) = db.get_stream(key)? else {
+                if let ReadId::New = id {
+                    *id = ReadId::After(StreamId::MIN);
+                }
+                continue;
+            };
+            let entries = match *id {
+                ReadId::New => {
+                    *id = ReadId::After(stream.last_id());
+                    continue;
+                }
+                ReadId::After(after) => stream.read_after(after, self.count),
+                ReadId::LastEntry => stream
+                    .last_entry()
+                    .map(|(id, fields)| vec![(id, fields.clone())])
+                    .unwrap_or_default(),
+            };
+            if !entries.is_empty() {
+                frames.push(Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(key.clone())),
+                    entries_frame(entries),
+                ]));
+            }
+        }
+        if frames.is_empty() {
+            Ok(Frame::Null)
+        } else {
+            Ok(Frame::Array(frames))
+        }
+    }
+}
+
+/// read right away, and as long as nothing can be read and `block` is given, read again
+/// every time XADD appends to one of the streams. `read` returns `None` when it has
+/// nothing to reply yet, which is replied as a null once the timeout is reached
+pub(super) async fn read_or_block(
+    db: &DbHolder,
+    connection: &mut Connection,
+    keys: &[String],
+    block: Option<Option<Duration>>,
+    mut read: impl FnMut(&mut Database) -> Option<Frame>,
+) -> Result<()> {
+    let deadline = block.map(|timeout| timeout.map(|timeout| Instant::now() + timeout));
+    let notify = Arc::new(Notify::new());
+    loop {
+        let frame = {
+            let mut db = db.lock();
+            let frame = read(&mut db);
+            if frame.is_none() && deadline.is_some() {
+                // registered under the same lock as the read, so that no XADD is missed
+                db.wait_for_keys(keys, &notify);
+            }
+            frame
+        };
+        match frame {
+            Some(frame) => return connection.write_frame(frame).await,
+            None if deadline.is_none() => return connection.write_frame(Frame::Null).await,
+            None => {}
+        }
+
+        let expired = async {
+            match deadline.flatten() {
+                Some(deadline) => sleep_until(deadline).await,
+                None => pending().await,
+            }
+        };
+        let (woken, closed) = select! {
+            _ = notify.notified() => (true, false),
+            _ = expired => (false, false),
+            _ = connection.closed() => (false, true),
+        };
+        db.lock().stop_waiting(keys, &notify);
+        if closed {
+            return Ok(());
+        }
+        if !woken {
+            return connection.write_frame(Frame::Null).await;
+        }
+    }
+}
+
+/// MAXLEN, MINID and LIMIT as given to XADD and XTRIM
+#[derive(Default)]
+struct TrimOptions {
+    strategy: Option<TrimStrategy>,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl TrimOptions {
+    /// consume the option starting with `token`, return false if it isn't a trim option
+    fn parse(&mut self, token: &str, parser: &mut Parser) -> Result<bool> {
+        let token = token.to_lowercase();
+        if token == "limit" {
+            self.limit = Some(parser.next_int()?.max(0) as usize);
+            return Ok(true);
+        }
+        if token != "maxlen" && token != "minid" {
+            return Ok(false);
+        }
+        let mut threshold = parser.next_string()?;
+        self.approximate = threshold == "~";
+        if threshold == "~" || threshold == "=" {
+            threshold = parser.next_string()?;
+        }
+        self.strategy = Some(if token == "maxlen" {
+            match threshold.parse::<i64>() {
+                Ok(max_len) if max_len >= 0 => TrimStrategy::MaxLen(max_len as usize),
+                Ok(_) => return Err("The MAXLEN argument must be >= 0.".into()),
+                Err(_) => return Err("value is not an integer or out of range".into()),
+            }
+        } else {
+            TrimStrategy::MinId(StreamId::parse(&threshold, 0).ok_or(INVALID_ID)?)
+        });
+        Ok(true)
+    }
+
+    fn finish(self) -> Result<Option<Trim>> {
+        if self.limit.is_some() && !self.approximate {
+            return Err("syntax error, LIMIT cannot be used without the special ~ option".into());
+        }
+        Ok(self.strategy.map(|strategy| Trim {
+            strategy,
+            approximate: self.approximate,
+            limit: self.limit,
+        }))
+    }
+}
+
+fn parse_new_id(id: &str) -> Result<NewId> {
+    if id == "*" {
+        return Ok(NewId::Auto);
+    }
+    if let Some(ms) = id.strip_suffix("-*") {
+        return ms
+            .parse()
+            .map(NewId::AutoSeq)
+            .map_err(|_| INVALID_ID.into());
+    }
+    match StreamId::parse(id, 0) {
+        Some(id) => Ok(NewId::Explicit(id)),
+        None => Err(INVALID_ID.into()),
+    }
+}
+
+/// a bound of XRANGE such as `-`, `+`, `1526985054069`, `1526985054069-0` or `(1526985054069-0`.
+/// an incomplete ID covers every sequence of its millisecond
+pub(super) fn parse_range_id(id: &str, end: bool) -> Result<StreamId> {
+    let (id, exclusive) = match id.strip_prefix('(') {
+        Some(id) => (id, true),
+        None => (id, false),
+    };
+    let parsed = match id {
+        "-" if !exclusive => StreamId::MIN,
+        "+" if !exclusive => StreamId::MAX,
+        _ => StreamId::parse(id, if end { u64::MAX } else { 0 }).ok_or(INVALID_ID)?,
+    };
+    if !exclusive {
+        return Ok(parsed);
+    }
+    match end {
+        true => parsed
+            .prev()
+            .ok_or_else(|| "invalid end ID for the interval".into()),
+        false => parsed
+            .next()
+            .ok_or_else(|| "invalid start ID for the interval".into()),
+    }
+}
+
+pub(super) fn entries_frame(entries: Vec<(StreamId, Fields)>) -> Frame {
+    Frame::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_frame(id, fields))
+            .collect(),
+    )
+}
+
+/// an entry is replied as its ID followed by the list of its fields and values
+pub(super) fn entry_frame(id: StreamId, fields: Fields) -> Frame {
+    let mut pairs = Vec::with_capacity(fields.len() * 2);
+    for (field, value) in fields {
+        pairs.push(Frame::Bulk(field));
+        pairs.push(Frame::Bulk(value));
+    }
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(id.to_string())),
+        Frame::Array(pairs),
+    ])
+}
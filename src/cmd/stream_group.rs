@@ -0,0 +1,790 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{now_ms, ClaimOptions, Class, Database, Fields, Stream, StreamId, WrongType},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+use super::stream::{entries_frame, entry_frame, parse_range_id, read_or_block, INVALID_ID};
+
+const NO_KEY: &str = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+
+/// XGROUP CREATE, SETID, DESTROY, CREATECONSUMER and DELCONSUMER
+#[derive(Debug)]
+pub struct XGroup {
+    key: String,
+    group: Bytes,
+    action: GroupAction,
+}
+
+#[derive(Debug)]
+enum GroupAction {
+    Create { id: GroupId, mkstream: bool },
+    SetId(GroupId),
+    Destroy,
+    CreateConsumer(Bytes),
+    DelConsumer(Bytes),
+}
+
+/// the last delivered ID given to XGROUP CREATE and SETID
+#[derive(Debug, Clone, Copy)]
+enum GroupId {
+    Id(StreamId),
+    /// `$`, the last ID of the stream
+    Last,
+}
+
+#[derive(Debug)]
+pub struct XReadGroup {
+    group: Bytes,
+    consumer: Bytes,
+    count: Option<usize>,
+    /// `None` doesn't block, `Some(None)` blocks forever
+    block: Option<Option<Duration>>,
+    no_ack: bool,
+    keys: Vec<String>,
+    /// `None` stands for `>`, the entries never delivered to the group
+    ids: Vec<Option<StreamId>>,
+}
+
+#[derive(Debug)]
+pub struct XAck {
+    key: String,
+    group: Bytes,
+    ids: Vec<StreamId>,
+}
+
+#[derive(Debug)]
+pub struct XPending {
+    key: String,
+    group: Bytes,
+    /// the extended form, without it only a summary is replied
+    range: Option<PendingRange>,
+}
+
+#[derive(Debug)]
+struct PendingRange {
+    min_idle: u64,
+    start: StreamId,
+    end: StreamId,
+    count: usize,
+    consumer: Option<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct XClaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    ids: Vec<StreamId>,
+    options: ClaimOptions,
+    last_id: Option<StreamId>,
+}
+
+#[derive(Debug)]
+pub struct XAutoClaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    min_idle: u64,
+    start: StreamId,
+    count: usize,
+    just_id: bool,
+}
+
+/// XINFO STREAM, GROUPS and CONSUMERS
+#[derive(Debug)]
+pub struct XInfo {
+    key: String,
+    subject: InfoSubject,
+}
+
+#[derive(Debug)]
+enum InfoSubject {
+    Stream,
+    Groups,
+    Consumers(Bytes),
+}
+
+impl XGroup {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XGroup> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let key = parser.next_string()?;
+        let group = parser.next_bytes()?;
+        let action = match &subcommand[..] {
+            "create" => {
+                let id = parse_group_id(&parser.next_string()?)?;
+                let mkstream = match parser.next_string() {
+                    Ok(option) if option.to_lowercase() == "mkstream" => true,
+                    Ok(_) => return Err("syntax error".into()),
+                    Err(ParseError::EndOfStream) => false,
+                    Err(e) => return Err(e.into()),
+                };
+                GroupAction::Create { id, mkstream }
+            }
+            "setid" => GroupAction::SetId(parse_group_id(&parser.next_string()?)?),
+            "destroy" => GroupAction::Destroy,
+            "createconsumer" => GroupAction::CreateConsumer(parser.next_bytes()?),
+            "delconsumer" => GroupAction::DelConsumer(parser.next_bytes()?),
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(XGroup { key, group, action })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let stream = match (db.get_stream_mut(&self.key), &self.action) {
+            (Ok(Some(stream)), _) => stream,
+            (Ok(None), GroupAction::Create { mkstream: true, .. }) => {
+                match db.get_or_insert_stream(&self.key) {
+                    Ok(stream) => stream,
+                    Err(e) => return e.into(),
+                }
+            }
+            (Ok(None), _) => return Frame::Error(NO_KEY.to_string()),
+            (Err(e), _) => return e.into(),
+        };
+        let resolve = |id: GroupId, stream: &Stream| match id {
+            GroupId::Id(id) => id,
+            GroupId::Last => stream.last_id(),
+        };
+
+        let (frame, event) = match self.action {
+            GroupAction::Create { id, .. } => {
+                let id = resolve(id, stream);
+                if stream.create_group(self.group, id) {
+                    (Frame::into_simple("OK"), Some("xgroup-create"))
+                } else {
+                    let error = "BUSYGROUP Consumer Group name already exists".to_string();
+                    (Frame::Error(error), None)
+                }
+            }
+            GroupAction::SetId(id) => {
+                let id = resolve(id, stream);
+                match stream.group_mut(&self.group) {
+                    Some(group) => {
+                        group.set_last_delivered(id);
+                        (Frame::into_simple("OK"), Some("xgroup-setid"))
+                    }
+                    None => (no_such_group(&self.key, &self.group), None),
+                }
+            }
+            GroupAction::Destroy => {
+                let destroyed = stream.destroy_group(&self.group);
+                let event = destroyed.then_some("xgroup-destroy");
+                (Frame::Integer(destroyed as i64), event)
+            }
+            GroupAction::CreateConsumer(consumer) => match stream.group_mut(&self.group) {
+                Some(group) => {
+                    let created = group.create_consumer(consumer, now_ms());
+                    let event = created.then_some("xgroup-createconsumer");
+                    (Frame::Integer(created as i64), event)
+                }
+                None => (no_such_group(&self.key, &self.group), None),
+            },
+            GroupAction::DelConsumer(consumer) => match stream.group_mut(&self.group) {
+                Some(group) => match group.delete_consumer(&consumer) {
+                    Some(pending) => (Frame::Integer(pending as i64), Some("xgroup-delconsumer")),
+                    None => (Frame::Integer(0), None),
+                },
+                None => (no_such_group(&self.key, &self.group), None),
+            },
+        };
+        if let Some(event) = event {
+            db.notify(Class::Stream, event, &self.key);
+        }
+        frame
+    }
+}
+
+impl XReadGroup {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XReadGroup> {
+        if parser.next_string()?.to_lowercase() != "group" {
+            return Err("syntax error".into());
+        }
+        let group = parser.next_bytes()?;
+        let consumer = parser.next_bytes()?;
+        let (mut count, mut block, mut no_ack) = (None, None, false);
+        loop {
+            match &parser.next_string()?.to_lowercase()[..] {
+                "count" => count = Some(parser.next_int()?.max(0) as usize),
+                "block" => {
+                    block = match parser.next_int()? {
+                        timeout if timeout < 0 => return Err("timeout is negative".into()),
+                        0 => Some(None),
+                        timeout => Some(Some(Duration::from_millis(timeout as u64))),
+                    }
+                }
+                "noack" => no_ack = true,
+                "streams" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+
+        let mut keys = parser.remaining_strings()?;
+        if keys.len() % 2 != 0 {
+            return Err("Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.".into());
+        }
+        let ids = keys
+            .split_off(keys.len() / 2)
+            .iter()
+            .map(|id| match &id[..] {
+                ">" => Ok(None),
+                id => StreamId::parse(id, 0)
+                    .map(Some)
+                    .ok_or_else(|| INVALID_ID.into()),
+            })
+            .collect::<Result<_>>()?;
+        Ok(XReadGroup {
+            group,
+            consumer,
+            count,
+            block,
+            no_ack,
+            keys,
+            ids,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        read_or_block(db, connection, &self.keys, self.block, |db| self.read(db)).await
+    }
+
+    /// never blocks, which is how XREADGROUP behaves within a transaction
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        self.read(db).unwrap_or(Frame::Null)
+    }
+
+    /// new entries are only replied for the streams which have some, while
+    /// the history of the consumer is replied for every stream, even if empty
+    fn read(&self, db: &mut Database) -> Option<Frame> {
+        // every stream and group must exist before anything is delivered
+        for key in &self.keys {
+            match db.get_stream(key) {
+                Ok(Some(stream)) if stream.group(&self.group).is_some() => {}
+                Ok(_) => {
+                    return Some(Frame::Error(format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        key,
+                        String::from_utf8_lossy(&self.group)
+                    )))
+                }
+                Err(e) => return Some(e.into()),
+            }
+        }
+
+        let now = now_ms();
+        let mut frames = vec![];
+        for (key, id) in self.keys.iter().zip(&self.ids) {
+            let Ok(Some(stream)) = db.get_stream_mut(key) else {
+                continue;
+            };
+            let entries = match id {
+                None => {
+                    let entries = stream
+                        .read_group(&self.group, &self.consumer, self.count, self.no_ack, now)
+                        .unwrap_or_default();
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    entries_frame(entries)
+                }
+                Some(after) => {
+                    let history = stream
+                        .read_history(&self.group, &self.consumer, *after, self.count, now)
+                        .unwrap_or_default();
+                    history_frame(history)
+                }
+            };
+            frames.push(Frame::Array(vec![
+                Frame::Bulk(Bytes::from(key.clone())),
+                entries,
+            ]));
+        }
+        (!frames.is_empty()).then_some(Frame::Array(frames))
+    }
+}
+
+impl XAck {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XAck> {
+        let key = parser.next_string()?;
+        let group = parser.next_bytes()?;
+        let ids = parse_ids(parser.remaining_strings()?)?;
+        Ok(XAck { key, group, ids })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_stream_mut(&self.key) {
+            Ok(Some(stream)) => {
+                let acked = match stream.group_mut(&self.group) {
+                    Some(group) => self.ids.iter().filter(|id| group.ack(**id)).count(),
+                    None => 0,
+                };
+                Frame::Integer(acked as i64)
+            }
+            Ok(None) => Frame::Integer(0),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl XPending {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XPending> {
+        let key = parser.next_string()?;
+        let group = parser.next_bytes()?;
+        let mut start = match parser.next_string() {
+            Ok(start) => start,
+            Err(ParseError::EndOfStream) => {
+                return Ok(XPending {
+                    key,
+                    group,
+                    range: None,
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut min_idle = 0;
+        if start.to_lowercase() == "idle" {
+            min_idle = parser.next_int()?.max(0) as u64;
+            start = parser.next_string()?;
+        }
+        let end = parser.next_string()?;
+        let count = parser.next_int()?.max(0) as usize;
+        let consumer = match parser.next_bytes() {
+            Ok(consumer) => Some(consumer),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let range = PendingRange {
+            min_idle,
+            start: parse_range_id(&start, false)?,
+            end: parse_range_id(&end, true)?,
+            count,
+            consumer,
+        };
+        Ok(XPending {
+            key,
+            group,
+            range: Some(range),
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let group = match db.get_stream(&self.key) {
+            Ok(Some(stream)) => stream.group(&self.group),
+            Ok(None) => None,
+            Err(e) => return e.into(),
+        };
+        let Some(group) = group else {
+            return Frame::Error(format!(
+                "NOGROUP No such key '{}' or consumer group '{}'",
+                self.key,
+                String::from_utf8_lossy(&self.group)
+            ));
+        };
+        let pending = group.pending();
+
+        let Some(range) = self.range else {
+            let (Some((first, _)), Some((last, _))) =
+                (pending.first_key_value(), pending.last_key_value())
+            else {
+                return Frame::Array(vec![
+                    Frame::Integer(0),
+                    Frame::Null,
+                    Frame::Null,
+                    Frame::Null,
+                ]);
+            };
+            let consumers = group
+                .consumers()
+                .filter(|(_, consumer)| consumer.pending_len() > 0)
+                .map(|(name, consumer)| {
+                    Frame::Array(vec![
+                        Frame::Bulk(name.clone()),
+                        Frame::Bulk(Bytes::from(consumer.pending_len().to_string())),
+                    ])
+                })
+                .collect();
+            return Frame::Array(vec![
+                Frame::Integer(pending.len() as i64),
+                id_frame(*first),
+                id_frame(*last),
+                Frame::Array(consumers),
+            ]);
+        };
+
+        if range.start > range.end {
+            return Frame::Array(vec![]);
+        }
+        let now = now_ms();
+        let entries = pending
+            .range(range.start..=range.end)
+            .filter(|(_, entry)| {
+                range
+                    .consumer
+                    .as_ref()
+                    .is_none_or(|consumer| *consumer == entry.consumer)
+            })
+            .filter(|(_, entry)| now.saturating_sub(entry.delivered_at) >= range.min_idle)
+            .take(range.count)
+            .map(|(id, entry)| {
+                Frame::Array(vec![
+                    id_frame(*id),
+                    Frame::Bulk(entry.consumer.clone()),
+                    Frame::Integer(now.saturating_sub(entry.delivered_at) as i64),
+                    Frame::Integer(entry.delivery_count as i64),
+                ])
+            })
+            .collect();
+        Frame::Array(entries)
+    }
+}
+
+impl XClaim {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XClaim> {
+        let key = parser.next_string()?;
+        let group = parser.next_bytes()?;
+        let consumer = parser.next_bytes()?;
+        let min_idle = parser.next_int()?.max(0) as u64;
+
+        // IDs come first, the first token which isn't an ID is an option
+        let mut ids = vec![];
+        let mut token = Some(parser.next_string()?);
+        while let Some(id) = token.as_deref().and_then(|id| StreamId::parse(id, 0)) {
+            ids.push(id);
+            token = next_optional(parser)?;
+        }
+        if ids.is_empty() {
+            return Err(INVALID_ID.into());
+        }
+
+        let mut options = ClaimOptions {
+            min_idle,
+            ..Default::default()
+        };
+        let mut last_id = None;
+        while let Some(option) = token {
+            match &option.to_lowercase()[..] {
+                "idle" => {
+                    let idle = parser.next_int()?.max(0) as u64;
+                    options.delivered_at = Some(now_ms().saturating_sub(idle));
+                }
+                "time" => options.delivered_at = Some(parser.next_int()?.max(0) as u64),
+                "retrycount" => options.retry_count = Some(parser.next_int()?.max(0) as u64),
+                "force" => options.force = true,
+                "justid" => options.just_id = true,
+                "lastid" => {
+                    let id = parser.next_string()?;
+                    last_id = Some(StreamId::parse(&id, 0).ok_or(INVALID_ID)?);
+                }
+                _ => return Err(format!("Unrecognized XCLAIM option '{}'", option).into()),
+            }
+            token = next_optional(parser)?;
+        }
+        Ok(XClaim {
+            key,
+            group,
+            consumer,
+            ids,
+            options,
+            last_id,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let stream = match stream_with_group(db, &self.key, &self.group) {
+            Ok(stream) => stream,
+            Err(frame) => return frame,
+        };
+        if let (Some(last_id), Some(group)) = (self.last_id, stream.group_mut(&self.group)) {
+            if last_id > group.last_delivered() {
+                group.set_last_delivered(last_id);
+            }
+        }
+        let claimed = stream
+            .claim(
+                &self.group,
+                &self.consumer,
+                &self.ids,
+                self.options,
+                now_ms(),
+            )
+            .unwrap_or_default();
+        claimed_frame(claimed.entries, self.options.just_id)
+    }
+}
+
+impl XAutoClaim {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XAutoClaim> {
+        let key = parser.next_string()?;
+        let group = parser.next_bytes()?;
+        let consumer = parser.next_bytes()?;
+        let min_idle = parser.next_int()?.max(0) as u64;
+        let start = parse_range_id(&parser.next_string()?, false)?;
+        let (mut count, mut just_id) = (100, false);
+        while let Some(option) = next_optional(parser)? {
+            match &option.to_lowercase()[..] {
+                "count" => {
+                    count = match parser.next_int()? {
+                        count if count <= 0 => {
+                            return Err("COUNT must be > 0".into());
+                        }
+                        count => count as usize,
+                    }
+                }
+                "justid" => just_id = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(XAutoClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            start,
+            count,
+            just_id,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let stream = match stream_with_group(db, &self.key, &self.group) {
+            Ok(stream) => stream,
+            Err(frame) => return frame,
+        };
+        let options = ClaimOptions {
+            min_idle: self.min_idle,
+            just_id: self.just_id,
+            ..Default::default()
+        };
+        let claimed = stream
+            .auto_claim(
+                &self.group,
+                &self.consumer,
+                self.start,
+                self.count,
+                options,
+                now_ms(),
+            )
+            .unwrap_or_default();
+        Frame::Array(vec![
+            id_frame(claimed.next),
+            claimed_frame(claimed.entries, self.just_id),
+            Frame::Array(claimed.deleted.into_iter().map(id_frame).collect()),
+        ])
+    }
+}
+
+impl XInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<XInfo> {
+        let subcommand = parser.next_string()?.to_lowercase();
+        let key = parser.next_string()?;
+        let subject = match &subcommand[..] {
+            "stream" => InfoSubject::Stream,
+            "groups" => InfoSubject::Groups,
+            "consumers" => InfoSubject::Consumers(parser.next_bytes()?),
+            _ => return Err(format!("unknown subcommand '{}'", subcommand).into()),
+        };
+        Ok(XInfo { key, subject })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let stream = match db.get_stream(&self.key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => return Frame::Error("ERR no such key".to_string()),
+            Err(e) => return e.into(),
+        };
+        let now = now_ms();
+
+        if let InfoSubject::Consumers(name) = &self.subject {
+            let Some(group) = stream.group(name) else {
+                return no_such_group(&self.key, name);
+            };
+            let consumers = group
+                .consumers()
+                .map(|(name, consumer)| {
+                    let inactive = consumer
+                        .active_at
+                        .map_or(-1, |active_at| now.saturating_sub(active_at) as i64);
+                    info_frame(vec![
+                        ("name", Frame::Bulk(name.clone())),
+                        ("pending", Frame::Integer(consumer.pending_len() as i64)),
+                        (
+                            "idle",
+                            Frame::Integer(now.saturating_sub(consumer.seen_at) as i64),
+                        ),
+                        ("inactive", Frame::Integer(inactive)),
+                    ])
+                })
+                .collect();
+            return Frame::Array(consumers);
+        }
+
+        if let InfoSubject::Groups = self.subject {
+            let groups = stream
+                .groups()
+                .map(|(name, group)| {
+                    let lag = stream.lag(group) as u64;
+                    info_frame(vec![
+                        ("name", Frame::Bulk(name.clone())),
+                        (
+                            "consumers",
+                            Frame::Integer(group.consumers().count() as i64),
+                        ),
+                        ("pending", Frame::Integer(group.pending().len() as i64)),
+                        ("last-delivered-id", id_frame(group.last_delivered())),
+                        (
+                            "entries-read",
+                            Frame::Integer(stream.entries_added().saturating_sub(lag) as i64),
+                        ),
+                        ("lag", Frame::Integer(lag as i64)),
+                    ])
+                })
+                .collect();
+            return Frame::Array(groups);
+        }
+
+        let entry = |entry: Option<(StreamId, &Fields)>| match entry {
+            Some((id, fields)) => entry_frame(id, fields.clone()),
+            None => Frame::Null,
+        };
+        info_frame(vec![
+            ("length", Frame::Integer(stream.len() as i64)),
+            ("last-generated-id", id_frame(stream.last_id())),
+            ("max-deleted-entry-id", id_frame(stream.max_deleted_id())),
+            (
+                "entries-added",
+                Frame::Integer(stream.entries_added() as i64),
+            ),
+            (
+                "recorded-first-entry-id",
+                id_frame(stream.first_entry().map_or(StreamId::MIN, |(id, _)| id)),
+            ),
+            ("groups", Frame::Integer(stream.groups().count() as i64)),
+            ("first-entry", entry(stream.first_entry())),
+            ("last-entry", entry(stream.last_entry())),
+        ])
+    }
+}
+
+/// the stream stored at key, as long as it has the group
+fn stream_with_group<'a>(
+    db: &'a mut Database,
+    key: &str,
+    group: &[u8],
+) -> std::result::Result<&'a mut Stream, Frame> {
+    match db.get_stream_mut(key) {
+        Ok(Some(stream)) if stream.group(group).is_some() => Ok(stream),
+        Ok(_) => Err(Frame::Error(format!(
+            "NOGROUP No such key '{}' or consumer group '{}'",
+            key,
+            String::from_utf8_lossy(group)
+        ))),
+        Err(WrongType) => Err(WrongType.into()),
+    }
+}
+
+fn no_such_group(key: &str, group: &[u8]) -> Frame {
+    Frame::Error(format!(
+        "NOGROUP No such consumer group '{}' for key name '{}'",
+        String::from_utf8_lossy(group),
+        key
+    ))
+}
+
+fn parse_group_id(id: &str) -> Result<GroupId> {
+    match id {
+        "$" => Ok(GroupId::Last),
+        id => StreamId::parse(id, 0)
+            .map(GroupId::Id)
+            .ok_or_else(|| INVALID_ID.into()),
+    }
+}
+
+fn parse_ids(ids: Vec<String>) -> Result<Vec<StreamId>> {
+    ids.iter()
+        .map(|id| StreamId::parse(id, 0).ok_or_else(|| INVALID_ID.into()))
+        .collect()
+}
+
+fn next_optional(parser: &mut Parser) -> Result<Option<String>> {
+    match parser.next_string() {
+        Ok(token) => Ok(Some(token)),
+        Err(ParseError::EndOfStream) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn id_frame(id: StreamId) -> Frame {
+    Frame::Bulk(Bytes::from(id.to_string()))
+}
+
+/// entries pending for a consumer, the deleted ones come with null fields
+fn history_frame(history: Vec<(StreamId, Option<Fields>)>) -> Frame {
+    let entries = history
+        .into_iter()
+        .map(|(id, fields)| match fields {
+            Some(fields) => entry_frame(id, fields),
+            None => Frame::Array(vec![id_frame(id), Frame::Null]),
+        })
+        .collect();
+    Frame::Array(entries)
+}
+
+fn claimed_frame(entries: Vec<(StreamId, Fields)>, just_id: bool) -> Frame {
+    if just_id {
+        Frame::Array(entries.into_iter().map(|(id, _)| id_frame(id)).collect())
+    } else {
+        entries_frame(entries)
+    }
+}
+
+/// XINFO replies are flat lists of names and values
+fn info_frame(fields: Vec<(&'static str, Frame)>) -> Frame {
+    let mut frames = Vec::with_capacity(fields.len() * 2);
+    for (name, value) in fields {
+        frames.push(Frame::Bulk(Bytes::from_static(name.as_bytes())));
+        frames.push(value);
+    }
+    Frame::Array(frames)
+}
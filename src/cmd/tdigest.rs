@@ -0,0 +1,356 @@
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, TDigest, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+const DEFAULT_COMPRESSION: f64 = 100.0;
+
+#[derive(Debug)]
+pub struct TDigestCreate {
+    key: String,
+    compression: f64,
+}
+
+#[derive(Debug)]
+pub struct TDigestAdd {
+    key: String,
+    values: Vec<f64>,
+}
+
+/// TDIGEST.QUANTILE and TDIGEST.CDF
+#[derive(Debug)]
+pub struct TDigestQuery {
+    key: String,
+    values: Vec<f64>,
+    cdf: bool,
+}
+
+/// TDIGEST.MIN and TDIGEST.MAX
+#[derive(Debug)]
+pub struct TDigestExtreme {
+    key: String,
+    max: bool,
+}
+
+#[derive(Debug)]
+pub struct TDigestMerge {
+    destination: String,
+    sources: Vec<String>,
+    compression: Option<f64>,
+    replace: bool,
+}
+
+#[derive(Debug)]
+pub struct TDigestReset {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct TDigestTrimmedMean {
+    key: String,
+    low: f64,
+    high: f64,
+}
+
+impl TDigestCreate {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TDigestCreate> {
+        let key = parser.next_string()?;
+        let compression = match parser.next_string() {
+            Ok(token) if token.to_lowercase() == "compression" => parse_compression(parser)?,
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => DEFAULT_COMPRESSION,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(TDigestCreate { key, compression })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if db.get(&self.key).is_some() {
+            return Frame::Error("ERR T-Digest: key already exists".to_string());
+        }
+        db.insert(
+            self.key.clone(),
+            Value::TDigest(TDigest::new(self.compression)),
+        );
+        db.notify(Class::Module, "tdigest.create", &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TDigestAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TDigestAdd> {
+        let key = parser.next_string()?;
+        let values = parse_floats(parser)?;
+        if values.iter().any(|value| value.is_infinite()) {
+            return Err("T-Digest: error parsing val parameter".into());
+        }
+        Ok(TDigestAdd { key, values })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_tdigest_mut(&self.key) {
+            Ok(Some(digest)) => {
+                for value in self.values {
+                    digest.add(value);
+                }
+                db.notify(Class::Module, "tdigest.add", &self.key);
+                Frame::into_simple("OK")
+            }
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TDigestQuery {
+    /// `cdf` tells whether the values are samples whose rank is asked (TDIGEST.CDF)
+    /// rather than quantiles
+    pub fn parse_frames(parser: &mut Parser, cdf: bool) -> Result<TDigestQuery> {
+        let key = parser.next_string()?;
+        let values = parse_floats(parser)?;
+        if !cdf && values.iter().any(|q| !(0.0..=1.0).contains(q)) {
+            return Err("T-Digest: quantile should be in [0,1]".into());
+        }
+        Ok(TDigestQuery { key, values, cdf })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let digest = match db.get_tdigest_mut(&self.key) {
+            Ok(Some(digest)) => digest,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        digest.compress();
+        let results = self
+            .values
+            .iter()
+            .map(|value| {
+                Frame::into_double(if self.cdf {
+                    digest.cdf(*value)
+                } else {
+                    digest.quantile(*value)
+                })
+            })
+            .collect();
+        Frame::Array(results)
+    }
+}
+
+impl TDigestExtreme {
+    pub fn parse_frames(parser: &mut Parser, max: bool) -> Result<TDigestExtreme> {
+        let key = parser.next_string()?;
+        Ok(TDigestExtreme { key, max })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_tdigest(&self.key) {
+            Ok(Some(digest)) if self.max => Frame::into_double(digest.max()),
+            Ok(Some(digest)) => Frame::into_double(digest.min()),
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TDigestMerge {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TDigestMerge> {
+        let destination = parser.next_string()?;
+        let numkeys = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err("T-Digest: numkeys needs to be a positive integer".into());
+        }
+        if numkeys as u64 > parser.remaining() as u64 {
+            return Err("syntax error".into());
+        }
+        let sources = (0..numkeys)
+            .map(|_| parser.next_string())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let (mut compression, mut replace) = (None, false);
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match &token[..] {
+                "compression" => compression = Some(parse_compression(parser)?),
+                "override" => replace = true,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        Ok(TDigestMerge {
+            destination,
+            sources,
+            compression,
+            replace,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the samples of the destination are kept unless OVERRIDE is given, a new
+    /// destination takes the largest compression of the sources by default
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let mut sources = Vec::with_capacity(self.sources.len());
+        for key in &self.sources {
+            match db.get_tdigest(key) {
+                Ok(Some(digest)) => sources.push(digest.clone()),
+                Ok(None) => return no_such_key(),
+                Err(e) => return e.into(),
+            }
+        }
+        let existing = match db.get_tdigest(&self.destination) {
+            Ok(digest) => digest.cloned(),
+            Err(e) => return e.into(),
+        };
+
+        let compression = self.compression.unwrap_or_else(|| match &existing {
+            Some(digest) if !self.replace => digest.compression(),
+            _ => sources.iter().map(TDigest::compression).fold(0.0, f64::max),
+        });
+        let mut merged = TDigest::new(compression);
+        if let Some(existing) = existing.filter(|_| !self.replace) {
+            merged.merge(&existing);
+        }
+        for source in &sources {
+            merged.merge(source);
+        }
+        merged.compress();
+
+        match db.get_tdigest_mut(&self.destination) {
+            // the deadline of an existing destination is kept
+            Ok(Some(digest)) => *digest = merged,
+            _ => db.insert(self.destination.clone(), Value::TDigest(merged)),
+        }
+        db.notify(Class::Module, "tdigest.merge", &self.destination);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TDigestReset {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TDigestReset> {
+        let key = parser.next_string()?;
+        Ok(TDigestReset { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_tdigest_mut(&self.key) {
+            Ok(Some(digest)) => {
+                digest.reset();
+                db.notify(Class::Module, "tdigest.reset", &self.key);
+                Frame::into_simple("OK")
+            }
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TDigestTrimmedMean {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TDigestTrimmedMean> {
+        let key = parser.next_string()?;
+        let (low, high) = (parser.next_float()?, parser.next_float()?);
+        if !(0.0..=1.0).contains(&low) || !(0.0..=1.0).contains(&high) {
+            return Err(
+                "T-Digest: low_cut_percentile and high_cut_percentile should be in [0,1]".into(),
+            );
+        }
+        if low >= high {
+            return Err(
+                "T-Digest: low_cut_percentile should be lower than high_cut_percentile".into(),
+            );
+        }
+        Ok(TDigestTrimmedMean { key, low, high })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_tdigest_mut(&self.key) {
+            Ok(Some(digest)) => {
+                digest.compress();
+                Frame::into_double(digest.trimmed_mean(self.low, self.high))
+            }
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+fn parse_compression(parser: &mut Parser) -> Result<f64> {
+    match parser.next_int() {
+        Ok(compression) if compression > 0 => Ok(compression as f64),
+        _ => Err("T-Digest: error parsing compression parameter".into()),
+    }
+}
+
+/// every remaining argument as a float, at least one is required
+fn parse_floats(parser: &mut Parser) -> Result<Vec<f64>> {
+    let mut values = vec![parser.next_float()?];
+    loop {
+        match parser.next_float() {
+            Ok(value) => values.push(value),
+            Err(ParseError::EndOfStream) => return Ok(values),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR T-Digest: key does not exist".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Command;
+
+    #[test]
+    fn tdigest_merge_numkeys_test() {
+        // the count isn't trusted to allocate the keys
+        let words = ["TDIGEST.MERGE", "dest", "100000000000", "a"];
+        let e = Command::try_parse(&words).map(|_| ()).unwrap_err();
+        assert_eq!(e.to_string(), "syntax error");
+        assert!(Command::try_parse(&["TDIGEST.MERGE", "dest", "2", "a", "b"]).is_ok());
+    }
+}
@@ -0,0 +1,830 @@
+use bytes::Bytes;
+use std::{iter::Peekable, vec};
+use tracing::instrument;
+
+use crate::{
+    db::{
+        now_ms, Aggregation, Class, CompactionRule, Database, DuplicatePolicy, SampleError,
+        TimeSeries, Value,
+    },
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+/// the arguments after the fixed ones, their options come in any order
+type Args = Peekable<vec::IntoIter<String>>;
+
+/// TS.CREATE and TS.ALTER
+#[derive(Debug)]
+pub struct TsCreate {
+    key: String,
+    options: SeriesOptions,
+    alter: bool,
+}
+
+#[derive(Debug)]
+pub struct TsAdd {
+    key: String,
+    timestamp: u64,
+    value: f64,
+    options: SeriesOptions,
+    on_duplicate: Option<DuplicatePolicy>,
+}
+
+#[derive(Debug)]
+pub struct TsMAdd {
+    samples: Vec<(String, u64, f64)>,
+}
+
+#[derive(Debug)]
+pub struct TsGet {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct TsDel {
+    key: String,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug)]
+pub struct TsCreateRule {
+    source: String,
+    destination: String,
+    aggregation: Aggregation,
+    bucket: u64,
+}
+
+#[derive(Debug)]
+pub struct TsDeleteRule {
+    source: String,
+    destination: String,
+}
+
+/// TS.RANGE and TS.REVRANGE
+#[derive(Debug)]
+pub struct TsRange {
+    key: String,
+    range: RangeOptions,
+}
+
+/// TS.MRANGE and TS.MREVRANGE
+#[derive(Debug)]
+pub struct TsMRange {
+    range: RangeOptions,
+    labels: LabelsReply,
+    filters: Vec<LabelFilter>,
+}
+
+#[derive(Debug)]
+pub struct TsQueryIndex {
+    filters: Vec<LabelFilter>,
+}
+
+#[derive(Debug)]
+pub struct TsInfo {
+    key: String,
+}
+
+/// RETENTION, DUPLICATE_POLICY and LABELS, which take every argument after them
+#[derive(Debug, Default)]
+struct SeriesOptions {
+    retention: Option<u64>,
+    duplicate_policy: Option<DuplicatePolicy>,
+    labels: Option<Vec<(String, String)>>,
+}
+
+#[derive(Debug)]
+struct RangeOptions {
+    from: u64,
+    to: u64,
+    timestamps: Option<Vec<u64>>,
+    values: Option<(f64, f64)>,
+    count: Option<usize>,
+    aggregation: Option<(Aggregation, u64)>,
+    rev: bool,
+}
+
+/// the labels replied with every series by TS.MRANGE
+#[derive(Debug)]
+enum LabelsReply {
+    None,
+    All,
+    Selected(Vec<String>),
+}
+
+/// `label=value` matches the series with one of the values, `label=(a,b)` is a list of
+/// values, and `label=` matches the series without the label. `!=` negates the match
+#[derive(Debug)]
+struct LabelFilter {
+    label: String,
+    values: Vec<String>,
+    equal: bool,
+}
+
+impl TsCreate {
+    /// `alter` tells whether the options change an existing series (TS.ALTER)
+    pub fn parse_frames(parser: &mut Parser, alter: bool) -> Result<TsCreate> {
+        let key = parser.next_string()?;
+        let mut args = remaining(parser)?;
+        let mut options = SeriesOptions::default();
+        while let Some(token) = args.next() {
+            if !options.parse(&token, &mut args)? {
+                return Err(format!("syntax error near '{}'", token).into());
+            }
+        }
+        Ok(TsCreate {
+            key,
+            options,
+            alter,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let options = self.options;
+        if self.alter {
+            match db.get_timeseries_mut(&self.key) {
+                Ok(Some(series)) => {
+                    series.alter(options.retention, options.duplicate_policy, options.labels)
+                }
+                Ok(None) => return no_such_key(),
+                Err(e) => return e.into(),
+            }
+        } else {
+            if db.get(&self.key).is_some() {
+                return Frame::Error("ERR TSDB: key already exists".to_string());
+            }
+            db.insert(self.key.clone(), Value::TimeSeries(options.create()));
+        }
+        let event = if self.alter { "ts.alter" } else { "ts.create" };
+        db.notify(Class::Module, event, &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TsAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsAdd> {
+        let key = parser.next_string()?;
+        let timestamp = match &parser.next_string()?[..] {
+            "*" => now_ms(),
+            timestamp => parse_timestamp(timestamp)?,
+        };
+        let value = parser.next_float()?;
+        let mut args = remaining(parser)?;
+        let mut options = SeriesOptions::default();
+        let mut on_duplicate = None;
+        while let Some(token) = args.next() {
+            if token.to_lowercase() == "on_duplicate" {
+                on_duplicate = Some(parse_policy(&mut args)?);
+            } else if !options.parse(&token, &mut args)? {
+                return Err(format!("syntax error near '{}'", token).into());
+            }
+        }
+        Ok(TsAdd {
+            key,
+            timestamp,
+            value,
+            options,
+            on_duplicate,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// a missing series is created with the options, which are ignored otherwise
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_timeseries(&self.key) {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                db.insert(self.key.clone(), Value::TimeSeries(self.options.create()));
+                db.notify(Class::Module, "ts.create", &self.key);
+            }
+            Err(e) => return e.into(),
+        }
+        add_sample(db, &self.key, self.timestamp, self.value, self.on_duplicate)
+    }
+}
+
+impl TsMAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsMAdd> {
+        let mut samples = vec![];
+        loop {
+            let key = match parser.next_string() {
+                Ok(key) => key,
+                Err(ParseError::EndOfStream) if !samples.is_empty() => break,
+                Err(e) => return Err(e.into()),
+            };
+            let timestamp = match &parser.next_string()?[..] {
+                "*" => now_ms(),
+                timestamp => parse_timestamp(timestamp)?,
+            };
+            samples.push((key, timestamp, parser.next_float()?));
+        }
+        Ok(TsMAdd { samples })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// every sample is replied with its timestamp or the error which refused it
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let replies = self
+            .samples
+            .into_iter()
+            .map(|(key, timestamp, value)| add_sample(db, &key, timestamp, value, None))
+            .collect();
+        Frame::Array(replies)
+    }
+}
+
+impl TsGet {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsGet> {
+        let key = parser.next_string()?;
+        Ok(TsGet { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the last sample, an empty series replies an empty array
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_timeseries(&self.key) {
+            Ok(Some(series)) => match series.last() {
+                Some(sample) => sample_frame(sample),
+                None => Frame::Array(vec![]),
+            },
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TsDel {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsDel> {
+        let key = parser.next_string()?;
+        let from = parse_bound(&parser.next_string()?)?;
+        let to = parse_bound(&parser.next_string()?)?;
+        Ok(TsDel { key, from, to })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_timeseries_mut(&self.key) {
+            Ok(Some(series)) => {
+                let deleted = series.delete_range(self.from, self.to);
+                if deleted > 0 {
+                    db.notify(Class::Module, "ts.del", &self.key);
+                }
+                Frame::Integer(deleted as i64)
+            }
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TsCreateRule {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsCreateRule> {
+        let source = parser.next_string()?;
+        let destination = parser.next_string()?;
+        let mut args = remaining(parser)?;
+        match args.next() {
+            Some(token) if token.to_lowercase() == "aggregation" => {}
+            _ => return Err("TSDB: AGGREGATION is required".into()),
+        }
+        let (aggregation, bucket) = parse_aggregation(&mut args)?;
+        if let Some(token) = args.next() {
+            return Err(format!("syntax error near '{}'", token).into());
+        }
+        Ok(TsCreateRule {
+            source,
+            destination,
+            aggregation,
+            bucket,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// compactions aren't chained: a source can't be a destination and the other way round
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if self.source == self.destination {
+            return Frame::Error(
+                "ERR TSDB: the source key and destination key should be different".to_string(),
+            );
+        }
+        let (source, destination) = match (
+            db.get_timeseries(&self.source),
+            db.get_timeseries(&self.destination),
+        ) {
+            (Ok(Some(source)), Ok(Some(destination))) => (source, destination),
+            (Err(e), _) | (_, Err(e)) => return e.into(),
+            _ => return no_such_key(),
+        };
+        if destination.source().is_some() {
+            return Frame::Error(
+                "ERR TSDB: the destination key already has a src rule".to_string(),
+            );
+        }
+        if source.source().is_some() || !destination.rules().is_empty() {
+            return Frame::Error("ERR TSDB: compaction rules can't be chained".to_string());
+        }
+
+        if let Ok(Some(destination)) = db.get_timeseries_mut(&self.destination) {
+            destination.set_source(Some(self.source.clone()));
+        }
+        if let Ok(Some(source)) = db.get_timeseries_mut(&self.source) {
+            let rule = CompactionRule::new(self.destination.clone(), self.aggregation, self.bucket);
+            source.add_rule(rule);
+        }
+        db.notify(Class::Module, "ts.createrule", &self.source);
+        db.notify(Class::Module, "ts.createrule", &self.destination);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TsDeleteRule {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsDeleteRule> {
+        let source = parser.next_string()?;
+        let destination = parser.next_string()?;
+        Ok(TsDeleteRule {
+            source,
+            destination,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_timeseries_mut(&self.source) {
+            Ok(Some(source)) => {
+                if !source.remove_rule(&self.destination) {
+                    return Frame::Error("ERR TSDB: compaction rule does not exist".to_string());
+                }
+            }
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        }
+        if let Ok(Some(destination)) = db.get_timeseries_mut(&self.destination) {
+            destination.set_source(None);
+        }
+        db.notify(Class::Module, "ts.deleterule", &self.source);
+        db.notify(Class::Module, "ts.deleterule", &self.destination);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TsRange {
+    /// `rev` tells whether the samples are replied from the latest (TS.REVRANGE)
+    pub fn parse_frames(parser: &mut Parser, rev: bool) -> Result<TsRange> {
+        let key = parser.next_string()?;
+        let mut range = RangeOptions::parse_bounds(parser, rev)?;
+        let mut args = remaining(parser)?;
+        while let Some(token) = args.next() {
+            if !range.parse(&token, &mut args)? {
+                return Err(format!("syntax error near '{}'", token).into());
+            }
+        }
+        Ok(TsRange { key, range })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_timeseries(&self.key) {
+            Ok(Some(series)) => samples_frame(self.range.query(series)),
+            Ok(None) => no_such_key(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl TsMRange {
+    /// `rev` tells whether the samples are replied from the latest (TS.MREVRANGE)
+    pub fn parse_frames(parser: &mut Parser, rev: bool) -> Result<TsMRange> {
+        let mut range = RangeOptions::parse_bounds(parser, rev)?;
+        let mut args = remaining(parser)?;
+        let mut labels = LabelsReply::None;
+        let mut filters = vec![];
+        while let Some(token) = args.next() {
+            match &token.to_lowercase()[..] {
+                "withlabels" => labels = LabelsReply::All,
+                "selected_labels" => {
+                    let mut selected = vec![];
+                    while args
+                        .peek()
+                        .is_some_and(|arg| arg.to_lowercase() != "filter")
+                    {
+                        selected.extend(args.next());
+                    }
+                    labels = LabelsReply::Selected(selected);
+                }
+                "filter" => filters = parse_filters(&mut args)?,
+                _ if range.parse(&token, &mut args)? => {}
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        if filters.is_empty() {
+            return Err("TSDB: FILTER is required".into());
+        }
+        Ok(TsMRange {
+            range,
+            labels,
+            filters,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// every matching series is replied as its key, its labels and its samples
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let series = db
+            .all_timeseries()
+            .into_iter()
+            .filter(|(_, series)| self.filters.iter().all(|filter| filter.matches(series)))
+            .map(|(key, series)| {
+                let labels = match &self.labels {
+                    LabelsReply::None => vec![],
+                    LabelsReply::All => series
+                        .labels()
+                        .iter()
+                        .map(|(name, value)| label_frame(name, Some(value)))
+                        .collect(),
+                    LabelsReply::Selected(names) => names
+                        .iter()
+                        .map(|name| label_frame(name, series.label(name)))
+                        .collect(),
+                };
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(key.clone())),
+                    Frame::Array(labels),
+                    samples_frame(self.range.query(series)),
+                ])
+            })
+            .collect();
+        Frame::Array(series)
+    }
+}
+
+impl TsQueryIndex {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsQueryIndex> {
+        let filters = parse_filters(&mut remaining(parser)?)?;
+        Ok(TsQueryIndex { filters })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let keys = db
+            .all_timeseries()
+            .into_iter()
+            .filter(|(_, series)| self.filters.iter().all(|filter| filter.matches(series)))
+            .map(|(key, _)| Frame::Bulk(Bytes::from(key.clone())))
+            .collect();
+        Frame::Array(keys)
+    }
+}
+
+impl TsInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TsInfo> {
+        let key = parser.next_string()?;
+        Ok(TsInfo { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let series = match db.get_timeseries(&self.key) {
+            Ok(Some(series)) => series,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let timestamp = |sample: Option<(u64, f64)>| {
+            Frame::Integer(sample.map_or(0, |(timestamp, _)| timestamp as i64))
+        };
+        let labels = series
+            .labels()
+            .iter()
+            .map(|(name, value)| label_frame(name, Some(value)))
+            .collect();
+        let rules = series
+            .rules()
+            .iter()
+            .map(|rule| {
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from(rule.destination.clone())),
+                    Frame::Integer(rule.bucket as i64),
+                    Frame::Simple(rule.aggregation.name().to_uppercase()),
+                ])
+            })
+            .collect();
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"totalSamples")),
+            Frame::Integer(series.len() as i64),
+            Frame::Bulk(Bytes::from_static(b"firstTimestamp")),
+            timestamp(series.first()),
+            Frame::Bulk(Bytes::from_static(b"lastTimestamp")),
+            timestamp(series.last()),
+            Frame::Bulk(Bytes::from_static(b"retentionTime")),
+            Frame::Integer(series.retention() as i64),
+            Frame::Bulk(Bytes::from_static(b"duplicatePolicy")),
+            Frame::Bulk(Bytes::from_static(
+                series.duplicate_policy().name().as_bytes(),
+            )),
+            Frame::Bulk(Bytes::from_static(b"labels")),
+            Frame::Array(labels),
+            Frame::Bulk(Bytes::from_static(b"sourceKey")),
+            series.source().map_or(Frame::Null, |source| {
+                Frame::Bulk(Bytes::from(source.to_string()))
+            }),
+            Frame::Bulk(Bytes::from_static(b"rules")),
+            Frame::Array(rules),
+        ])
+    }
+}
+
+impl SeriesOptions {
+    /// parse the option starting with `token`, false when it isn't one of them
+    fn parse(&mut self, token: &str, args: &mut Args) -> Result<bool> {
+        match &token.to_lowercase()[..] {
+            "retention" => match next(args)?.parse() {
+                Ok(retention) => self.retention = Some(retention),
+                Err(_) => return Err("TSDB: invalid retention".into()),
+            },
+            "duplicate_policy" => self.duplicate_policy = Some(parse_policy(args)?),
+            "labels" => {
+                let mut labels = vec![];
+                while let Some(name) = args.next() {
+                    labels.push((name, next(args)?));
+                }
+                self.labels = Some(labels);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// a series keeps every sample and refuses duplicates by default
+    fn create(self) -> TimeSeries {
+        TimeSeries::new(
+            self.retention.unwrap_or(0),
+            self.duplicate_policy.unwrap_or(DuplicatePolicy::Block),
+            self.labels.unwrap_or_default(),
+        )
+    }
+}
+
+impl RangeOptions {
+    fn parse_bounds(parser: &mut Parser, rev: bool) -> Result<RangeOptions> {
+        let from = parse_bound(&parser.next_string()?)?;
+        let to = parse_bound(&parser.next_string()?)?;
+        Ok(RangeOptions {
+            from,
+            to,
+            timestamps: None,
+            values: None,
+            count: None,
+            aggregation: None,
+            rev,
+        })
+    }
+
+    /// parse the option starting with `token`, false when it isn't one of them
+    fn parse(&mut self, token: &str, args: &mut Args) -> Result<bool> {
+        match &token.to_lowercase()[..] {
+            "filter_by_ts" => {
+                let mut timestamps = vec![];
+                while let Some(timestamp) = args.peek().and_then(|arg| arg.parse().ok()) {
+                    args.next();
+                    timestamps.push(timestamp);
+                }
+                self.timestamps = Some(timestamps);
+            }
+            "filter_by_value" => {
+                let mut bound = || -> Result<f64> {
+                    next(args)?
+                        .parse()
+                        .map_err(|_| "TSDB: cannot parse value filter".into())
+                };
+                self.values = Some((bound()?, bound()?));
+            }
+            "count" => match next(args)?.parse() {
+                Ok(count) => self.count = Some(count),
+                Err(_) => return Err("TSDB: invalid COUNT".into()),
+            },
+            "aggregation" => self.aggregation = Some(parse_aggregation(args)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// the samples are filtered before they are aggregated, COUNT limits the reply
+    fn query(&self, series: &TimeSeries) -> Vec<(u64, f64)> {
+        let mut samples = series.range(self.from, self.to);
+        if let Some(timestamps) = &self.timestamps {
+            samples.retain(|(timestamp, _)| timestamps.contains(timestamp));
+        }
+        if let Some((min, max)) = self.values {
+            samples.retain(|(_, value)| (min..=max).contains(value));
+        }
+        if let Some((aggregation, bucket)) = self.aggregation {
+            samples = aggregation.by_buckets(&samples, bucket);
+        }
+        if self.rev {
+            samples.reverse();
+        }
+        if let Some(count) = self.count {
+            samples.truncate(count);
+        }
+        samples
+    }
+}
+
+impl LabelFilter {
+    fn parse(filter: &str) -> Result<LabelFilter> {
+        let (label, value, equal) = match filter.split_once("!=") {
+            Some((label, value)) => (label, value, false),
+            None => match filter.split_once('=') {
+                Some((label, value)) => (label, value, true),
+                None => {
+                    return Err(format!("TSDB: failed parsing labels filter '{}'", filter).into())
+                }
+            },
+        };
+        let values = match value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+            Some(list) => list.split(',').map(str::to_string).collect(),
+            None => vec![value.to_string()],
+        };
+        Ok(LabelFilter {
+            label: label.to_string(),
+            values,
+            equal,
+        })
+    }
+
+    fn matches(&self, series: &TimeSeries) -> bool {
+        let value = series.label(&self.label).unwrap_or_default();
+        self.values.iter().any(|v| v == value) == self.equal
+    }
+}
+
+/// add a sample to an existing series, the buckets it closes are written to the
+/// destinations of the compaction rules
+fn add_sample(
+    db: &mut Database,
+    key: &str,
+    timestamp: u64,
+    value: f64,
+    policy: Option<DuplicatePolicy>,
+) -> Frame {
+    let closed = match db.get_timeseries_mut(key) {
+        Ok(Some(series)) => series.add(timestamp, value, policy),
+        Ok(None) => return no_such_key(),
+        Err(e) => return e.into(),
+    };
+    match closed {
+        Ok(closed) => {
+            db.notify(Class::Module, "ts.add", key);
+            for (destination, timestamp, value) in closed {
+                if let Ok(Some(series)) = db.get_timeseries_mut(&destination) {
+                    series.upsert(timestamp, value);
+                    db.notify(Class::Module, "ts.add", &destination);
+                }
+            }
+            Frame::Integer(timestamp as i64)
+        }
+        Err(SampleError::TooOld) => {
+            Frame::Error("ERR TSDB: Timestamp is older than retention".to_string())
+        }
+        Err(SampleError::Duplicate) => Frame::Error(
+            "ERR TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode"
+                .to_string(),
+        ),
+    }
+}
+
+/// at least one filter must match series by the value of a label
+fn parse_filters(args: &mut Args) -> Result<Vec<LabelFilter>> {
+    let filters = args
+        .map(|filter| LabelFilter::parse(&filter))
+        .collect::<Result<Vec<_>>>()?;
+    if !filters
+        .iter()
+        .any(|filter| filter.equal && filter.values.iter().any(|value| !value.is_empty()))
+    {
+        return Err("TSDB: please provide at least one matcher".into());
+    }
+    Ok(filters)
+}
+
+fn parse_aggregation(args: &mut Args) -> Result<(Aggregation, u64)> {
+    let Some(aggregation) = Aggregation::parse(&next(args)?) else {
+        return Err("TSDB: Unknown aggregation type".into());
+    };
+    match next(args)?.parse() {
+        Ok(bucket) if bucket > 0 => Ok((aggregation, bucket)),
+        _ => Err("TSDB: bucketDuration must be greater than zero".into()),
+    }
+}
+
+fn parse_policy(args: &mut Args) -> Result<DuplicatePolicy> {
+    DuplicatePolicy::parse(&next(args)?).ok_or_else(|| "TSDB: Unknown DUPLICATE_POLICY".into())
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<u64> {
+    timestamp
+        .parse()
+        .map_err(|_| "TSDB: invalid timestamp".into())
+}
+
+/// `-` and `+` are the earliest and latest timestamps
+fn parse_bound(bound: &str) -> Result<u64> {
+    match bound {
+        "-" => Ok(0),
+        "+" => Ok(u64::MAX),
+        timestamp => parse_timestamp(timestamp),
+    }
+}
+
+fn remaining(parser: &mut Parser) -> Result<Args> {
+    match parser.remaining_strings() {
+        Ok(args) => Ok(args.into_iter().peekable()),
+        Err(ParseError::EndOfStream) => Ok(vec![].into_iter().peekable()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn next(args: &mut Args) -> Result<String> {
+    args.next()
+        .ok_or_else(|| "wrong number of arguments".into())
+}
+
+fn sample_frame((timestamp, value): (u64, f64)) -> Frame {
+    Frame::Array(vec![
+        Frame::Integer(timestamp as i64),
+        Frame::into_double(value),
+    ])
+}
+
+fn samples_frame(samples: Vec<(u64, f64)>) -> Frame {
+    Frame::Array(samples.into_iter().map(sample_frame).collect())
+}
+
+fn label_frame(name: &str, value: Option<&str>) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(name.to_string())),
+        value.map_or(Frame::Null, |value| {
+            Frame::Bulk(Bytes::from(value.to_string()))
+        }),
+    ])
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR TSDB: the key does not exist".to_string())
+}
@@ -0,0 +1,211 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, TopK, Value},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+const DEFAULT_WIDTH: i64 = 8;
+const DEFAULT_DEPTH: i64 = 7;
+const DEFAULT_DECAY: f64 = 0.9;
+/// every unit of an increment may decay a counter, which bounds the work of TOPK.INCRBY
+const MAX_INCREMENT: i64 = 100_000;
+
+#[derive(Debug)]
+pub struct TopKReserve {
+    key: String,
+    k: usize,
+    width: usize,
+    depth: usize,
+    decay: f64,
+}
+
+/// TOPK.ADD and TOPK.INCRBY
+#[derive(Debug)]
+pub struct TopKAdd {
+    key: String,
+    increments: Vec<(Bytes, u32)>,
+}
+
+#[derive(Debug)]
+pub struct TopKQuery {
+    key: String,
+    items: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct TopKList {
+    key: String,
+    with_count: bool,
+}
+
+impl TopKReserve {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TopKReserve> {
+        let key = parser.next_string()?;
+        let k = parser.next_int()?;
+        if k <= 0 {
+            return Err("TopK: invalid k".into());
+        }
+        let (width, depth, decay) = match parser.next_int() {
+            Ok(width) => (width, parser.next_int()?, parser.next_float()?),
+            Err(ParseError::EndOfStream) => (DEFAULT_WIDTH, DEFAULT_DEPTH, DEFAULT_DECAY),
+            Err(e) => return Err(e.into()),
+        };
+        if width <= 0 {
+            return Err("TopK: invalid width".into());
+        }
+        if depth <= 0 {
+            return Err("TopK: invalid depth".into());
+        }
+        if !(decay > 0.0 && decay <= 1.0) {
+            return Err("TopK: invalid decay value. must be '<= 1' & '> 0'".into());
+        }
+        Ok(TopKReserve {
+            key,
+            k: k as usize,
+            width: width as usize,
+            depth: depth as usize,
+            decay,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        if db.get(&self.key).is_some() {
+            return Frame::Error("ERR TopK: key already exists".to_string());
+        }
+        let topk = match TopK::new(self.k, self.width, self.depth, self.decay) {
+            Ok(topk) => topk,
+            Err(e) => return Frame::Error(format!("ERR {}", e)),
+        };
+        db.insert(self.key.clone(), Value::TopK(topk));
+        db.notify(Class::Module, "topk.reserve", &self.key);
+        Frame::into_simple("OK")
+    }
+}
+
+impl TopKAdd {
+    /// `with_increments` tells whether every item is followed by its increment (TOPK.INCRBY)
+    pub fn parse_frames(parser: &mut Parser, with_increments: bool) -> Result<TopKAdd> {
+        let key = parser.next_string()?;
+        let mut increments = vec![];
+        loop {
+            let item = match parser.next_bytes() {
+                Ok(item) => item,
+                Err(ParseError::EndOfStream) if !increments.is_empty() => break,
+                Err(e) => return Err(e.into()),
+            };
+            let increment = if with_increments {
+                parser.next_int()?
+            } else {
+                1
+            };
+            if !(0..=MAX_INCREMENT).contains(&increment) {
+                return Err(
+                    "TopK: increment must be an integer greater or equal to 0 and smaller or equal to 100000"
+                        .into(),
+                );
+            }
+            increments.push((item, increment as u32));
+        }
+        Ok(TopKAdd { key, increments })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let topk = match db.get_topk_mut(&self.key) {
+            Ok(Some(topk)) => topk,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let expelled = self
+            .increments
+            .iter()
+            .map(|(item, increment)| match topk.add(item, *increment) {
+                Some(expelled) => Frame::Bulk(expelled),
+                None => Frame::Null,
+            })
+            .collect();
+        db.notify(Class::Module, "topk.add", &self.key);
+        Frame::Array(expelled)
+    }
+}
+
+impl TopKQuery {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TopKQuery> {
+        let key = parser.next_string()?;
+        let items = parser.remaining_bytes()?;
+        Ok(TopKQuery { key, items })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let topk = match db.get_topk(&self.key) {
+            Ok(Some(topk)) => topk,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let found = self
+            .items
+            .iter()
+            .map(|item| Frame::Integer(topk.contains(item) as i64))
+            .collect();
+        Frame::Array(found)
+    }
+}
+
+impl TopKList {
+    pub fn parse_frames(parser: &mut Parser) -> Result<TopKList> {
+        let key = parser.next_string()?;
+        let with_count = match parser.next_string() {
+            Ok(token) if token.to_lowercase() == "withcount" => true,
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(TopKList { key, with_count })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let topk = match db.get_topk(&self.key) {
+            Ok(Some(topk)) => topk,
+            Ok(None) => return no_such_key(),
+            Err(e) => return e.into(),
+        };
+        let mut frames = vec![];
+        for (item, count) in topk.list() {
+            frames.push(Frame::Bulk(item));
+            if self.with_count {
+                frames.push(Frame::Integer(count as i64));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
+
+fn no_such_key() -> Frame {
+    Frame::Error("ERR TopK: key does not exist".to_string())
+}
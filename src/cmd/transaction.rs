@@ -0,0 +1,253 @@
+use tracing::instrument;
+
+use crate::{cmd::Command, db::Database, parser::Parser, Connection, DbHolder, Frame, Result};
+
+/// MULTI, the commands which follow are queued until EXEC or DISCARD
+#[derive(Debug)]
+pub struct Multi;
+
+/// EXEC, apply the queued commands at once
+#[derive(Debug)]
+pub struct Exec;
+
+/// DISCARD, drop the queued commands
+#[derive(Debug)]
+pub struct Discard;
+
+/// WATCH, EXEC fails if one of the keys is modified in the meantime
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+/// UNWATCH, forget the watched keys
+#[derive(Debug)]
+pub struct Unwatch;
+
+/// the commands a client has queued since MULTI
+#[derive(Default)]
+pub(crate) struct Transaction {
+    commands: Vec<Command>,
+    /// a command has been rejected while queuing, EXEC discards the transaction
+    aborted: bool,
+}
+
+impl Multi {
+    /// the queue itself is kept by the connection handler
+    #[instrument(skip(connection))]
+    pub async fn execute(self, connection: &mut Connection) -> Result<()> {
+        connection.write_frame(Frame::into_simple("OK")).await
+    }
+}
+
+impl Exec {
+    /// within a transaction EXEC is handled by the connection handler
+    #[instrument(skip(connection))]
+    pub async fn execute(self, connection: &mut Connection) -> Result<()> {
+        let error = Frame::Error("ERR EXEC without MULTI".to_string());
+        connection.write_frame(error).await
+    }
+}
+
+impl Discard {
+    /// within a transaction DISCARD is handled by the connection handler
+    #[instrument(skip(connection))]
+    pub async fn execute(self, connection: &mut Connection) -> Result<()> {
+        let error = Frame::Error("ERR DISCARD without MULTI".to_string());
+        connection.write_frame(error).await
+    }
+}
+
+impl Watch {
+    pub fn parse_frames(parser: &mut Parser) -> Result<Watch> {
+        let keys = parser.remaining_strings()?;
+        Ok(Watch { keys })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        db.watch(self.keys);
+        Frame::into_simple("OK")
+    }
+}
+
+impl Unwatch {
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        db.unwatch(db.client());
+        Frame::into_simple("OK")
+    }
+}
+
+impl Transaction {
+    /// reply QUEUED, or an error if the command can't be part of a transaction
+    pub(crate) fn queue(&mut self, cmd: Command) -> Frame {
+        match cmd {
+            Command::Multi(_) => self.reject("ERR MULTI calls can not be nested".to_string()),
+            Command::Watch(_) => self.reject("ERR WATCH inside MULTI is not allowed".to_string()),
+            cmd if cmd.is_connection_bound() => {
+                self.reject("ERR Command not allowed inside a transaction".to_string())
+            }
+            cmd => {
+                self.commands.push(cmd);
+                Frame::into_simple("QUEUED")
+            }
+        }
+    }
+
+    /// a command which couldn't be queued, such as one with a syntax error,
+    /// makes EXEC discard the whole transaction
+    pub(crate) fn reject(&mut self, error: String) -> Frame {
+        self.aborted = true;
+        Frame::Error(error)
+    }
+
+    /// apply the queued commands under a single lock, so that no other client
+    /// sees the database in between. their replies are gathered in an array, or
+    /// the reply is null if a watched key has been touched
+    pub(crate) fn exec(self, db: &DbHolder) -> Frame {
+        let client = db.client();
+        let mut db = db.lock();
+        let touched = db.unwatch(client);
+        if self.aborted {
+            return Frame::Error(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            );
+        }
+        if touched {
+            return Frame::Null;
+        }
+        let replies = self
+            .commands
+            .into_iter()
+            .map(|cmd| cmd.apply(&mut db))
+            .collect();
+        Frame::Array(replies)
+    }
+
+    /// DISCARD drops the watched keys along with the queue
+    pub(crate) fn discard(self, db: &DbHolder) -> Frame {
+        db.lock().unwatch(db.client());
+        Frame::into_simple("OK")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use bytes::Bytes;
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::*;
+
+    fn holder() -> DbHolder {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx)
+    }
+
+    fn bulk(value: &'static str) -> Frame {
+        Frame::Bulk(Bytes::from_static(value.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn queue_test() {
+        let server = holder();
+        let (db, _pushes) = server.connect();
+        let mut transaction = Transaction::default();
+        let queued = Frame::into_simple("QUEUED");
+        assert_eq!(
+            transaction.queue(Command::parse(&["SET", "k", "v"])),
+            queued
+        );
+        assert_eq!(transaction.queue(Command::parse(&["GET", "k"])), queued);
+        // nothing is applied before EXEC
+        assert_eq!(server.get("k").unwrap(), None);
+        let replies = Frame::Array(vec![
+            Frame::into_simple("OK"),
+            Frame::Array(vec![bulk("v")]),
+        ]);
+        assert_eq!(transaction.exec(&db), replies);
+
+        let mut transaction = Transaction::default();
+        transaction.queue(Command::parse(&["SET", "k", "w"]));
+        assert_eq!(transaction.discard(&db), Frame::into_simple("OK"));
+        assert_eq!(server.get("k").unwrap(), Some(Bytes::from_static(b"v")));
+    }
+
+    #[tokio::test]
+    async fn reject_test() {
+        let (db, _pushes) = holder().connect();
+        for words in [
+            &["MULTI"][..],
+            &["WATCH", "k"],
+            &["SUBSCRIBE", "c"],
+            &["RESET"],
+        ] {
+            let mut transaction = Transaction::default();
+            transaction.queue(Command::parse(&["SET", "k", "v"]));
+            let reply = transaction.queue(Command::parse(words));
+            assert!(matches!(reply, Frame::Error(e) if e.starts_with("ERR")));
+            let reply = transaction.exec(&db);
+            assert!(matches!(reply, Frame::Error(e) if e.starts_with("EXECABORT")));
+        }
+        // the queued commands of an aborted transaction aren't applied
+        assert_eq!(db.get("k").unwrap(), None);
+
+        let mut transaction = Transaction::default();
+        transaction.reject("ERR wrong number of arguments".to_string());
+        transaction.queue(Command::parse(&["SET", "k", "v"]));
+        assert!(matches!(transaction.exec(&db), Frame::Error(_)));
+        assert_eq!(db.get("k").unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn single_lock_test() {
+        let server = holder();
+        let (db, _pushes) = server.connect();
+        let done = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let (server, done) = (server.clone(), done.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    server
+                        .set("k".to_string(), Bytes::from_static(b"other"), None)
+                        .unwrap();
+                }
+            })
+        };
+        // no write of another client comes between the commands of a transaction
+        for _ in 0..1000 {
+            let mut transaction = Transaction::default();
+            transaction.queue(Command::parse(&["SET", "k", "mine"]));
+            transaction.queue(Command::parse(&["GET", "k"]));
+            let reply = transaction.exec(&db);
+            assert_eq!(
+                reply,
+                Frame::Array(vec![
+                    Frame::into_simple("OK"),
+                    Frame::Array(vec![bulk("mine")])
+                ])
+            );
+        }
+        done.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,617 @@
+use bytes::Bytes;
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, Metric, Quantization, Value, VectorSet},
+    filter::Expr,
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 200;
+const DEFAULT_EF: usize = 100;
+const DEFAULT_COUNT: usize = 10;
+/// the nodes a filtered search visits by result before giving up
+const DEFAULT_FILTER_EF: usize = 100;
+
+#[derive(Debug)]
+pub struct VAdd {
+    key: String,
+    vector: Vec<f32>,
+    element: Bytes,
+    quantization: Option<Quantization>,
+    metric: Option<Metric>,
+    m: Option<usize>,
+    ef: Option<usize>,
+    /// an empty string removes the attributes
+    attributes: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct VSim {
+    key: String,
+    query: VQuery,
+    with_scores: bool,
+    with_attributes: bool,
+    count: usize,
+    epsilon: Option<f64>,
+    ef: usize,
+    filter: Option<String>,
+    filter_ef: Option<usize>,
+    truth: bool,
+}
+
+/// the vector VSIM looks for the neighbours of
+#[derive(Debug)]
+enum VQuery {
+    Vector(Vec<f32>),
+    Element(Bytes),
+}
+
+#[derive(Debug)]
+pub struct VRem {
+    key: String,
+    element: Bytes,
+}
+
+#[derive(Debug)]
+pub struct VCard {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct VDim {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct VEmb {
+    key: String,
+    element: Bytes,
+}
+
+#[derive(Debug)]
+pub struct VGetAttr {
+    key: String,
+    element: Bytes,
+}
+
+#[derive(Debug)]
+pub struct VSetAttr {
+    key: String,
+    element: Bytes,
+    attributes: String,
+}
+
+#[derive(Debug)]
+pub struct VInfo {
+    key: String,
+}
+
+#[derive(Debug)]
+pub struct VLinks {
+    key: String,
+    element: Bytes,
+    with_scores: bool,
+}
+
+impl VAdd {
+    /// `key (FP32 blob | VALUES n v...) element [NOQUANT | Q8 | BIN] [METRIC COSINE | L2]
+    /// [M links] [EF exploration] [SETATTR json] [CAS]`
+    pub fn parse_frames(parser: &mut Parser) -> Result<VAdd> {
+        let key = parser.next_string()?;
+        let vector = parse_vector(parser)?;
+        let element = parser.next_bytes()?;
+        let mut add = VAdd {
+            key,
+            vector,
+            element,
+            quantization: None,
+            metric: None,
+            m: None,
+            ef: None,
+            attributes: None,
+        };
+        let mut args = remaining(parser)?.into_iter();
+        while let Some(token) = args.next() {
+            match &token.to_lowercase()[..] {
+                "noquant" => add.quantization = Some(Quantization::NoQuant),
+                "q8" => add.quantization = Some(Quantization::Q8),
+                "bin" => add.quantization = Some(Quantization::Binary),
+                "metric" => {
+                    let metric = args.next().unwrap_or_default();
+                    add.metric = Some(match &metric.to_lowercase()[..] {
+                        "cosine" => Metric::Cosine,
+                        "l2" => Metric::L2,
+                        _ => return Err(format!("unknown metric '{}'", metric).into()),
+                    });
+                }
+                "m" => add.m = Some(parse_positive(args.next())?),
+                "ef" => add.ef = Some(parse_positive(args.next())?),
+                "setattr" => add.attributes = Some(args.next().ok_or("wrong number of arguments")?),
+                // the insertions are never run on another thread, there is nothing to check
+                "cas" => {}
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        Ok(add)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the options of the set are fixed by the first element, 1 is replied when the
+    /// element is new
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let attributes = match self.attributes.as_deref().map(parse_attributes) {
+            Some(Ok(attributes)) => Some(attributes),
+            Some(Err(frame)) => return frame,
+            None => None,
+        };
+        match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => {
+                if set.dim() != self.vector.len() {
+                    return Frame::Error(format!(
+                        "ERR Vector dimension mismatch - got {} but set has {}",
+                        self.vector.len(),
+                        set.dim()
+                    ));
+                }
+                if self.quantization.is_some_and(|q| q != set.quantization()) {
+                    return Frame::Error(
+                        "ERR asked quantization mismatch with existing vector set".to_string(),
+                    );
+                }
+                if self.metric.is_some_and(|metric| metric != set.metric()) {
+                    return Frame::Error(
+                        "ERR asked metric mismatch with existing vector set".to_string(),
+                    );
+                }
+            }
+            Ok(None) => {
+                let set = VectorSet::new(
+                    self.vector.len(),
+                    self.quantization.unwrap_or(Quantization::Q8),
+                    self.metric.unwrap_or(Metric::Cosine),
+                    self.m.unwrap_or(DEFAULT_M),
+                    self.ef.unwrap_or(DEFAULT_EF_CONSTRUCTION),
+                );
+                db.insert(self.key.clone(), Value::VectorSet(set));
+            }
+            Err(e) => return e.into(),
+        }
+        let set = db.get_vectorset_mut(&self.key).unwrap().unwrap();
+        let added = set.add(self.element.clone(), &self.vector);
+        if let Some(attributes) = attributes {
+            set.set_attributes(&self.element, attributes);
+        }
+        db.notify(Class::Module, "vadd", &self.key);
+        Frame::Integer(added as i64)
+    }
+}
+
+impl VSim {
+    /// `key (ELE element | FP32 blob | VALUES n v...) [WITHSCORES] [WITHATTRIBS]
+    /// [COUNT n] [EPSILON d] [EF exploration] [FILTER expression] [FILTER-EF effort]
+    /// [TRUTH] [NOTHREAD]`
+    pub fn parse_frames(parser: &mut Parser) -> Result<VSim> {
+        let key = parser.next_string()?;
+        let query = match parser.next_string()?.to_lowercase().as_str() {
+            "ele" => VQuery::Element(parser.next_bytes()?),
+            kind => VQuery::Vector(parse_vector_of(kind, parser)?),
+        };
+        let mut sim = VSim {
+            key,
+            query,
+            with_scores: false,
+            with_attributes: false,
+            count: DEFAULT_COUNT,
+            epsilon: None,
+            ef: DEFAULT_EF,
+            filter: None,
+            filter_ef: None,
+            truth: false,
+        };
+        let mut args = remaining(parser)?.into_iter();
+        while let Some(token) = args.next() {
+            match &token.to_lowercase()[..] {
+                "withscores" => sim.with_scores = true,
+                "withattribs" => sim.with_attributes = true,
+                "count" => sim.count = parse_positive(args.next())?,
+                "epsilon" => {
+                    sim.epsilon = match args.next().map(|arg| arg.parse::<f64>()) {
+                        Some(Ok(epsilon)) if (0.0..=1.0).contains(&epsilon) => Some(epsilon),
+                        _ => return Err("EPSILON must be between 0 and 1".into()),
+                    }
+                }
+                "ef" => sim.ef = parse_positive(args.next())?,
+                "filter" => sim.filter = Some(args.next().ok_or("wrong number of arguments")?),
+                "filter-ef" => sim.filter_ef = Some(parse_positive(args.next())?),
+                "truth" => sim.truth = true,
+                // the searches are never run on another thread anyway
+                "nothread" => {}
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        Ok(sim)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the closest elements first, each followed by its similarity from 1 down to 0
+    /// and its attributes when asked for
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Array(vec![]),
+            Err(e) => return e.into(),
+        };
+        let vector = match self.query {
+            VQuery::Vector(vector) if vector.len() != set.dim() => {
+                return Frame::Error(format!(
+                    "ERR Vector dimension mismatch - got {} but set has {}",
+                    vector.len(),
+                    set.dim()
+                ))
+            }
+            VQuery::Vector(vector) => vector,
+            VQuery::Element(element) => match set.embedding(&element) {
+                Some(vector) => vector,
+                None => return Frame::Error("ERR element not found in set".to_string()),
+            },
+        };
+        let filter = match self.filter.as_deref().map(Expr::parse).transpose() {
+            Ok(filter) => filter,
+            Err(e) => return Frame::Error(format!("ERR {}", e)),
+        };
+        let accept = |attributes: Option<&serde_json::Value>| {
+            filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(attributes))
+        };
+
+        let results = match self.truth {
+            true => set.search_exact(&vector, self.count, &accept),
+            false => {
+                let budget = filter
+                    .as_ref()
+                    .map(|_| self.filter_ef.unwrap_or(self.count * DEFAULT_FILTER_EF));
+                set.search(&vector, self.count, self.ef, &accept, budget)
+            }
+        };
+        let mut frames = vec![];
+        for (element, score) in results {
+            if self.epsilon.is_some_and(|epsilon| score < 1.0 - epsilon) {
+                break;
+            }
+            frames.push(Frame::Bulk(element.clone()));
+            if self.with_scores {
+                frames.push(Frame::into_double(score));
+            }
+            if self.with_attributes {
+                frames.push(attributes_frame(set.attributes(element)));
+            }
+        }
+        Frame::Array(frames)
+    }
+}
+
+impl VRem {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VRem> {
+        let key = parser.next_string()?;
+        let element = parser.next_bytes()?;
+        Ok(VRem { key, element })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let removed = match db.get_vectorset_mut(&self.key) {
+            Ok(Some(set)) => set.remove(&self.element),
+            Ok(None) => false,
+            Err(e) => return e.into(),
+        };
+        if removed {
+            db.notify(Class::Module, "vrem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl VCard {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VCard> {
+        let key = parser.next_string()?;
+        Ok(VCard { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_vectorset(&self.key) {
+            Ok(set) => Frame::Integer(set.map_or(0, VectorSet::len) as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl VDim {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VDim> {
+        let key = parser.next_string()?;
+        Ok(VDim { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => Frame::Integer(set.dim() as i64),
+            Ok(None) => Frame::Error("ERR key does not exist".to_string()),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl VEmb {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VEmb> {
+        let key = parser.next_string()?;
+        let element = parser.next_bytes()?;
+        Ok(VEmb { key, element })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the vector as it is stored, quantization loses some precision
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        match set.embedding(&self.element) {
+            Some(vector) => Frame::Array(vector.into_iter().map(float_frame).collect()),
+            None => Frame::Null,
+        }
+    }
+}
+
+impl VGetAttr {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VGetAttr> {
+        let key = parser.next_string()?;
+        let element = parser.next_bytes()?;
+        Ok(VGetAttr { key, element })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => attributes_frame(set.attributes(&self.element)),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl VSetAttr {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VSetAttr> {
+        let key = parser.next_string()?;
+        let element = parser.next_bytes()?;
+        let attributes = parser.next_string()?;
+        Ok(VSetAttr {
+            key,
+            element,
+            attributes,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// an empty string removes the attributes, 0 is replied when there is no element
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let attributes = match parse_attributes(&self.attributes) {
+            Ok(attributes) => attributes,
+            Err(frame) => return frame,
+        };
+        match db.get_vectorset_mut(&self.key) {
+            Ok(Some(set)) => {
+                let found = set.set_attributes(&self.element, attributes);
+                if found {
+                    db.notify(Class::Module, "vsetattr", &self.key);
+                }
+                Frame::Integer(found as i64)
+            }
+            Ok(None) => Frame::Integer(0),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl VInfo {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VInfo> {
+        let key = parser.next_string()?;
+        Ok(VInfo { key })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let bulk = |text: &'static str| Frame::Bulk(Bytes::from_static(text.as_bytes()));
+        Frame::Array(vec![
+            bulk("quant-type"),
+            bulk(set.quantization().name()),
+            bulk("distance"),
+            bulk(set.metric().name()),
+            bulk("vector-dim"),
+            Frame::Integer(set.dim() as i64),
+            bulk("size"),
+            Frame::Integer(set.len() as i64),
+            bulk("max-level"),
+            Frame::Integer(set.max_level() as i64),
+            bulk("hnsw-m"),
+            Frame::Integer(set.m() as i64),
+        ])
+    }
+}
+
+impl VLinks {
+    pub fn parse_frames(parser: &mut Parser) -> Result<VLinks> {
+        let key = parser.next_string()?;
+        let element = parser.next_bytes()?;
+        let with_scores = match parser.next_string() {
+            Ok(option) if option.to_lowercase() == "withscores" => true,
+            Ok(option) => return Err(format!("syntax error near '{}'", option).into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(VLinks {
+            key,
+            element,
+            with_scores,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    /// the neighbours of the element in the graph, from the lowest level
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let set = match db.get_vectorset(&self.key) {
+            Ok(Some(set)) => set,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        let Some(levels) = set.links(&self.element) else {
+            return Frame::Null;
+        };
+        let levels = levels
+            .into_iter()
+            .map(|links| {
+                let mut frames = vec![];
+                for (element, score) in links {
+                    frames.push(Frame::Bulk(element.clone()));
+                    if self.with_scores {
+                        frames.push(Frame::into_double(score));
+                    }
+                }
+                Frame::Array(frames)
+            })
+            .collect();
+        Frame::Array(levels)
+    }
+}
+
+/// `FP32 blob` of little endian floats, or `VALUES n v...`
+fn parse_vector(parser: &mut Parser) -> Result<Vec<f32>> {
+    let kind = parser.next_string()?.to_lowercase();
+    parse_vector_of(&kind, parser)
+}
+
+fn parse_vector_of(kind: &str, parser: &mut Parser) -> Result<Vec<f32>> {
+    let vector: Vec<f32> = match kind {
+        "fp32" => {
+            let blob = parser.next_bytes()?;
+            if blob.len() % 4 != 0 {
+                return Err("invalid FP32 blob, its length must be a multiple of 4".into());
+            }
+            blob.chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect()
+        }
+        "values" => {
+            let count = parser.next_int()?;
+            (0..count)
+                .map(|_| Ok(parser.next_float()? as f32))
+                .collect::<Result<_>>()?
+        }
+        "reduce" => return Err("REDUCE is not supported".into()),
+        _ => return Err(format!("syntax error near '{}'", kind).into()),
+    };
+    if vector.is_empty() || vector.iter().any(|x| !x.is_finite()) {
+        return Err("the vector must have at least one finite component".into());
+    }
+    Ok(vector)
+}
+
+/// the attributes are a JSON object, an empty string stands for none
+fn parse_attributes(text: &str) -> std::result::Result<Option<serde_json::Value>, Frame> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    match serde_json::from_str(text) {
+        Ok(attributes @ serde_json::Value::Object(_)) => Ok(Some(attributes)),
+        _ => Err(Frame::Error(
+            "ERR Invalid JSON in attributes, an object is expected".to_string(),
+        )),
+    }
+}
+
+fn parse_positive(arg: Option<String>) -> Result<usize> {
+    match arg.as_deref().map(str::parse) {
+        Some(Ok(value)) if value > 0 => Ok(value),
+        _ => Err("value must be a positive integer".into()),
+    }
+}
+
+fn remaining(parser: &mut Parser) -> Result<Vec<String>> {
+    match parser.remaining_strings() {
+        Ok(args) => Ok(args),
+        Err(ParseError::EndOfStream) => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn attributes_frame(attributes: Option<&serde_json::Value>) -> Frame {
+    attributes.map_or(Frame::Null, |attributes| {
+        Frame::Bulk(Bytes::from(attributes.to_string()))
+    })
+}
+
+/// single precision floats are spelled with the digits they have
+fn float_frame(value: f32) -> Frame {
+    Frame::Bulk(Bytes::from(value.to_string()))
+}
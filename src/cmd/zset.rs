@@ -0,0 +1,1193 @@
+use std::{collections::HashMap, future::pending, time::Duration};
+
+use bytes::Bytes;
+use tokio::{select, time::sleep};
+use tracing::instrument;
+
+use crate::{
+    db::{Class, Database, LexBound, Popped, ScoreBound, Set, Value, WrongType, ZSet},
+    parser::{ParseError, Parser},
+    Connection, DbHolder, Frame, Result,
+};
+
+#[derive(Debug)]
+pub struct ZAdd {
+    key: String,
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+    pairs: Vec<(f64, Bytes)>,
+}
+
+#[derive(Debug)]
+pub struct ZRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct ZScore {
+    key: String,
+    member: Bytes,
+}
+
+#[derive(Debug)]
+pub struct ZMScore {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: String,
+    increment: f64,
+    member: Bytes,
+}
+
+#[derive(Debug)]
+pub struct ZCard {
+    key: String,
+}
+
+/// ZCOUNT and ZLEXCOUNT
+#[derive(Debug)]
+pub struct ZCount {
+    key: String,
+    range: RangeSpec,
+}
+
+/// ZRANK and ZREVRANK
+#[derive(Debug)]
+pub struct ZRank {
+    key: String,
+    member: Bytes,
+    reverse: bool,
+    with_score: bool,
+}
+
+/// ZRANGE and ZRANGESTORE
+#[derive(Debug)]
+pub struct ZRange {
+    key: String,
+    destination: Option<String>,
+    range: RangeSpec,
+    reverse: bool,
+    offset: i64,
+    count: Option<usize>,
+    with_scores: bool,
+}
+
+/// ZPOPMIN and ZPOPMAX
+#[derive(Debug)]
+pub struct ZPop {
+    key: String,
+    count: Option<usize>,
+    max: bool,
+}
+
+/// BZPOPMIN and BZPOPMAX
+#[derive(Debug)]
+pub struct BZPop {
+    keys: Vec<String>,
+    max: bool,
+    timeout: Option<Duration>,
+}
+
+/// ZMPOP and BZMPOP
+#[derive(Debug)]
+pub struct ZMPop {
+    keys: Vec<String>,
+    max: bool,
+    count: usize,
+    block: bool,
+    timeout: Option<Duration>,
+}
+
+/// how waiting for a blocking pop ended
+enum Wait {
+    Popped(Popped),
+    TimedOut,
+    Closed,
+}
+
+/// ZREMRANGEBYRANK, ZREMRANGEBYSCORE and ZREMRANGEBYLEX
+#[derive(Debug)]
+pub struct ZRemRange {
+    key: String,
+    range: RangeSpec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZSetOp {
+    Union,
+    Inter,
+    Diff,
+}
+
+impl ZSetOp {
+    /// the keyspace event of the STORE variant
+    fn store_event(self) -> &'static str {
+        match self {
+            ZSetOp::Union => "zunionstore",
+            ZSetOp::Inter => "zinterstore",
+            ZSetOp::Diff => "zdiffstore",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+/// ZUNION, ZINTER, ZDIFF and their STORE variants
+#[derive(Debug)]
+pub struct ZCombine {
+    op: ZSetOp,
+    destination: Option<String>,
+    keys: Vec<String>,
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+#[derive(Debug)]
+enum RangeSpec {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+impl RangeSpec {
+    /// the keyspace event of ZREMRANGEBYRANK and friends
+    fn remove_event(&self) -> &'static str {
+        match self {
+            RangeSpec::Rank(..) => "zremrangebyrank",
+            RangeSpec::Score(..) => "zremrangebyscore",
+            RangeSpec::Lex(..) => "zremrangebylex",
+        }
+    }
+}
+
+/// a source of ZUNION and friends, plain sets count as every member scoring 1
+enum Source<'a> {
+    ZSet(&'a ZSet),
+    Set(&'a Set),
+}
+
+impl ZAdd {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZAdd> {
+        let key = parser.next_string()?;
+        let mut zadd = ZAdd {
+            key,
+            nx: false,
+            xx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+            pairs: vec![],
+        };
+        // flags come first, the first token which isn't a flag is a score
+        let mut score = loop {
+            let token = parser.next_string()?;
+            match &token.to_lowercase()[..] {
+                "nx" => zadd.nx = true,
+                "xx" => zadd.xx = true,
+                "gt" => zadd.gt = true,
+                "lt" => zadd.lt = true,
+                "ch" => zadd.ch = true,
+                "incr" => zadd.incr = true,
+                _ => break token,
+            }
+        };
+        loop {
+            let member = parser.next_bytes()?;
+            zadd.pairs.push((parse_float(&score)?, member));
+            score = match parser.next_string() {
+                Ok(score) => score,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+        }
+
+        if zadd.nx && zadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        if (zadd.gt && zadd.lt) || ((zadd.gt || zadd.lt) && zadd.nx) {
+            return Err("GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+        if zadd.incr && zadd.pairs.len() > 1 {
+            return Err("INCR option supports a single increment-element pair".into());
+        }
+        Ok(zadd)
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_or_insert_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let (frame, event) = if self.incr {
+            let (increment, member) = self.pairs[0].clone();
+            let frame = self.increment(zset, increment, member);
+            let updated = !matches!(frame, Frame::Null | Frame::Error(_));
+            (frame, updated.then_some("zincr"))
+        } else {
+            let (mut added, mut changed) = (0, 0);
+            for (score, member) in self.pairs.iter().cloned() {
+                match zset.score(&member) {
+                    None if self.xx => {}
+                    None => {
+                        zset.insert(member, score);
+                        added += 1;
+                    }
+                    Some(current) if self.allows_update(current, score) => {
+                        zset.insert(member, score);
+                        changed += 1;
+                    }
+                    Some(_) => {}
+                }
+            }
+            let frame = Frame::Integer(if self.ch { added + changed } else { added });
+            (frame, (added + changed > 0).then_some("zadd"))
+        };
+        if let Some(event) = event {
+            db.notify(Class::ZSet, event, &self.key);
+        }
+        // XX may have left the newly created set empty
+        db.remove_if_empty(&self.key);
+        db.serve_blocked(&self.key);
+        frame
+    }
+
+    fn increment(&self, zset: &mut ZSet, increment: f64, member: Bytes) -> Frame {
+        let current = zset.score(&member);
+        if (self.nx && current.is_some()) || (self.xx && current.is_none()) {
+            return Frame::Null;
+        }
+        let score = current.unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            return Frame::Error("resulting score is not a number (NaN)".to_string());
+        }
+        if current.is_some_and(|current| !self.allows_update(current, score)) {
+            return Frame::Null;
+        }
+        zset.insert(member, score);
+        Frame::into_double(score)
+    }
+
+    fn allows_update(&self, current: f64, score: f64) -> bool {
+        current != score
+            && !self.nx
+            && (!self.gt || score > current)
+            && (!self.lt || score < current)
+    }
+}
+
+impl ZRem {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZRem> {
+        Ok(ZRem {
+            key: parser.next_string()?,
+            members: parser.remaining_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let removed = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => self.members.iter().filter(|m| zset.remove(m)).count(),
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if removed > 0 {
+            db.notify(Class::ZSet, "zrem", &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl ZScore {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZScore> {
+        Ok(ZScore {
+            key: parser.next_string()?,
+            member: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => zset
+                .and_then(|zset| zset.score(&self.member))
+                .map_or(Frame::Null, Frame::into_double),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl ZMScore {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZMScore> {
+        Ok(ZMScore {
+            key: parser.next_string()?,
+            members: parser.remaining_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let scores = self.members.iter().map(|member| {
+            zset.and_then(|zset| zset.score(member))
+                .map_or(Frame::Null, Frame::into_double)
+        });
+        Frame::Array(scores.collect())
+    }
+}
+
+impl ZIncrBy {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZIncrBy> {
+        Ok(ZIncrBy {
+            key: parser.next_string()?,
+            increment: parser.next_float()?,
+            member: parser.next_bytes()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_or_insert_zset(&self.key) {
+            Ok(zset) => zset,
+            Err(e) => return e.into(),
+        };
+        let score = zset.score(&self.member).unwrap_or(0.0) + self.increment;
+        if score.is_nan() {
+            db.remove_if_empty(&self.key);
+            return Frame::Error("resulting score is not a number (NaN)".to_string());
+        }
+        zset.insert(self.member, score);
+        db.notify(Class::ZSet, "zincr", &self.key);
+        db.serve_blocked(&self.key);
+        Frame::into_double(score)
+    }
+}
+
+impl ZCard {
+    pub fn parse_frames(parser: &mut Parser) -> Result<ZCard> {
+        Ok(ZCard {
+            key: parser.next_string()?,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match db.get_zset(&self.key) {
+            Ok(zset) => Frame::Integer(zset.map_or(0, |zset| zset.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl ZCount {
+    /// `lex` tells whether the bounds are lexicographical (ZLEXCOUNT) or scores (ZCOUNT)
+    pub fn parse_frames(parser: &mut Parser, lex: bool) -> Result<ZCount> {
+        let key = parser.next_string()?;
+        let (min, max) = (parser.next_bytes()?, parser.next_bytes()?);
+        let range = if lex {
+            RangeSpec::Lex(parse_lex_bound(&min)?, parse_lex_bound(&max)?)
+        } else {
+            RangeSpec::Score(parse_score_bound(&min)?, parse_score_bound(&max)?)
+        };
+        Ok(ZCount { key, range })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Integer(0),
+            Err(e) => return e.into(),
+        };
+        let count = match &self.range {
+            RangeSpec::Score(min, max) => zset.count_by_score(*min, *max),
+            RangeSpec::Lex(min, max) => zset.count_by_lex(min, max),
+            RangeSpec::Rank(..) => unreachable!("ZCOUNT never parses a rank range"),
+        };
+        Frame::Integer(count as i64)
+    }
+}
+
+impl ZRank {
+    pub fn parse_frames(parser: &mut Parser, reverse: bool) -> Result<ZRank> {
+        let key = parser.next_string()?;
+        let member = parser.next_bytes()?;
+        let with_score = match parser.next_string() {
+            Ok(token) if token.eq_ignore_ascii_case("withscore") => true,
+            Ok(token) => return Err(format!("syntax error near '{}'", token).into()),
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ZRank {
+            key,
+            member,
+            reverse,
+            with_score,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let zset = match db.get_zset(&self.key) {
+            Ok(Some(zset)) => zset,
+            Ok(None) => return Frame::Null,
+            Err(e) => return e.into(),
+        };
+        match (
+            zset.rank(&self.member, self.reverse),
+            zset.score(&self.member),
+        ) {
+            (Some(rank), Some(score)) if self.with_score => {
+                Frame::Array(vec![Frame::Integer(rank as i64), Frame::into_double(score)])
+            }
+            (Some(rank), _) => Frame::Integer(rank as i64),
+            _ => Frame::Null,
+        }
+    }
+}
+
+impl ZRange {
+    /// the variant storing its result at a destination key
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    /// `store` tells whether a destination key leads the arguments (ZRANGESTORE)
+    pub fn parse_frames(parser: &mut Parser, store: bool) -> Result<ZRange> {
+        let destination = if store {
+            Some(parser.next_string()?)
+        } else {
+            None
+        };
+        let key = parser.next_string()?;
+        let (start, stop) = (parser.next_bytes()?, parser.next_bytes()?);
+
+        let (mut by_score, mut by_lex, mut reverse, mut with_scores) = (false, false, false, false);
+        let mut limit = None;
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e.into()),
+            };
+            match &token[..] {
+                "byscore" => by_score = true,
+                "bylex" => by_lex = true,
+                "rev" => reverse = true,
+                "limit" => limit = Some((parser.next_int()?, parser.next_int()?)),
+                "withscores" if !store => with_scores = true,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+        if by_score && by_lex {
+            return Err("syntax error, BYSCORE and BYLEX can't be combined".into());
+        }
+        if limit.is_some() && !by_score && !by_lex {
+            return Err(
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                    .into(),
+            );
+        }
+        if with_scores && by_lex {
+            return Err("syntax error, WITHSCORES not supported in combination with BYLEX".into());
+        }
+
+        // reversed score and lex ranges are given from max to min
+        let (min, max) = if reverse && (by_score || by_lex) {
+            (stop, start)
+        } else {
+            (start, stop)
+        };
+        let range = if by_score {
+            RangeSpec::Score(parse_score_bound(&min)?, parse_score_bound(&max)?)
+        } else if by_lex {
+            RangeSpec::Lex(parse_lex_bound(&min)?, parse_lex_bound(&max)?)
+        } else {
+            RangeSpec::Rank(parse_int(&min)?, parse_int(&max)?)
+        };
+        let (offset, count) = match limit {
+            Some((offset, count)) if count >= 0 => (offset, Some(count as usize)),
+            Some((offset, _)) => (offset, None),
+            None => (0, None),
+        };
+        Ok(ZRange {
+            key,
+            destination,
+            range,
+            reverse,
+            offset,
+            count,
+            with_scores,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let entries = match db.get_zset(&self.key) {
+            Ok(Some(zset)) if self.offset >= 0 => select(
+                zset,
+                &self.range,
+                self.reverse,
+                self.offset as usize,
+                self.count,
+            ),
+            Ok(_) => vec![],
+            Err(e) => return e.into(),
+        };
+        match self.destination {
+            Some(destination) => {
+                let len = entries.len();
+                store(db, destination, entries, "zrangestore");
+                Frame::Integer(len as i64)
+            }
+            None => entries_frame(entries, self.with_scores),
+        }
+    }
+}
+
+impl ZPop {
+    pub fn parse_frames(parser: &mut Parser, max: bool) -> Result<ZPop> {
+        let key = parser.next_string()?;
+        let count = match parser.next_int() {
+            Ok(count) if count < 0 => return Err("value is out of range, must be positive".into()),
+            Ok(count) => Some(count as usize),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ZPop { key, count, max })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let popped = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => zset.pop(self.count.unwrap_or(1), self.max),
+            Ok(None) => vec![],
+            Err(e) => return e.into(),
+        };
+        if !popped.is_empty() {
+            db.notify(Class::ZSet, pop_event(self.max), &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        entries_frame(popped, true)
+    }
+}
+
+impl BZPop {
+    pub fn parse_frames(parser: &mut Parser, max: bool) -> Result<BZPop> {
+        let mut keys = parser.remaining_strings()?;
+        if keys.len() < 2 {
+            return Err("wrong number of arguments for command".into());
+        }
+        let timeout = parse_timeout(&keys.pop().unwrap_or_default())?;
+        Ok(BZPop { keys, max, timeout })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = match wait_pop(db, connection, &self.keys, self.max, 1, self.timeout).await {
+            Ok(Wait::Popped(popped)) => Self::frame(Some(popped)),
+            Ok(Wait::TimedOut) => Frame::Null,
+            Ok(Wait::Closed) => return Ok(()),
+            Err(e) => e.into(),
+        };
+        connection.write_frame(frame).await
+    }
+
+    /// never blocks, which is how BZPOPMIN and BZPOPMAX behave within a transaction
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match pop_first(db, &self.keys, self.max, 1) {
+            Ok(popped) => Self::frame(popped),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn frame(popped: Option<Popped>) -> Frame {
+        let Some((key, mut entries)) = popped else {
+            return Frame::Null;
+        };
+        let (member, score) = entries.remove(0);
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from(key)),
+            Frame::Bulk(member),
+            Frame::into_double(score),
+        ])
+    }
+}
+
+impl ZMPop {
+    pub fn parse_frames(parser: &mut Parser, block: bool) -> Result<ZMPop> {
+        let timeout = if block {
+            parse_timeout(&parser.next_string()?)?
+        } else {
+            None
+        };
+        let numkeys = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err("numkeys should be greater than 0".into());
+        }
+        // the keys are followed by MIN or MAX
+        if numkeys as u64 >= parser.remaining() as u64 {
+            return Err("syntax error".into());
+        }
+        let keys = (0..numkeys)
+            .map(|_| parser.next_string())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let max = match parser.next_string()?.to_lowercase().as_str() {
+            "min" => false,
+            "max" => true,
+            _ => return Err("syntax error".into()),
+        };
+        let count = match parser.next_string() {
+            Ok(option) if option.to_lowercase() == "count" => match parser.next_int()? {
+                count if count <= 0 => return Err("count should be greater than 0".into()),
+                count => count as usize,
+            },
+            Ok(_) => return Err("syntax error".into()),
+            Err(ParseError::EndOfStream) => 1,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ZMPop {
+            keys,
+            max,
+            count,
+            block,
+            timeout,
+        })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        if !self.block {
+            let frame = self.apply(&mut db.lock());
+            return connection.write_frame(frame).await;
+        }
+        let frame = match wait_pop(
+            db,
+            connection,
+            &self.keys,
+            self.max,
+            self.count,
+            self.timeout,
+        )
+        .await
+        {
+            Ok(Wait::Popped(popped)) => Self::frame(Some(popped)),
+            Ok(Wait::TimedOut) => Frame::Null,
+            Ok(Wait::Closed) => return Ok(()),
+            Err(e) => e.into(),
+        };
+        connection.write_frame(frame).await
+    }
+
+    /// never blocks, which is how BZMPOP behaves within a transaction
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        match pop_first(db, &self.keys, self.max, self.count) {
+            Ok(popped) => Self::frame(popped),
+            Err(e) => e.into(),
+        }
+    }
+
+    fn frame(popped: Option<Popped>) -> Frame {
+        let Some((key, entries)) = popped else {
+            return Frame::Null;
+        };
+        let entries = entries
+            .into_iter()
+            .map(|(member, score)| {
+                Frame::Array(vec![Frame::Bulk(member), Frame::into_double(score)])
+            })
+            .collect();
+        Frame::Array(vec![Frame::Bulk(Bytes::from(key)), Frame::Array(entries)])
+    }
+}
+
+impl ZRemRange {
+    pub fn parse_frames(parser: &mut Parser, by: &str) -> Result<ZRemRange> {
+        let key = parser.next_string()?;
+        let (min, max) = (parser.next_bytes()?, parser.next_bytes()?);
+        let range = match by {
+            "rank" => RangeSpec::Rank(parse_int(&min)?, parse_int(&max)?),
+            "score" => RangeSpec::Score(parse_score_bound(&min)?, parse_score_bound(&max)?),
+            _ => RangeSpec::Lex(parse_lex_bound(&min)?, parse_lex_bound(&max)?),
+        };
+        Ok(ZRemRange { key, range })
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let removed = match db.get_zset_mut(&self.key) {
+            Ok(Some(zset)) => {
+                let entries = select(zset, &self.range, false, 0, None);
+                for (member, _) in &entries {
+                    zset.remove(member);
+                }
+                entries.len()
+            }
+            Ok(None) => 0,
+            Err(e) => return e.into(),
+        };
+        if removed > 0 {
+            db.notify(Class::ZSet, self.range.remove_event(), &self.key);
+        }
+        db.remove_if_empty(&self.key);
+        Frame::Integer(removed as i64)
+    }
+}
+
+impl ZCombine {
+    /// the variant storing its result at a destination key
+    pub(crate) fn is_store(&self) -> bool {
+        self.destination.is_some()
+    }
+
+    /// `store` tells whether a destination key leads the arguments (ZUNIONSTORE, ...)
+    pub fn parse_frames(parser: &mut Parser, op: ZSetOp, store: bool) -> Result<ZCombine> {
+        let destination = if store {
+            Some(parser.next_string()?)
+        } else {
+            None
+        };
+        let numkeys = parser.next_int()?;
+        if numkeys <= 0 {
+            return Err("at least 1 input key is needed".into());
+        }
+        let keys = (0..numkeys)
+            .map(|_| parser.next_string())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let mut combine = ZCombine {
+            op,
+            destination,
+            weights: vec![1.0; keys.len()],
+            keys,
+            aggregate: Aggregate::Sum,
+            with_scores: false,
+        };
+        loop {
+            let token = match parser.next_string() {
+                Ok(token) => token.to_lowercase(),
+                Err(ParseError::EndOfStream) => return Ok(combine),
+                Err(e) => return Err(e.into()),
+            };
+            match &token[..] {
+                "weights" if op != ZSetOp::Diff => {
+                    for weight in combine.weights.iter_mut() {
+                        *weight = parser.next_float()?;
+                    }
+                }
+                "aggregate" if op != ZSetOp::Diff => {
+                    combine.aggregate = match &parser.next_string()?.to_lowercase()[..] {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err("syntax error".into()),
+                    }
+                }
+                "withscores" if combine.destination.is_none() => combine.with_scores = true,
+                _ => return Err(format!("syntax error near '{}'", token).into()),
+            }
+        }
+    }
+
+    #[instrument(skip(db, connection))]
+    pub async fn execute(self, db: &DbHolder, connection: &mut Connection) -> Result<()> {
+        let frame = self.apply(&mut db.lock());
+        connection.write_frame(frame).await
+    }
+
+    pub(crate) fn apply(self, db: &mut Database) -> Frame {
+        let result = match self.combine(db) {
+            Ok(result) => result,
+            Err(e) => return e.into(),
+        };
+        match self.destination {
+            Some(destination) => {
+                let len = result.len();
+                if !result.is_empty() {
+                    db.insert(destination.clone(), Value::ZSet(result));
+                    db.notify(Class::ZSet, self.op.store_event(), &destination);
+                    db.serve_blocked(&destination);
+                } else if db.remove(&destination).is_some() {
+                    db.notify(Class::Generic, "del", &destination);
+                }
+                Frame::Integer(len as i64)
+            }
+            None => entries_frame(
+                result.iter().map(|(m, s)| (m.clone(), s)).collect(),
+                self.with_scores,
+            ),
+        }
+    }
+
+    fn combine(&self, db: &Database) -> std::result::Result<ZSet, WrongType> {
+        let mut sources = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            sources.push(match db.get(key) {
+                Some(Value::ZSet(zset)) => Some(Source::ZSet(zset)),
+                Some(Value::Set(set)) => Some(Source::Set(set)),
+                Some(_) => return Err(WrongType),
+                None => None,
+            });
+        }
+
+        let mut scores: HashMap<Bytes, f64> = HashMap::new();
+        match self.op {
+            ZSetOp::Union => {
+                for (source, weight) in sources.iter().zip(&self.weights) {
+                    for (member, score) in source.iter().flat_map(Source::entries) {
+                        let score = weighted(score, *weight);
+                        scores
+                            .entry(member)
+                            .and_modify(|acc| *acc = self.aggregate.apply(*acc, score))
+                            .or_insert(score);
+                    }
+                }
+            }
+            ZSetOp::Inter => {
+                if let Some(sources) = sources
+                    .iter()
+                    .map(Option::as_ref)
+                    .collect::<Option<Vec<_>>>()
+                {
+                    'members: for (member, score) in sources[0].entries() {
+                        let mut acc = weighted(score, self.weights[0]);
+                        for (source, weight) in sources.iter().zip(&self.weights).skip(1) {
+                            match source.score(&member) {
+                                Some(score) => {
+                                    acc = self.aggregate.apply(acc, weighted(score, *weight))
+                                }
+                                None => continue 'members,
+                            }
+                        }
+                        scores.insert(member, acc);
+                    }
+                }
+            }
+            ZSetOp::Diff => {
+                if let Some(first) = &sources[0] {
+                    for (member, score) in first.entries() {
+                        let excluded = sources[1..]
+                            .iter()
+                            .flatten()
+                            .any(|source| source.score(&member).is_some());
+                        if !excluded {
+                            scores.insert(member, score);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut result = ZSet::default();
+        for (member, score) in scores {
+            result.insert(member, score);
+        }
+        Ok(result)
+    }
+}
+
+impl Aggregate {
+    fn apply(self, acc: f64, score: f64) -> f64 {
+        match self {
+            // inf + -inf is regarded as 0 like redis does
+            Aggregate::Sum => Some(acc + score).filter(|s| !s.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => acc.min(score),
+            Aggregate::Max => acc.max(score),
+        }
+    }
+}
+
+impl Source<'_> {
+    fn entries(&self) -> Vec<(Bytes, f64)> {
+        match self {
+            Source::ZSet(zset) => zset.iter().map(|(m, s)| (m.clone(), s)).collect(),
+            Source::Set(set) => set.iter().map(|m| (m, 1.0)).collect(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Source::ZSet(zset) => zset.score(member),
+            Source::Set(set) => set.contains(member).then_some(1.0),
+        }
+    }
+}
+
+fn weighted(score: f64, weight: f64) -> f64 {
+    // 0 * inf is regarded as 0
+    Some(score * weight).filter(|s| !s.is_nan()).unwrap_or(0.0)
+}
+
+/// pick the members of a range, ordered from the highest score if `reverse` is set
+fn select(
+    zset: &ZSet,
+    range: &RangeSpec,
+    reverse: bool,
+    offset: usize,
+    count: Option<usize>,
+) -> Vec<(Bytes, f64)> {
+    match range {
+        RangeSpec::Rank(start, stop) => match normalize_rank(*start, *stop, zset.len()) {
+            Some((start, stop)) => zset.range_by_rank(start, stop, reverse),
+            None => vec![],
+        },
+        RangeSpec::Score(min, max) => zset.range_by_score(*min, *max, reverse, offset, count),
+        RangeSpec::Lex(min, max) => zset.range_by_lex(min, max, reverse, offset, count),
+    }
+}
+
+/// turn a possibly negative rank range into an inclusive range of valid ranks
+fn normalize_rank(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        None
+    } else {
+        Some((start as usize, stop as usize))
+    }
+}
+
+/// pop from the first key holding a non-empty sorted set
+fn pop_first(
+    db: &mut Database,
+    keys: &[String],
+    max: bool,
+    count: usize,
+) -> std::result::Result<Option<Popped>, WrongType> {
+    for key in keys {
+        if let Some(zset) = db.get_zset_mut(key)? {
+            let popped = zset.pop(count, max);
+            db.notify(Class::ZSet, pop_event(max), key);
+            db.remove_if_empty(key);
+            return Ok(Some((key.clone(), popped)));
+        }
+    }
+    Ok(None)
+}
+
+/// pop right away if possible, otherwise wait until another client feeds one of the keys.
+/// the client is also released when the timeout is reached or the connection is closed
+async fn wait_pop(
+    db: &DbHolder,
+    connection: &mut Connection,
+    keys: &[String],
+    max: bool,
+    count: usize,
+    timeout: Option<Duration>,
+) -> std::result::Result<Wait, WrongType> {
+    let (id, mut receiver) = {
+        let mut db = db.lock();
+        if let Some(popped) = pop_first(&mut db, keys, max, count)? {
+            return Ok(Wait::Popped(popped));
+        }
+        db.block_pop(keys.to_vec(), max, count)
+    };
+
+    let expired = async {
+        match timeout {
+            Some(timeout) => sleep(timeout).await,
+            None => pending().await,
+        }
+    };
+    let wait = select! {
+        popped = &mut receiver => match popped {
+            Ok(popped) => return Ok(Wait::Popped(popped)),
+            Err(_) => Wait::TimedOut,
+        },
+        _ = expired => Wait::TimedOut,
+        _ = connection.closed() => Wait::Closed,
+    };
+
+    // the entries may have been handed over right before giving up
+    db.lock().unblock(id);
+    match receiver.try_recv() {
+        Ok(popped) => Ok(Wait::Popped(popped)),
+        Err(_) => Ok(wait),
+    }
+}
+
+/// replace the destination with the entries, an empty result only deletes it.
+/// `event` is the keyspace event of the command storing them
+pub(super) fn store(
+    db: &mut Database,
+    destination: String,
+    entries: Vec<(Bytes, f64)>,
+    event: &str,
+) {
+    if !entries.is_empty() {
+        let mut zset = ZSet::default();
+        for (member, score) in entries {
+            zset.insert(member, score);
+        }
+        db.insert(destination.clone(), Value::ZSet(zset));
+        db.notify(Class::ZSet, event, &destination);
+        db.serve_blocked(&destination);
+    } else if db.remove(&destination).is_some() {
+        db.notify(Class::Generic, "del", &destination);
+    }
+}
+
+/// the keyspace event of ZPOPMIN or ZPOPMAX, blocking or not
+fn pop_event(max: bool) -> &'static str {
+    if max {
+        "zpopmax"
+    } else {
+        "zpopmin"
+    }
+}
+
+fn entries_frame(entries: Vec<(Bytes, f64)>, with_scores: bool) -> Frame {
+    let mut frames = Vec::with_capacity(entries.len() * 2);
+    for (member, score) in entries {
+        frames.push(Frame::Bulk(member));
+        if with_scores {
+            frames.push(Frame::into_double(score));
+        }
+    }
+    Frame::Array(frames)
+}
+
+fn parse_float(value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(value) if !value.is_nan() => Ok(value),
+        _ => Err("value is not a valid float".into()),
+    }
+}
+
+/// a timeout in seconds, zero blocks forever
+fn parse_timeout(value: &str) -> Result<Option<Duration>> {
+    match value.parse::<f64>() {
+        Ok(timeout) if timeout < 0.0 => Err("timeout is negative".into()),
+        Ok(0.0) => Ok(None),
+        Ok(timeout) if timeout.is_finite() => Ok(Some(Duration::from_secs_f64(timeout))),
+        _ => Err("timeout is not a float or out of range".into()),
+    }
+}
+
+fn parse_int(value: &[u8]) -> Result<i64> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| "value is not an integer or out of range".into())
+}
+
+fn parse_score_bound(bound: &[u8]) -> Result<ScoreBound> {
+    let bound = String::from_utf8_lossy(bound);
+    let (value, exclusive) = match bound.strip_prefix('(') {
+        Some(value) => (value, true),
+        None => (&bound[..], false),
+    };
+    match value.parse::<f64>() {
+        Ok(value) if !value.is_nan() => Ok(ScoreBound { value, exclusive }),
+        _ => Err("min or max is not a float".into()),
+    }
+}
+
+fn parse_lex_bound(bound: &[u8]) -> Result<LexBound> {
+    match bound.first() {
+        Some(b'-') if bound.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bound.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(Bytes::copy_from_slice(&bound[1..]))),
+        Some(b'(') => Ok(LexBound::Exclusive(Bytes::copy_from_slice(&bound[1..]))),
+        _ => Err("min or max not valid string range item".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Command;
+
+    #[test]
+    fn zmpop_numkeys_test() {
+        let parse = |words: &[&str]| Command::try_parse(words).map(|_| ());
+        // the count isn't trusted to allocate the keys
+        let e = parse(&["ZMPOP", "100000000000", "a", "MIN"]).unwrap_err();
+        assert_eq!(e.to_string(), "syntax error");
+        let e = parse(&["BZMPOP", "0", "2", "a", "MIN"]).unwrap_err();
+        assert_eq!(e.to_string(), "syntax error");
+        assert!(parse(&["ZMPOP", "2", "a", "b", "MIN"]).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+//! the tunable parameters of the server, read and changed with CONFIG GET and CONFIG SET.
+//! small collections use a compact encoding until they outgrow these limits
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::db::{format_events, parse_events};
+
+pub(crate) static HASH_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+pub(crate) static HASH_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+pub(crate) static SET_MAX_INTSET_ENTRIES: AtomicUsize = AtomicUsize::new(512);
+pub(crate) static SET_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+pub(crate) static SET_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+pub(crate) static ZSET_MAX_LISTPACK_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+pub(crate) static ZSET_MAX_LISTPACK_VALUE: AtomicUsize = AtomicUsize::new(64);
+/// the classes of keyspace events which are published, none by default
+pub(crate) static NOTIFY_KEYSPACE_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// milliseconds a script may run before the other clients are told the server is busy
+pub(crate) static BUSY_REPLY_THRESHOLD: AtomicUsize = AtomicUsize::new(5000);
+
+/// how the value of a parameter is written
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Format {
+    Integer,
+    /// the letters of the keyspace event classes, such as `KEA`
+    Events,
+}
+
+/// every parameter by name, in the order CONFIG GET lists them
+pub(crate) static PARAMETERS: [(&str, &AtomicUsize, Format); 10] = [
+    (
+        "hash-max-listpack-entries",
+        &HASH_MAX_LISTPACK_ENTRIES,
+        Format::Integer,
+    ),
+    (
+        "hash-max-listpack-value",
+        &HASH_MAX_LISTPACK_VALUE,
+        Format::Integer,
+    ),
+    (
+        "set-max-intset-entries",
+        &SET_MAX_INTSET_ENTRIES,
+        Format::Integer,
+    ),
+    (
+        "set-max-listpack-entries",
+        &SET_MAX_LISTPACK_ENTRIES,
+        Format::Integer,
+    ),
+    (
+        "set-max-listpack-value",
+        &SET_MAX_LISTPACK_VALUE,
+        Format::Integer,
+    ),
+    (
+        "zset-max-listpack-entries",
+        &ZSET_MAX_LISTPACK_ENTRIES,
+        Format::Integer,
+    ),
+    (
+        "zset-max-listpack-value",
+        &ZSET_MAX_LISTPACK_VALUE,
+        Format::Integer,
+    ),
+    (
+        "notify-keyspace-events",
+        &NOTIFY_KEYSPACE_EVENTS,
+        Format::Events,
+    ),
+    (
+        "busy-reply-threshold",
+        &BUSY_REPLY_THRESHOLD,
+        Format::Integer,
+    ),
+    // the former name of busy-reply-threshold
+    ("lua-time-limit", &BUSY_REPLY_THRESHOLD, Format::Integer),
+];
+
+pub(crate) fn get(parameter: &AtomicUsize) -> usize {
+    parameter.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set(parameter: &AtomicUsize, value: usize) {
+    parameter.store(value, Ordering::Relaxed)
+}
+
+/// a parameter changed by a test, its previous value is restored on drop. the
+/// parameters are shared by the tests running in parallel, so the tests changing
+/// them run one at a time
+#[cfg(test)]
+pub(crate) struct Override {
+    parameter: &'static AtomicUsize,
+    previous: usize,
+    _serial: std::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl Override {
+    pub(crate) fn new(parameter: &'static AtomicUsize, value: usize) -> Override {
+        static SERIAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = get(parameter);
+        set(parameter, value);
+        Override {
+            parameter,
+            previous,
+            _serial: serial,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Drop for Override {
+    fn drop(&mut self) {
+        set(self.parameter, self.previous);
+    }
+}
+
+impl Format {
+    pub(crate) fn write(self, value: usize) -> String {
+        match self {
+            Format::Integer => value.to_string(),
+            Format::Events => format_events(value),
+        }
+    }
+
+    /// the reason is returned when the value is invalid
+    pub(crate) fn read(self, value: &str) -> Result<usize, &'static str> {
+        match self {
+            Format::Integer => value
+                .parse()
+                .map_err(|_| "argument couldn't be parsed into an integer"),
+            Format::Events => {
+                parse_events(value).ok_or("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")
+            }
+        }
+    }
+}
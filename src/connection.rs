@@ -41,6 +41,13 @@ impl Connection {
         }
     }
 
+    /// wait until the peer closes the connection. data received meanwhile is
+    /// kept in the buffer for the next `read_frame`
+    pub async fn closed(&mut self) -> Result<()> {
+        while 0 != self.stream.read_buf(&mut self.buf).await? {}
+        Ok(())
+    }
+
     fn parse_frame(&mut self) -> Result<Option<Frame>> {
         use crate::frame::Error::Incomplete;
         if self.buf.is_empty() {
@@ -49,7 +56,7 @@ impl Connection {
         let mut src = Cursor::new(&self.buf[..]);
         match Frame::check(&mut src) {
             Ok(_) => {
-                // `check` will have moved cursor util end of frame. 
+                // `check` will have moved cursor util end of frame.
                 // we can get the length of the frame by cursor's position
                 let len = src.position();
                 // reset the position then parse the frame
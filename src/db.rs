@@ -1,9 +1,9 @@
-use crate::Result;
+use crate::{Frame, Result};
 use bytes::Bytes;
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::Add,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, MutexGuard, TryLockError},
     time::Duration,
 };
 use tokio::{
@@ -12,16 +12,103 @@ use tokio::{
     time::{sleep, Instant},
 };
 
+mod blocking;
+use blocking::BlockedClients;
+pub(crate) use blocking::Popped;
+
+mod bloom;
+pub(crate) use bloom::{BloomFilter, FilterFull};
+
+mod cms;
+pub(crate) use cms::CountMinSketch;
+
+mod cuckoo;
+pub(crate) use cuckoo::CuckooFilter;
+
+mod events;
+pub(crate) use events::{format_events, parse_events, Class};
+
+mod hash;
+pub(crate) use hash::{ExpireCondition, Hash};
+
+mod json;
+pub(crate) use json::{lookup_mut, normalize, remove, type_name, Format, JsonPath};
+
+mod pubsub;
+use pubsub::Broker;
+pub(crate) use pubsub::BACKLOG;
+
+mod roaring;
+pub(crate) use roaring::{BitOp, Roaring};
+
+mod scripts;
+use scripts::Scripts;
+pub(crate) use scripts::{sha1_hex, ScriptControl};
+
+mod search;
+use search::Indexes;
+pub(crate) use search::{Document, FieldKind, FieldSpec, SearchIndex};
+
+mod set;
+pub(crate) use set::Set;
+
+mod skiplist;
+mod stream;
+pub(crate) use stream::{
+    now_ms, ClaimOptions, Fields, NewId, Stream, StreamId, Trim, TrimStrategy,
+};
+
+mod tdigest;
+pub(crate) use tdigest::TDigest;
+
+mod timeseries;
+pub(crate) use timeseries::{
+    Aggregation, CompactionRule, DuplicatePolicy, SampleError, TimeSeries,
+};
+
+mod topk;
+pub(crate) use topk::TopK;
+
+mod tracking;
+use tracking::Clients;
+pub(crate) use tracking::{Tracking, SERVER};
+
+mod vectorset;
+pub(crate) use vectorset::{Metric, Quantization, VectorSet};
+
+mod watch;
+use watch::Watches;
+
+mod zset;
+pub(crate) use zset::{LexBound, ScoreBound, ZSet};
+
 const DEFAULT_SLEEP_TIME: Duration = Duration::from_secs(5);
+/// how soon the cleaner tries again when the database is locked
+const BUSY_SLEEP_TIME: Duration = Duration::from_millis(100);
 
 #[derive(Clone)]
 pub struct DbHolder {
     holder: Arc<SharedDb>,
+    /// the client the commands applied through this holder come from
+    client: u64,
 }
 
 pub struct SharedDb {
     database: Mutex<Database>,
-    clean_task_notifier: Arc<Notify>,
+    /// reachable without the lock, which a running script holds
+    script_control: Arc<ScriptControl>,
+    /// the clients which have come and gone since the database was last locked
+    registry: Mutex<Registry>,
+}
+
+/// clients are registered apart from the database, whose lock a running script
+/// holds, so that they can connect to tell it to stop. they are handed to the
+/// database the next time it is locked
+#[derive(Default)]
+struct Registry {
+    last_id: u64,
+    connected: Vec<(u64, mpsc::Sender<Frame>)>,
+    disconnected: Vec<u64>,
 }
 
 struct DbCleaner {
@@ -31,20 +118,59 @@ struct DbCleaner {
     _shutdown_completed_tx: mpsc::Sender<()>,
 }
 
-struct Database {
-    entries: HashMap<String, Bytes>,
+pub(crate) struct Database {
+    entries: HashMap<String, Value>,
     expiration: BTreeMap<String, Instant>,
+    /// the earliest field deadline of every hash which has volatile fields
+    field_expiration: BTreeMap<String, Instant>,
+    clean_task_notifier: Arc<Notify>,
+    blocked: BlockedClients,
+    broker: Broker,
+    indexes: Indexes,
+    clients: Clients,
+    watches: Watches,
+    scripts: Scripts,
+    script_control: Arc<ScriptControl>,
+    /// the keys created empty by the write being applied, see `create`
+    created: HashSet<String>,
+    /// the client whose command is applied, set whenever the database is locked
+    client: u64,
 }
 
+pub(crate) enum Value {
+    String(Bytes),
+    /// a string which is an integer, kept as a number
+    Integer(i64),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+    Bloom(BloomFilter),
+    Cuckoo(CuckooFilter),
+    Cms(CountMinSketch),
+    TopK(TopK),
+    TDigest(TDigest),
+    Json(serde_json::Value),
+    TimeSeries(TimeSeries),
+    VectorSet(VectorSet),
+    Roaring(Roaring),
+}
+
+/// returned when a command is applied to a key holding another type of value
+#[derive(Debug)]
+pub struct WrongType;
+
 impl DbHolder {
     pub fn new(
         shutdown_notifier: broadcast::Receiver<()>,
         shutdown_completed_tx: mpsc::Sender<()>,
     ) -> DbHolder {
         let notifier = Arc::new(Notify::new());
+        let database = Database::new(notifier.clone());
         let holder = Arc::new(SharedDb {
-            database: Mutex::new(Database::new()),
-            clean_task_notifier: notifier.clone(),
+            script_control: database.script_control.clone(),
+            database: Mutex::new(database),
+            registry: Mutex::default(),
         });
 
         let db = holder.clone();
@@ -57,64 +183,96 @@ impl DbHolder {
             };
             cleaner.clean_expired_keys().await;
         });
-        DbHolder { holder }
+        DbHolder {
+            holder,
+            client: SERVER,
+        }
     }
 
-    pub fn get(&self, key: &str) -> Option<Bytes> {
-        self.holder
-            .database
-            .lock()
-            .unwrap()
-            .entries
-            .get(key)
-            .cloned()
+    /// register a new client. its commands are applied through the returned holder
+    /// and its push messages are received on the returned channel
+    pub fn connect(&self) -> (DbHolder, mpsc::Receiver<Frame>) {
+        let (sender, receiver) = mpsc::channel(BACKLOG);
+        let mut registry = self.holder.registry.lock().unwrap();
+        registry.last_id += 1;
+        let client = registry.last_id;
+        registry.connected.push((client, sender));
+        drop(registry);
+        let holder = DbHolder {
+            holder: self.holder.clone(),
+            client,
+        };
+        (holder, receiver)
     }
 
-    pub fn set(&self, key: String, value: Bytes, expiration: Option<Duration>) -> Result<()> {
-        let mut db = self.holder.database.lock().unwrap();
-        let _prev = db.entries.insert(key.clone(), value);
-        db.expiration.remove(&key);
-
-        let next_expiration_time = db.expiration.values().min().cloned();
+    pub fn client(&self) -> u64 {
+        self.client
+    }
 
-        if let Some(dur) = expiration {
-            let expire_time = Instant::now().add(dur);
-            db.expiration.insert(key, expire_time);
-        }
+    /// forget the client, along with what it tracks and watches
+    pub fn disconnect(&self) {
+        let mut registry = self.holder.registry.lock().unwrap();
+        registry.disconnected.push(self.client);
+    }
 
-        if let Some(next_expiration_time) = next_expiration_time {
-            if expiration.is_none() {
-                self.holder.clean_task_notifier.notify_one();
-            } else if let Some(expiration) = expiration {
-                let expiration = Instant::now() + expiration;
-                if expiration < next_expiration_time {
-                    self.holder.clean_task_notifier.notify_one();
-                }
-            }
-        }
+    /// the key is tracked for the client if it caches what it reads
+    pub fn get(&self, key: &str) -> std::result::Result<Option<Bytes>, WrongType> {
+        self.lock().read_string(key)
+    }
 
+    pub fn set(&self, key: String, value: Bytes, expiration: Option<Duration>) -> Result<()> {
+        self.lock().set(key, value, expiration);
         Ok(())
     }
+
+    /// the running script, if any, can be waited for or stopped without the lock
+    pub(crate) fn script_control(&self) -> &ScriptControl {
+        &self.holder.script_control
+    }
+
+    /// lock the database so that a command can be applied atomically
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Database> {
+        self.holder.lock(self.client)
+    }
 }
 
 impl SharedDb {
-    /// clean expired keys. return next expired duration if exists
-    fn clean_expired_keys(&self) -> Option<Duration> {
+    fn lock(&self, client: u64) -> MutexGuard<'_, Database> {
         let mut db = self.database.lock().unwrap();
-        let now = Instant::now();
-        let mut expired_keys = vec![];
-        for (key, time) in &db.expiration {
-            if time < &now {
-                expired_keys.push(key.clone());
-            }
+        self.register(&mut db);
+        db.client = client;
+        db
+    }
+
+    /// hand the clients which have come and gone to the database
+    fn register(&self, db: &mut Database) {
+        let mut registry = self.registry.lock().unwrap();
+        for (id, sender) in registry.connected.drain(..) {
+            db.connect(id, sender);
+        }
+        for id in registry.disconnected.drain(..) {
+            db.disconnect(id);
         }
-        db.entries.retain(|x, _| !expired_keys.contains(x));
-        db.expiration.retain(|x, _| !expired_keys.contains(x));
+    }
+
+    /// clean expired keys. return next expired duration if exists. the cleaner
+    /// runs on a worker of the runtime, so it doesn't wait for the lock held by
+    /// a running script, which would starve the clients telling it to stop
+    fn clean_expired_keys(&self) -> Option<Duration> {
+        let mut db = match self.database.try_lock() {
+            Ok(db) => db,
+            Err(TryLockError::WouldBlock) => return Some(BUSY_SLEEP_TIME),
+            Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+        };
+        self.register(&mut db);
+        db.client = SERVER;
+        db.remove_expired(Instant::now());
 
         db.expiration
-            .iter()
-            .min_by(|&this, &that| this.1.cmp(that.1))
-            .map(|(_, time)| *time - Instant::now())
+            .values()
+            .chain(db.field_expiration.values())
+            .min()
+            .map(|time| *time - Instant::now())
     }
 }
 
@@ -135,14 +293,267 @@ impl DbCleaner {
 }
 
 impl Database {
-    fn new() -> Database {
+    fn new(clean_task_notifier: Arc<Notify>) -> Database {
         Database {
             entries: HashMap::new(),
             expiration: BTreeMap::new(),
+            field_expiration: BTreeMap::new(),
+            clean_task_notifier,
+            blocked: BlockedClients::default(),
+            broker: Broker::default(),
+            indexes: Indexes::default(),
+            clients: Clients::default(),
+            watches: Watches::default(),
+            scripts: Scripts::default(),
+            script_control: Arc::default(),
+            created: HashSet::new(),
+            client: SERVER,
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    pub(crate) fn get_string(&self, key: &str) -> std::result::Result<Option<Bytes>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(Value::Integer(value)) => Ok(Some(Bytes::from(value.to_string()))),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// the string at key as GET reads it: a miss is notified and the key is
+    /// tracked for the client if it caches what it reads
+    pub(crate) fn read_string(
+        &mut self,
+        key: &str,
+    ) -> std::result::Result<Option<Bytes>, WrongType> {
+        let value = match self.entries.get(key) {
+            Some(Value::String(value)) => value.clone(),
+            Some(Value::Integer(value)) => Bytes::from(value.to_string()),
+            Some(_) => return Err(WrongType),
+            None => {
+                self.notify(Class::KeyMiss, "keymiss", key);
+                self.track(key);
+                return Ok(None);
+            }
+        };
+        self.track(key);
+        Ok(Some(value))
+    }
+
+    /// store a string, the previous value and its deadlines are dropped
+    pub(crate) fn set(&mut self, key: String, value: Bytes, expiration: Option<Duration>) {
+        let prev = self.entries.insert(key.clone(), Value::string(value));
+        if prev.is_none() {
+            self.notify(Class::New, "new", &key);
+        }
+        self.notify(Class::String, "set", &key);
+        if expiration.is_some() {
+            self.notify(Class::Generic, "expire", &key);
+        }
+        self.expiration.remove(&key);
+        self.field_expiration.remove(&key);
+
+        let next_expiration_time = self.expiration.values().min().cloned();
+
+        if let Some(dur) = expiration {
+            let expire_time = Instant::now().add(dur);
+            self.expiration.insert(key, expire_time);
+        }
+
+        if let Some(next_expiration_time) = next_expiration_time {
+            if expiration.is_none() {
+                self.clean_task_notifier.notify_one();
+            } else if let Some(expiration) = expiration {
+                let expiration = Instant::now() + expiration;
+                if expiration < next_expiration_time {
+                    self.clean_task_notifier.notify_one();
+                }
+            }
+        }
+    }
+
+    /// modify a string in place, unlike `insert` the deadline of the key is kept.
+    /// an integer is turned into its bytes first
+    pub(crate) fn get_string_mut(
+        &mut self,
+        key: &str,
+    ) -> std::result::Result<Option<&mut Bytes>, WrongType> {
+        if let Some(Value::Integer(integer)) = self.entries.get(key) {
+            let bytes = Bytes::from(integer.to_string());
+            self.entries.insert(key.to_string(), Value::String(bytes));
+        }
+        match self.value_mut(key) {
+            Some(Value::String(value)) => Ok(Some(value)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// signal that the key is written: the search indexes covering it catch up,
+    /// the clients caching it are told to drop it and the ones watching it fail
+    /// their next EXEC. it is part of notifying the event of the write, so that
+    /// failed writes and the ones changing nothing aren't signalled
+    fn touch(&mut self, key: &str) {
+        self.reindex(key);
+        self.invalidate(key);
+        self.bump_version(key);
+    }
+
+    pub(crate) fn script_control(&self) -> Arc<ScriptControl> {
+        self.script_control.clone()
+    }
+
+    /// the value at key for a command about to modify it
+    fn value_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.entries.get_mut(key)
+    }
+
+    /// remove the keys and the hash fields past their deadline, each removal is
+    /// notified like the writes of the clients
+    fn remove_expired(&mut self, now: Instant) {
+        let mut expired_keys = vec![];
+        for (key, time) in &self.expiration {
+            if time < &now {
+                expired_keys.push(key.clone());
+            }
+        }
+        self.entries.retain(|x, _| !expired_keys.contains(x));
+        for key in &expired_keys {
+            self.notify(Class::Expired, "expired", key);
+        }
+        self.expiration.retain(|x, _| !expired_keys.contains(x));
+        self.field_expiration
+            .retain(|x, _| !expired_keys.contains(x));
+
+        let mut volatile_hashes = vec![];
+        for (key, time) in &self.field_expiration {
+            if time < &now {
+                volatile_hashes.push(key.clone());
+            }
+        }
+        for key in volatile_hashes {
+            self.remove_expired_fields(&key, now);
+        }
+    }
+
+    /// remove a key together with every deadline attached to it
+    pub(crate) fn remove(&mut self, key: &str) -> Option<Value> {
+        self.created.remove(key);
+        self.expiration.remove(key);
+        self.field_expiration.remove(key);
+        self.entries.remove(key)
+    }
+
+    /// store a value at key, the previous value and its deadlines are dropped
+    pub(crate) fn insert(&mut self, key: String, value: Value) {
+        if self.remove(&key).is_none() {
+            self.notify(Class::New, "new", &key);
+        }
+        self.entries.insert(key, value);
+    }
+
+    /// remove the key if it holds an empty collection
+    pub(crate) fn remove_if_empty(&mut self, key: &str) {
+        if self.entries.get(key).is_some_and(Value::is_empty) {
+            self.remove_empty(key);
+        }
+    }
+
+    /// remove a key a write has left empty. a key created for the write, which
+    /// then added nothing, never existed as far as the clients can tell
+    fn remove_empty(&mut self, key: &str) {
+        let existed = !self.created.contains(key);
+        self.remove(key);
+        if existed {
+            self.notify(Class::Generic, "del", key);
         }
     }
 }
 
+/// the value of a string if it is written the way the integer is printed,
+/// so that turning it back into bytes gives the same string
+pub(crate) fn as_integer(value: &[u8]) -> Option<i64> {
+    let integer: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    (integer.to_string().as_bytes() == value).then_some(integer)
+}
+
+impl Value {
+    /// a string value, integers are stored as such
+    pub(crate) fn string(value: Bytes) -> Value {
+        match as_integer(&value) {
+            Some(integer) => Value::Integer(integer),
+            None => Value::String(value),
+        }
+    }
+
+    /// the representation in use, as OBJECT ENCODING reports it
+    pub(crate) fn encoding(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "int",
+            // short strings are allocated together with their object by redis
+            Value::String(value) if value.len() <= 44 => "embstr",
+            Value::String(_) => "raw",
+            Value::Hash(hash) => hash.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::ZSet(zset) => zset.encoding(),
+            Value::Stream(_) => "stream",
+            // values of module types are opaque to redis
+            Value::Bloom(_)
+            | Value::Cuckoo(_)
+            | Value::Cms(_)
+            | Value::TopK(_)
+            | Value::TDigest(_)
+            | Value::Json(_)
+            | Value::TimeSeries(_)
+            | Value::VectorSet(_)
+            | Value::Roaring(_) => "raw",
+        }
+    }
+
+    /// collections are removed from the keyspace once they become empty
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Integer(_) => false,
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+            // a stream outlives its entries, its last ID must not be forgotten
+            Value::Stream(_) => false,
+            Value::Bloom(_)
+            | Value::Cuckoo(_)
+            | Value::Cms(_)
+            | Value::TopK(_)
+            | Value::TDigest(_)
+            | Value::TimeSeries(_) => false,
+            // a document may be an empty object
+            Value::Json(_) => false,
+            Value::VectorSet(set) => set.is_empty(),
+            Value::Roaring(bitmap) => bitmap.is_empty(),
+        }
+    }
+}
+
+impl std::fmt::Display for WrongType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        )
+    }
+}
+
+impl std::error::Error for WrongType {}
+
+impl From<WrongType> for Frame {
+    fn from(e: WrongType) -> Frame {
+        Frame::Error(e.to_string())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -167,9 +578,87 @@ mod tests {
                 Some(Duration::from_secs(1)),
             )
             .unwrap();
-        assert_eq!(dbhodler.get("test"), Some(Bytes::from_static(b"h")));
+        assert_eq!(
+            dbhodler.get("test").unwrap(),
+            Some(Bytes::from_static(b"h"))
+        );
         sleep(Duration::from_secs(4)).await;
-        assert_eq!(dbhodler.get("test"), None);
+        assert_eq!(dbhodler.get("test").unwrap(), None);
+        shutdown_tx.send(()).unwrap();
+        drop(shutdown_completed_tx);
+        shutdown_completed_rx.recv().await;
+    }
+
+    /// the test runtime has a single thread, like a server on a single CPU
+    #[tokio::test]
+    async fn clean_during_script_test() {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        let dbholder = DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx);
+        let key = "test".to_string();
+        let expiration = Some(Duration::from_millis(100));
+        dbholder
+            .set(key, Bytes::from_static(b"h"), expiration)
+            .unwrap();
+
+        let script = {
+            let dbholder = dbholder.clone();
+            std::thread::spawn(move || {
+                let mut db = dbholder.lock();
+                crate::script::run(&mut db, "while true do end", vec![], vec![], false)
+            })
+        };
+        while dbholder.script_control().elapsed().is_none() {
+            sleep(Duration::from_millis(10)).await;
+        }
+        // the cleaner wakes up for the key while the script runs
+        sleep(Duration::from_millis(300)).await;
+        dbholder.script_control().kill().unwrap();
+        let reply = script.join().unwrap();
+        assert!(matches!(reply, Frame::Error(e) if e.contains("SCRIPT KILL")));
+
+        sleep(Duration::from_millis(300)).await;
+        assert_eq!(dbholder.lock().get("test").map(|_| ()), None);
+    }
+
+    #[tokio::test]
+    async fn expire_hash_fields_test() {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, mut shutdown_completed_rx) = mpsc::channel(1);
+        let dbholder = DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx.clone());
+        {
+            let mut db = dbholder.lock();
+            let hash = db.get_or_insert_hash("hash").unwrap();
+            hash.insert(Bytes::from_static(b"f1"), Bytes::from_static(b"v"));
+            hash.insert(Bytes::from_static(b"f2"), Bytes::from_static(b"v"));
+            let now = Instant::now();
+            let deadline = now + Duration::from_millis(100);
+            assert_eq!(
+                hash.expire(b"f1", deadline, ExpireCondition::Always, now),
+                1
+            );
+            assert_eq!(hash.expire(b"f2", deadline, ExpireCondition::Gt, now), 0);
+            assert_eq!(
+                hash.expire(b"f3", deadline, ExpireCondition::Always, now),
+                -2
+            );
+            let deadline = now + Duration::from_millis(300);
+            assert_eq!(hash.expire(b"f2", deadline, ExpireCondition::Lt, now), 1);
+            db.sync_hash("hash");
+        }
+
+        sleep(Duration::from_millis(200)).await;
+        {
+            let mut db = dbholder.lock();
+            let hash = db.get_hash("hash").unwrap().unwrap();
+            assert!(!hash.contains(b"f1"));
+            assert_eq!(hash.len(), 1);
+        }
+
+        // the cleaner removes the key together with its last field
+        sleep(Duration::from_millis(300)).await;
+        assert!(!dbholder.lock().entries.contains_key("hash"));
+
         shutdown_tx.send(()).unwrap();
         drop(shutdown_completed_tx);
         shutdown_completed_rx.recv().await;
@@ -0,0 +1,181 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use tokio::sync::{oneshot, Notify};
+
+use super::{Class, Database, Value};
+
+/// what a blocked client receives: the key it was served from and the popped entries
+pub(crate) type Popped = (String, Vec<(Bytes, f64)>);
+
+/// a client blocked by BZPOPMIN, BZPOPMAX or BZMPOP
+pub(crate) struct BlockedPop {
+    keys: Vec<String>,
+    max: bool,
+    count: usize,
+    sender: oneshot::Sender<Popped>,
+}
+
+/// clients blocked on sorted sets. they are served in FIFO order per key, and each
+/// of them pops its elements directly, so an element is never handed to two clients.
+/// readers such as XREAD don't consume anything, so they are all woken up instead
+#[derive(Default)]
+pub(crate) struct BlockedClients {
+    next_id: u64,
+    waiters: HashMap<u64, BlockedPop>,
+    queues: HashMap<String, VecDeque<u64>>,
+    readers: HashMap<String, Vec<Arc<Notify>>>,
+}
+
+impl Database {
+    /// block until one of the keys is fed by another client.
+    /// return an id for `unblock` and the receiver of the popped entries
+    pub(crate) fn block_pop(
+        &mut self,
+        keys: Vec<String>,
+        max: bool,
+        count: usize,
+    ) -> (u64, oneshot::Receiver<Popped>) {
+        let blocked = &mut self.blocked;
+        let id = blocked.next_id;
+        blocked.next_id += 1;
+        for key in &keys {
+            blocked.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        let (sender, receiver) = oneshot::channel();
+        blocked.waiters.insert(
+            id,
+            BlockedPop {
+                keys,
+                max,
+                count,
+                sender,
+            },
+        );
+        (id, receiver)
+    }
+
+    /// stop waiting, typically because the timeout has been reached
+    pub(crate) fn unblock(&mut self, id: u64) {
+        self.blocked.remove(id);
+    }
+
+    /// get notified the next time one of the keys is signaled by `signal_ready`
+    pub(crate) fn wait_for_keys(&mut self, keys: &[String], notify: &Arc<Notify>) {
+        for key in keys {
+            let readers = self.blocked.readers.entry(key.clone()).or_default();
+            readers.push(notify.clone());
+        }
+    }
+
+    pub(crate) fn stop_waiting(&mut self, keys: &[String], notify: &Arc<Notify>) {
+        for key in keys {
+            if let Some(readers) = self.blocked.readers.get_mut(key) {
+                readers.retain(|reader| !Arc::ptr_eq(reader, notify));
+                if readers.is_empty() {
+                    self.blocked.readers.remove(key);
+                }
+            }
+        }
+    }
+
+    /// wake up every reader waiting for new data at key
+    pub(crate) fn signal_ready(&mut self, key: &str) {
+        for reader in self.blocked.readers.remove(key).into_iter().flatten() {
+            reader.notify_one();
+        }
+    }
+
+    /// hand the elements of a sorted set to the clients blocked on it.
+    /// must be invoked by every command which may add members to a sorted set
+    pub(crate) fn serve_blocked(&mut self, key: &str) {
+        loop {
+            let zset = match self.entries.get_mut(key) {
+                Some(Value::ZSet(zset)) if !zset.is_empty() => zset,
+                _ => break,
+            };
+            let Some(waiter) = self.blocked.next_waiter(key) else {
+                break;
+            };
+
+            let popped = zset.pop(waiter.count, waiter.max);
+            match waiter.sender.send((key.to_string(), popped)) {
+                Ok(()) => {
+                    let event = if waiter.max { "zpopmax" } else { "zpopmin" };
+                    self.notify(Class::ZSet, event, key);
+                }
+                // the client has gone away, give the elements back for the next one
+                Err((_, popped)) => {
+                    for (member, score) in popped {
+                        zset.insert(member, score);
+                    }
+                }
+            }
+        }
+        self.remove_if_empty(key);
+    }
+}
+
+impl BlockedClients {
+    /// the client which has been waiting for the key the longest
+    fn next_waiter(&mut self, key: &str) -> Option<BlockedPop> {
+        let id = *self.queues.get(key)?.front()?;
+        self.remove(id)
+    }
+
+    fn remove(&mut self, id: u64) -> Option<BlockedPop> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|waiting| *waiting != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    #[test]
+    fn serve_blocked_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (_, mut first) = db.block_pop(vec!["a".to_string(), "z".to_string()], false, 1);
+        let (_, mut second) = db.block_pop(vec!["z".to_string()], true, 2);
+        let (_, third) = db.block_pop(vec!["z".to_string()], false, 1);
+
+        let zset = db.get_or_insert_zset("z").unwrap();
+        zset.insert(Bytes::from_static(b"m1"), 1.0);
+        db.serve_blocked("z");
+        // one element wakes exactly one client, the one which waited the longest
+        let (key, popped) = first.try_recv().unwrap();
+        assert_eq!(key, "z");
+        assert_eq!(popped, vec![(Bytes::from_static(b"m1"), 1.0)]);
+        assert!(second.try_recv().is_err());
+        assert!(db.get("z").is_none());
+
+        // a client which has gone away doesn't swallow the elements
+        drop(third);
+        let zset = db.get_or_insert_zset("z").unwrap();
+        for (member, score) in [("m2", 2.0), ("m3", 3.0), ("m4", 4.0)] {
+            zset.insert(Bytes::from(member), score);
+        }
+        db.serve_blocked("z");
+        let (_, popped) = second.try_recv().unwrap();
+        assert_eq!(popped.len(), 2);
+        assert_eq!(popped[0].0, Bytes::from_static(b"m4"));
+        assert_eq!(db.get_zset("z").unwrap().unwrap().len(), 1);
+        assert!(db.blocked.waiters.is_empty() && db.blocked.queues.is_empty());
+    }
+}
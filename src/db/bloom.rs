@@ -0,0 +1,182 @@
+use std::f64::consts::LN_2;
+
+use super::{Database, Value, WrongType};
+use crate::hyperloglog::murmur_hash64a;
+
+/// the error rate of every new sub-filter is tightened so that the overall rate stays bounded
+const TIGHTENING_RATIO: f64 = 0.5;
+const HASH_SEED: u64 = 0xc6a4_a793_5bd1_e995;
+
+/// a scalable bloom filter: once the last sub-filter reaches its capacity a larger
+/// one is stacked on top of it, unless the filter was reserved as non scaling
+#[derive(Debug, Clone)]
+pub(crate) struct BloomFilter {
+    layers: Vec<Layer>,
+    error_rate: f64,
+    /// growth factor of the capacity of new sub-filters, `None` for a non scaling filter
+    expansion: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    bits: Vec<u64>,
+    bit_count: u64,
+    hashes: u32,
+    capacity: u64,
+    items: u64,
+}
+
+/// returned when adding to a non scaling filter which reached its capacity
+#[derive(Debug)]
+pub(crate) struct FilterFull;
+
+impl BloomFilter {
+    pub(crate) fn new(error_rate: f64, capacity: u64, expansion: Option<u32>) -> BloomFilter {
+        BloomFilter {
+            layers: vec![Layer::new(capacity, error_rate)],
+            error_rate,
+            expansion,
+        }
+    }
+
+    pub(crate) fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash(item);
+        self.layers.iter().any(|layer| layer.contains(hash))
+    }
+
+    /// `true` if the item was added, `false` if it may already be in the filter
+    pub(crate) fn add(&mut self, item: &[u8]) -> Result<bool, FilterFull> {
+        let hash = hash(item);
+        if self.layers.iter().any(|layer| layer.contains(hash)) {
+            return Ok(false);
+        }
+        let last = self.layers.last().expect("a filter has at least one layer");
+        if last.items >= last.capacity {
+            let expansion = self.expansion.ok_or(FilterFull)?;
+            let capacity = last.capacity.saturating_mul(expansion as u64);
+            let error_rate = self.error_rate * TIGHTENING_RATIO.powi(self.layers.len() as i32);
+            self.layers.push(Layer::new(capacity, error_rate));
+        }
+        let last = self
+            .layers
+            .last_mut()
+            .expect("a filter has at least one layer");
+        last.add(hash);
+        Ok(true)
+    }
+
+    pub(crate) fn capacity(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.capacity).sum()
+    }
+
+    /// the memory taken by the bits of every sub-filter
+    pub(crate) fn size(&self) -> usize {
+        self.layers.iter().map(|layer| layer.bits.len() * 8).sum()
+    }
+
+    pub(crate) fn filters(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub(crate) fn items(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.items).sum()
+    }
+
+    pub(crate) fn expansion(&self) -> Option<u32> {
+        self.expansion
+    }
+}
+
+impl Layer {
+    fn new(capacity: u64, error_rate: f64) -> Layer {
+        let bits_per_item = -error_rate.ln() / (LN_2 * LN_2);
+        let bit_count = ((capacity as f64 * bits_per_item).ceil() as u64).max(64);
+        Layer {
+            bits: vec![0; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hashes: (LN_2 * bits_per_item).ceil().max(1.0) as u32,
+            capacity,
+            items: 0,
+        }
+    }
+
+    /// the bits of an item, derived from two hashes by double hashing
+    fn positions(&self, (a, b): (u64, u64)) -> impl Iterator<Item = u64> + '_ {
+        (0..self.hashes as u64).map(move |i| a.wrapping_add(i.wrapping_mul(b)) % self.bit_count)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    fn add(&mut self, hash: (u64, u64)) {
+        let positions: Vec<u64> = self.positions(hash).collect();
+        for bit in positions {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+    }
+}
+
+fn hash(item: &[u8]) -> (u64, u64) {
+    let a = murmur_hash64a(item, HASH_SEED);
+    (a, murmur_hash64a(item, a))
+}
+
+impl Database {
+    pub(crate) fn get_bloom(&self, key: &str) -> Result<Option<&BloomFilter>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Bloom(filter)) => Ok(Some(filter)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the filter stored at key, the one created when the key doesn't exist is built by `create`
+    pub(crate) fn get_or_insert_bloom(
+        &mut self,
+        key: &str,
+        create: impl FnOnce() -> BloomFilter,
+    ) -> Result<&mut BloomFilter, WrongType> {
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Bloom(create()));
+        match value {
+            Value::Bloom(filter) => Ok(filter),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling_bloom_test() {
+        let mut filter = BloomFilter::new(0.01, 1000, Some(2));
+        for i in 0..5000 {
+            filter.add(format!("url:{}", i).as_bytes()).unwrap();
+        }
+        assert!((0..5000).all(|i| filter.contains(format!("url:{}", i).as_bytes())));
+        // 1000 + 2000 + 4000
+        assert_eq!(filter.filters(), 3);
+        assert_eq!(filter.capacity(), 7000);
+
+        let false_positives = (0..10000)
+            .filter(|i| filter.contains(format!("other:{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        let mut fixed = BloomFilter::new(0.01, 10, None);
+        let added = (0..100)
+            .map_while(|i| fixed.add(format!("{}", i).as_bytes()).ok())
+            .count();
+        assert!(fixed.items() == 10 && added < 100);
+    }
+}
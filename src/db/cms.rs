@@ -0,0 +1,133 @@
+use super::{Database, Value, WrongType};
+use crate::hyperloglog::murmur_hash64a;
+
+/// the most counters a sketch may have, 256MB of them
+const MAX_COUNTERS: usize = 1 << 26;
+
+/// a count-min sketch: `depth` rows of `width` counters, each row hashing items with
+/// its own seed. the count of an item is the smallest of its counters, which can only
+/// overestimate the real frequency
+#[derive(Debug, Clone)]
+pub(crate) struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    /// the dimensions are checked before the counters are allocated
+    pub(crate) fn new(width: usize, depth: usize) -> Result<CountMinSketch, &'static str> {
+        match width.checked_mul(depth) {
+            Some(len) if len <= MAX_COUNTERS => Ok(CountMinSketch {
+                width,
+                depth,
+                counters: vec![0; len],
+            }),
+            _ => Err("CMS: dimensions are too large"),
+        }
+    }
+
+    /// the dimensions giving an overestimation of at most `error` times the total count,
+    /// with a `probability` of exceeding it
+    pub(crate) fn dimensions(error: f64, probability: f64) -> (usize, usize) {
+        let width = (2.0 / error).ceil() as usize;
+        let depth = (probability.ln() / 0.5f64.ln()).ceil() as usize;
+        (width.max(1), depth.max(1))
+    }
+
+    /// increment the counters of the item, returning its new estimated count
+    pub(crate) fn increment(&mut self, item: &[u8], increment: u32) -> u32 {
+        let mut min = u32::MAX;
+        for row in 0..self.depth {
+            let index = row * self.width + self.column(item, row);
+            let counter = &mut self.counters[index];
+            *counter = counter.saturating_add(increment);
+            min = min.min(*counter);
+        }
+        min
+    }
+
+    pub(crate) fn query(&self, item: &[u8]) -> u32 {
+        (0..self.depth)
+            .map(|row| self.counters[row * self.width + self.column(item, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// replace the counters with the weighted sum of the ones of the sources,
+    /// which must all have the same dimensions
+    pub(crate) fn merge(&mut self, sources: &[(&CountMinSketch, i64)]) {
+        for (i, counter) in self.counters.iter_mut().enumerate() {
+            let sum: i64 = sources
+                .iter()
+                .map(|(source, weight)| source.counters[i] as i64 * weight)
+                .sum();
+            *counter = sum.clamp(0, u32::MAX as i64) as u32;
+        }
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.depth
+    }
+
+    fn column(&self, item: &[u8], row: usize) -> usize {
+        (murmur_hash64a(item, row as u64) % self.width as u64) as usize
+    }
+}
+
+impl Database {
+    pub(crate) fn get_cms(&self, key: &str) -> Result<Option<&CountMinSketch>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Cms(sketch)) => Ok(Some(sketch)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_cms_mut(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<&mut CountMinSketch>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Cms(sketch)) => Ok(Some(sketch)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_min_sketch_test() {
+        let (width, depth) = CountMinSketch::dimensions(0.001, 0.01);
+        assert_eq!((width, depth), (2000, 7));
+
+        let mut sketch = CountMinSketch::new(width, depth).unwrap();
+        for i in 0..1000u32 {
+            sketch.increment(format!("caller:{}", i).as_bytes(), i % 10 + 1);
+        }
+        assert_eq!(sketch.increment(b"caller:9", 5), 15);
+        // never below the real count, and rarely much above it
+        let overestimated = (0..1000u32)
+            .filter(|i| sketch.query(format!("caller:{}", i).as_bytes()) > i % 10 + 1 + 5)
+            .count();
+        assert!(overestimated < 20);
+        assert!(sketch.query(b"unknown") <= 10);
+
+        let mut merged = CountMinSketch::new(width, depth).unwrap();
+        merged.merge(&[(&sketch, 2), (&sketch, 1)]);
+        assert_eq!(merged.query(b"caller:9"), 45);
+
+        assert!(CountMinSketch::new(usize::MAX, 2).is_err());
+        assert!(CountMinSketch::new(MAX_COUNTERS, 2).is_err());
+        let (width, depth) = CountMinSketch::dimensions(1e-12, 0.01);
+        assert!(CountMinSketch::new(width, depth).is_err());
+    }
+}
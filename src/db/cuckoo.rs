@@ -0,0 +1,301 @@
+use rand::Rng;
+
+use super::{bloom::FilterFull, Database, Value, WrongType};
+use crate::hyperloglog::murmur_hash64a;
+
+/// an empty slot, fingerprints are never zero
+const EMPTY: u8 = 0;
+
+/// a cuckoo filter of 8-bit fingerprints. unlike a bloom filter items can be deleted,
+/// and sub-filters are stacked when an item can't be placed in the last one
+#[derive(Debug, Clone)]
+pub(crate) struct CuckooFilter {
+    layers: Vec<Layer>,
+    capacity: u64,
+    bucket_size: usize,
+    max_iterations: u32,
+    /// growth factor of the capacity of new sub-filters, zero forbids growing
+    expansion: u32,
+    inserted: u64,
+    deleted: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    slots: Vec<u8>,
+    buckets: u64,
+}
+
+impl CuckooFilter {
+    pub(crate) fn new(
+        capacity: u64,
+        bucket_size: usize,
+        max_iterations: u32,
+        expansion: u32,
+    ) -> CuckooFilter {
+        CuckooFilter {
+            layers: vec![Layer::new(capacity, bucket_size)],
+            capacity,
+            bucket_size,
+            max_iterations,
+            expansion,
+            inserted: 0,
+            deleted: 0,
+        }
+    }
+
+    /// add an item even if it may already be there, which lets it be deleted as many times
+    pub(crate) fn add(&mut self, item: &[u8]) -> Result<(), FilterFull> {
+        let (hash, fingerprint) = fingerprint(item);
+        let last = self
+            .layers
+            .last_mut()
+            .expect("a filter has at least one layer");
+        if !last.insert(hash, fingerprint, self.bucket_size, self.max_iterations) {
+            if self.expansion == 0 {
+                return Err(FilterFull);
+            }
+            let growth = (self.expansion as u64).saturating_pow(self.layers.len() as u32);
+            let mut layer = Layer::new(self.capacity.saturating_mul(growth), self.bucket_size);
+            layer.insert(hash, fingerprint, self.bucket_size, self.max_iterations);
+            self.layers.push(layer);
+        }
+        self.inserted += 1;
+        Ok(())
+    }
+
+    pub(crate) fn contains(&self, item: &[u8]) -> bool {
+        self.count(item) > 0
+    }
+
+    /// how many times the fingerprint of the item was added, which may overestimate
+    pub(crate) fn count(&self, item: &[u8]) -> u64 {
+        let (hash, fingerprint) = fingerprint(item);
+        self.layers
+            .iter()
+            .map(|layer| {
+                let (first, second) = layer.bucket_pair(hash, fingerprint);
+                let mut count = layer.count(first, fingerprint, self.bucket_size);
+                if second != first {
+                    count += layer.count(second, fingerprint, self.bucket_size);
+                }
+                count
+            })
+            .sum()
+    }
+
+    /// remove one occurrence of the item, newest sub-filters first
+    pub(crate) fn delete(&mut self, item: &[u8]) -> bool {
+        let (hash, fingerprint) = fingerprint(item);
+        let bucket_size = self.bucket_size;
+        for layer in self.layers.iter_mut().rev() {
+            let (first, second) = layer.bucket_pair(hash, fingerprint);
+            for bucket in [first, second] {
+                let slots = layer.bucket_mut(bucket, bucket_size);
+                if let Some(slot) = slots.iter_mut().find(|slot| **slot == fingerprint) {
+                    *slot = EMPTY;
+                    self.inserted -= 1;
+                    self.deleted += 1;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.layers.iter().map(|layer| layer.slots.len()).sum()
+    }
+
+    pub(crate) fn buckets(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.buckets).sum()
+    }
+
+    pub(crate) fn filters(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub(crate) fn inserted(&self) -> u64 {
+        self.inserted
+    }
+
+    pub(crate) fn deleted(&self) -> u64 {
+        self.deleted
+    }
+
+    pub(crate) fn bucket_size(&self) -> usize {
+        self.bucket_size
+    }
+
+    pub(crate) fn expansion(&self) -> u32 {
+        self.expansion
+    }
+
+    pub(crate) fn max_iterations(&self) -> u32 {
+        self.max_iterations
+    }
+}
+
+impl Layer {
+    fn new(capacity: u64, bucket_size: usize) -> Layer {
+        let buckets = capacity
+            .div_ceil(bucket_size as u64)
+            .max(1)
+            .next_power_of_two();
+        Layer {
+            slots: vec![EMPTY; buckets as usize * bucket_size],
+            buckets,
+        }
+    }
+
+    fn bucket_pair(&self, hash: u64, fingerprint: u8) -> (u64, u64) {
+        let first = hash % self.buckets;
+        (first, self.alternate(first, fingerprint))
+    }
+
+    /// the other bucket of a fingerprint, applying it twice gives back the first one
+    fn alternate(&self, bucket: u64, fingerprint: u8) -> u64 {
+        (bucket ^ (fingerprint as u64).wrapping_mul(0x5bd1_e995)) % self.buckets
+    }
+
+    fn bucket_mut(&mut self, bucket: u64, bucket_size: usize) -> &mut [u8] {
+        let start = bucket as usize * bucket_size;
+        &mut self.slots[start..start + bucket_size]
+    }
+
+    fn count(&self, bucket: u64, fingerprint: u8, bucket_size: usize) -> u64 {
+        let start = bucket as usize * bucket_size;
+        self.slots[start..start + bucket_size]
+            .iter()
+            .filter(|slot| **slot == fingerprint)
+            .count() as u64
+    }
+
+    fn place(&mut self, bucket: u64, fingerprint: u8, bucket_size: usize) -> bool {
+        match self
+            .bucket_mut(bucket, bucket_size)
+            .iter_mut()
+            .find(|slot| **slot == EMPTY)
+        {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// place the fingerprint, evicting others to their alternate bucket when both are full.
+    /// the evictions are undone when no room was found within `max_iterations`
+    fn insert(
+        &mut self,
+        hash: u64,
+        fingerprint: u8,
+        bucket_size: usize,
+        max_iterations: u32,
+    ) -> bool {
+        let (first, second) = self.bucket_pair(hash, fingerprint);
+        if self.place(first, fingerprint, bucket_size)
+            || self.place(second, fingerprint, bucket_size)
+        {
+            return true;
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut evictions = vec![];
+        let mut current = fingerprint;
+        let mut bucket = if rng.gen() { first } else { second };
+        for _ in 0..max_iterations {
+            let slot = rng.gen_range(0..bucket_size);
+            std::mem::swap(
+                &mut current,
+                &mut self.bucket_mut(bucket, bucket_size)[slot],
+            );
+            evictions.push((bucket, slot));
+            bucket = self.alternate(bucket, current);
+            if self.place(bucket, current, bucket_size) {
+                return true;
+            }
+        }
+        for (bucket, slot) in evictions.into_iter().rev() {
+            std::mem::swap(
+                &mut current,
+                &mut self.bucket_mut(bucket, bucket_size)[slot],
+            );
+        }
+        false
+    }
+}
+
+/// the hash choosing the buckets and the non zero fingerprint stored in them
+fn fingerprint(item: &[u8]) -> (u64, u8) {
+    let hash = murmur_hash64a(item, 0);
+    (hash, (hash % 255 + 1) as u8)
+}
+
+impl Database {
+    pub(crate) fn get_cuckoo(&self, key: &str) -> Result<Option<&CuckooFilter>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Cuckoo(filter)) => Ok(Some(filter)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_cuckoo_mut(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<&mut CuckooFilter>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Cuckoo(filter)) => Ok(Some(filter)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the filter stored at key, the one created when the key doesn't exist is built by `create`
+    pub(crate) fn get_or_insert_cuckoo(
+        &mut self,
+        key: &str,
+        create: impl FnOnce() -> CuckooFilter,
+    ) -> Result<&mut CuckooFilter, WrongType> {
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Cuckoo(create()));
+        match value {
+            Value::Cuckoo(filter) => Ok(filter),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuckoo_test() {
+        let mut filter = CuckooFilter::new(1000, 2, 20, 1);
+        for i in 0..3000 {
+            filter.add(format!("url:{}", i).as_bytes()).unwrap();
+        }
+        assert!(filter.filters() > 1);
+        assert!((0..3000).all(|i| filter.contains(format!("url:{}", i).as_bytes())));
+
+        filter.add(b"url:0").unwrap();
+        assert!(filter.count(b"url:0") >= 2);
+        assert!(filter.delete(b"url:0") && filter.delete(b"url:0"));
+        assert!((1..3000).all(|i| filter.contains(format!("url:{}", i).as_bytes())));
+        assert_eq!((filter.inserted(), filter.deleted()), (2999, 2));
+
+        let mut fixed = CuckooFilter::new(4, 1, 10, 0);
+        let added = (0..100)
+            .map_while(|i| fixed.add(format!("{}", i).as_bytes()).ok())
+            .count();
+        assert!(added < 100 && fixed.inserted() == added as u64);
+    }
+}
@@ -0,0 +1,254 @@
+use bytes::Bytes;
+
+use super::Database;
+use crate::config;
+
+/// the class of a keyspace event, each has a letter in notify-keyspace-events
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Class {
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    ZSet,
+    Expired,
+    Evicted,
+    Stream,
+    Module,
+    KeyMiss,
+    New,
+}
+
+/// publish to `__keyspace@0__:<key>` channels
+const KEYSPACE: usize = 1 << 12;
+/// publish to `__keyevent@0__:<event>` channels
+const KEYEVENT: usize = 1 << 13;
+
+const LETTERS: [(char, Class); 12] = [
+    ('g', Class::Generic),
+    ('$', Class::String),
+    ('l', Class::List),
+    ('s', Class::Set),
+    ('h', Class::Hash),
+    ('z', Class::ZSet),
+    ('x', Class::Expired),
+    ('e', Class::Evicted),
+    ('t', Class::Stream),
+    ('d', Class::Module),
+    ('m', Class::KeyMiss),
+    ('n', Class::New),
+];
+
+/// the classes `A` stands for, every one but key misses and new keys
+const ALL: usize = (1 << 10) - 1;
+
+impl Class {
+    fn bit(self) -> usize {
+        1 << self as usize
+    }
+}
+
+/// the flags of a notify-keyspace-events string such as `KEA` or `Kx`
+pub(crate) fn parse_events(text: &str) -> Option<usize> {
+    text.chars().try_fold(0, |flags, c| {
+        let flag = match c {
+            'A' => ALL,
+            'K' => KEYSPACE,
+            'E' => KEYEVENT,
+            c => LETTERS.iter().find(|(letter, _)| *letter == c)?.1.bit(),
+        };
+        Some(flags | flag)
+    })
+}
+
+/// the notify-keyspace-events string of the flags, in the order redis writes it
+pub(crate) fn format_events(flags: usize) -> String {
+    let mut text = String::new();
+    let letter = |class: Class| LETTERS[class as usize].0;
+    if flags & ALL == ALL {
+        text.push('A');
+    } else {
+        for (letter, class) in &LETTERS[..10] {
+            if flags & class.bit() != 0 {
+                text.push(*letter);
+            }
+        }
+    }
+    if flags & KEYSPACE != 0 {
+        text.push('K');
+    }
+    if flags & KEYEVENT != 0 {
+        text.push('E');
+    }
+    for class in [Class::KeyMiss, Class::New] {
+        if flags & class.bit() != 0 {
+            text.push(letter(class));
+        }
+    }
+    text
+}
+
+impl Database {
+    /// a key has been created empty for a write. the new key event is published
+    /// along with the first event of the write, which a failed write never sends
+    pub(super) fn create(&mut self, key: &str) {
+        self.created.insert(key.to_string());
+    }
+
+    /// publish a keyspace event if its class is enabled. every event but a key
+    /// miss or a new key follows a write, the key is touched whether or not the
+    /// event is published
+    pub(crate) fn notify(&mut self, class: Class, event: &str, key: &str) {
+        if !matches!(class, Class::KeyMiss | Class::New) {
+            if self.created.remove(key) {
+                self.notify(Class::New, "new", key);
+            }
+            self.touch(key);
+        }
+        let flags = config::get(&config::NOTIFY_KEYSPACE_EVENTS);
+        if flags & class.bit() == 0 {
+            return;
+        }
+        if flags & KEYSPACE != 0 {
+            let channel = Bytes::from(format!("__keyspace@0__:{}", key));
+            self.publish(&channel, &Bytes::copy_from_slice(event.as_bytes()));
+        }
+        if flags & KEYEVENT != 0 {
+            let channel = Bytes::from(format!("__keyevent@0__:{}", event));
+            self.publish(&channel, &Bytes::copy_from_slice(key.as_bytes()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::{mpsc, Notify};
+
+    use super::*;
+    use crate::{db::BACKLOG, Command, Frame};
+
+    #[test]
+    fn events_flags_test() {
+        let cases = [
+            ("", ""),
+            ("KEA", "AKE"),
+            ("Kx", "xK"),
+            ("Eg$lshzxetd", "AE"),
+            ("nmEKA", "AKEmn"),
+            ("xKx", "xK"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(format_events(parse_events(text).unwrap()), expected);
+        }
+        assert!(parse_events("Kq").is_none());
+    }
+
+    #[test]
+    fn module_events_test() {
+        let flags = parse_events("Ed").unwrap();
+        let _events = config::Override::new(&config::NOTIFY_KEYSPACE_EVENTS, flags);
+        let (mut db, mut receiver) = subscribed();
+
+        // the writes which change nothing, or fail, aren't notified
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["BF.RESERVE", "bf", "0.01", "100"], &["bf.reserve"]),
+            (&["BF.MADD", "bf", "a", "b"], &["bf.add"]),
+            (&["BF.ADD", "bf", "a"], &[]),
+            (&["CF.ADD", "cf", "a"], &["cf.add"]),
+            (&["CF.ADDNX", "cf", "a"], &[]),
+            (&["CF.DEL", "cf", "a"], &["cf.del"]),
+            (&["CF.DEL", "cf", "a"], &[]),
+            (&["CMS.INITBYDIM", "cms", "10", "2"], &["cms.init"]),
+            (&["CMS.INCRBY", "cms", "a", "1"], &["cms.incrby"]),
+            (&["CMS.MERGE", "cms", "1", "cms"], &["cms.merge"]),
+            (&["TOPK.RESERVE", "topk", "2"], &["topk.reserve"]),
+            (&["TOPK.ADD", "topk", "a"], &["topk.add"]),
+            (&["TDIGEST.CREATE", "td"], &["tdigest.create"]),
+            (&["TDIGEST.ADD", "td", "1", "2"], &["tdigest.add"]),
+            (&["TDIGEST.MERGE", "td2", "1", "td"], &["tdigest.merge"]),
+            (&["TDIGEST.RESET", "td"], &["tdigest.reset"]),
+            (&["TDIGEST.QUANTILE", "td2", "0.5"], &[]),
+            (&["TS.CREATE", "ts"], &["ts.create"]),
+            (&["TS.CREATE", "ts2"], &["ts.create"]),
+            (
+                &["TS.CREATERULE", "ts", "ts2", "AGGREGATION", "sum", "10"],
+                &["ts.createrule", "ts.createrule"],
+            ),
+            (&["TS.ADD", "ts", "1", "1"], &["ts.add"]),
+            (&["TS.ADD", "ts", "20", "1"], &["ts.add", "ts.add"]),
+            (&["TS.DEL", "ts", "0", "5"], &["ts.del"]),
+            (&["TS.DEL", "ts", "0", "5"], &[]),
+            (&["TS.ADD", "topk", "1", "1"], &[]),
+            (
+                &["JSON.SET", "doc", "$", r#"{"n":1,"a":[]}"#],
+                &["json.set"],
+            ),
+            (&["JSON.NUMINCRBY", "doc", "$.n", "2"], &["json.numincrby"]),
+            (&["JSON.ARRPOP", "doc", "$.a"], &[]),
+            (&["JSON.ARRAPPEND", "doc", "$.a", "1"], &["json.arrappend"]),
+            (&["JSON.STRAPPEND", "doc", "$.n", r#""x""#], &[]),
+            (&["VADD", "vset", "VALUES", "2", "1", "0", "a"], &["vadd"]),
+            (&["VSETATTR", "vset", "b", r#"{"x":1}"#], &[]),
+            (&["VSETATTR", "vset", "a", r#"{"x":1}"#], &["vsetattr"]),
+            (&["VREM", "vset", "a"], &["vrem"]),
+            (&["R.ADD", "r", "1", "2"], &["r.add"]),
+            (&["R.ADD", "r", "1"], &[]),
+            (&["R.REM", "r", "1"], &["r.rem"]),
+            (&["R.BITOP", "OR", "r2", "r"], &["r.bitop"]),
+        ];
+        assert_events(&mut db, &mut receiver, cases);
+    }
+
+    #[test]
+    fn new_events_test() {
+        let flags = parse_events("Egn").unwrap();
+        let _events = config::Override::new(&config::NOTIFY_KEYSPACE_EVENTS, flags);
+        let (mut db, mut receiver) = subscribed();
+
+        // a key created for a write which fails, or adds nothing, never existed
+        let cases: &[(&[&str], &[&str])] = &[
+            (&["HSET", "h", "f", "v"], &["new"]),
+            (&["HSET", "h", "f", "w"], &[]),
+            (&["XADD", "s", "0-0", "f", "v"], &[]),
+            (&["XADD", "s", "*", "f", "v"], &["new"]),
+            (&["SET", "str", "x"], &["new"]),
+            (&["SADD", "str", "m"], &[]),
+            (&["ZADD", "z", "XX", "1", "m"], &[]),
+            (&["ZADD", "z", "1", "m"], &["new"]),
+            (&["ZREM", "z", "m"], &["del"]),
+            (&["BF.ADD", "bf", "a"], &["new"]),
+        ];
+        assert_events(&mut db, &mut receiver, cases);
+        assert!(db.get("s").is_some() && db.get("z").is_none());
+    }
+
+    /// a database with a subscriber to every keyevent channel
+    fn subscribed() -> (Database, mpsc::Receiver<Frame>) {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, receiver) = mpsc::channel(BACKLOG);
+        db.add_subscriber(1, sender);
+        db.psubscribe(1, Bytes::from_static(b"__keyevent@0__:*"));
+        (db, receiver)
+    }
+
+    /// apply every command, checking the events it publishes
+    fn assert_events(
+        db: &mut Database,
+        receiver: &mut mpsc::Receiver<Frame>,
+        cases: &[(&[&str], &[&str])],
+    ) {
+        for (words, expected) in cases {
+            Command::parse(words).apply(db);
+            let mut events = vec![];
+            while let Ok(Frame::Array(message)) = receiver.try_recv() {
+                if let [_, _, Frame::Bulk(channel), _] = &message[..] {
+                    events.push(String::from_utf8_lossy(&channel[15..]).into_owned());
+                }
+            }
+            assert_eq!(events, *expected, "{:?}", words);
+        }
+    }
+}
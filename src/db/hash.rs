@@ -0,0 +1,307 @@
+use std::{collections::HashMap, time::Duration};
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use super::{Class, Database, Value, WrongType};
+use crate::config;
+
+/// conditions accepted by HEXPIRE and HPEXPIRE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ExpireCondition {
+    Always,
+    Nx,
+    Xx,
+    Gt,
+    Lt,
+}
+
+/// a hash keeps its fields in a listpack, a plain vector searched linearly,
+/// until it holds too many of them or a too long one
+#[derive(Debug, Default)]
+pub(crate) struct Hash {
+    fields: Fields,
+    expiration: HashMap<Bytes, Instant>,
+}
+
+#[derive(Debug)]
+enum Fields {
+    Listpack(Vec<(Bytes, Bytes)>),
+    Table(HashMap<Bytes, Bytes>),
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields::Listpack(vec![])
+    }
+}
+
+impl Hash {
+    pub(crate) fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        match &self.fields {
+            Fields::Listpack(pairs) => pairs
+                .iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, value)| value),
+            Fields::Table(fields) => fields.get(field),
+        }
+    }
+
+    /// insert a field and drop its previous ttl. return true if the field is new
+    pub(crate) fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        self.expiration.remove(&field);
+        match &mut self.fields {
+            Fields::Listpack(pairs) => {
+                let max_value = config::get(&config::HASH_MAX_LISTPACK_VALUE);
+                let too_long = field.len() > max_value || value.len() > max_value;
+                let inserted = match pairs.iter_mut().find(|(name, _)| *name == field) {
+                    Some((_, previous)) => {
+                        *previous = value;
+                        false
+                    }
+                    None => {
+                        pairs.push((field, value));
+                        true
+                    }
+                };
+                if too_long || pairs.len() > config::get(&config::HASH_MAX_LISTPACK_ENTRIES) {
+                    self.fields = Fields::Table(std::mem::take(pairs).into_iter().collect());
+                }
+                inserted
+            }
+            Fields::Table(fields) => fields.insert(field, value).is_none(),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, field: &[u8]) -> bool {
+        self.expiration.remove(field);
+        match &mut self.fields {
+            Fields::Listpack(pairs) => match pairs.iter().position(|(name, _)| name == field) {
+                Some(i) => {
+                    pairs.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Fields::Table(fields) => fields.remove(field).is_some(),
+        }
+    }
+
+    pub(crate) fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.fields {
+            Fields::Listpack(pairs) => pairs.len(),
+            Fields::Table(fields) => fields.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, &Bytes)> + '_> {
+        match &self.fields {
+            Fields::Listpack(pairs) => Box::new(pairs.iter().map(|(field, value)| (field, value))),
+            Fields::Table(fields) => Box::new(fields.iter()),
+        }
+    }
+
+    /// the name OBJECT ENCODING reports
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.fields {
+            Fields::Listpack(_) => "listpack",
+            Fields::Table(_) => "hashtable",
+        }
+    }
+
+    /// set the deadline of a field. the reply codes follow HEXPIRE:
+    /// -2 no such field, 0 condition not met, 1 deadline updated,
+    /// 2 field deleted because the deadline has already passed
+    pub(crate) fn expire(
+        &mut self,
+        field: &[u8],
+        deadline: Instant,
+        condition: ExpireCondition,
+        now: Instant,
+    ) -> i64 {
+        if !self.contains(field) {
+            return -2;
+        }
+        let current = self.expiration.get(field);
+        let allowed = match (condition, current) {
+            (ExpireCondition::Always, _) => true,
+            (ExpireCondition::Nx, current) => current.is_none(),
+            (ExpireCondition::Xx, current) => current.is_some(),
+            // a field without ttl is regarded as living forever
+            (ExpireCondition::Gt, Some(current)) => deadline > *current,
+            (ExpireCondition::Gt, None) => false,
+            (ExpireCondition::Lt, Some(current)) => deadline < *current,
+            (ExpireCondition::Lt, None) => true,
+        };
+        if !allowed {
+            return 0;
+        }
+        if deadline <= now {
+            self.remove(field);
+            return 2;
+        }
+        self.expiration
+            .insert(Bytes::copy_from_slice(field), deadline);
+        1
+    }
+
+    /// remaining time to live of a field.
+    /// `None` if the field doesn't exist, `Some(None)` if it is persistent
+    pub(crate) fn ttl(&self, field: &[u8], now: Instant) -> Option<Option<Duration>> {
+        if !self.contains(field) {
+            return None;
+        }
+        Some(self.expiration.get(field).map(|deadline| *deadline - now))
+    }
+
+    /// drop the ttl of a field. the reply codes follow HPERSIST:
+    /// -2 no such field, -1 field has no ttl, 1 ttl removed
+    pub(crate) fn persist(&mut self, field: &[u8]) -> i64 {
+        if !self.contains(field) {
+            -2
+        } else if self.expiration.remove(field).is_some() {
+            1
+        } else {
+            -1
+        }
+    }
+
+    /// return whether some field has expired
+    fn remove_expired(&mut self, now: Instant) -> bool {
+        let expired: Vec<Bytes> = self
+            .expiration
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in &expired {
+            self.remove(field);
+        }
+        !expired.is_empty()
+    }
+
+    fn next_expiration(&self) -> Option<Instant> {
+        self.expiration.values().min().cloned()
+    }
+}
+
+impl Database {
+    /// get the hash stored at key. fields which have expired are dropped first
+    pub(crate) fn get_hash(&mut self, key: &str) -> Result<Option<&mut Hash>, WrongType> {
+        self.remove_expired_fields(key, Instant::now());
+        match self.entries.get_mut(key) {
+            Some(Value::Hash(hash)) => Ok(Some(hash)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the hash stored at key, an empty one is created if the key doesn't exist.
+    /// `sync_hash` must be invoked once the modification is done
+    pub(crate) fn get_or_insert_hash(&mut self, key: &str) -> Result<&mut Hash, WrongType> {
+        self.remove_expired_fields(key, Instant::now());
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Hash(Hash::default()));
+        match value {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// bring the bookkeeping of a hash up to date after a command: the key is
+    /// removed with its last field and the cleaner learns about the next field
+    /// deadline. the change itself is signalled by the event of the command,
+    /// this does nothing when the hash hasn't changed
+    pub(crate) fn sync_hash(&mut self, key: &str) {
+        let next_expiration = match self.entries.get(key) {
+            Some(Value::Hash(hash)) if hash.is_empty() => {
+                self.remove_empty(key);
+                return;
+            }
+            Some(Value::Hash(hash)) => hash.next_expiration(),
+            _ => return,
+        };
+
+        match next_expiration {
+            Some(next_expiration) => {
+                let earliest = self
+                    .expiration
+                    .values()
+                    .chain(self.field_expiration.values())
+                    .min()
+                    .cloned();
+                self.field_expiration
+                    .insert(key.to_string(), next_expiration);
+                if earliest.is_none_or(|earliest| next_expiration < earliest) {
+                    self.clean_task_notifier.notify_one();
+                }
+            }
+            None => {
+                self.field_expiration.remove(key);
+            }
+        }
+    }
+
+    pub(super) fn remove_expired_fields(&mut self, key: &str, now: Instant) {
+        if self
+            .field_expiration
+            .get(key)
+            .is_none_or(|deadline| *deadline > now)
+        {
+            return;
+        }
+        if let Some(Value::Hash(hash)) = self.entries.get_mut(key) {
+            if hash.remove_expired(now) {
+                self.notify(Class::Hash, "hexpired", key);
+            }
+        }
+        self.sync_hash(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::Command;
+
+    #[test]
+    fn unchanged_hash_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        Command::parse(&["HSET", "h", "f", "v"]).apply(&mut db);
+        let keys = vec!["h".to_string(), "missing".to_string()];
+        db.watch(keys.clone());
+
+        let writes: [&[&str]; 5] = [
+            &["HDEL", "h", "g"],
+            &["HDEL", "missing", "f"],
+            &["HEXPIRE", "h", "100", "FIELDS", "1", "g"],
+            &["HEXPIRE", "missing", "100", "FIELDS", "1", "f"],
+            &["HPERSIST", "h", "FIELDS", "1", "f"],
+        ];
+        for words in writes {
+            Command::parse(words).apply(&mut db);
+        }
+        assert!(!db.unwatch(db.client));
+
+        db.watch(keys);
+        Command::parse(&["HEXPIRE", "h", "100", "FIELDS", "1", "f"]).apply(&mut db);
+        assert!(db.unwatch(db.client));
+    }
+}
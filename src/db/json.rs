@@ -0,0 +1,750 @@
+use regex::Regex;
+use serde_json::Value as Json;
+use std::cmp::Ordering;
+
+use super::{Database, Value, WrongType};
+
+/// a path selecting values of a json document. a path starting with `$` is a JSONPath and
+/// selects any number of values, any other path is in the legacy syntax where `.` is the
+/// root and commands reply about a single value
+#[derive(Debug, Clone)]
+pub(crate) struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+    legacy: bool,
+}
+
+/// the way to a value from the root of its document
+pub(crate) type Location = Vec<Step>;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Step {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Child(Selector),
+    /// the selector is applied to the node and to every node below it
+    Descendant(Selector),
+}
+
+#[derive(Debug, Clone)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Slice(Option<i64>, Option<i64>, i64),
+    Union(Vec<Selector>),
+    Filter(Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Operand),
+    /// true when the operand selects a value
+    Exists(Operand),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    /// a path from the current node, `@`
+    Relative(Vec<Segment>),
+    /// a path from the root of the document, `$`
+    Absolute(Vec<Segment>),
+    Literal(Json),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+}
+
+impl JsonPath {
+    pub(crate) fn parse(source: &str) -> Result<JsonPath, String> {
+        let (text, legacy) = match source.strip_prefix('.').unwrap_or(source) {
+            _ if source.starts_with('$') => (source.to_string(), false),
+            rest if rest.is_empty() || rest.starts_with('[') => (format!("${}", rest), true),
+            rest => (format!("$.{}", rest), true),
+        };
+        let mut reader = Reader {
+            chars: text.chars().collect(),
+            pos: 1,
+        };
+        let segments = reader.segments()?;
+        if reader.pos < reader.chars.len() {
+            return reader.error();
+        }
+        Ok(JsonPath {
+            source: source.to_string(),
+            segments,
+            legacy,
+        })
+    }
+
+    pub(crate) fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    pub(crate) fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// the locations of the selected values, in document order
+    pub(crate) fn locations(&self, root: &Json) -> Vec<Location> {
+        select(root, root, &self.segments)
+            .into_iter()
+            .map(|(location, _)| location)
+            .collect()
+    }
+
+    pub(crate) fn select<'a>(&self, root: &'a Json) -> Vec<&'a Json> {
+        select(root, root, &self.segments)
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// where JSON.SET adds a member when the path selects nothing: the objects selected
+    /// by the path without its last segment, provided that one is a plain name
+    pub(crate) fn insertion_points(&self, root: &Json) -> Vec<(Location, String)> {
+        match self.segments.split_last() {
+            Some((Segment::Child(Selector::Name(name)), parent)) => select(root, root, parent)
+                .into_iter()
+                .filter(|(_, value)| value.is_object())
+                .map(|(location, _)| (location, name.clone()))
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+pub(crate) fn lookup_mut<'a>(root: &'a mut Json, location: &[Step]) -> Option<&'a mut Json> {
+    location.iter().try_fold(root, |value, step| match step {
+        Step::Key(key) => value.get_mut(key.as_str()),
+        Step::Index(index) => value.get_mut(*index),
+    })
+}
+
+/// remove the values below the root at the locations, returning how many were removed
+pub(crate) fn remove(root: &mut Json, mut locations: Vec<Location>) -> usize {
+    // deeper values and later elements go first, so that the other locations stay valid
+    locations.sort_unstable_by(|a, b| b.cmp(a));
+    locations.dedup();
+    let mut removed = 0;
+    for location in locations {
+        let Some((last, parent)) = location.split_last() else {
+            continue;
+        };
+        match (lookup_mut(root, parent), last) {
+            (Some(Json::Object(map)), Step::Key(key)) => {
+                removed += map.shift_remove(key).is_some() as usize
+            }
+            (Some(Json::Array(array)), Step::Index(index)) if *index < array.len() => {
+                array.remove(*index);
+                removed += 1;
+            }
+            _ => {}
+        }
+    }
+    removed
+}
+
+/// the name JSON.TYPE gives to the type of a value
+pub(crate) fn type_name(value: &Json) -> &'static str {
+    match value {
+        Json::Null => "null",
+        Json::Bool(_) => "boolean",
+        Json::Number(n) if n.is_f64() => "number",
+        Json::Number(_) => "integer",
+        Json::String(_) => "string",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+    }
+}
+
+/// the whitespace JSON.GET puts in its serialization, none by default
+#[derive(Debug, Default)]
+pub(crate) struct Format {
+    pub(crate) indent: String,
+    pub(crate) newline: String,
+    pub(crate) space: String,
+}
+
+impl Format {
+    pub(crate) fn serialize(&self, value: &Json) -> String {
+        let mut out = String::new();
+        self.write(value, 0, &mut out);
+        out
+    }
+
+    fn write(&self, value: &Json, level: usize, out: &mut String) {
+        match value {
+            Json::Array(array) if !array.is_empty() => {
+                out.push('[');
+                for (i, element) in array.iter().enumerate() {
+                    self.separate(i, level + 1, out);
+                    self.write(element, level + 1, out);
+                }
+                self.separate(0, level, out);
+                out.push(']');
+            }
+            Json::Object(map) if !map.is_empty() => {
+                out.push('{');
+                for (i, (key, member)) in map.iter().enumerate() {
+                    self.separate(i, level + 1, out);
+                    out.push_str(&Json::from(key.as_str()).to_string());
+                    out.push(':');
+                    out.push_str(&self.space);
+                    self.write(member, level + 1, out);
+                }
+                self.separate(0, level, out);
+                out.push('}');
+            }
+            scalar => out.push_str(&scalar.to_string()),
+        }
+    }
+
+    /// the comma after the previous element, then the new line and the indentation
+    fn separate(&self, i: usize, level: usize, out: &mut String) {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&self.newline);
+        out.push_str(&self.indent.repeat(level));
+    }
+}
+
+fn select<'a>(root: &'a Json, node: &'a Json, segments: &[Segment]) -> Vec<(Location, &'a Json)> {
+    let mut current = vec![(vec![], node)];
+    for segment in segments {
+        let mut next = vec![];
+        for (location, value) in current {
+            match segment {
+                Segment::Child(selector) => apply(root, selector, location, value, &mut next),
+                Segment::Descendant(selector) => {
+                    for (location, value) in descendants(location, value) {
+                        apply(root, selector, location, value, &mut next);
+                    }
+                }
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+fn apply<'a>(
+    root: &'a Json,
+    selector: &Selector,
+    location: Location,
+    value: &'a Json,
+    out: &mut Vec<(Location, &'a Json)>,
+) {
+    match (selector, value) {
+        (Selector::Name(name), Json::Object(map)) => {
+            if let Some(member) = map.get(name) {
+                out.push((child(&location, Step::Key(name.clone())), member));
+            }
+        }
+        (Selector::Index(index), Json::Array(array)) => {
+            if let Some(index) = normalize(*index, array.len()) {
+                out.push((child(&location, Step::Index(index)), &array[index]));
+            }
+        }
+        (Selector::Slice(start, end, step), Json::Array(array)) => {
+            for index in slice(*start, *end, *step, array.len()) {
+                out.push((child(&location, Step::Index(index)), &array[index]));
+            }
+        }
+        (Selector::Wildcard, _) => out.extend(children(&location, value)),
+        (Selector::Union(selectors), _) => {
+            for selector in selectors {
+                apply(root, selector, location.clone(), value, out);
+            }
+        }
+        (Selector::Filter(expr), _) => out.extend(
+            children(&location, value)
+                .into_iter()
+                .filter(|(_, child)| expr.eval(root, child)),
+        ),
+        _ => {}
+    }
+}
+
+fn child(location: &Location, step: Step) -> Location {
+    let mut location = location.clone();
+    location.push(step);
+    location
+}
+
+fn children<'a>(location: &Location, value: &'a Json) -> Vec<(Location, &'a Json)> {
+    match value {
+        Json::Object(map) => map
+            .iter()
+            .map(|(key, member)| (child(location, Step::Key(key.clone())), member))
+            .collect(),
+        Json::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(index, element)| (child(location, Step::Index(index)), element))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// the node followed by every node below it, in document order
+fn descendants(location: Location, value: &Json) -> Vec<(Location, &Json)> {
+    let mut nodes = vec![];
+    let mut stack = vec![(location, value)];
+    while let Some((location, value)) = stack.pop() {
+        stack.extend(children(&location, value).into_iter().rev());
+        nodes.push((location, value));
+    }
+    nodes
+}
+
+/// a negative index counts from the end of the array
+pub(crate) fn normalize(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// the indices of `[start:end:step]`, which are clamped to the array like python does
+fn slice(start: Option<i64>, end: Option<i64>, step: i64, len: usize) -> Vec<usize> {
+    let len = len as i64;
+    let clamp = |index: i64, low: i64, high: i64| {
+        if index < 0 {
+            (index + len).max(low)
+        } else {
+            index.min(high)
+        }
+    };
+    let mut indices = vec![];
+    if step > 0 {
+        let mut index = start.map_or(0, |i| clamp(i, 0, len));
+        let end = end.map_or(len, |i| clamp(i, 0, len));
+        while index < end {
+            indices.push(index as usize);
+            index += step;
+        }
+    } else if step < 0 {
+        let mut index = start.map_or(len - 1, |i| clamp(i, -1, len - 1));
+        let end = end.map_or(-1, |i| clamp(i, -1, len - 1));
+        while index > end {
+            indices.push(index as usize);
+            index += step;
+        }
+    }
+    indices
+}
+
+impl Expr {
+    fn eval(&self, root: &Json, node: &Json) -> bool {
+        match self {
+            Expr::Or(left, right) => left.eval(root, node) || right.eval(root, node),
+            Expr::And(left, right) => left.eval(root, node) && right.eval(root, node),
+            Expr::Not(expr) => !expr.eval(root, node),
+            Expr::Exists(operand) => operand.resolve(root, node).is_some(),
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (left.resolve(root, node), right.resolve(root, node));
+                match (op, left, right) {
+                    (Op::Eq, left, right) => equal(left, right),
+                    (Op::Ne, left, right) => !equal(left, right),
+                    (Op::Match, Some(Json::String(text)), Some(Json::String(pattern))) => {
+                        Regex::new(pattern).is_ok_and(|regex| regex.is_match(text))
+                    }
+                    (Op::Match, _, _) => false,
+                    (op, Some(left), Some(right)) => {
+                        let ordering = match (left, right) {
+                            (Json::Number(a), Json::Number(b)) => {
+                                a.as_f64().partial_cmp(&b.as_f64())
+                            }
+                            (Json::String(a), Json::String(b)) => Some(a.cmp(b)),
+                            _ => None,
+                        };
+                        ordering.is_some_and(|ordering| match op {
+                            Op::Lt => ordering == Ordering::Less,
+                            Op::Le => ordering != Ordering::Greater,
+                            Op::Gt => ordering == Ordering::Greater,
+                            _ => ordering != Ordering::Less,
+                        })
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// numbers are equal whatever their representation, `1 == 1.0`
+fn equal(left: Option<&Json>, right: Option<&Json>) -> bool {
+    match (left, right) {
+        (Some(Json::Number(a)), Some(Json::Number(b))) => a.as_f64() == b.as_f64(),
+        (left, right) => left == right,
+    }
+}
+
+impl Operand {
+    /// the single value the operand stands for, a path selecting several has none
+    fn resolve<'a>(&'a self, root: &'a Json, node: &'a Json) -> Option<&'a Json> {
+        let selected = match self {
+            Operand::Literal(value) => return Some(value),
+            Operand::Relative(segments) => select(root, node, segments),
+            Operand::Absolute(segments) => select(root, root, segments),
+        };
+        match &selected[..] {
+            [(_, value)] => Some(value),
+            _ => None,
+        }
+    }
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn error<T>(&self) -> Result<T, String> {
+        Err(format!("invalid JSONPath at offset {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.eat_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    fn eat_str(&mut self, text: &str) -> bool {
+        let len = text.chars().count();
+        let found = self
+            .chars
+            .get(self.pos..self.pos + len)
+            .is_some_and(|chars| chars.iter().copied().eq(text.chars()));
+        if found {
+            self.pos += len;
+        }
+        found
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// the segments up to the first character which can't continue the path
+    fn segments(&mut self) -> Result<Vec<Segment>, String> {
+        let mut segments = vec![];
+        loop {
+            if self.eat_str("..") {
+                let selector = if self.eat('[') {
+                    self.bracket()?
+                } else {
+                    self.dotted()?
+                };
+                segments.push(Segment::Descendant(selector));
+            } else if self.eat('.') {
+                segments.push(Segment::Child(self.dotted()?));
+            } else if self.eat('[') {
+                segments.push(Segment::Child(self.bracket()?));
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+
+    fn dotted(&mut self) -> Result<Selector, String> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| !c.is_whitespace() && !".[]()=!<>&|,~'\"@".contains(c))
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return self.error();
+        }
+        Ok(Selector::Name(self.chars[start..self.pos].iter().collect()))
+    }
+
+    /// the selector within brackets, the opening one is already read
+    fn bracket(&mut self) -> Result<Selector, String> {
+        self.skip_spaces();
+        let selector = if self.eat('*') {
+            Selector::Wildcard
+        } else if self.eat('?') {
+            self.skip_spaces();
+            if !self.eat('(') {
+                return self.error();
+            }
+            let expr = self.or()?;
+            self.skip_spaces();
+            if !self.eat(')') {
+                return self.error();
+            }
+            Selector::Filter(Box::new(expr))
+        } else {
+            let mut selectors = vec![self.member()?];
+            self.skip_spaces();
+            while self.eat(',') {
+                self.skip_spaces();
+                selectors.push(self.member()?);
+                self.skip_spaces();
+            }
+            match selectors.len() {
+                1 => selectors.remove(0),
+                _ => Selector::Union(selectors),
+            }
+        };
+        self.skip_spaces();
+        if !self.eat(']') {
+            return self.error();
+        }
+        Ok(selector)
+    }
+
+    /// a quoted name, an index or a slice
+    fn member(&mut self) -> Result<Selector, String> {
+        if let Some(quote @ ('\'' | '"')) = self.peek() {
+            return Ok(Selector::Name(self.quoted(quote)?));
+        }
+        let start = self.integer();
+        self.skip_spaces();
+        if !self.eat(':') {
+            return start.map_or_else(|| self.error(), |index| Ok(Selector::Index(index)));
+        }
+        self.skip_spaces();
+        let end = self.integer();
+        self.skip_spaces();
+        let step = if self.eat(':') {
+            self.skip_spaces();
+            self.integer().unwrap_or(1)
+        } else {
+            1
+        };
+        Ok(Selector::Slice(start, end, step))
+    }
+
+    fn integer(&mut self) -> Option<i64> {
+        let start = self.pos;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let integer = text.parse().ok();
+        if integer.is_none() {
+            self.pos = start;
+        }
+        integer
+    }
+
+    /// a string within quotes, a backslash escapes the character after it
+    fn quoted(&mut self, quote: char) -> Result<String, String> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return self.error(),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') if self.pos + 1 < self.chars.len() => {
+                    text.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        loop {
+            self.skip_spaces();
+            if !self.eat_str("||") {
+                return Ok(expr);
+            }
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            self.skip_spaces();
+            if !self.eat_str("&&") {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.skip_spaces();
+        if self.eat('!') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.or()?;
+            self.skip_spaces();
+            if !self.eat(')') {
+                return self.error();
+            }
+            return Ok(expr);
+        }
+        let left = self.operand()?;
+        self.skip_spaces();
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("=~", Op::Match),
+        ];
+        let Some(op) = ops
+            .into_iter()
+            .find_map(|(text, op)| self.eat_str(text).then_some(op))
+        else {
+            return Ok(Expr::Exists(left));
+        };
+        self.skip_spaces();
+        let right = self.operand()?;
+        Ok(Expr::Compare(left, op, right))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        if self.eat('@') {
+            return Ok(Operand::Relative(self.segments()?));
+        }
+        if self.eat('$') {
+            return Ok(Operand::Absolute(self.segments()?));
+        }
+        if let Some(quote @ ('\'' | '"')) = self.peek() {
+            return Ok(Operand::Literal(Json::String(self.quoted(quote)?)));
+        }
+        // numbers, booleans and null are read as json
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        match serde_json::from_str(&text) {
+            Ok(value) => Ok(Operand::Literal(value)),
+            Err(_) => {
+                self.pos = start;
+                self.error()
+            }
+        }
+    }
+}
+
+impl Database {
+    pub(crate) fn get_json(&self, key: &str) -> Result<Option<&Json>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Json(document)) => Ok(Some(document)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_json_mut(&mut self, key: &str) -> Result<Option<&mut Json>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Json(document)) => Ok(Some(document)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_path_test() {
+        let document = json!({
+            "store": {
+                "book": [
+                    {"title": "Sayings", "price": 8.95, "tags": ["a"]},
+                    {"title": "Sword", "price": 12.99},
+                    {"title": "Moby Dick", "price": 8.99, "isbn": "0-553"},
+                ],
+                "bicycle": {"color": "red", "price": 19.95},
+            },
+        });
+        let titles = |path: &str| {
+            let path = JsonPath::parse(path).unwrap();
+            path.select(&document)
+                .into_iter()
+                .map(|value| value.as_str().unwrap_or("?").to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles("$.store.book[0].title"), ["Sayings"]);
+        assert_eq!(titles("$.store.book[-1].title"), ["Moby Dick"]);
+        assert_eq!(
+            titles("$..book[?(@.price < 10)].title"),
+            ["Sayings", "Moby Dick"]
+        );
+        assert_eq!(titles("$..book[?(@.isbn)]['title']"), ["Moby Dick"]);
+        assert_eq!(
+            titles("$..book[?(@.price > 9 || @.title =~ 'Say')].title"),
+            ["Sayings", "Sword"]
+        );
+        assert_eq!(titles("$.store.book[::-2].title"), ["Moby Dick", "Sayings"]);
+        assert_eq!(titles("$.store.book[0,2].title"), ["Sayings", "Moby Dick"]);
+        assert_eq!(titles("store.bicycle.color"), ["red"]);
+        assert_eq!(
+            JsonPath::parse("$..price").unwrap().select(&document).len(),
+            4
+        );
+        assert!(JsonPath::parse(".").unwrap().is_root());
+        assert!(JsonPath::parse("$.a[").is_err());
+
+        let mut document = document.clone();
+        let prices = JsonPath::parse("$..price").unwrap();
+        let locations = prices.locations(&document);
+        assert_eq!(remove(&mut document, locations), 4);
+        assert!(prices.select(&document).is_empty());
+        let books = JsonPath::parse("$.store.book[0:2]").unwrap();
+        let locations = books.locations(&document);
+        assert_eq!(remove(&mut document, locations), 2);
+        assert_eq!(
+            Format::default().serialize(&document["store"]["book"]),
+            r#"[{"title":"Moby Dick","isbn":"0-553"}]"#
+        );
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use super::Database;
+use crate::{glob::glob_match, Frame};
+
+/// the number of messages a subscriber may fall behind. once its queue is full
+/// further messages are dropped for it, so that a slow subscriber never blocks publishers
+pub(crate) const BACKLOG: usize = 1024;
+
+/// the channels of pub/sub and the clients subscribed to them. patterns are indexed
+/// by their literal prefix, the bytes before the first special character, then by
+/// the literal suffix of the rest, so that a message is only matched against the
+/// patterns which both start and end like its channel. only the patterns with
+/// neither, such as `*` or `*.*`, are matched against every channel
+#[derive(Default)]
+pub(crate) struct Broker {
+    subscribers: HashMap<u64, Subscriber>,
+    channels: HashMap<Bytes, HashSet<u64>>,
+    patterns: HashMap<Bytes, HashSet<u64>>,
+    literals: HashMap<Bytes, HashMap<Bytes, HashSet<Bytes>>>,
+}
+
+struct Subscriber {
+    sender: mpsc::Sender<Frame>,
+    channels: BTreeSet<Bytes>,
+    patterns: BTreeSet<Bytes>,
+}
+
+impl Broker {
+    /// queue a message for the subscriber, return whether it was queued. it is
+    /// dropped if the queue of the subscriber is full, or its connection closing
+    fn deliver(&self, id: u64, frame: Frame) -> bool {
+        self.subscribers[&id].sender.try_send(frame).is_ok()
+    }
+
+    fn index(&mut self, pattern: &Bytes) {
+        let (prefix, suffix) = literals(pattern);
+        let suffixes = self.literals.entry(prefix).or_default();
+        suffixes.entry(suffix).or_default().insert(pattern.clone());
+    }
+
+    fn unindex(&mut self, pattern: &Bytes) {
+        let (prefix, suffix) = literals(pattern);
+        let Some(suffixes) = self.literals.get_mut(&prefix) else {
+            return;
+        };
+        if let Some(patterns) = suffixes.get_mut(&suffix) {
+            patterns.remove(pattern);
+            if patterns.is_empty() {
+                suffixes.remove(&suffix);
+            }
+        }
+        if suffixes.is_empty() {
+            self.literals.remove(&prefix);
+        }
+    }
+
+    /// the patterns which may match the channel, those starting with one of its
+    /// prefixes and ending with a suffix of the rest
+    fn candidates<'a>(&'a self, channel: &'a [u8]) -> impl Iterator<Item = &'a Bytes> {
+        (0..=channel.len())
+            .filter_map(|end| Some((end, self.literals.get(&channel[..end])?)))
+            .flat_map(move |(end, suffixes)| {
+                (end..=channel.len()).filter_map(move |start| suffixes.get(&channel[start..]))
+            })
+            .flatten()
+    }
+}
+
+/// the literal prefix of a pattern, and the literal suffix of what follows it.
+/// every channel the pattern matches starts with the one and ends with the other
+fn literals(pattern: &Bytes) -> (Bytes, Bytes) {
+    let prefix = literal_prefix(pattern);
+    let suffix = literal_suffix(&pattern.slice(prefix.len()..));
+    (prefix, suffix)
+}
+
+/// the part of a pattern which every matching channel starts with
+fn literal_prefix(pattern: &Bytes) -> Bytes {
+    let end = pattern
+        .iter()
+        .position(|c| matches!(c, b'*' | b'?' | b'[' | b'\\'))
+        .unwrap_or(pattern.len());
+    pattern.slice(..end)
+}
+
+/// the part of a pattern which every matching channel ends with
+fn literal_suffix(pattern: &Bytes) -> Bytes {
+    let start = pattern
+        .iter()
+        .rposition(|c| matches!(c, b'*' | b'?' | b'[' | b']' | b'\\'))
+        .map_or(0, |i| i + 1);
+    pattern.slice(start..)
+}
+
+impl Database {
+    /// register a client in subscriber mode, the messages of its channels and
+    /// patterns are handed to `sender`. the client id is used by the other pub/sub methods
+    pub(crate) fn add_subscriber(&mut self, id: u64, sender: mpsc::Sender<Frame>) {
+        let subscriber = Subscriber {
+            sender,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+        };
+        self.broker.subscribers.insert(id, subscriber);
+    }
+
+    /// forget a subscriber together with its subscriptions
+    pub(crate) fn remove_subscriber(&mut self, id: u64) {
+        for channel in self.channels_of(id) {
+            self.unsubscribe(id, &channel);
+        }
+        for pattern in self.patterns_of(id) {
+            self.punsubscribe(id, &pattern);
+        }
+        self.broker.subscribers.remove(&id);
+    }
+
+    /// return the number of subscriptions of the subscriber
+    pub(crate) fn subscribe(&mut self, id: u64, channel: Bytes) -> usize {
+        let broker = &mut self.broker;
+        let Some(subscriber) = broker.subscribers.get_mut(&id) else {
+            return 0;
+        };
+        if subscriber.channels.insert(channel.clone()) {
+            broker.channels.entry(channel).or_default().insert(id);
+        }
+        self.subscriptions(id)
+    }
+
+    /// return the number of subscriptions left to the subscriber
+    pub(crate) fn unsubscribe(&mut self, id: u64, channel: &[u8]) -> usize {
+        let broker = &mut self.broker;
+        if let Some(subscriber) = broker.subscribers.get_mut(&id) {
+            subscriber.channels.remove(channel);
+        }
+        if let Some(subscribers) = broker.channels.get_mut(channel) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                broker.channels.remove(channel);
+            }
+        }
+        self.subscriptions(id)
+    }
+
+    /// return the number of subscriptions of the subscriber
+    pub(crate) fn psubscribe(&mut self, id: u64, pattern: Bytes) -> usize {
+        let broker = &mut self.broker;
+        let Some(subscriber) = broker.subscribers.get_mut(&id) else {
+            return 0;
+        };
+        if subscriber.patterns.insert(pattern.clone()) {
+            if !broker.patterns.contains_key(&pattern) {
+                broker.index(&pattern);
+            }
+            broker.patterns.entry(pattern).or_default().insert(id);
+        }
+        self.subscriptions(id)
+    }
+
+    /// return the number of subscriptions left to the subscriber
+    pub(crate) fn punsubscribe(&mut self, id: u64, pattern: &Bytes) -> usize {
+        let broker = &mut self.broker;
+        if let Some(subscriber) = broker.subscribers.get_mut(&id) {
+            subscriber.patterns.remove(pattern);
+        }
+        if let Some(subscribers) = broker.patterns.get_mut(pattern) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                broker.patterns.remove(pattern);
+                broker.unindex(pattern);
+            }
+        }
+        self.subscriptions(id)
+    }
+
+    /// the number of channels and patterns a subscriber listens to
+    pub(crate) fn subscriptions(&self, id: u64) -> usize {
+        self.broker.subscribers.get(&id).map_or(0, |subscriber| {
+            subscriber.channels.len() + subscriber.patterns.len()
+        })
+    }
+
+    /// the channels a subscriber listens to, in lexicographical order
+    pub(crate) fn channels_of(&self, id: u64) -> Vec<Bytes> {
+        self.broker
+            .subscribers
+            .get(&id)
+            .map_or(vec![], |subscriber| {
+                subscriber.channels.iter().cloned().collect()
+            })
+    }
+
+    /// the patterns a subscriber listens to, in lexicographical order
+    pub(crate) fn patterns_of(&self, id: u64) -> Vec<Bytes> {
+        self.broker
+            .subscribers
+            .get(&id)
+            .map_or(vec![], |subscriber| {
+                subscriber.patterns.iter().cloned().collect()
+            })
+    }
+
+    /// the channels with at least one subscriber, in lexicographical order.
+    /// only those matching the pattern if one is given
+    pub(crate) fn active_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let mut channels: Vec<Bytes> = self
+            .broker
+            .channels
+            .keys()
+            .filter(|channel| pattern.is_none_or(|pattern| glob_match(pattern, channel)))
+            .cloned()
+            .collect();
+        channels.sort();
+        channels
+    }
+
+    /// the number of clients subscribed to the channel, patterns aside
+    pub(crate) fn channel_subscribers(&self, channel: &[u8]) -> usize {
+        self.broker.channels.get(channel).map_or(0, HashSet::len)
+    }
+
+    /// the number of distinct patterns subscribed to
+    pub(crate) fn active_patterns(&self) -> usize {
+        self.broker.patterns.len()
+    }
+
+    /// send a message of the channel to a single client, if it is subscribed to
+    /// the channel. return false when the queue of the client is full
+    pub(super) fn send_message(&self, id: u64, channel: Bytes, message: Frame) -> bool {
+        let subscribed = self.broker.subscribers.get(&id);
+        if subscribed.is_none_or(|subscriber| !subscriber.channels.contains(&channel)) {
+            return true;
+        }
+        let frame = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"message")),
+            Frame::Bulk(channel),
+            message,
+        ]);
+        self.broker.deliver(id, frame)
+    }
+
+    /// send a message to the subscribers of the channel and of the patterns
+    /// matching it. return the number of deliveries, a client subscribed both
+    /// to the channel and to a pattern receives the message twice. a subscriber
+    /// which has fallen `BACKLOG` messages behind misses the message, and isn't
+    /// counted, rather than blocking the publisher
+    pub(crate) fn publish(&mut self, channel: &Bytes, message: &Bytes) -> usize {
+        let broker = &self.broker;
+        let mut delivered = 0;
+        for id in broker.channels.get(channel).into_iter().flatten() {
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(channel.clone()),
+                Frame::Bulk(message.clone()),
+            ]);
+            delivered += broker.deliver(*id, frame) as usize;
+        }
+        for pattern in broker.candidates(channel) {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for id in &broker.patterns[pattern] {
+                let frame = Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(b"pmessage")),
+                    Frame::Bulk(pattern.clone()),
+                    Frame::Bulk(channel.clone()),
+                    Frame::Bulk(message.clone()),
+                ]);
+                delivered += broker.deliver(*id, frame) as usize;
+            }
+        }
+        delivered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    #[test]
+    fn publish_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (a, b) = (1, 2);
+        let (sender, mut fast) = mpsc::channel(BACKLOG);
+        db.add_subscriber(a, sender);
+        let (sender, _slow) = mpsc::channel(1);
+        db.add_subscriber(b, sender);
+        let news = Bytes::from_static(b"news");
+        assert_eq!(db.subscribe(a, news.clone()), 1);
+        assert_eq!(db.subscribe(a, Bytes::from_static(b"alerts")), 2);
+        assert_eq!(db.subscribe(a, news.clone()), 2);
+        db.subscribe(b, news.clone());
+
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"first")), 2);
+        // the slow subscriber has a full queue and misses the message
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"second")), 1);
+        assert_eq!(
+            fast.try_recv().unwrap(),
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(news.clone()),
+                Frame::Bulk(Bytes::from_static(b"first")),
+            ])
+        );
+
+        assert_eq!(
+            db.channels_of(a),
+            [Bytes::from_static(b"alerts"), news.clone()]
+        );
+        assert_eq!(db.unsubscribe(a, b"news"), 1);
+        db.remove_subscriber(b);
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"third")), 0);
+        assert!(!db.broker.channels.contains_key(&news));
+    }
+
+    #[test]
+    fn full_queue_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut receiver) = mpsc::channel(2);
+        db.add_subscriber(1, sender);
+        let news = Bytes::from_static(b"news");
+        db.subscribe(1, news.clone());
+        db.psubscribe(1, Bytes::from_static(b"n*"));
+
+        // the channel message fills the queue, the pattern one is dropped
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"first")), 2);
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"second")), 0);
+        assert!(!db.send_message(1, news.clone(), Frame::Null));
+        assert!(db.send_message(1, Bytes::from_static(b"other"), Frame::Null));
+        let message = |frame: Frame| match frame {
+            Frame::Array(items) => items.last().cloned(),
+            _ => None,
+        };
+        let first = Some(Frame::Bulk(Bytes::from_static(b"first")));
+        assert_eq!(message(receiver.try_recv().unwrap()), first);
+        assert_eq!(message(receiver.try_recv().unwrap()), first);
+
+        // the subscriber gets messages again once it has caught up
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"third")), 2);
+        assert_eq!(db.publish(&news, &Bytes::from_static(b"fourth")), 0);
+        let third = Some(Frame::Bulk(Bytes::from_static(b"third")));
+        assert_eq!(message(receiver.try_recv().unwrap()), third);
+        assert_eq!(message(receiver.try_recv().unwrap()), third);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn pattern_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut receiver) = mpsc::channel(BACKLOG);
+        let id = 1;
+        db.add_subscriber(id, sender);
+        for pattern in ["orders.*.created", "orders.eu.*", "*", "orders.us.created"] {
+            db.psubscribe(id, Bytes::from(pattern));
+        }
+        db.subscribe(id, Bytes::from_static(b"orders.eu.created"));
+        assert_eq!(db.subscriptions(id), 5);
+        assert_eq!(db.broker.literals.len(), 4);
+
+        let channel = Bytes::from_static(b"orders.eu.created");
+        assert_eq!(db.publish(&channel, &Bytes::from_static(b"42")), 4);
+        let mut patterns = vec![];
+        while let Ok(Frame::Array(frame)) = receiver.try_recv() {
+            if let [Frame::Bulk(kind), Frame::Bulk(pattern), ..] = &frame[..] {
+                if kind == "pmessage" {
+                    patterns.push(pattern.clone());
+                }
+            }
+        }
+        patterns.sort();
+        assert_eq!(patterns, ["*", "orders.*.created", "orders.eu.*"]);
+
+        db.punsubscribe(id, &Bytes::from_static(b"*"));
+        db.punsubscribe(id, &Bytes::from_static(b"orders.us.created"));
+        assert_eq!(db.broker.literals.len(), 2);
+        let channel = Bytes::from_static(b"orders.us.created");
+        assert_eq!(db.publish(&channel, &Bytes::new()), 1);
+        assert_eq!(db.active_patterns(), 2);
+        assert_eq!(db.active_channels(Some(b"orders.*")), ["orders.eu.created"]);
+        assert!(db.active_channels(Some(b"news*")).is_empty());
+        assert_eq!(db.channel_subscribers(b"orders.eu.created"), 1);
+        db.remove_subscriber(id);
+        assert!(db.broker.patterns.is_empty() && db.broker.literals.is_empty());
+    }
+
+    #[test]
+    fn leading_wildcard_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut receiver) = mpsc::channel(BACKLOG);
+        let id = 1;
+        db.add_subscriber(id, sender);
+        for i in 0..1000 {
+            db.psubscribe(id, Bytes::from(format!("*.event{}", i)));
+        }
+        for pattern in ["*", "*.event1?", "*[0-9]", "*\\*"] {
+            db.psubscribe(id, Bytes::from(pattern));
+        }
+        // only the patterns without a literal suffix are left to match every channel
+        let suffixes = &db.broker.literals[&Bytes::new()];
+        assert_eq!(suffixes.len(), 1001);
+        assert_eq!(suffixes[&Bytes::new()].len(), 4);
+
+        let channel = Bytes::from_static(b"orders.event12");
+        assert_eq!(db.publish(&channel, &Bytes::new()), 4);
+        let mut patterns = vec![];
+        while let Ok(Frame::Array(frame)) = receiver.try_recv() {
+            if let [_, Frame::Bulk(pattern), ..] = &frame[..] {
+                patterns.push(pattern.clone());
+            }
+        }
+        patterns.sort();
+        assert_eq!(patterns, ["*", "*.event12", "*.event1?", "*[0-9]"]);
+        assert_eq!(
+            db.publish(&Bytes::from_static(b"event12"), &Bytes::new()),
+            2
+        );
+
+        for i in 0..1000 {
+            db.punsubscribe(id, &Bytes::from(format!("*.event{}", i)));
+        }
+        assert_eq!(db.broker.literals[&Bytes::new()].len(), 1);
+    }
+
+    #[test]
+    fn same_prefix_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut receiver) = mpsc::channel(BACKLOG);
+        let id = 1;
+        db.add_subscriber(id, sender);
+        for i in 0..1000 {
+            db.psubscribe(id, Bytes::from(format!("orders.*.event{}", i)));
+        }
+        for pattern in ["orders.*.created", "orders.*.paid", "orders.*"] {
+            db.psubscribe(id, Bytes::from(pattern));
+        }
+
+        // of the patterns sharing the prefix, only the ones ending like the
+        // channel are matched against it
+        let channel = Bytes::from_static(b"orders.eu.created");
+        let mut candidates: Vec<_> = db.broker.candidates(&channel).cloned().collect();
+        candidates.sort();
+        assert_eq!(candidates, ["orders.*", "orders.*.created"]);
+        assert_eq!(db.publish(&channel, &Bytes::new()), 2);
+        let channel = Bytes::from_static(b"orders.us.event12");
+        assert_eq!(db.broker.candidates(&channel).count(), 2);
+        assert_eq!(db.publish(&channel, &Bytes::new()), 2);
+        let mut patterns = vec![];
+        while let Ok(Frame::Array(frame)) = receiver.try_recv() {
+            if let [_, Frame::Bulk(pattern), ..] = &frame[..] {
+                patterns.push(pattern.clone());
+            }
+        }
+        assert_eq!(
+            patterns,
+            [
+                "orders.*.created",
+                "orders.*",
+                "orders.*.event12",
+                "orders.*"
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use std::{collections::BTreeMap, ops::RangeInclusive};
+
+use super::{Database, Value, WrongType};
+
+/// an array holds at most this many values, a bitmap takes the same 8KB
+const ARRAY_MAX: usize = 4096;
+const WORDS: usize = 1024;
+
+/// a compressed set of 64 bit integers. the integers are split by their 48 high bits
+/// into containers holding the 16 low bits, either as a sorted array when there are
+/// few of them or as a bitmap
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Roaring {
+    containers: BTreeMap<u64, Container>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Container {
+    Array(Vec<u16>),
+    Bitmap {
+        words: Box<[u64; WORDS]>,
+        len: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum BitOp {
+    And,
+    Or,
+    Xor,
+    /// the values of the first bitmap which are in none of the others
+    AndNot,
+}
+
+fn split(value: u64) -> (u64, u16) {
+    (value >> 16, value as u16)
+}
+
+impl Container {
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap { len, .. } => *len,
+        }
+    }
+
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bitmap { words, .. } => words[low as usize / 64] >> (low % 64) & 1 == 1,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Err(i) = values.binary_search(&low) else {
+                    return false;
+                };
+                values.insert(i, low);
+                if values.len() > ARRAY_MAX {
+                    *self = Container::from_words(self.words());
+                }
+                true
+            }
+            Container::Bitmap { words, len } => {
+                let word = &mut words[low as usize / 64];
+                let bit = 1 << (low % 64);
+                let inserted = *word & bit == 0;
+                *word |= bit;
+                *len += inserted as usize;
+                inserted
+            }
+        }
+    }
+
+    fn remove(&mut self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Ok(i) = values.binary_search(&low) else {
+                    return false;
+                };
+                values.remove(i);
+                true
+            }
+            Container::Bitmap { words, len } => {
+                let word = &mut words[low as usize / 64];
+                let bit = 1 << (low % 64);
+                let removed = *word & bit != 0;
+                *word &= !bit;
+                *len -= removed as usize;
+                if *len <= ARRAY_MAX {
+                    *self = Container::Array(self.iter().collect());
+                }
+                removed
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bitmap { words, .. } => {
+                Box::new(words.iter().enumerate().flat_map(|(i, word)| {
+                    let mut word = *word;
+                    std::iter::from_fn(move || {
+                        if word == 0 {
+                            return None;
+                        }
+                        let bit = word.trailing_zeros();
+                        word &= word - 1;
+                        Some((i * 64) as u16 + bit as u16)
+                    })
+                }))
+            }
+        }
+    }
+
+    fn words(&self) -> Box<[u64; WORDS]> {
+        match self {
+            Container::Array(values) => {
+                let mut words = Box::new([0; WORDS]);
+                for low in values {
+                    words[*low as usize / 64] |= 1 << (low % 64);
+                }
+                words
+            }
+            Container::Bitmap { words, .. } => words.clone(),
+        }
+    }
+
+    /// the smallest container for the bits
+    fn from_words(words: Box<[u64; WORDS]>) -> Container {
+        let len = words.iter().map(|word| word.count_ones() as usize).sum();
+        let bitmap = Container::Bitmap { words, len };
+        match len {
+            0..=ARRAY_MAX => Container::Array(bitmap.iter().collect()),
+            _ => bitmap,
+        }
+    }
+
+    fn combine(&self, other: &Container, op: BitOp) -> Container {
+        if let (Container::Array(a), Container::Array(b)) = (self, other) {
+            return Container::Array(merge(a, b, op));
+        }
+        let mut words = self.words();
+        let other = other.words();
+        for (word, other) in words.iter_mut().zip(other.iter()) {
+            *word = match op {
+                BitOp::And => *word & other,
+                BitOp::Or => *word | other,
+                BitOp::Xor => *word ^ other,
+                BitOp::AndNot => *word & !other,
+            };
+        }
+        Container::from_words(words)
+    }
+}
+
+/// combine two sorted arrays
+fn merge(a: &[u16], b: &[u16], op: BitOp) -> Vec<u16> {
+    let (mut i, mut j) = (0, 0);
+    let mut merged = vec![];
+    while i < a.len() || j < b.len() {
+        let (value, in_a, in_b) = match (a.get(i), b.get(j)) {
+            (Some(x), Some(y)) if x == y => (*x, true, true),
+            (Some(x), Some(y)) if x < y => (*x, true, false),
+            (Some(x), None) => (*x, true, false),
+            (_, Some(y)) => (*y, false, true),
+            (None, None) => unreachable!(),
+        };
+        i += in_a as usize;
+        j += in_b as usize;
+        let keep = match op {
+            BitOp::And => in_a && in_b,
+            BitOp::Or => true,
+            BitOp::Xor => in_a != in_b,
+            BitOp::AndNot => in_a && !in_b,
+        };
+        if keep {
+            merged.push(value);
+        }
+    }
+    merged
+}
+
+impl BitOp {
+    pub(crate) fn parse(name: &str) -> Option<BitOp> {
+        match &name.to_lowercase()[..] {
+            "and" => Some(BitOp::And),
+            "or" => Some(BitOp::Or),
+            "xor" => Some(BitOp::Xor),
+            "andnot" => Some(BitOp::AndNot),
+            _ => None,
+        }
+    }
+}
+
+impl Roaring {
+    pub(crate) fn len(&self) -> u64 {
+        self.containers
+            .values()
+            .map(|container| container.len() as u64)
+            .sum()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    pub(crate) fn contains(&self, value: u64) -> bool {
+        let (high, low) = split(value);
+        self.containers
+            .get(&high)
+            .is_some_and(|container| container.contains(low))
+    }
+
+    /// returns false when the value was already there
+    pub(crate) fn insert(&mut self, value: u64) -> bool {
+        let (high, low) = split(value);
+        self.containers
+            .entry(high)
+            .or_insert_with(|| Container::Array(vec![]))
+            .insert(low)
+    }
+
+    pub(crate) fn remove(&mut self, value: u64) -> bool {
+        let (high, low) = split(value);
+        let Some(container) = self.containers.get_mut(&high) else {
+            return false;
+        };
+        let removed = container.remove(low);
+        if container.len() == 0 {
+            self.containers.remove(&high);
+        }
+        removed
+    }
+
+    /// the values within the range, in ascending order
+    pub(crate) fn range(&self, range: RangeInclusive<u64>) -> impl Iterator<Item = u64> + '_ {
+        let (start, end) = (*range.start(), *range.end());
+        self.containers
+            // an empty range must not panic the map
+            .range(split(start).0..=split(end).0.max(split(start).0))
+            .flat_map(|(high, container)| container.iter().map(move |low| high << 16 | low as u64))
+            .skip_while(move |value| *value < start)
+            .take_while(move |value| *value <= end)
+    }
+
+    pub(crate) fn combine(&self, other: &Roaring, op: BitOp) -> Roaring {
+        let mut containers = BTreeMap::new();
+        let empty = Container::Array(vec![]);
+        let highs: Vec<u64> = match op {
+            BitOp::And => self
+                .containers
+                .keys()
+                .filter(|high| other.containers.contains_key(high))
+                .copied()
+                .collect(),
+            BitOp::AndNot => self.containers.keys().copied().collect(),
+            BitOp::Or | BitOp::Xor => {
+                let mut highs: Vec<u64> = self
+                    .containers
+                    .keys()
+                    .chain(other.containers.keys())
+                    .copied()
+                    .collect();
+                highs.sort_unstable();
+                highs.dedup();
+                highs
+            }
+        };
+        for high in highs {
+            let a = self.containers.get(&high).unwrap_or(&empty);
+            let b = other.containers.get(&high).unwrap_or(&empty);
+            let container = a.combine(b, op);
+            if container.len() > 0 {
+                containers.insert(high, container);
+            }
+        }
+        Roaring { containers }
+    }
+}
+
+impl Database {
+    pub(crate) fn get_roaring(&self, key: &str) -> Result<Option<&Roaring>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Roaring(bitmap)) => Ok(Some(bitmap)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_roaring_mut(&mut self, key: &str) -> Result<Option<&mut Roaring>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Roaring(bitmap)) => Ok(Some(bitmap)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the number of containers of each kind, arrays first
+    fn containers(bitmap: &Roaring) -> (usize, usize) {
+        let arrays = bitmap
+            .containers
+            .values()
+            .filter(|container| matches!(container, Container::Array(_)))
+            .count();
+        (arrays, bitmap.containers.len() - arrays)
+    }
+
+    #[test]
+    fn roaring_test() {
+        let mut evens = Roaring::default();
+        let mut thirds = Roaring::default();
+        for value in 0..20000u64 {
+            if value % 2 == 0 {
+                assert!(evens.insert(value));
+            }
+            if value % 3 == 0 {
+                thirds.insert(value);
+            }
+        }
+        assert!(!evens.insert(4));
+        evens.insert(u64::MAX);
+        assert_eq!(evens.len(), 10001);
+        assert_eq!(containers(&evens), (1, 1));
+        assert!(evens.contains(u64::MAX) && !evens.contains(3));
+        assert_eq!(
+            evens.range(19995..=u64::MAX).collect::<Vec<_>>(),
+            [19996, 19998, u64::MAX]
+        );
+        assert_eq!(evens.range(RangeInclusive::new(70000, 5)).count(), 0);
+
+        let check = |op, expected: fn(u64) -> bool| {
+            let combined = evens.combine(&thirds, op);
+            let values: Vec<u64> = combined.range(0..=u64::MAX).collect();
+            let mut wanted: Vec<u64> = (0..20000).filter(|value| expected(*value)).collect();
+            if matches!(op, BitOp::Or | BitOp::Xor | BitOp::AndNot) {
+                wanted.push(u64::MAX);
+            }
+            assert_eq!(values, wanted);
+        };
+        check(BitOp::And, |v| v % 6 == 0);
+        check(BitOp::Or, |v| v % 2 == 0 || v % 3 == 0);
+        check(BitOp::Xor, |v| (v % 2 == 0) != (v % 3 == 0));
+        check(BitOp::AndNot, |v| v % 2 == 0 && v % 3 != 0);
+
+        // the bitmaps go back to arrays as they empty
+        for value in (0..20000).step_by(2) {
+            assert!(evens.remove(value));
+        }
+        assert!(!evens.remove(0));
+        assert_eq!(evens.len(), 1);
+        assert_eq!(containers(&evens), (1, 0));
+        evens.remove(u64::MAX);
+        assert!(evens.is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use super::Database;
+
+/// the scripts loaded by EVAL or SCRIPT LOAD, by the SHA1 digest of their body
+#[derive(Default)]
+pub(crate) struct Scripts {
+    bodies: HashMap<String, String>,
+}
+
+/// the script being run. it is kept outside of the database lock, which the
+/// script holds, so that other clients can tell it is busy and stop it
+#[derive(Default)]
+pub(crate) struct ScriptControl {
+    running: Mutex<Option<Running>>,
+    /// woken when the running script ends
+    done: Notify,
+}
+
+struct Running {
+    started: Instant,
+    /// a script which has written can't be killed without breaking atomicity
+    wrote: bool,
+    killed: bool,
+}
+
+/// the lowercase hex SHA1 digest of the script
+pub(crate) fn sha1_hex(body: &[u8]) -> String {
+    sha1_smol::Sha1::from(body).digest().to_string()
+}
+
+impl Database {
+    /// cache the script, returning its digest
+    pub(crate) fn load_script(&mut self, body: String) -> String {
+        let sha = sha1_hex(body.as_bytes());
+        self.scripts.bodies.entry(sha.clone()).or_insert(body);
+        sha
+    }
+
+    /// the body of a cached script, the digest is case insensitive
+    pub(crate) fn script(&self, sha: &str) -> Option<String> {
+        self.scripts.bodies.get(&sha.to_lowercase()).cloned()
+    }
+
+    pub(crate) fn flush_scripts(&mut self) {
+        self.scripts.bodies.clear();
+    }
+}
+
+impl ScriptControl {
+    pub(crate) fn start(&self) {
+        *self.running.lock().unwrap() = Some(Running {
+            started: Instant::now(),
+            wrote: false,
+            killed: false,
+        });
+    }
+
+    pub(crate) fn stop(&self) {
+        self.running.lock().unwrap().take();
+        self.done.notify_waiters();
+    }
+
+    /// the running script is about to write
+    pub(crate) fn write(&self) {
+        if let Some(running) = self.running.lock().unwrap().as_mut() {
+            running.wrote = true;
+        }
+    }
+
+    /// whether SCRIPT KILL has been called on the running script
+    pub(crate) fn killed(&self) -> bool {
+        let running = self.running.lock().unwrap();
+        running.as_ref().is_some_and(|running| running.killed)
+    }
+
+    /// how long the running script has been running, if any
+    pub(crate) fn elapsed(&self) -> Option<Duration> {
+        let running = self.running.lock().unwrap();
+        running.as_ref().map(|running| running.started.elapsed())
+    }
+
+    /// wait for the running script to end, or for the timeout to pass
+    pub(crate) async fn wait(&self, timeout: Duration) {
+        let done = self.done.notified();
+        if self.elapsed().is_some() {
+            let _ = tokio::time::timeout(timeout, done).await;
+        }
+    }
+
+    /// ask the running script to stop, which it does at its next check
+    pub(crate) fn kill(&self) -> Result<(), &'static str> {
+        match self.running.lock().unwrap().as_mut() {
+            None => Err("NOTBUSY No scripts in execution right now."),
+            Some(running) if running.wrote => Err("UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."),
+            Some(running) => {
+                running.killed = true;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_control_test() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+
+        let control = ScriptControl::default();
+        assert!(control.kill().is_err() && control.elapsed().is_none());
+        control.start();
+        assert!(!control.killed());
+        control.kill().unwrap();
+        assert!(control.killed());
+        control.stop();
+
+        // a script which has written can't be killed
+        control.start();
+        control.write();
+        assert!(control.kill().unwrap_err().starts_with("UNKILLABLE"));
+        assert!(!control.killed());
+        control.stop();
+    }
+}
@@ -0,0 +1,561 @@
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::Bound,
+};
+
+use super::{Database, Hash, Value};
+use crate::search::{tokenize, Query};
+
+/// the secondary indexes over hashes, by name
+#[derive(Debug, Default)]
+pub(crate) struct Indexes {
+    by_name: BTreeMap<String, SearchIndex>,
+    /// keys written since the indexes were last brought up to date
+    dirty: HashSet<String>,
+}
+
+/// an index over the hashes whose key starts with one of the prefixes
+#[derive(Debug)]
+pub(crate) struct SearchIndex {
+    prefixes: Vec<String>,
+    fields: Vec<FieldSpec>,
+    documents: HashMap<String, Document>,
+    /// the inverted index of every field of the schema, in the same order
+    postings: Vec<Postings>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct FieldSpec {
+    /// the field of the hashes
+    pub(crate) name: String,
+    /// the name queries know the field by
+    pub(crate) alias: String,
+    pub(crate) kind: FieldKind,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum FieldKind {
+    Text {
+        weight: f64,
+    },
+    Tag {
+        separator: char,
+        case_sensitive: bool,
+    },
+    Numeric,
+}
+
+#[derive(Debug)]
+enum Postings {
+    /// the keys by word, or by tag
+    Terms(HashMap<String, BTreeSet<String>>),
+    /// the keys by value, ordered by `ordered_bits`
+    Numbers(BTreeMap<u64, BTreeSet<String>>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Document {
+    /// every field of the hash, ordered by name
+    pub(crate) fields: Vec<(Bytes, Bytes)>,
+    /// what was indexed for every field of the schema
+    values: Vec<Indexed>,
+}
+
+#[derive(Debug)]
+enum Indexed {
+    Missing,
+    Words(Vec<String>),
+    Tags(Vec<String>),
+    Number(f64),
+}
+
+impl FieldKind {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            FieldKind::Text { .. } => "TEXT",
+            FieldKind::Tag { .. } => "TAG",
+            FieldKind::Numeric => "NUMERIC",
+        }
+    }
+}
+
+impl SearchIndex {
+    /// no prefix covers every key
+    pub(crate) fn new(mut prefixes: Vec<String>, fields: Vec<FieldSpec>) -> SearchIndex {
+        if prefixes.is_empty() {
+            prefixes.push(String::new());
+        }
+        let postings = fields
+            .iter()
+            .map(|field| match field.kind {
+                FieldKind::Numeric => Postings::Numbers(BTreeMap::new()),
+                _ => Postings::Terms(HashMap::new()),
+            })
+            .collect();
+        SearchIndex {
+            prefixes,
+            fields,
+            documents: HashMap::new(),
+            postings,
+        }
+    }
+
+    pub(crate) fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    pub(crate) fn fields(&self) -> &[FieldSpec] {
+        &self.fields
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &String> {
+        self.documents.keys()
+    }
+
+    pub(crate) fn document(&self, key: &str) -> Option<&Document> {
+        self.documents.get(key)
+    }
+
+    fn covers(&self, key: &str) -> bool {
+        self.prefixes.iter().any(|prefix| key.starts_with(prefix))
+    }
+
+    fn add(&mut self, key: &str, hash: &Hash) {
+        let mut fields: Vec<(Bytes, Bytes)> = hash
+            .iter()
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        fields.sort_unstable();
+
+        let mut values = Vec::with_capacity(self.fields.len());
+        for (spec, postings) in self.fields.iter().zip(&mut self.postings) {
+            let Some(raw) = hash.get(spec.name.as_bytes()) else {
+                values.push(Indexed::Missing);
+                continue;
+            };
+            let raw = String::from_utf8_lossy(raw);
+            let value = match (&spec.kind, postings) {
+                (FieldKind::Text { .. }, Postings::Terms(terms)) => {
+                    let words = tokenize(&raw);
+                    for word in &words {
+                        terms
+                            .entry(word.clone())
+                            .or_default()
+                            .insert(key.to_string());
+                    }
+                    Indexed::Words(words)
+                }
+                (
+                    FieldKind::Tag {
+                        separator,
+                        case_sensitive,
+                    },
+                    Postings::Terms(terms),
+                ) => {
+                    let tags: Vec<String> = raw
+                        .split(*separator)
+                        .map(|tag| normalize_tag(tag, *case_sensitive))
+                        .filter(|tag| !tag.is_empty())
+                        .collect();
+                    for tag in &tags {
+                        terms
+                            .entry(tag.clone())
+                            .or_default()
+                            .insert(key.to_string());
+                    }
+                    Indexed::Tags(tags)
+                }
+                (FieldKind::Numeric, Postings::Numbers(numbers)) => match raw.trim().parse() {
+                    Ok(number) if !f64::is_nan(number) => {
+                        numbers
+                            .entry(ordered_bits(number))
+                            .or_default()
+                            .insert(key.to_string());
+                        Indexed::Number(number)
+                    }
+                    _ => Indexed::Missing,
+                },
+                _ => Indexed::Missing,
+            };
+            values.push(value);
+        }
+        self.documents
+            .insert(key.to_string(), Document { fields, values });
+    }
+
+    fn remove(&mut self, key: &str) {
+        let Some(document) = self.documents.remove(key) else {
+            return;
+        };
+        for (value, postings) in document.values.iter().zip(&mut self.postings) {
+            match (value, postings) {
+                (Indexed::Words(terms) | Indexed::Tags(terms), Postings::Terms(index)) => {
+                    for term in terms {
+                        if let Some(holders) = index.get_mut(term) {
+                            holders.remove(key);
+                            if holders.is_empty() {
+                                index.remove(term);
+                            }
+                        }
+                    }
+                }
+                (Indexed::Number(number), Postings::Numbers(index)) => {
+                    let bits = ordered_bits(*number);
+                    if let Some(holders) = index.get_mut(&bits) {
+                        holders.remove(key);
+                        if holders.is_empty() {
+                            index.remove(&bits);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn field(&self, alias: &str) -> Result<usize, String> {
+        self.fields
+            .iter()
+            .position(|field| field.alias == alias)
+            .ok_or_else(|| format!("Unknown field '{}'", alias))
+    }
+
+    /// the position of the TEXT fields a query applies to, all of them without a field
+    fn text_fields(&self, alias: Option<&str>) -> Result<Vec<usize>, String> {
+        let Some(alias) = alias else {
+            return Ok((0..self.fields.len())
+                .filter(|i| matches!(self.fields[*i].kind, FieldKind::Text { .. }))
+                .collect());
+        };
+        let i = self.field(alias)?;
+        match self.fields[i].kind {
+            FieldKind::Text { .. } => Ok(vec![i]),
+            _ => Err(format!("field '{}' is not a TEXT field", alias)),
+        }
+    }
+
+    /// the keys of the documents matching the query with their score, the best first
+    pub(crate) fn search(&self, query: &Query) -> Result<Vec<(&str, f64)>, String> {
+        let words = query.words();
+        let total = self.documents.len() as f64;
+        // rare words weigh more than common ones
+        let idf: Vec<f64> = words
+            .iter()
+            .map(|word| {
+                let mut holders = HashSet::new();
+                for postings in &self.postings[..] {
+                    if let Postings::Terms(terms) = postings {
+                        holders.extend(terms.get(*word).into_iter().flatten());
+                    }
+                }
+                (1.0 + total / (holders.len() as f64).max(1.0)).ln()
+            })
+            .collect();
+
+        let mut results: Vec<(&str, f64)> = self
+            .eval(query)?
+            .into_iter()
+            .map(|key| {
+                let document = &self.documents[key];
+                // an empty sum of floats is negative zero
+                let score = words
+                    .iter()
+                    .zip(&idf)
+                    .map(|(word, idf)| document.frequency(&self.fields, word) * idf)
+                    .fold(0.0, |score, term| score + term);
+                (key, score)
+            })
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        Ok(results)
+    }
+
+    fn eval(&self, query: &Query) -> Result<BTreeSet<&str>, String> {
+        let keys = match query {
+            Query::All => self.documents.keys().map(String::as_str).collect(),
+            Query::Phrase { field, words } => {
+                let mut keys = BTreeSet::new();
+                for i in self.text_fields(field.as_deref())? {
+                    let Postings::Terms(terms) = &self.postings[i] else {
+                        continue;
+                    };
+                    let mut candidates: Option<BTreeSet<&str>> = None;
+                    for word in words {
+                        let holders = terms.get(word).into_iter().flatten().map(String::as_str);
+                        candidates = Some(match candidates {
+                            None => holders.collect(),
+                            Some(candidates) => {
+                                holders.filter(|k| candidates.contains(k)).collect()
+                            }
+                        });
+                    }
+                    keys.extend(
+                        candidates
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter(|key| self.documents[*key].has_phrase(i, words)),
+                    );
+                }
+                keys
+            }
+            Query::Prefix { field, prefix } => {
+                let mut keys = BTreeSet::new();
+                for i in self.text_fields(field.as_deref())? {
+                    if let Postings::Terms(terms) = &self.postings[i] {
+                        for (_, holders) in
+                            terms.iter().filter(|(word, _)| word.starts_with(prefix))
+                        {
+                            keys.extend(holders.iter().map(String::as_str));
+                        }
+                    }
+                }
+                keys
+            }
+            Query::Tags { field, tags } => {
+                let i = self.field(field)?;
+                let (FieldKind::Tag { case_sensitive, .. }, Postings::Terms(terms)) =
+                    (&self.fields[i].kind, &self.postings[i])
+                else {
+                    return Err(format!("field '{}' is not a TAG field", field));
+                };
+                tags.iter()
+                    .flat_map(|tag| terms.get(&normalize_tag(tag, *case_sensitive)))
+                    .flatten()
+                    .map(String::as_str)
+                    .collect()
+            }
+            Query::Range { field, min, max } => {
+                let i = self.field(field)?;
+                let Postings::Numbers(numbers) = &self.postings[i] else {
+                    return Err(format!("field '{}' is not a NUMERIC field", field));
+                };
+                if range_is_empty(*min, *max) {
+                    return Ok(BTreeSet::new());
+                }
+                numbers
+                    .range((min.map(ordered_bits), max.map(ordered_bits)))
+                    .flat_map(|(_, holders)| holders.iter().map(String::as_str))
+                    .collect()
+            }
+            Query::And(queries) => {
+                let mut keys = self.eval(&queries[0])?;
+                for query in &queries[1..] {
+                    let other = self.eval(query)?;
+                    keys.retain(|key| other.contains(key));
+                }
+                keys
+            }
+            Query::Or(queries) => {
+                let mut keys = BTreeSet::new();
+                for query in queries {
+                    keys.extend(self.eval(query)?);
+                }
+                keys
+            }
+            Query::Not(query) => {
+                let excluded = self.eval(query)?;
+                self.documents
+                    .keys()
+                    .map(String::as_str)
+                    .filter(|key| !excluded.contains(key))
+                    .collect()
+            }
+        };
+        Ok(keys)
+    }
+}
+
+impl Document {
+    /// the value of a field of the hash
+    pub(crate) fn get(&self, field: &str) -> Option<&Bytes> {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == field.as_bytes())
+            .map(|(_, value)| value)
+    }
+
+    /// the value of an attribute of the schema, numbers for NUMERIC ones
+    pub(crate) fn number(&self, fields: &[FieldSpec], alias: &str) -> Option<f64> {
+        let i = fields.iter().position(|field| field.alias == alias)?;
+        match self.values[i] {
+            Indexed::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    fn has_phrase(&self, field: usize, words: &[String]) -> bool {
+        match &self.values[field] {
+            Indexed::Words(tokens) => tokens.windows(words.len()).any(|window| window == words),
+            _ => false,
+        }
+    }
+
+    /// how often the word appears in the TEXT fields relative to their length, by weight
+    fn frequency(&self, fields: &[FieldSpec], word: &str) -> f64 {
+        fields
+            .iter()
+            .zip(&self.values)
+            .map(|(spec, value)| match (&spec.kind, value) {
+                (FieldKind::Text { weight }, Indexed::Words(tokens)) if !tokens.is_empty() => {
+                    let count = tokens.iter().filter(|token| *token == word).count();
+                    weight * count as f64 / tokens.len() as f64
+                }
+                _ => 0.0,
+            })
+            .sum()
+    }
+}
+
+fn normalize_tag(tag: &str, case_sensitive: bool) -> String {
+    match case_sensitive {
+        true => tag.trim().to_string(),
+        false => tag.trim().to_lowercase(),
+    }
+}
+
+/// map a float to an integer which sorts the same way
+fn ordered_bits(value: f64) -> u64 {
+    // zero and negative zero are the same value
+    let bits = (value + 0.0).to_bits();
+    if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | 1 << 63
+    }
+}
+
+/// whether a range has no room for any value, a range of the map would panic
+fn range_is_empty(min: Bound<f64>, max: Bound<f64>) -> bool {
+    match (min, max) {
+        (Bound::Included(min), Bound::Included(max)) => min > max,
+        (
+            Bound::Included(min) | Bound::Excluded(min),
+            Bound::Included(max) | Bound::Excluded(max),
+        ) => min >= max,
+        _ => false,
+    }
+}
+
+impl Database {
+    /// remember that a key was written, the indexes covering it catch up before their
+    /// next query
+    pub(super) fn reindex(&mut self, key: &str) {
+        if self.indexes.by_name.values().any(|index| index.covers(key)) {
+            self.indexes.dirty.insert(key.to_string());
+        }
+    }
+
+    /// the hashes the index covers are indexed at once. returns false when there is
+    /// already an index with that name
+    pub(crate) fn create_index(&mut self, name: String, mut index: SearchIndex) -> bool {
+        if self.indexes.by_name.contains_key(&name) {
+            return false;
+        }
+        for (key, value) in &self.entries {
+            if let Value::Hash(hash) = value {
+                if index.covers(key) {
+                    index.add(key, hash);
+                }
+            }
+        }
+        self.indexes.by_name.insert(name, index);
+        true
+    }
+
+    pub(crate) fn drop_index(&mut self, name: &str) -> Option<SearchIndex> {
+        self.refresh_indexes();
+        self.indexes.by_name.remove(name)
+    }
+
+    pub(crate) fn index_names(&self) -> Vec<String> {
+        self.indexes.by_name.keys().cloned().collect()
+    }
+
+    /// the index, up to date with every write
+    pub(crate) fn search_index(&mut self, name: &str) -> Option<&SearchIndex> {
+        self.refresh_indexes();
+        self.indexes.by_name.get(name)
+    }
+
+    fn refresh_indexes(&mut self) {
+        for key in std::mem::take(&mut self.indexes.dirty) {
+            let hash = match self.entries.get(&key) {
+                Some(Value::Hash(hash)) => Some(hash),
+                _ => None,
+            };
+            for index in self.indexes.by_name.values_mut() {
+                if index.covers(&key) {
+                    index.remove(&key);
+                    if let Some(hash) = hash {
+                        index.add(&key, hash);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_index_test() {
+        let field = |name: &str, kind| FieldSpec {
+            name: name.to_string(),
+            alias: name.to_string(),
+            kind,
+        };
+        let mut index = SearchIndex::new(
+            vec!["doc:".to_string()],
+            vec![
+                field("title", FieldKind::Text { weight: 1.0 }),
+                field(
+                    "tags",
+                    FieldKind::Tag {
+                        separator: ',',
+                        case_sensitive: false,
+                    },
+                ),
+                field("price", FieldKind::Numeric),
+            ],
+        );
+        let documents = [
+            ("doc:1", "Red apple pie", "Fruit,Dessert", "10"),
+            ("doc:2", "Apple apple juice", "fruit", "-3.5"),
+            ("doc:3", "Cherry pie", "dessert", "7"),
+        ];
+        for (key, title, tags, price) in documents {
+            let mut hash = Hash::default();
+            hash.insert(Bytes::from_static(b"title"), Bytes::from(title));
+            hash.insert(Bytes::from_static(b"tags"), Bytes::from(tags));
+            hash.insert(Bytes::from_static(b"price"), Bytes::from(price));
+            index.add(key, &hash);
+        }
+        assert!(!index.covers("other:1"));
+
+        let search = |index: &SearchIndex, query: &str| -> Vec<String> {
+            let query = Query::parse(query).unwrap();
+            let results = index.search(&query).unwrap();
+            results
+                .into_iter()
+                .map(|(key, _)| key.to_string())
+                .collect()
+        };
+        // the document with the word twice in fewer words comes first
+        assert_eq!(search(&index, "apple"), ["doc:2", "doc:1"]);
+        assert_eq!(search(&index, "\"apple pie\""), ["doc:1"]);
+        assert_eq!(search(&index, "@tags:{DESSERT} -cherry"), ["doc:1"]);
+        assert_eq!(search(&index, "@price:[-inf (7] | ch*"), ["doc:2", "doc:3"]);
+        assert!(index.search(&Query::parse("@nope:{a}").unwrap()).is_err());
+
+        index.remove("doc:2");
+        assert_eq!(search(&index, "apple"), ["doc:1"]);
+        assert_eq!(index.len(), 2);
+    }
+}
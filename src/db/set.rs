@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use rand::{seq::index, Rng};
+
+use super::{as_integer, Database, Value, WrongType};
+use crate::config;
+
+/// an unordered set of members. a set of integers is a sorted intset, other
+/// small sets are a listpack searched linearly. larger sets keep their members
+/// in a vector as well as a map, so a random member can be picked in O(1)
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Set {
+    members: Members,
+}
+
+#[derive(Debug, Clone)]
+enum Members {
+    IntSet(Vec<i64>),
+    Listpack(Vec<Bytes>),
+    Table {
+        members: Vec<Bytes>,
+        positions: HashMap<Bytes, usize>,
+    },
+}
+
+impl Default for Members {
+    fn default() -> Self {
+        Members::IntSet(vec![])
+    }
+}
+
+impl Set {
+    /// return true if the member is new
+    pub(crate) fn insert(&mut self, member: Bytes) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+        if let Members::IntSet(values) = &mut self.members {
+            match as_integer(&member) {
+                Some(value) if values.len() < config::get(&config::SET_MAX_INTSET_ENTRIES) => {
+                    let i = values.partition_point(|v| *v < value);
+                    values.insert(i, value);
+                    return true;
+                }
+                Some(_) => self.members = Members::table(self.iter()),
+                None => {
+                    let fits = values.len() < config::get(&config::SET_MAX_LISTPACK_ENTRIES)
+                        && member.len() <= config::get(&config::SET_MAX_LISTPACK_VALUE);
+                    self.members = match fits {
+                        true => Members::Listpack(self.iter().collect()),
+                        false => Members::table(self.iter()),
+                    };
+                }
+            }
+        }
+        if let Members::Listpack(members) = &mut self.members {
+            if members.len() < config::get(&config::SET_MAX_LISTPACK_ENTRIES)
+                && member.len() <= config::get(&config::SET_MAX_LISTPACK_VALUE)
+            {
+                members.push(member);
+                return true;
+            }
+            self.members = Members::table(self.iter());
+        }
+        let Members::Table { members, positions } = &mut self.members else {
+            unreachable!()
+        };
+        positions.insert(member.clone(), members.len());
+        members.push(member);
+        true
+    }
+
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.members {
+            Members::IntSet(values) => {
+                match as_integer(member).map(|value| values.binary_search(&value)) {
+                    Some(Ok(i)) => {
+                        values.remove(i);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            Members::Listpack(members) => match members.iter().position(|m| m == member) {
+                Some(i) => {
+                    members.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Members::Table { members, positions } => {
+                let Some(pos) = positions.remove(member) else {
+                    return false;
+                };
+                members.swap_remove(pos);
+                if let Some(moved) = members.get(pos) {
+                    positions.insert(moved.clone(), pos);
+                }
+                true
+            }
+        }
+    }
+
+    pub(crate) fn contains(&self, member: &[u8]) -> bool {
+        match &self.members {
+            Members::IntSet(values) => {
+                as_integer(member).is_some_and(|value| values.binary_search(&value).is_ok())
+            }
+            Members::Listpack(members) => members.iter().any(|m| m == member),
+            Members::Table { positions, .. } => positions.contains_key(member),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match &self.members {
+            Members::IntSet(values) => values.len(),
+            Members::Listpack(members) | Members::Table { members, .. } => members.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Bytes> + '_ {
+        (0..self.len()).map(|i| self.member(i))
+    }
+
+    /// the name OBJECT ENCODING reports
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.members {
+            Members::IntSet(_) => "intset",
+            Members::Listpack(_) => "listpack",
+            Members::Table { .. } => "hashtable",
+        }
+    }
+
+    /// the member at a position of the encoding, integers are printed on the fly
+    fn member(&self, i: usize) -> Bytes {
+        match &self.members {
+            Members::IntSet(values) => Bytes::from(values[i].to_string()),
+            Members::Listpack(members) | Members::Table { members, .. } => members[i].clone(),
+        }
+    }
+
+    /// remove and return up to `count` random members
+    pub(crate) fn pop_random(&mut self, count: usize) -> Vec<Bytes> {
+        let mut rng = rand::thread_rng();
+        let mut popped = Vec::with_capacity(count.min(self.len()));
+        while popped.len() < count && !self.is_empty() {
+            let member = self.member(rng.gen_range(0..self.len()));
+            self.remove(&member);
+            popped.push(member);
+        }
+        popped
+    }
+
+    /// return `count` random members like SRANDMEMBER does: members are distinct
+    /// if count is positive, and may repeat if it is negative
+    pub(crate) fn random(&self, count: i64) -> Vec<Bytes> {
+        let mut rng = rand::thread_rng();
+        if self.is_empty() {
+            return vec![];
+        }
+        if count >= 0 {
+            let amount = (count as usize).min(self.len());
+            index::sample(&mut rng, self.len(), amount)
+                .into_iter()
+                .map(|i| self.member(i))
+                .collect()
+        } else {
+            (0..count.unsigned_abs())
+                .map(|_| self.member(rng.gen_range(0..self.len())))
+                .collect()
+        }
+    }
+
+    /// walk `count` members from `cursor`. the returned cursor is 0 once the walk is finished
+    pub(crate) fn scan(&self, cursor: usize, count: usize) -> (usize, Vec<Bytes>) {
+        if cursor >= self.len() {
+            return (0, vec![]);
+        }
+        let end = (cursor + count).min(self.len());
+        let next = if end == self.len() { 0 } else { end };
+        (next, (cursor..end).map(|i| self.member(i)).collect())
+    }
+}
+
+impl Members {
+    fn table(members: impl Iterator<Item = Bytes>) -> Members {
+        let members: Vec<Bytes> = members.collect();
+        let positions = members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| (member.clone(), i))
+            .collect();
+        Members::Table { members, positions }
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<T: IntoIterator<Item = Bytes>>(iter: T) -> Self {
+        let mut set = Set::default();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+impl Database {
+    pub(crate) fn get_set(&self, key: &str) -> Result<Option<&Set>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// `remove_if_empty` must be invoked once the modification is done
+    pub(crate) fn get_set_mut(&mut self, key: &str) -> Result<Option<&mut Set>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Set(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the set stored at key, an empty one is created if the key doesn't exist.
+    /// `remove_if_empty` must be invoked once the modification is done
+    pub(crate) fn get_or_insert_set(&mut self, key: &str) -> Result<&mut Set, WrongType> {
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Set(Set::default()));
+        match value {
+            Value::Set(set) => Ok(set),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_keeps_positions_test() {
+        let mut set: Set = ["a", "b", "c", "d"].into_iter().map(Bytes::from).collect();
+        assert!(!set.insert(Bytes::from_static(b"a")));
+        assert!(set.remove(b"a"));
+        assert!(!set.remove(b"a"));
+        // "d" was moved into the hole left by "a"
+        assert!(set.remove(b"d"));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(b"b") && set.contains(b"c"));
+
+        let (cursor, members) = set.scan(0, 1);
+        assert_eq!((cursor, members.len()), (1, 1));
+        let (cursor, members) = set.scan(cursor, 10);
+        assert_eq!((cursor, members.len()), (0, 1));
+        assert_eq!(set.random(-5).len(), 5);
+        assert_eq!(set.random(5).len(), 2);
+        assert_eq!(set.pop_random(5).len(), 2);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn encoding_upgrade_test() {
+        let mut set: Set = (0..10).rev().map(|i| Bytes::from(i.to_string())).collect();
+        assert_eq!(set.encoding(), "intset");
+        // integers are kept sorted, and "01" is not an integer written the usual way
+        assert_eq!(set.iter().next(), Some(Bytes::from_static(b"0")));
+        assert!(!set.contains(b"01"));
+        assert!(set.insert(Bytes::from_static(b"01")));
+        assert_eq!(set.encoding(), "listpack");
+        assert!(set.contains(b"7") && set.contains(b"01"));
+        for i in 10..200 {
+            set.insert(Bytes::from(i.to_string()));
+        }
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), 201);
+        assert!(set.remove(b"01") && set.remove(b"199"));
+        assert_eq!(set.iter().filter(|m| set.contains(m)).count(), 199);
+    }
+}
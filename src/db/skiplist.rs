@@ -0,0 +1,350 @@
+use std::cmp::Ordering;
+
+use bytes::Bytes;
+use rand::Rng;
+
+const MAX_LEVEL: usize = 32;
+/// probability of a node to be promoted to the next level
+const PROMOTION: f64 = 0.25;
+/// the header node always lives in the first slot
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone)]
+struct Level {
+    forward: Option<usize>,
+    /// number of nodes between this node and `forward` at this level, used to compute ranks
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+/// a skip list ordered by (score, member) which also tracks the rank of every node.
+/// nodes are stored in an arena and refer to each other by index
+#[derive(Debug, Clone)]
+pub(crate) struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    level: usize,
+    len: usize,
+}
+
+/// a handle to a node, only valid until the list is modified
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Cursor(usize);
+
+impl SkipList {
+    pub(crate) fn new() -> SkipList {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: vec![],
+            level: 1,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// insert a node, the member must not be in the list yet
+    pub(crate) fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, &member) == Ordering::Less {
+                    rank[i] += self.nodes[x].levels[i].span;
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let new = self.alloc(Node {
+            member,
+            score,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0
+                };
+                level
+            ],
+        });
+        for i in 0..level {
+            let prev = update[i];
+            self.nodes[new].levels[i].forward = self.nodes[prev].levels[i].forward;
+            self.nodes[prev].levels[i].forward = Some(new);
+            self.nodes[new].levels[i].span = self.nodes[prev].levels[i].span - (rank[0] - rank[i]);
+            self.nodes[prev].levels[i].span = rank[0] - rank[i] + 1;
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+
+        self.nodes[new].backward = if update[0] == HEAD {
+            None
+        } else {
+            Some(update[0])
+        };
+        if let Some(next) = self.nodes[new].levels[0].forward {
+            self.nodes[next].backward = Some(new);
+        }
+        self.len += 1;
+    }
+
+    /// remove the node matching both score and member
+    pub(crate) fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, member) == Ordering::Less {
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+            update[i] = x;
+        }
+        match self.nodes[x].levels[0].forward {
+            Some(target) if self.cmp_node(target, score, member) == Ordering::Equal => {
+                self.unlink(target, &update);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// 0-based rank of the node matching both score and member
+    pub(crate) fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if self.cmp_node(next, score, member) != Ordering::Greater {
+                    rank += self.nodes[x].levels[i].span;
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+            if x != HEAD && self.cmp_node(x, score, member) == Ordering::Equal {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// the node at a 0-based rank
+    pub(crate) fn by_rank(&self, rank: usize) -> Option<Cursor> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span <= target {
+                    traversed += self.nodes[x].levels[i].span;
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+            if traversed == target {
+                return Some(Cursor(x));
+            }
+        }
+        None
+    }
+
+    /// the first node for which `before` returns false.
+    /// `before` must hold for a prefix of the list
+    pub(crate) fn first_where_not(&self, before: impl Fn(f64, &Bytes) -> bool) -> Option<Cursor> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if before(self.nodes[next].score, &self.nodes[next].member) {
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.nodes[x].levels[0].forward.map(Cursor)
+    }
+
+    /// the last node for which `within` returns true.
+    /// `within` must hold for a prefix of the list
+    pub(crate) fn last_where(&self, within: impl Fn(f64, &Bytes) -> bool) -> Option<Cursor> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if within(self.nodes[next].score, &self.nodes[next].member) {
+                    x = next;
+                } else {
+                    break;
+                }
+            }
+        }
+        (x != HEAD).then_some(Cursor(x))
+    }
+
+    pub(crate) fn next(&self, cursor: Cursor) -> Option<Cursor> {
+        self.nodes[cursor.0].levels[0].forward.map(Cursor)
+    }
+
+    pub(crate) fn prev(&self, cursor: Cursor) -> Option<Cursor> {
+        self.nodes[cursor.0].backward.map(Cursor)
+    }
+
+    pub(crate) fn get(&self, cursor: Cursor) -> (&Bytes, f64) {
+        let node = &self.nodes[cursor.0];
+        (&node.member, node.score)
+    }
+
+    /// iterate from a node to the tail, or to the head if `reverse` is set
+    pub(crate) fn walk(
+        &self,
+        from: Option<Cursor>,
+        reverse: bool,
+    ) -> impl Iterator<Item = (&Bytes, f64)> {
+        std::iter::successors(from, move |cursor| {
+            if reverse {
+                self.prev(*cursor)
+            } else {
+                self.next(*cursor)
+            }
+        })
+        .map(|cursor| self.get(cursor))
+    }
+
+    fn cmp_node(&self, node: usize, score: f64, member: &[u8]) -> Ordering {
+        let node = &self.nodes[node];
+        node.score
+            .total_cmp(&score)
+            .then_with(|| node.member[..].cmp(member))
+    }
+
+    fn unlink(&mut self, target: usize, update: &[usize; MAX_LEVEL]) {
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[*prev].levels[i].forward == Some(target) {
+                self.nodes[*prev].levels[i].span += self.nodes[target].levels[i].span;
+                self.nodes[*prev].levels[i].span -= 1;
+                self.nodes[*prev].levels[i].forward = self.nodes[target].levels[i].forward;
+            } else {
+                self.nodes[*prev].levels[i].span -= 1;
+            }
+        }
+        if let Some(next) = self.nodes[target].levels[0].forward {
+            self.nodes[next].backward = self.nodes[target].backward;
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        // release the slot so that it can be reused by the next insertion
+        self.nodes[target].member = Bytes::new();
+        self.nodes[target].levels.clear();
+        self.free.push(target);
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+fn random_level() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_bool(PROMOTION) {
+        level += 1;
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_test() {
+        let mut list = SkipList::new();
+        let mut expected = vec![];
+        for i in 0..500 {
+            let score = ((i * 7919) % 101) as f64;
+            let member = Bytes::from(format!("m{}", i));
+            list.insert(score, member.clone());
+            expected.push((score, member));
+        }
+        for (score, member) in expected.iter().step_by(3) {
+            assert!(list.remove(*score, member));
+        }
+        assert!(!list.remove(1000.0, b"m0"));
+        let mut expected: Vec<_> = expected
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| i % 3 != 0)
+            .map(|(_, e)| e)
+            .collect();
+        expected.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+
+        assert_eq!(list.len(), expected.len());
+        for (rank, (score, member)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(*score, member), Some(rank));
+            assert_eq!(list.get(list.by_rank(rank).unwrap()), (member, *score));
+        }
+        let walked: Vec<_> = list
+            .walk(list.by_rank(list.len() - 1), true)
+            .map(|(m, _)| m.clone())
+            .collect();
+        let reversed: Vec<_> = expected.iter().rev().map(|(_, m)| m.clone()).collect();
+        assert_eq!(walked, reversed);
+
+        let first = list.first_where_not(|score, _| score < 50.0).unwrap();
+        assert!(list.get(first).1 >= 50.0);
+        assert!(list.prev(first).is_none_or(|prev| list.get(prev).1 < 50.0));
+    }
+}
@@ -0,0 +1,709 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::Bound,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+
+use super::{Database, Value, WrongType};
+
+/// number of entries a node of the redis radix tree holds. approximate trimming
+/// only removes whole nodes, so it removes entries in chunks of this size
+const TRIM_CHUNK: usize = 100;
+/// at most how many entries approximate trimming removes when LIMIT isn't given
+const DEFAULT_TRIM_LIMIT: usize = 100 * TRIM_CHUNK;
+
+/// the ID of a stream entry, `<milliseconds>-<sequence>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Hash)]
+pub(crate) struct StreamId {
+    pub(crate) ms: u64,
+    pub(crate) seq: u64,
+}
+
+/// the ID requested by XADD
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum NewId {
+    /// `*`
+    Auto,
+    /// `<ms>-*`
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// how a stream is trimmed by XADD and XTRIM
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Trim {
+    pub(crate) strategy: TrimStrategy,
+    /// `~`, only remove whole chunks of entries, which is cheaper
+    pub(crate) approximate: bool,
+    /// at most how many entries are removed, only allowed when approximate
+    pub(crate) limit: Option<usize>,
+}
+
+pub(crate) type Fields = Vec<(Bytes, Bytes)>;
+
+/// an append-only log of entries ordered by ID
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    last_id: StreamId,
+    /// number of entries ever added, deleted ones included
+    entries_added: u64,
+    max_deleted_id: StreamId,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+}
+
+/// a consumer group keeps track of what has been delivered to its consumers
+/// and what they haven't acknowledged yet
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ConsumerGroup {
+    last_delivered: StreamId,
+    /// the pending entries list, shared by every consumer of the group
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Bytes, Consumer>,
+}
+
+/// an entry which has been delivered but not acknowledged yet
+#[derive(Debug, Clone)]
+pub(crate) struct PendingEntry {
+    pub(crate) consumer: Bytes,
+    /// unix time in milliseconds of the last delivery
+    pub(crate) delivered_at: u64,
+    pub(crate) delivery_count: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Consumer {
+    /// unix time in milliseconds of the last interaction, successful or not
+    pub(crate) seen_at: u64,
+    /// unix time in milliseconds of the last time it read or claimed entries
+    pub(crate) active_at: Option<u64>,
+    pending: BTreeSet<StreamId>,
+}
+
+/// which entries XCLAIM and XAUTOCLAIM claim and how they update them
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ClaimOptions {
+    /// only claim entries idle for at least this many milliseconds
+    pub(crate) min_idle: u64,
+    /// unix time in milliseconds to record as the last delivery, now by default
+    pub(crate) delivered_at: Option<u64>,
+    pub(crate) retry_count: Option<u64>,
+    /// claim entries which aren't pending yet, as long as they are in the stream
+    pub(crate) force: bool,
+    /// don't increment the delivery count
+    pub(crate) just_id: bool,
+}
+
+/// what XCLAIM and XAUTOCLAIM report
+#[derive(Debug, Default)]
+pub(crate) struct Claimed {
+    pub(crate) entries: Vec<(StreamId, Fields)>,
+    /// pending entries which were deleted from the stream, they are dropped from the group
+    pub(crate) deleted: Vec<StreamId>,
+    /// where XAUTOCLAIM should resume, `0-0` once the whole list has been scanned
+    pub(crate) next: StreamId,
+}
+
+/// returned when the ID given to XADD can't be appended
+#[derive(Debug)]
+pub(crate) enum StreamIdError {
+    Zero,
+    TooSmall,
+}
+
+impl StreamId {
+    pub(crate) const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub(crate) const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    /// parse `<ms>-<seq>`, or `<ms>` in which case the sequence is `default_seq`
+    pub(crate) fn parse(id: &str, default_seq: u64) -> Option<StreamId> {
+        match id.split_once('-') {
+            Some((ms, seq)) => Some(StreamId {
+                ms: ms.parse().ok()?,
+                seq: seq.parse().ok()?,
+            }),
+            None => Some(StreamId {
+                ms: id.parse().ok()?,
+                seq: default_seq,
+            }),
+        }
+    }
+
+    /// the smallest ID greater than this one
+    pub(crate) fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// the greatest ID smaller than this one
+    pub(crate) fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl fmt::Display for StreamIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamIdError::Zero => {
+                write!(f, "ERR The ID specified in XADD must be greater than 0-0")
+            }
+            StreamIdError::TooSmall => write!(
+                f,
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+            ),
+        }
+    }
+}
+
+impl Stream {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// the greatest ID ever added, entries may have been deleted since
+    pub(crate) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub(crate) fn last_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries
+            .last_key_value()
+            .map(|(id, fields)| (*id, fields))
+    }
+
+    /// append an entry, its ID must be greater than every ID added before
+    pub(crate) fn add(&mut self, id: NewId, fields: Fields) -> Result<StreamId, StreamIdError> {
+        let last = self.last_id;
+        let id = match id {
+            NewId::Auto => {
+                let now = now_ms();
+                if now > last.ms {
+                    StreamId { ms: now, seq: 0 }
+                } else {
+                    last.next().ok_or(StreamIdError::TooSmall)?
+                }
+            }
+            NewId::AutoSeq(ms) if ms == last.ms => StreamId {
+                ms,
+                seq: last.seq.checked_add(1).ok_or(StreamIdError::TooSmall)?,
+            },
+            NewId::AutoSeq(ms) => StreamId {
+                ms,
+                seq: if ms == 0 { 1 } else { 0 },
+            },
+            NewId::Explicit(id) => id,
+        };
+        if id == StreamId::MIN {
+            return Err(StreamIdError::Zero);
+        }
+        if id <= last {
+            return Err(StreamIdError::TooSmall);
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+        Ok(id)
+    }
+
+    /// entries within `start..=end`, from the end if `reverse` is set
+    pub(crate) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        reverse: bool,
+        count: Option<usize>,
+    ) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return vec![];
+        }
+        let range = self.entries.range(start..=end);
+        let count = count.unwrap_or(usize::MAX);
+        let clone = |(id, fields): (&StreamId, &Fields)| (*id, fields.clone());
+        if reverse {
+            range.rev().take(count).map(clone).collect()
+        } else {
+            range.take(count).map(clone).collect()
+        }
+    }
+
+    /// entries whose ID is greater than `after`
+    pub(crate) fn read_after(
+        &self,
+        after: StreamId,
+        count: Option<usize>,
+    ) -> Vec<(StreamId, Fields)> {
+        self.entries
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect()
+    }
+
+    pub(crate) fn delete(&mut self, id: StreamId) -> bool {
+        let deleted = self.entries.remove(&id).is_some();
+        if deleted {
+            self.max_deleted_id = self.max_deleted_id.max(id);
+        }
+        deleted
+    }
+
+    /// remove the oldest entries according to `trim`, return how many were removed
+    pub(crate) fn trim(&mut self, trim: Trim) -> usize {
+        let mut removable = match trim.strategy {
+            TrimStrategy::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        if trim.approximate {
+            removable = removable.min(trim.limit.unwrap_or(DEFAULT_TRIM_LIMIT));
+            removable -= removable % TRIM_CHUNK;
+        }
+        for _ in 0..removable {
+            if let Some((id, _)) = self.entries.pop_first() {
+                self.max_deleted_id = self.max_deleted_id.max(id);
+            }
+        }
+        removable
+    }
+
+    pub(crate) fn first_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries
+            .first_key_value()
+            .map(|(id, fields)| (*id, fields))
+    }
+
+    pub(crate) fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub(crate) fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    /// number of entries which haven't been delivered to a group yet
+    pub(crate) fn lag(&self, group: &ConsumerGroup) -> usize {
+        self.entries
+            .range((Bound::Excluded(group.last_delivered), Bound::Unbounded))
+            .count()
+    }
+
+    pub(crate) fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub(crate) fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub(crate) fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// return false if the group already exists
+    pub(crate) fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        let group = ConsumerGroup {
+            last_delivered,
+            ..Default::default()
+        };
+        self.groups.insert(name, group);
+        true
+    }
+
+    pub(crate) fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// deliver the entries which haven't been delivered to the group yet.
+    /// unless `no_ack` is set, they are pending until the consumer acknowledges them.
+    /// return `None` if the group doesn't exist
+    pub(crate) fn read_group(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: Option<usize>,
+        no_ack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let group = self.groups.get_mut(group)?;
+        let entries: Vec<_> = self
+            .entries
+            .range((Bound::Excluded(group.last_delivered), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+
+        let reader = group.touch_consumer(consumer, now);
+        if !entries.is_empty() {
+            reader.active_at = Some(now);
+        }
+        if let Some((last, _)) = entries.last() {
+            group.last_delivered = *last;
+        }
+        if !no_ack {
+            for (id, _) in &entries {
+                group.deliver(*id, consumer, now, 1);
+            }
+        }
+        Some(entries)
+    }
+
+    /// the entries pending for a consumer whose ID is greater than `after`.
+    /// entries deleted from the stream meanwhile come without fields.
+    /// return `None` if the group doesn't exist
+    pub(crate) fn read_history(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        after: StreamId,
+        count: Option<usize>,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Option<Fields>)>> {
+        let group = self.groups.get_mut(group)?;
+        let reader = group.touch_consumer(consumer, now);
+        let ids = reader
+            .pending
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX));
+        Some(ids.map(|id| (*id, self.entries.get(id).cloned())).collect())
+    }
+
+    /// transfer the ownership of pending entries to `consumer`.
+    /// return `None` if the group doesn't exist
+    pub(crate) fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        ids: &[StreamId],
+        options: ClaimOptions,
+        now: u64,
+    ) -> Option<Claimed> {
+        let group = self.groups.get_mut(group)?;
+        group.touch_consumer(consumer, now);
+        let mut claimed = Claimed::default();
+        for id in ids {
+            let Some(fields) = self.entries.get(id) else {
+                if group.ack(*id) {
+                    claimed.deleted.push(*id);
+                }
+                continue;
+            };
+            match group.pending.get(id) {
+                Some(entry) if now.saturating_sub(entry.delivered_at) < options.min_idle => {
+                    continue
+                }
+                Some(_) => {}
+                None if options.force => {}
+                None => continue,
+            }
+            group.transfer(*id, consumer, now, options);
+            claimed.entries.push((*id, fields.clone()));
+        }
+        Some(claimed)
+    }
+
+    /// scan the pending entries from `start` and claim up to `count` of them.
+    /// return `None` if the group doesn't exist
+    pub(crate) fn auto_claim(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        start: StreamId,
+        count: usize,
+        options: ClaimOptions,
+        now: u64,
+    ) -> Option<Claimed> {
+        let group = self.groups.get_mut(group)?;
+        group.touch_consumer(consumer, now);
+        // bound the work done by a single call like redis does
+        let mut attempts = count.saturating_mul(10);
+        let mut claimed = Claimed::default();
+        let mut cursor = Some(start);
+        while let Some(from) = cursor {
+            if claimed.entries.len() >= count || attempts == 0 {
+                break;
+            }
+            attempts -= 1;
+            let Some((id, entry)) = group.pending.range(from..).next() else {
+                cursor = None;
+                break;
+            };
+            let (id, idle) = (*id, now.saturating_sub(entry.delivered_at));
+            cursor = id.next();
+            if idle < options.min_idle {
+                continue;
+            }
+            match self.entries.get(&id) {
+                Some(fields) => {
+                    group.transfer(id, consumer, now, options);
+                    claimed.entries.push((id, fields.clone()));
+                }
+                None => {
+                    group.ack(id);
+                    claimed.deleted.push(id);
+                }
+            }
+        }
+        claimed.next = cursor
+            .and_then(|from| group.pending.range(from..).next().map(|(id, _)| *id))
+            .unwrap_or(StreamId::MIN);
+        Some(claimed)
+    }
+}
+
+impl ConsumerGroup {
+    pub(crate) fn last_delivered(&self) -> StreamId {
+        self.last_delivered
+    }
+
+    pub(crate) fn set_last_delivered(&mut self, id: StreamId) {
+        self.last_delivered = id;
+    }
+
+    pub(crate) fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub(crate) fn consumers(&self) -> impl Iterator<Item = (&Bytes, &Consumer)> {
+        self.consumers.iter()
+    }
+
+    /// return false if the consumer already exists
+    pub(crate) fn create_consumer(&mut self, name: Bytes, now: u64) -> bool {
+        if self.consumers.contains_key(&name) {
+            return false;
+        }
+        self.touch_consumer(&name, now);
+        true
+    }
+
+    /// delete a consumer together with its pending entries, return how many it had
+    pub(crate) fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// acknowledge an entry, return false if it wasn't pending
+    pub(crate) fn ack(&mut self, id: StreamId) -> bool {
+        let Some(entry) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(consumer) = self.consumers.get_mut(&entry.consumer) {
+            consumer.pending.remove(&id);
+        }
+        true
+    }
+
+    /// get a consumer, created on the fly, and record that it has been seen
+    fn touch_consumer(&mut self, name: &Bytes, now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.clone()).or_insert(Consumer {
+            seen_at: now,
+            active_at: None,
+            pending: BTreeSet::new(),
+        });
+        consumer.seen_at = now;
+        consumer
+    }
+
+    fn deliver(&mut self, id: StreamId, consumer: &Bytes, now: u64, delivery_count: u64) {
+        self.ack(id);
+        let entry = PendingEntry {
+            consumer: consumer.clone(),
+            delivered_at: now,
+            delivery_count,
+        };
+        self.pending.insert(id, entry);
+        self.touch_consumer(consumer, now).pending.insert(id);
+    }
+
+    fn transfer(&mut self, id: StreamId, consumer: &Bytes, now: u64, options: ClaimOptions) {
+        let mut delivery_count = self
+            .pending
+            .get(&id)
+            .map_or(0, |entry| entry.delivery_count);
+        if !options.just_id {
+            delivery_count += 1;
+        }
+        self.deliver(
+            id,
+            consumer,
+            options.delivered_at.unwrap_or(now),
+            options.retry_count.unwrap_or(delivery_count),
+        );
+        self.touch_consumer(consumer, now).active_at = Some(now);
+    }
+}
+
+impl Consumer {
+    pub(crate) fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// unix time in milliseconds, which is also the clock of generated IDs
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Database {
+    pub(crate) fn get_stream(&self, key: &str) -> Result<Option<&Stream>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_stream_mut(&mut self, key: &str) -> Result<Option<&mut Stream>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the stream stored at key, an empty one is created if the key doesn't exist.
+    /// unlike other collections, a stream stays in the keyspace once it is empty
+    pub(crate) fn get_or_insert_stream(&mut self, key: &str) -> Result<&mut Stream, WrongType> {
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Stream(Stream::default()));
+        match value {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    #[test]
+    fn add_and_trim_test() {
+        let mut stream = Stream::default();
+        assert!(matches!(
+            stream.add(NewId::Explicit(id(0, 0)), vec![]),
+            Err(StreamIdError::Zero)
+        ));
+        assert_eq!(stream.add(NewId::AutoSeq(0), vec![]).unwrap(), id(0, 1));
+        assert_eq!(stream.add(NewId::AutoSeq(5), vec![]).unwrap(), id(5, 0));
+        assert_eq!(stream.add(NewId::AutoSeq(5), vec![]).unwrap(), id(5, 1));
+        assert!(matches!(
+            stream.add(NewId::Explicit(id(5, 1)), vec![]),
+            Err(StreamIdError::TooSmall)
+        ));
+        assert_eq!(stream.len(), 3);
+        assert!(Stream::default().add(NewId::Auto, vec![]).unwrap() > id(5, 1));
+
+        for ms in 10..260 {
+            stream.add(NewId::Explicit(id(ms, 0)), vec![]).unwrap();
+        }
+        // approximate trimming only removes whole chunks
+        let approximate = |strategy| Trim {
+            strategy,
+            approximate: true,
+            limit: None,
+        };
+        assert_eq!(stream.trim(approximate(TrimStrategy::MaxLen(200))), 0);
+        assert_eq!(stream.trim(approximate(TrimStrategy::MaxLen(100))), 100);
+        assert_eq!(stream.len(), 153);
+        let exact = Trim {
+            strategy: TrimStrategy::MinId(id(200, 0)),
+            approximate: false,
+            limit: None,
+        };
+        assert_eq!(stream.trim(exact), 93);
+        assert_eq!(
+            stream.range(StreamId::MIN, StreamId::MAX, false, Some(1))[0].0,
+            id(200, 0)
+        );
+        assert_eq!(stream.read_after(id(258, 0), None).len(), 1);
+    }
+
+    #[test]
+    fn consumer_group_test() {
+        let mut stream = Stream::default();
+        for ms in 1..=4 {
+            stream.add(NewId::Explicit(id(ms, 0)), vec![]).unwrap();
+        }
+        let (alice, bob) = (Bytes::from_static(b"alice"), Bytes::from_static(b"bob"));
+        assert!(stream.create_group(Bytes::from_static(b"g"), StreamId::MIN));
+        assert!(stream.read_group(b"nope", &alice, None, false, 0).is_none());
+
+        let read = stream
+            .read_group(b"g", &alice, Some(3), false, 1000)
+            .unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(stream.lag(stream.group(b"g").unwrap()), 1);
+        assert!(stream.group_mut(b"g").unwrap().ack(id(1, 0)));
+        assert!(stream.delete(id(2, 0)));
+
+        // entries deleted from the stream are dropped instead of being claimed
+        let options = ClaimOptions {
+            min_idle: 500,
+            ..Default::default()
+        };
+        let claimed = stream
+            .auto_claim(b"g", &bob, StreamId::MIN, 10, options, 1200)
+            .unwrap();
+        assert!(claimed.entries.is_empty() && claimed.deleted.is_empty());
+        let claimed = stream
+            .auto_claim(b"g", &bob, StreamId::MIN, 10, options, 2000)
+            .unwrap();
+        assert_eq!(claimed.entries.len(), 1);
+        assert_eq!(claimed.deleted, vec![id(2, 0)]);
+        assert_eq!(claimed.next, StreamId::MIN);
+
+        let group = stream.group(b"g").unwrap();
+        let pending = &group.pending()[&id(3, 0)];
+        assert_eq!((&pending.consumer, pending.delivery_count), (&bob, 2));
+        assert_eq!(group.pending().len(), 1);
+        let history = stream.read_history(b"g", &alice, StreamId::MIN, None, 2000);
+        assert_eq!(history.unwrap().len(), 0);
+    }
+}
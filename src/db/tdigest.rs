@@ -0,0 +1,352 @@
+use std::f64::consts::PI;
+
+use super::{Database, Value, WrongType};
+
+/// samples are buffered and merged into the centroids by batches of this many compressions
+const BUFFER_FACTOR: f64 = 6.0;
+
+/// a merging t-digest: samples are summarized by centroids which are small near both
+/// ends of the distribution, so that extreme quantiles stay accurate
+#[derive(Debug, Clone)]
+pub(crate) struct TDigest {
+    compression: f64,
+    /// ordered by mean and merged
+    centroids: Vec<Centroid>,
+    /// samples which still have to be merged into the centroids
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+impl TDigest {
+    pub(crate) fn new(compression: f64) -> TDigest {
+        TDigest {
+            compression,
+            centroids: vec![],
+            buffer: vec![],
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub(crate) fn compression(&self) -> f64 {
+        self.compression
+    }
+
+    pub(crate) fn add(&mut self, value: f64) {
+        self.add_weighted(value, 1.0);
+    }
+
+    /// fold the samples of another digest into this one
+    pub(crate) fn merge(&mut self, other: &TDigest) {
+        for centroid in other.centroids.iter().chain(&other.buffer) {
+            self.add_weighted(centroid.mean, centroid.weight);
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = TDigest::new(self.compression);
+    }
+
+    pub(crate) fn min(&self) -> f64 {
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            self.min
+        }
+    }
+
+    pub(crate) fn max(&self) -> f64 {
+        if self.is_empty() {
+            f64::NAN
+        } else {
+            self.max
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.centroids.is_empty() && self.buffer.is_empty()
+    }
+
+    fn add_weighted(&mut self, value: f64, weight: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(Centroid {
+            mean: value,
+            weight,
+        });
+        if self.buffer.len() as f64 >= self.compression * BUFFER_FACTOR {
+            self.compress();
+        }
+    }
+
+    /// merge the buffered samples with the centroids, the weight a centroid may reach
+    /// is bounded by the k1 scale function
+    pub(crate) fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|centroid| centroid.weight).sum();
+
+        let mut merged = Vec::with_capacity(all.len());
+        let mut current = all[0];
+        let mut weight_so_far = 0.0;
+        let mut limit = total * self.q_limit(0.0);
+        for centroid in all.into_iter().skip(1) {
+            if weight_so_far + current.weight + centroid.weight <= limit {
+                let weight = current.weight + centroid.weight;
+                current.mean += (centroid.mean - current.mean) * centroid.weight / weight;
+                current.weight = weight;
+            } else {
+                weight_so_far += current.weight;
+                merged.push(current);
+                limit = total * self.q_limit(weight_so_far / total);
+                current = centroid;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// the quantile one unit of the scale function away from `q`
+    fn q_limit(&self, q: f64) -> f64 {
+        let k = self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin() + 1.0;
+        let k = k.min(self.compression / 4.0);
+        ((k * 2.0 * PI / self.compression).sin() + 1.0) / 2.0
+    }
+
+    fn total_weight(&self) -> f64 {
+        self.centroids.iter().map(|centroid| centroid.weight).sum()
+    }
+
+    /// the estimated value at quantile `q`, the digest must have been compressed
+    pub(crate) fn quantile(&self, q: f64) -> f64 {
+        let centroids = &self.centroids;
+        let n = centroids.len();
+        if n == 0 {
+            return f64::NAN;
+        }
+        if n == 1 {
+            return centroids[0].mean;
+        }
+        let total = self.total_weight();
+        let index = q * total;
+        if index < 1.0 {
+            return self.min;
+        }
+        let (first, last) = (centroids[0], centroids[n - 1]);
+        // the tails are interpolated towards the extremes, which are known exactly
+        if first.weight > 1.0 && index < first.weight / 2.0 {
+            return self.min + (index - 1.0) / (first.weight / 2.0 - 1.0) * (first.mean - self.min);
+        }
+        if index > total - 1.0 {
+            return self.max;
+        }
+        if last.weight > 1.0 && total - index <= last.weight / 2.0 {
+            return self.max
+                - (total - index - 1.0) / (last.weight / 2.0 - 1.0) * (self.max - last.mean);
+        }
+
+        let mut weight_so_far = first.weight / 2.0;
+        for pair in centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let dw = (left.weight + right.weight) / 2.0;
+            if weight_so_far + dw > index {
+                // a single sample is exact, it isn't spread around its mean
+                let mut left_unit = 0.0;
+                if left.weight == 1.0 {
+                    if index - weight_so_far < 0.5 {
+                        return left.mean;
+                    }
+                    left_unit = 0.5;
+                }
+                let mut right_unit = 0.0;
+                if right.weight == 1.0 {
+                    if weight_so_far + dw - index <= 0.5 {
+                        return right.mean;
+                    }
+                    right_unit = 0.5;
+                }
+                let z1 = index - weight_so_far - left_unit;
+                let z2 = weight_so_far + dw - index - right_unit;
+                return weighted_average(left.mean, z2, right.mean, z1);
+            }
+            weight_so_far += dw;
+        }
+        let z1 = index - total - last.weight / 2.0;
+        let z2 = last.weight / 2.0 - z1;
+        weighted_average(last.mean, z1, self.max, z2)
+    }
+
+    /// the estimated fraction of samples below `value`, the digest must have been compressed
+    pub(crate) fn cdf(&self, value: f64) -> f64 {
+        let centroids = &self.centroids;
+        let n = centroids.len();
+        if n == 0 {
+            return f64::NAN;
+        }
+        if value < self.min {
+            return 0.0;
+        }
+        if value > self.max {
+            return 1.0;
+        }
+        if n == 1 {
+            return if self.max - self.min <= f64::EPSILON {
+                0.5
+            } else {
+                (value - self.min) / (self.max - self.min)
+            };
+        }
+        let total = self.total_weight();
+        let (first, last) = (centroids[0], centroids[n - 1]);
+        if value < first.mean {
+            if first.mean - self.min <= 0.0 {
+                return 0.0;
+            }
+            if value == self.min {
+                return 0.5 / total;
+            }
+            return (1.0
+                + (value - self.min) / (first.mean - self.min) * (first.weight / 2.0 - 1.0))
+                / total;
+        }
+        if value > last.mean {
+            if self.max - last.mean <= 0.0 {
+                return 1.0;
+            }
+            if value == self.max {
+                return 1.0 - 0.5 / total;
+            }
+            let tail = (1.0
+                + (self.max - value) / (self.max - last.mean) * (last.weight / 2.0 - 1.0))
+                / total;
+            return 1.0 - tail;
+        }
+
+        let mut weight_so_far = 0.0;
+        let mut i = 0;
+        while i < n - 1 {
+            let (left, right) = (centroids[i], centroids[i + 1]);
+            if left.mean == value {
+                let equal: f64 = centroids[i..]
+                    .iter()
+                    .take_while(|centroid| centroid.mean == value)
+                    .map(|centroid| centroid.weight)
+                    .sum();
+                return (weight_so_far + equal / 2.0) / total;
+            }
+            if left.mean <= value && value < right.mean {
+                let dw = (left.weight + right.weight) / 2.0;
+                if right.mean - left.mean <= 0.0 {
+                    return (weight_so_far + dw) / total;
+                }
+                let (mut left_excluded, mut right_excluded) = (0.0, 0.0);
+                if left.weight == 1.0 {
+                    if right.weight == 1.0 {
+                        return (weight_so_far + 1.0) / total;
+                    }
+                    left_excluded = 0.5;
+                } else if right.weight == 1.0 {
+                    right_excluded = 0.5;
+                }
+                let dw_no_singleton = dw - left_excluded - right_excluded;
+                let base = weight_so_far + left.weight / 2.0 + left_excluded;
+                return (base + dw_no_singleton * (value - left.mean) / (right.mean - left.mean))
+                    / total;
+            }
+            weight_so_far += left.weight;
+            i += 1;
+        }
+        1.0 - 0.5 / total
+    }
+
+    /// the mean of the samples between two quantiles, the digest must have been compressed
+    pub(crate) fn trimmed_mean(&self, low: f64, high: f64) -> f64 {
+        let total = self.total_weight();
+        let (from, to) = (low * total, high * total);
+        let (mut sum, mut weight, mut weight_so_far) = (0.0, 0.0, 0.0);
+        for centroid in &self.centroids {
+            let start = weight_so_far;
+            let end = weight_so_far + centroid.weight;
+            weight_so_far = end;
+            let included = end.min(to) - start.max(from);
+            if included > 0.0 {
+                sum += centroid.mean * included;
+                weight += included;
+            }
+        }
+        if weight == 0.0 {
+            f64::NAN
+        } else {
+            sum / weight
+        }
+    }
+}
+
+/// the average of two values, kept within them despite rounding
+fn weighted_average(x1: f64, w1: f64, x2: f64, w2: f64) -> f64 {
+    let (low, high) = if x1 <= x2 { (x1, x2) } else { (x2, x1) };
+    ((x1 * w1 + x2 * w2) / (w1 + w2)).clamp(low, high)
+}
+
+impl Database {
+    pub(crate) fn get_tdigest(&self, key: &str) -> Result<Option<&TDigest>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::TDigest(digest)) => Ok(Some(digest)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_tdigest_mut(&mut self, key: &str) -> Result<Option<&mut TDigest>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::TDigest(digest)) => Ok(Some(digest)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tdigest_test() {
+        let mut digest = TDigest::new(100.0);
+        for i in 1..=10000 {
+            digest.add(i as f64);
+        }
+        digest.compress();
+        assert!(digest.centroids.len() < 200);
+        assert_eq!((digest.min(), digest.max()), (1.0, 10000.0));
+        for (q, expected) in [(0.5, 5000.0), (0.99, 9900.0), (0.999, 9990.0)] {
+            let estimate = digest.quantile(q);
+            assert!((estimate - expected).abs() < 10.0, "{} at {}", estimate, q);
+        }
+        assert!((digest.cdf(2500.0) - 0.25).abs() < 0.001);
+        assert!((digest.trimmed_mean(0.1, 0.9) - 5000.5).abs() < 5.0);
+
+        let mut other = TDigest::new(100.0);
+        other.add(-5.0);
+        other.add(20000.0);
+        digest.merge(&other);
+        digest.compress();
+        assert_eq!(
+            (digest.quantile(0.0), digest.quantile(1.0)),
+            (-5.0, 20000.0)
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+
+use super::{Database, Value, WrongType};
+
+/// what happens when a sample is added at the timestamp of an existing one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DuplicatePolicy {
+    Block,
+    First,
+    Last,
+    Min,
+    Max,
+    Sum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Aggregation {
+    Avg,
+    Sum,
+    Min,
+    Max,
+    Count,
+    First,
+    Last,
+    Range,
+}
+
+/// samples of the source are aggregated by buckets into the destination series
+#[derive(Debug, Clone)]
+pub(crate) struct CompactionRule {
+    pub(crate) destination: String,
+    pub(crate) aggregation: Aggregation,
+    pub(crate) bucket: u64,
+    /// the start of the bucket still receiving samples
+    current: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimeSeries {
+    /// values by timestamp in milliseconds
+    samples: BTreeMap<u64, f64>,
+    /// how far behind the last sample samples are kept, zero keeps them all
+    retention: u64,
+    duplicate_policy: DuplicatePolicy,
+    labels: Vec<(String, String)>,
+    rules: Vec<CompactionRule>,
+    /// the series this one is compacted from
+    source: Option<String>,
+}
+
+/// the reasons a sample is refused
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum SampleError {
+    TooOld,
+    Duplicate,
+}
+
+impl DuplicatePolicy {
+    pub(crate) fn parse(name: &str) -> Option<DuplicatePolicy> {
+        match &name.to_lowercase()[..] {
+            "block" => Some(DuplicatePolicy::Block),
+            "first" => Some(DuplicatePolicy::First),
+            "last" => Some(DuplicatePolicy::Last),
+            "min" => Some(DuplicatePolicy::Min),
+            "max" => Some(DuplicatePolicy::Max),
+            "sum" => Some(DuplicatePolicy::Sum),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            DuplicatePolicy::Block => "block",
+            DuplicatePolicy::First => "first",
+            DuplicatePolicy::Last => "last",
+            DuplicatePolicy::Min => "min",
+            DuplicatePolicy::Max => "max",
+            DuplicatePolicy::Sum => "sum",
+        }
+    }
+}
+
+impl Aggregation {
+    pub(crate) fn parse(name: &str) -> Option<Aggregation> {
+        match &name.to_lowercase()[..] {
+            "avg" => Some(Aggregation::Avg),
+            "sum" => Some(Aggregation::Sum),
+            "min" => Some(Aggregation::Min),
+            "max" => Some(Aggregation::Max),
+            "count" => Some(Aggregation::Count),
+            "first" => Some(Aggregation::First),
+            "last" => Some(Aggregation::Last),
+            "range" => Some(Aggregation::Range),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Sum => "sum",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Count => "count",
+            Aggregation::First => "first",
+            Aggregation::Last => "last",
+            Aggregation::Range => "range",
+        }
+    }
+
+    /// the aggregate of values in timestamp order, there is at least one
+    fn apply(self, values: &[f64]) -> f64 {
+        let min = || values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = || values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        match self {
+            Aggregation::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregation::Sum => values.iter().sum(),
+            Aggregation::Min => min(),
+            Aggregation::Max => max(),
+            Aggregation::Count => values.len() as f64,
+            Aggregation::First => values[0],
+            Aggregation::Last => values[values.len() - 1],
+            Aggregation::Range => max() - min(),
+        }
+    }
+
+    /// aggregate samples in timestamp order by buckets aligned on the epoch, every bucket
+    /// is replied at its start
+    pub(crate) fn by_buckets(self, samples: &[(u64, f64)], bucket: u64) -> Vec<(u64, f64)> {
+        samples
+            .chunk_by(|a, b| a.0 / bucket == b.0 / bucket)
+            .map(|chunk| {
+                let values: Vec<f64> = chunk.iter().map(|(_, value)| *value).collect();
+                (chunk[0].0 - chunk[0].0 % bucket, self.apply(&values))
+            })
+            .collect()
+    }
+}
+
+impl CompactionRule {
+    pub(crate) fn new(destination: String, aggregation: Aggregation, bucket: u64) -> Self {
+        CompactionRule {
+            destination,
+            aggregation,
+            bucket,
+            current: None,
+        }
+    }
+}
+
+impl TimeSeries {
+    pub(crate) fn new(
+        retention: u64,
+        duplicate_policy: DuplicatePolicy,
+        labels: Vec<(String, String)>,
+    ) -> TimeSeries {
+        TimeSeries {
+            samples: BTreeMap::new(),
+            retention,
+            duplicate_policy,
+            labels,
+            rules: vec![],
+            source: None,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub(crate) fn retention(&self) -> u64 {
+        self.retention
+    }
+
+    pub(crate) fn duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    pub(crate) fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    pub(crate) fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn rules(&self) -> &[CompactionRule] {
+        &self.rules
+    }
+
+    pub(crate) fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub(crate) fn first(&self) -> Option<(u64, f64)> {
+        self.samples.first_key_value().map(|(t, v)| (*t, *v))
+    }
+
+    pub(crate) fn last(&self) -> Option<(u64, f64)> {
+        self.samples.last_key_value().map(|(t, v)| (*t, *v))
+    }
+
+    /// change the settings which are given, a shorter retention applies at once
+    pub(crate) fn alter(
+        &mut self,
+        retention: Option<u64>,
+        duplicate_policy: Option<DuplicatePolicy>,
+        labels: Option<Vec<(String, String)>>,
+    ) {
+        if let Some(retention) = retention {
+            self.retention = retention;
+            self.trim();
+        }
+        if let Some(policy) = duplicate_policy {
+            self.duplicate_policy = policy;
+        }
+        if let Some(labels) = labels {
+            self.labels = labels;
+        }
+    }
+
+    /// store a sample, `policy` overrides the duplicate policy of the series. the buckets
+    /// this closes are returned as samples for the destinations of the compaction rules
+    pub(crate) fn add(
+        &mut self,
+        timestamp: u64,
+        value: f64,
+        policy: Option<DuplicatePolicy>,
+    ) -> Result<Vec<(String, u64, f64)>, SampleError> {
+        if let Some((last, _)) = self.last() {
+            if self.retention > 0 && timestamp < last.saturating_sub(self.retention) {
+                return Err(SampleError::TooOld);
+            }
+        }
+        match self.samples.get_mut(&timestamp) {
+            Some(current) => match policy.unwrap_or(self.duplicate_policy) {
+                DuplicatePolicy::Block => return Err(SampleError::Duplicate),
+                DuplicatePolicy::First => {}
+                DuplicatePolicy::Last => *current = value,
+                DuplicatePolicy::Min => *current = current.min(value),
+                DuplicatePolicy::Max => *current = current.max(value),
+                DuplicatePolicy::Sum => *current += value,
+            },
+            None => {
+                self.samples.insert(timestamp, value);
+            }
+        }
+        let closed = self.compact(timestamp);
+        self.trim();
+        Ok(closed)
+    }
+
+    /// write a sample whatever the duplicate policy, as compactions do
+    pub(crate) fn upsert(&mut self, timestamp: u64, value: f64) {
+        self.samples.insert(timestamp, value);
+        self.trim();
+    }
+
+    /// a sample past the bucket of a rule closes that bucket, samples added to buckets
+    /// which are already closed don't update the destination
+    fn compact(&mut self, timestamp: u64) -> Vec<(String, u64, f64)> {
+        let mut closed = vec![];
+        for rule in &mut self.rules {
+            let start = timestamp - timestamp % rule.bucket;
+            match rule.current {
+                Some(current) if current < start => {
+                    let values: Vec<f64> = self
+                        .samples
+                        .range(current..current + rule.bucket)
+                        .map(|(_, value)| *value)
+                        .collect();
+                    if !values.is_empty() {
+                        let value = rule.aggregation.apply(&values);
+                        closed.push((rule.destination.clone(), current, value));
+                    }
+                    rule.current = Some(start);
+                }
+                None => rule.current = Some(start),
+                _ => {}
+            }
+        }
+        closed
+    }
+
+    fn trim(&mut self) {
+        if let (Some((last, _)), true) = (self.last(), self.retention > 0) {
+            self.samples = self.samples.split_off(&last.saturating_sub(self.retention));
+        }
+    }
+
+    /// the samples between both timestamps included
+    pub(crate) fn range(&self, from: u64, to: u64) -> Vec<(u64, f64)> {
+        if from > to {
+            return vec![];
+        }
+        self.samples
+            .range(from..=to)
+            .map(|(t, v)| (*t, *v))
+            .collect()
+    }
+
+    pub(crate) fn delete_range(&mut self, from: u64, to: u64) -> usize {
+        let deleted: Vec<u64> = self.range(from, to).iter().map(|(t, _)| *t).collect();
+        for timestamp in &deleted {
+            self.samples.remove(timestamp);
+        }
+        deleted.len()
+    }
+
+    pub(crate) fn add_rule(&mut self, rule: CompactionRule) {
+        self.rules.push(rule);
+    }
+
+    /// returns whether there was a rule towards the destination
+    pub(crate) fn remove_rule(&mut self, destination: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.destination != destination);
+        self.rules.len() != len
+    }
+
+    pub(crate) fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
+}
+
+impl Database {
+    pub(crate) fn get_timeseries(&self, key: &str) -> Result<Option<&TimeSeries>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::TimeSeries(series)) => Ok(Some(series)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_timeseries_mut(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<&mut TimeSeries>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::TimeSeries(series)) => Ok(Some(series)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// every time series with its key, ordered by key
+    pub(crate) fn all_timeseries(&self) -> Vec<(&String, &TimeSeries)> {
+        let mut all: Vec<(&String, &TimeSeries)> = self
+            .entries
+            .iter()
+            .filter_map(|(key, value)| match value {
+                Value::TimeSeries(series) => Some((key, series)),
+                _ => None,
+            })
+            .collect();
+        all.sort_unstable_by_key(|(key, _)| *key);
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeseries_test() {
+        let mut series = TimeSeries::new(100, DuplicatePolicy::Block, vec![]);
+        series.add_rule(CompactionRule::new("avg".to_string(), Aggregation::Avg, 10));
+        let mut compacted = vec![];
+        for timestamp in [1, 5, 9, 12, 18, 31] {
+            compacted.extend(series.add(timestamp, timestamp as f64, None).unwrap());
+        }
+        assert_eq!(
+            compacted,
+            vec![("avg".to_string(), 0, 5.0), ("avg".to_string(), 10, 15.0)]
+        );
+        assert_eq!(series.add(31, 1.0, None), Err(SampleError::Duplicate));
+        assert_eq!(series.add(31, 1.0, Some(DuplicatePolicy::Sum)), Ok(vec![]));
+        assert_eq!(series.last(), Some((31, 32.0)));
+
+        // the samples more than 100ms older than the last one are dropped
+        series.add(120, 0.0, None).unwrap();
+        assert_eq!(series.first(), Some((31, 32.0)));
+        assert_eq!(series.add(10, 0.0, None), Err(SampleError::TooOld));
+
+        let samples = series.range(0, u64::MAX);
+        assert_eq!(
+            Aggregation::Count.by_buckets(&samples, 100),
+            vec![(0, 1.0), (100, 1.0)]
+        );
+        assert_eq!(series.delete_range(0, 100), 1);
+        assert_eq!(series.len(), 1);
+    }
+}
@@ -0,0 +1,181 @@
+use bytes::Bytes;
+use rand::Rng;
+
+use super::{Database, Value, WrongType};
+use crate::hyperloglog::murmur_hash64a;
+
+/// seeds the fingerprints, the rows are hashed with their index as seed
+const FINGERPRINT_SEED: u64 = 1919;
+/// the decay of counters above this count is considered null
+const DECAY_LOOKUP_LEN: usize = 256;
+/// the most buckets, and the most items tracked, a Top-K may have
+const MAX_BUCKETS: usize = 1 << 24;
+
+/// a Top-K of the heaviest hitters with the HeavyKeeper algorithm. every row keeps a
+/// fingerprint and a counter per bucket, and a colliding item decays the counter of the
+/// current owner with a probability which shrinks exponentially with its count
+#[derive(Debug, Clone)]
+pub(crate) struct TopK {
+    k: usize,
+    width: usize,
+    depth: usize,
+    buckets: Vec<Bucket>,
+    /// the heavy hitters tracked so far, at most `k` of them
+    heap: Vec<(Bytes, u32)>,
+    decay_lookup: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    fingerprint: u64,
+    count: u32,
+}
+
+impl TopK {
+    /// the sizes are checked before the buckets are allocated
+    pub(crate) fn new(
+        k: usize,
+        width: usize,
+        depth: usize,
+        decay: f64,
+    ) -> Result<TopK, &'static str> {
+        let len = match width.checked_mul(depth) {
+            Some(len) if len <= MAX_BUCKETS && k <= MAX_BUCKETS => len,
+            _ => return Err("TopK: dimensions are too large"),
+        };
+        Ok(TopK {
+            k,
+            width,
+            depth,
+            buckets: vec![Bucket::default(); len],
+            heap: Vec::with_capacity(k),
+            decay_lookup: (0..DECAY_LOOKUP_LEN)
+                .map(|i| decay.powi(i as i32))
+                .collect(),
+        })
+    }
+
+    /// count the item `increment` times, returning the item expelled from the top list if any
+    pub(crate) fn add(&mut self, item: &Bytes, increment: u32) -> Option<Bytes> {
+        let fingerprint = murmur_hash64a(item, FINGERPRINT_SEED);
+        let mut rng = rand::thread_rng();
+        let mut max_count = 0;
+        for row in 0..self.depth {
+            let column = (murmur_hash64a(item, row as u64) % self.width as u64) as usize;
+            let bucket = &mut self.buckets[row * self.width + column];
+            if bucket.count == 0 {
+                *bucket = Bucket {
+                    fingerprint,
+                    count: increment,
+                };
+                max_count = max_count.max(bucket.count);
+            } else if bucket.fingerprint == fingerprint {
+                bucket.count = bucket.count.saturating_add(increment);
+                max_count = max_count.max(bucket.count);
+            } else {
+                for remaining in (1..=increment).rev() {
+                    let decay = self
+                        .decay_lookup
+                        .get(bucket.count as usize)
+                        .copied()
+                        .unwrap_or(0.0);
+                    if rng.gen::<f64>() < decay {
+                        bucket.count -= 1;
+                        if bucket.count == 0 {
+                            *bucket = Bucket {
+                                fingerprint,
+                                count: remaining,
+                            };
+                            max_count = max_count.max(remaining);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.update_heap(item, max_count)
+    }
+
+    fn update_heap(&mut self, item: &Bytes, count: u32) -> Option<Bytes> {
+        if let Some(entry) = self.heap.iter_mut().find(|(member, _)| member == item) {
+            entry.1 = entry.1.max(count);
+            return None;
+        }
+        if count == 0 {
+            return None;
+        }
+        if self.heap.len() < self.k {
+            self.heap.push((item.clone(), count));
+            return None;
+        }
+        let (min_index, (_, min_count)) = self
+            .heap
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (_, count))| *count)?;
+        if count <= *min_count {
+            return None;
+        }
+        let (expelled, _) = std::mem::replace(&mut self.heap[min_index], (item.clone(), count));
+        Some(expelled)
+    }
+
+    pub(crate) fn contains(&self, item: &[u8]) -> bool {
+        self.heap.iter().any(|(member, _)| member == item)
+    }
+
+    /// the heavy hitters from the most to the least frequent
+    pub(crate) fn list(&self) -> Vec<(Bytes, u32)> {
+        let mut list = self.heap.clone();
+        list.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        list
+    }
+}
+
+impl Database {
+    pub(crate) fn get_topk(&self, key: &str) -> Result<Option<&TopK>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::TopK(topk)) => Ok(Some(topk)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_topk_mut(&mut self, key: &str) -> Result<Option<&mut TopK>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::TopK(topk)) => Ok(Some(topk)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heavy_keeper_test() {
+        let mut topk = TopK::new(3, 50, 5, 0.9).unwrap();
+        let mut expelled = vec![];
+        for round in 0..100 {
+            for i in 0..30 {
+                // callers 0, 1 and 2 are much heavier than the others
+                let hits = if i < 3 { 20 - i } else { 1 };
+                let item = Bytes::from(format!("caller:{}", i));
+                expelled.extend(topk.add(&item, hits));
+            }
+            if round == 0 {
+                assert_eq!(topk.list().len(), 3);
+            }
+        }
+        let list: Vec<Bytes> = topk.list().into_iter().map(|(item, _)| item).collect();
+        assert_eq!(list, vec!["caller:0", "caller:1", "caller:2"]);
+        assert!(topk.contains(b"caller:1") && !topk.contains(b"caller:10"));
+        assert!(expelled.iter().all(|item| !topk.contains(item)));
+
+        assert!(TopK::new(3, usize::MAX, 2, 0.9).is_err());
+        assert!(TopK::new(3, MAX_BUCKETS, 2, 0.9).is_err());
+        assert!(TopK::new(usize::MAX, 8, 7, 0.9).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+use super::Database;
+use crate::Frame;
+
+/// the id standing for the server itself, such as the cleaner expiring keys.
+/// clients are numbered from 1
+pub(crate) const SERVER: u64 = 0;
+
+/// the channel a client redirected to is told about invalidations on, when it
+/// speaks RESP2 and is subscribed to it
+const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+/// the connected clients and the keys they cache. in the default mode the keys a
+/// client has read are remembered until they are invalidated once, in the
+/// broadcasting mode every key starting with one of its prefixes is announced
+#[derive(Default)]
+pub(crate) struct Clients {
+    clients: HashMap<u64, Client>,
+    keys: HashMap<String, HashSet<u64>>,
+    prefixes: HashMap<String, HashSet<u64>>,
+}
+
+struct Client {
+    /// the push messages of the client, written between its commands
+    sender: mpsc::Sender<Frame>,
+    tracking: Option<Tracking>,
+    /// set by CLIENT CACHING for the next command
+    caching: Option<bool>,
+    /// the version of RESP chosen with HELLO, push messages need RESP3
+    protocol: u8,
+}
+
+/// the options of CLIENT TRACKING ON
+#[derive(Debug, Default)]
+pub(crate) struct Tracking {
+    pub(crate) redirect: Option<u64>,
+    pub(crate) bcast: bool,
+    pub(crate) prefixes: Vec<String>,
+    /// only the keys read right after CLIENT CACHING YES are tracked
+    pub(crate) optin: bool,
+    /// the keys read right after CLIENT CACHING NO are not tracked
+    pub(crate) optout: bool,
+    /// the keys modified by the client itself are not invalidated for it
+    pub(crate) noloop: bool,
+}
+
+impl Database {
+    /// register a newly connected client. its push messages are handed to `sender`
+    pub(super) fn connect(&mut self, id: u64, sender: mpsc::Sender<Frame>) {
+        let client = Client {
+            sender,
+            tracking: None,
+            caching: None,
+            protocol: 2,
+        };
+        self.clients.clients.insert(id, client);
+    }
+
+    pub(crate) fn disconnect(&mut self, id: u64) {
+        self.stop_tracking(id);
+        self.unwatch(id);
+        self.clients.clients.remove(&id);
+    }
+
+    /// the client whose command is applied
+    pub(crate) fn client(&self) -> u64 {
+        self.client
+    }
+
+    /// the version of RESP the current client speaks
+    pub(crate) fn protocol(&self) -> u8 {
+        let client = self.clients.clients.get(&self.client);
+        client.map_or(2, |client| client.protocol)
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: u8) {
+        if let Some(client) = self.clients.clients.get_mut(&self.client) {
+            client.protocol = protocol;
+        }
+    }
+
+    /// turn tracking on for the current client, replacing its previous options
+    pub(crate) fn start_tracking(&mut self, tracking: Tracking) -> Result<(), String> {
+        let id = self.client;
+        if tracking
+            .redirect
+            .is_some_and(|redirect| !self.clients.clients.contains_key(&redirect))
+        {
+            return Err("ERR The client ID you want redirect to does not exist".to_string());
+        }
+        for (i, prefix) in tracking.prefixes.iter().enumerate() {
+            let overlapping = tracking.prefixes[i + 1..].iter().find(|other| {
+                other.starts_with(prefix.as_str()) || prefix.starts_with(other.as_str())
+            });
+            if let Some(other) = overlapping {
+                return Err(format!(
+                    "ERR Prefix '{}' overlaps with another provided prefix '{}'. Prefixes for a single client must not overlap.",
+                    prefix, other
+                ));
+            }
+        }
+
+        self.stop_tracking(id);
+        let clients = &mut self.clients;
+        let Some(client) = clients.clients.get_mut(&id) else {
+            return Ok(());
+        };
+        if tracking.bcast {
+            let prefixes = match tracking.prefixes.is_empty() {
+                true => vec![String::new()],
+                false => tracking.prefixes.clone(),
+            };
+            for prefix in prefixes {
+                clients.prefixes.entry(prefix).or_default().insert(id);
+            }
+        }
+        client.tracking = Some(tracking);
+        Ok(())
+    }
+
+    /// the keys the client has read are forgotten lazily, when they are invalidated
+    pub(crate) fn stop_tracking(&mut self, id: u64) {
+        let clients = &mut self.clients;
+        let Some(client) = clients.clients.get_mut(&id) else {
+            return;
+        };
+        client.caching = None;
+        let Some(tracking) = client.tracking.take() else {
+            return;
+        };
+        if tracking.bcast {
+            clients.prefixes.retain(|_, ids| {
+                ids.remove(&id);
+                !ids.is_empty()
+            });
+        }
+    }
+
+    /// the options of the current client, if it is tracking keys
+    pub(crate) fn tracking(&self) -> Option<&Tracking> {
+        let client = self.clients.clients.get(&self.client)?;
+        client.tracking.as_ref()
+    }
+
+    /// CLIENT CACHING YES or NO, for the next command of the current client
+    pub(crate) fn set_caching(&mut self, yes: bool) -> Result<(), &'static str> {
+        let client = self.clients.clients.get_mut(&self.client);
+        let Some(client) = client.filter(|client| client.tracking.is_some()) else {
+            return Err("ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled");
+        };
+        let tracking = client.tracking.as_ref().unwrap();
+        match (yes, tracking.optin, tracking.optout) {
+            (true, true, _) | (false, _, true) => {
+                client.caching = Some(yes);
+                Ok(())
+            }
+            (true, false, _) => {
+                Err("ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")
+            }
+            (false, _, false) => {
+                Err("ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")
+            }
+        }
+    }
+
+    /// forget CLIENT CACHING once the command following it is done
+    pub(crate) fn reset_caching(&mut self, id: u64) {
+        if let Some(client) = self.clients.clients.get_mut(&id) {
+            client.caching = None;
+        }
+    }
+
+    /// remember that the current client has read the key, if it caches it
+    pub(crate) fn track(&mut self, key: &str) {
+        let id = self.client;
+        let Some(client) = self.clients.clients.get(&id) else {
+            return;
+        };
+        let tracked = match &client.tracking {
+            Some(tracking) if tracking.bcast => false,
+            Some(tracking) if tracking.optin => client.caching == Some(true),
+            Some(tracking) if tracking.optout => client.caching != Some(false),
+            Some(_) => true,
+            None => false,
+        };
+        if tracked {
+            self.clients
+                .keys
+                .entry(key.to_string())
+                .or_default()
+                .insert(id);
+        }
+    }
+
+    /// tell the clients caching the key that it has been modified
+    pub(super) fn invalidate(&mut self, key: &str) {
+        let clients = &mut self.clients;
+        if clients.clients.is_empty() {
+            return;
+        }
+        let mut ids = clients.keys.remove(key).unwrap_or_default();
+        for (prefix, subscribers) in &clients.prefixes {
+            if key.starts_with(prefix.as_str()) {
+                ids.extend(subscribers);
+            }
+        }
+        let keys = Frame::Array(vec![Frame::Bulk(Bytes::copy_from_slice(key.as_bytes()))]);
+        let overflowing: Vec<u64> = ids
+            .into_iter()
+            .filter_map(|id| self.send_invalidation(id, &keys))
+            .collect();
+        // the cache of a client which can't be told about invalidations can't be
+        // trusted anymore, it is disconnected so that it drops it
+        for id in overflowing {
+            self.disconnect(id);
+        }
+    }
+
+    /// the invalidation goes to the client the tracking one redirects to, if any.
+    /// a RESP3 client receives it as a push message, a redirected RESP2 client as
+    /// a pub/sub message if it is subscribed to the invalidation channel. return
+    /// the client whose queue is full, if the invalidation couldn't be queued
+    fn send_invalidation(&self, id: u64, keys: &Frame) -> Option<u64> {
+        let client = self.clients.clients.get(&id)?;
+        let tracking = client.tracking.as_ref()?;
+        if tracking.noloop && id == self.client {
+            return None;
+        }
+        let (target_id, target) = match tracking.redirect {
+            None => (id, client),
+            Some(redirect) => match self.clients.clients.get(&redirect) {
+                Some(target) => (redirect, target),
+                None if client.protocol < 3 => return None,
+                None => {
+                    let broken = vec![
+                        Frame::Bulk(Bytes::from_static(b"tracking-redir-broken")),
+                        Frame::Integer(redirect as i64),
+                    ];
+                    return client
+                        .sender
+                        .try_send(Frame::Push(broken))
+                        .is_err()
+                        .then_some(id);
+                }
+            },
+        };
+        let queued = if target.protocol >= 3 {
+            let push = vec![Frame::Bulk(Bytes::from_static(b"invalidate")), keys.clone()];
+            target.sender.try_send(Frame::Push(push)).is_ok()
+        } else if tracking.redirect.is_some() {
+            let channel = Bytes::from_static(INVALIDATE_CHANNEL);
+            self.send_message(target_id, channel, keys.clone())
+        } else {
+            true
+        };
+        (!queued).then_some(target_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{cmd::Reset, Command};
+
+    #[test]
+    fn invalidation_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut reader) = mpsc::channel(16);
+        let reader_id = 1;
+        db.connect(reader_id, sender);
+        let (sender, mut watcher) = mpsc::channel(16);
+        let watcher_id = 2;
+        db.connect(watcher_id, sender);
+        let invalidate = |key: &'static str| {
+            Frame::Push(vec![
+                Frame::Bulk(Bytes::from_static(b"invalidate")),
+                Frame::Array(vec![Frame::Bulk(Bytes::from_static(key.as_bytes()))]),
+            ])
+        };
+
+        db.client = reader_id;
+        db.set_protocol(3);
+        db.start_tracking(Tracking::default()).unwrap();
+        db.track("k");
+        db.client = watcher_id;
+        db.set_protocol(3);
+        let tracking = Tracking {
+            bcast: true,
+            prefixes: vec!["user:".to_string()],
+            noloop: true,
+            ..Default::default()
+        };
+        db.start_tracking(tracking).unwrap();
+
+        db.client = SERVER;
+        db.invalidate("k");
+        assert_eq!(reader.try_recv().unwrap(), invalidate("k"));
+        // a key read once is invalidated once
+        db.invalidate("k");
+        assert!(reader.try_recv().is_err());
+
+        db.invalidate("user:1");
+        assert_eq!(watcher.try_recv().unwrap(), invalidate("user:1"));
+        db.client = watcher_id;
+        db.invalidate("user:2");
+        assert!(watcher.try_recv().is_err());
+        db.invalidate("other");
+        assert!(watcher.try_recv().is_err() && reader.try_recv().is_err());
+
+        // overlapping prefixes are rejected
+        let tracking = Tracking {
+            bcast: true,
+            prefixes: vec!["a".to_string(), "ab".to_string()],
+            ..Default::default()
+        };
+        assert!(db.start_tracking(tracking).is_err());
+        db.disconnect(watcher_id);
+        assert!(db.clients.prefixes.is_empty());
+    }
+
+    #[test]
+    fn unchanged_keys_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut pushes) = mpsc::channel(16);
+        let id = 1;
+        db.connect(id, sender);
+        db.set("s".to_string(), Bytes::from_static(b"v"), None);
+        Command::parse(&["SADD", "set", "m"]).apply(&mut db);
+        db.client = id;
+        db.set_protocol(3);
+        db.start_tracking(Tracking::default()).unwrap();
+        db.track("s");
+        db.track("set");
+
+        // neither a write refused because of the type of the value nor one
+        // changing nothing invalidates the key
+        db.client = SERVER;
+        for words in [
+            &["SADD", "s", "m"][..],
+            &["SREM", "set", "n"],
+            &["SADD", "set", "m"],
+        ] {
+            Command::parse(words).apply(&mut db);
+        }
+        assert!(pushes.try_recv().is_err());
+        Command::parse(&["SREM", "set", "m"]).apply(&mut db);
+        assert!(pushes.try_recv().is_ok());
+
+        // RESET turns tracking off
+        db.client = id;
+        db.track("s");
+        Reset.apply(&mut db);
+        assert!(db.tracking().is_none());
+        db.client = SERVER;
+        Command::parse(&["SADD", "s2", "m"]).apply(&mut db);
+        db.set("s".to_string(), Bytes::from_static(b"w"), None);
+        assert!(pushes.try_recv().is_err());
+    }
+
+    #[test]
+    fn resp2_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut pushes) = mpsc::channel(16);
+        db.connect(1, sender);
+        let (sender, mut target) = mpsc::channel(16);
+        db.connect(2, sender.clone());
+        db.add_subscriber(2, sender);
+        db.client = 1;
+        db.start_tracking(Tracking::default()).unwrap();
+        db.track("k");
+
+        // a RESP2 client can't receive push messages
+        db.client = SERVER;
+        db.invalidate("k");
+        assert!(pushes.try_recv().is_err());
+
+        // the redirected invalidations reach a RESP2 client only through the
+        // invalidation channel
+        db.client = 1;
+        let tracking = Tracking {
+            redirect: Some(2),
+            ..Default::default()
+        };
+        db.start_tracking(tracking).unwrap();
+        db.track("k");
+        db.subscribe(2, Bytes::from_static(b"news"));
+        db.client = SERVER;
+        db.invalidate("k");
+        assert!(target.try_recv().is_err());
+        db.client = 1;
+        db.track("k");
+        db.subscribe(2, Bytes::from_static(INVALIDATE_CHANNEL));
+        db.client = SERVER;
+        db.invalidate("k");
+        let message = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"message")),
+            Frame::Bulk(Bytes::from_static(INVALIDATE_CHANNEL)),
+            Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"k"))]),
+        ]);
+        assert_eq!(target.try_recv().unwrap(), message);
+        assert!(pushes.try_recv().is_err());
+    }
+
+    #[test]
+    fn full_queue_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        let (sender, mut pushes) = mpsc::channel(1);
+        db.connect(1, sender);
+        db.client = 1;
+        db.set_protocol(3);
+        db.start_tracking(Tracking::default()).unwrap();
+        db.track("a");
+        db.track("b");
+
+        // a client which misses an invalidation is disconnected
+        db.client = SERVER;
+        db.invalidate("a");
+        assert!(db.clients.clients.contains_key(&1));
+        db.invalidate("b");
+        assert!(!db.clients.clients.contains_key(&1));
+        assert!(pushes.try_recv().is_ok());
+        assert_eq!(
+            pushes.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,685 @@
+use bytes::Bytes;
+use rand::Rng;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::{Database, Value, WrongType};
+
+/// the levels of the graph are capped, a higher level is astronomically unlikely
+const MAX_LEVEL: usize = 16;
+
+/// how the components of the vectors are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Quantization {
+    NoQuant,
+    /// a byte per component, scaled by the largest one
+    Q8,
+    /// the sign of every component
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Metric {
+    Cosine,
+    L2,
+}
+
+/// the direction of a vector, its length is stored aside
+#[derive(Debug)]
+enum Encoded {
+    F32(Vec<f32>),
+    Q8 { values: Vec<i8>, scale: f32 },
+    Binary(Vec<u64>),
+}
+
+#[derive(Debug)]
+struct Node {
+    name: Bytes,
+    vector: Encoded,
+    norm: f32,
+    attributes: Option<serde_json::Value>,
+    /// the neighbours on every level the node belongs to, from the lowest
+    links: Vec<Vec<usize>>,
+}
+
+/// a vector to compare the nodes with, as a unit vector and its length
+struct Probe {
+    unit: Vec<f32>,
+    norm: f32,
+}
+
+/// a node and its distance to a probe, ordered by distance
+#[derive(Debug, Clone, Copy)]
+struct Candidate(f32, usize);
+
+/// a set of named vectors indexed by a hierarchical navigable small world graph: every
+/// node is linked to its closest neighbours on the lowest level and to fewer and fewer
+/// nodes on the levels above, which a search descends greedily
+#[derive(Debug)]
+pub(crate) struct VectorSet {
+    dim: usize,
+    quantization: Quantization,
+    metric: Metric,
+    /// the number of neighbours of the nodes, twice as many on the lowest level
+    m: usize,
+    /// the number of candidates considered when linking a new node
+    ef_construction: usize,
+    nodes: Vec<Option<Node>>,
+    /// the slots of the removed nodes
+    free: Vec<usize>,
+    by_name: HashMap<Bytes, usize>,
+    /// the node the searches start from, on the highest level
+    entry: Option<usize>,
+}
+
+impl Quantization {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Quantization::NoQuant => "f32",
+            Quantization::Q8 => "int8",
+            Quantization::Binary => "bin",
+        }
+    }
+}
+
+impl Metric {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::L2 => "l2",
+        }
+    }
+}
+
+impl Probe {
+    fn new(vector: &[f32]) -> Probe {
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let unit = match norm {
+            0.0 => vector.to_vec(),
+            _ => vector.iter().map(|x| x / norm).collect(),
+        };
+        Probe { unit, norm }
+    }
+}
+
+impl Encoded {
+    fn new(unit: &[f32], quantization: Quantization) -> Encoded {
+        match quantization {
+            Quantization::NoQuant => Encoded::F32(unit.to_vec()),
+            Quantization::Q8 => {
+                let max = unit.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                let values = unit.iter().map(|x| (x / scale).round() as i8).collect();
+                Encoded::Q8 { values, scale }
+            }
+            Quantization::Binary => {
+                let mut bits = vec![0u64; unit.len().div_ceil(64)];
+                for (i, x) in unit.iter().enumerate() {
+                    if *x > 0.0 {
+                        bits[i / 64] |= 1 << (i % 64);
+                    }
+                }
+                Encoded::Binary(bits)
+            }
+        }
+    }
+
+    /// the unit vector, as far as the quantization remembers it
+    fn decode(&self, dim: usize) -> Vec<f32> {
+        (0..dim).map(|i| self.component(i, dim)).collect()
+    }
+
+    fn component(&self, i: usize, dim: usize) -> f32 {
+        match self {
+            Encoded::F32(values) => values[i],
+            Encoded::Q8 { values, scale } => values[i] as f32 * scale,
+            Encoded::Binary(bits) => match bits[i / 64] >> (i % 64) & 1 {
+                1 => 1.0 / (dim as f32).sqrt(),
+                _ => -1.0 / (dim as f32).sqrt(),
+            },
+        }
+    }
+
+    fn dot(&self, unit: &[f32]) -> f32 {
+        match self {
+            Encoded::F32(values) => values.iter().zip(unit).map(|(a, b)| a * b).sum(),
+            Encoded::Q8 { values, scale } => {
+                values
+                    .iter()
+                    .zip(unit)
+                    .map(|(a, b)| *a as f32 * b)
+                    .sum::<f32>()
+                    * scale
+            }
+            Encoded::Binary(bits) => {
+                let sum: f32 = unit
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| match bits[i / 64] >> (i % 64) & 1 {
+                        1 => *x,
+                        _ => -x,
+                    })
+                    .sum();
+                sum / (unit.len() as f32).sqrt()
+            }
+        }
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl VectorSet {
+    pub(crate) fn new(
+        dim: usize,
+        quantization: Quantization,
+        metric: Metric,
+        m: usize,
+        ef_construction: usize,
+    ) -> VectorSet {
+        VectorSet {
+            dim,
+            quantization,
+            metric,
+            m,
+            ef_construction,
+            nodes: vec![],
+            free: vec![],
+            by_name: HashMap::new(),
+            entry: None,
+        }
+    }
+
+    pub(crate) fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub(crate) fn quantization(&self) -> Quantization {
+        self.quantization
+    }
+
+    pub(crate) fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub(crate) fn m(&self) -> usize {
+        self.m
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// the highest level of the graph, the one the searches start from
+    pub(crate) fn max_level(&self) -> usize {
+        self.entry
+            .map_or(0, |entry| self.node(entry).links.len() - 1)
+    }
+
+    /// the stored vector of an element, which loses precision with quantization
+    pub(crate) fn embedding(&self, name: &[u8]) -> Option<Vec<f32>> {
+        let node = self.node(*self.by_name.get(name)?);
+        Some(
+            node.vector
+                .decode(self.dim)
+                .into_iter()
+                .map(|x| x * node.norm)
+                .collect(),
+        )
+    }
+
+    pub(crate) fn attributes(&self, name: &[u8]) -> Option<&serde_json::Value> {
+        self.node(*self.by_name.get(name)?).attributes.as_ref()
+    }
+
+    /// returns false when there is no such element
+    pub(crate) fn set_attributes(
+        &mut self,
+        name: &[u8],
+        attributes: Option<serde_json::Value>,
+    ) -> bool {
+        let Some(&id) = self.by_name.get(name) else {
+            return false;
+        };
+        self.node_mut(id).attributes = attributes;
+        true
+    }
+
+    /// the neighbours of an element on every level, with their similarity
+    pub(crate) fn links(&self, name: &[u8]) -> Option<Vec<Vec<(&Bytes, f64)>>> {
+        let node = self.node(*self.by_name.get(name)?);
+        let probe = self.probe(node);
+        Some(
+            node.links
+                .iter()
+                .map(|links| {
+                    links
+                        .iter()
+                        .map(|id| {
+                            let neighbour = self.node(*id);
+                            (
+                                &neighbour.name,
+                                self.similarity(self.distance(&probe, neighbour)),
+                            )
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    /// the vector of an existing element is replaced, its attributes are kept. returns
+    /// whether the element is new
+    pub(crate) fn add(&mut self, name: Bytes, vector: &[f32]) -> bool {
+        let attributes = match self.by_name.get(&name) {
+            Some(&id) => self.node_mut(id).attributes.take(),
+            None => None,
+        };
+        let added = !self.remove(&name);
+        let probe = Probe::new(vector);
+        let level = self.random_level();
+        let node = Node {
+            name: name.clone(),
+            vector: Encoded::new(&probe.unit, self.quantization),
+            norm: probe.norm,
+            attributes,
+            links: vec![vec![]; level + 1],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = Some(node);
+                id
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.by_name.insert(name, id);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return added;
+        };
+        let top = self.max_level();
+        let mut entries = vec![entry];
+        for level in (level + 1..=top).rev() {
+            let closest = self.search_layer(&probe, &entries, 1, level, &|_| true, None);
+            entries = vec![closest[0].1];
+        }
+        for level in (0..=level.min(top)).rev() {
+            let found = self.search_layer(
+                &probe,
+                &entries,
+                self.ef_construction,
+                level,
+                &|_| true,
+                None,
+            );
+            let neighbours = self.select_neighbours(&found, self.m);
+            for &neighbour in &neighbours {
+                self.node_mut(neighbour).links[level].push(id);
+                if self.node(neighbour).links[level].len() > self.max_links(level) {
+                    self.prune(neighbour, level);
+                }
+            }
+            self.node_mut(id).links[level] = neighbours;
+            entries = found.iter().map(|candidate| candidate.1).collect();
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+        added
+    }
+
+    /// the nodes which were linked to the element are linked to its neighbours instead
+    pub(crate) fn remove(&mut self, name: &[u8]) -> bool {
+        let Some(id) = self.by_name.remove(name) else {
+            return false;
+        };
+        let node = self.nodes[id].take().unwrap();
+        self.free.push(id);
+        for (level, orphans) in node.links.iter().enumerate() {
+            // the nodes on either side of the removed one are relinked among themselves
+            let mut affected: Vec<usize> = (0..self.nodes.len())
+                .filter(|&other| {
+                    self.nodes[other].as_ref().is_some_and(|other| {
+                        other
+                            .links
+                            .get(level)
+                            .is_some_and(|links| links.contains(&id))
+                    })
+                })
+                .collect();
+            for &orphan in orphans {
+                if !affected.contains(&orphan) {
+                    affected.push(orphan);
+                }
+            }
+            for &other in &affected {
+                let links = &mut self.node_mut(other).links[level];
+                links.retain(|&link| link != id);
+                for &candidate in &affected {
+                    if candidate != other && !links.contains(&candidate) {
+                        links.push(candidate);
+                    }
+                }
+                self.prune(other, level);
+            }
+        }
+        if self.entry == Some(id) {
+            self.entry = (0..self.nodes.len())
+                .filter(|&other| self.nodes[other].is_some())
+                .max_by_key(|&other| self.node(other).links.len());
+        }
+        true
+    }
+
+    /// the closest elements to the vector which pass the filter, with their similarity.
+    /// `ef` is the number of candidates considered, a search with a filter gives up
+    /// after visiting `budget` nodes
+    pub(crate) fn search(
+        &self,
+        vector: &[f32],
+        count: usize,
+        ef: usize,
+        filter: &dyn Fn(Option<&serde_json::Value>) -> bool,
+        budget: Option<usize>,
+    ) -> Vec<(&Bytes, f64)> {
+        let Some(entry) = self.entry else {
+            return vec![];
+        };
+        let probe = Probe::new(vector);
+        let mut entries = vec![entry];
+        for level in (1..=self.max_level()).rev() {
+            let closest = self.search_layer(&probe, &entries, 1, level, &|_| true, None);
+            entries = vec![closest[0].1];
+        }
+        let accept = |node: &Node| filter(node.attributes.as_ref());
+        self.search_layer(&probe, &entries, ef.max(count), 0, &accept, budget)
+            .into_iter()
+            .take(count)
+            .map(|Candidate(distance, id)| (&self.node(id).name, self.similarity(distance)))
+            .collect()
+    }
+
+    /// compare the vector with every element, the truth approximate searches are
+    /// measured against
+    pub(crate) fn search_exact(
+        &self,
+        vector: &[f32],
+        count: usize,
+        filter: &dyn Fn(Option<&serde_json::Value>) -> bool,
+    ) -> Vec<(&Bytes, f64)> {
+        let probe = Probe::new(vector);
+        let mut candidates: Vec<Candidate> = self
+            .by_name
+            .values()
+            .map(|&id| self.node(id))
+            .filter(|node| filter(node.attributes.as_ref()))
+            .map(|node| Candidate(self.distance(&probe, node), self.by_name[&node.name]))
+            .collect();
+        candidates.sort_unstable();
+        candidates
+            .into_iter()
+            .take(count)
+            .map(|Candidate(distance, id)| (&self.node(id).name, self.similarity(distance)))
+            .collect()
+    }
+
+    fn node(&self, id: usize) -> &Node {
+        self.nodes[id].as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, id: usize) -> &mut Node {
+        self.nodes[id].as_mut().unwrap()
+    }
+
+    fn probe(&self, node: &Node) -> Probe {
+        Probe {
+            unit: node.vector.decode(self.dim),
+            norm: node.norm,
+        }
+    }
+
+    fn distance(&self, probe: &Probe, node: &Node) -> f32 {
+        match self.metric {
+            Metric::Cosine => 1.0 - node.vector.dot(&probe.unit).clamp(-1.0, 1.0),
+            Metric::L2 => probe
+                .unit
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let difference =
+                        x * probe.norm - node.vector.component(i, self.dim) * node.norm;
+                    difference * difference
+                })
+                .sum::<f32>()
+                .sqrt(),
+        }
+    }
+
+    /// the score of a distance, from 1 for identical vectors down to 0
+    fn similarity(&self, distance: f32) -> f64 {
+        match self.metric {
+            Metric::Cosine => 1.0 - distance as f64 / 2.0,
+            Metric::L2 => 1.0 / (1.0 + distance as f64),
+        }
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        match level {
+            0 => self.m * 2,
+            _ => self.m,
+        }
+    }
+
+    /// the level of a new node, each level holding about `1/m` of the nodes below
+    fn random_level(&self) -> usize {
+        let uniform: f64 = 1.0 - rand::thread_rng().gen::<f64>();
+        let level = -uniform.ln() / (self.m as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+
+    /// the `ef` closest nodes accepted by the filter, found by walking the links of a
+    /// level from the entries, the closest first
+    fn search_layer(
+        &self,
+        probe: &Probe,
+        entries: &[usize],
+        ef: usize,
+        level: usize,
+        accept: &dyn Fn(&Node) -> bool,
+        budget: Option<usize>,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates = BinaryHeap::new();
+        // the worst result is on top, to be replaced by a closer node
+        let mut results = BinaryHeap::new();
+        for &id in entries {
+            let node = self.node(id);
+            let candidate = Candidate(self.distance(probe, node), id);
+            candidates.push(Reverse(candidate));
+            if accept(node) {
+                results.push(candidate);
+            }
+        }
+        while results.len() > ef {
+            results.pop();
+        }
+        while let Some(Reverse(Candidate(distance, id))) = candidates.pop() {
+            if results.len() >= ef
+                && results
+                    .peek()
+                    .is_some_and(|worst: &Candidate| distance > worst.0)
+            {
+                break;
+            }
+            if budget.is_some_and(|budget| visited.len() >= budget) {
+                break;
+            }
+            for &neighbour in &self.node(id).links[level] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let node = self.node(neighbour);
+                let candidate = Candidate(self.distance(probe, node), neighbour);
+                if results.len() < ef || results.peek().is_some_and(|worst| candidate.0 < worst.0) {
+                    candidates.push(Reverse(candidate));
+                    if accept(node) {
+                        results.push(candidate);
+                        if results.len() > ef {
+                            results.pop();
+                        }
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+
+    /// keep the closest candidates which are closer to the node than to the neighbours
+    /// already kept, so that the links point in many directions. the candidates left
+    /// out fill the remaining room
+    fn select_neighbours(&self, candidates: &[Candidate], max: usize) -> Vec<usize> {
+        let mut selected: Vec<(usize, Probe)> = vec![];
+        let mut pruned = vec![];
+        for &Candidate(distance, id) in candidates {
+            if selected.len() == max {
+                break;
+            }
+            let node = self.node(id);
+            if selected
+                .iter()
+                .all(|(_, kept)| self.distance(kept, node) > distance)
+            {
+                selected.push((id, self.probe(node)));
+            } else {
+                pruned.push(id);
+            }
+        }
+        let mut selected: Vec<usize> = selected.into_iter().map(|(id, _)| id).collect();
+        let room = max.saturating_sub(selected.len());
+        selected.extend(pruned.into_iter().take(room));
+        selected
+    }
+
+    /// bring the links of a node on a level back under the limit
+    fn prune(&mut self, id: usize, level: usize) {
+        let node = self.node(id);
+        let probe = self.probe(node);
+        let mut candidates: Vec<Candidate> = node.links[level]
+            .iter()
+            .map(|&link| Candidate(self.distance(&probe, self.node(link)), link))
+            .collect();
+        candidates.sort_unstable();
+        let links = self.select_neighbours(&candidates, self.max_links(level));
+        self.node_mut(id).links[level] = links;
+    }
+}
+
+impl Database {
+    pub(crate) fn get_vectorset(&self, key: &str) -> Result<Option<&VectorSet>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::VectorSet(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn get_vectorset_mut(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<&mut VectorSet>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::VectorSet(set)) => Ok(Some(set)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectorset_test() {
+        let mut rng = rand::thread_rng();
+        let mut set = VectorSet::new(8, Quantization::NoQuant, Metric::L2, 4, 50);
+        let vectors: Vec<Vec<f32>> = (0..500)
+            .map(|_| (0..8).map(|_| rng.gen_range(-1.0..1.0)).collect())
+            .collect();
+        for (i, vector) in vectors.iter().enumerate() {
+            assert!(set.add(Bytes::from(i.to_string()), vector));
+        }
+        assert!(!set.add(Bytes::from("0"), &vectors[0]));
+        assert_eq!(set.len(), 500);
+
+        // the graph finds most of the true nearest neighbours
+        let mut found = 0;
+        for vector in vectors.iter().take(50) {
+            let truth: HashSet<&Bytes> = set
+                .search_exact(vector, 10, &|_| true)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect();
+            let approximate = set.search(vector, 10, 50, &|_| true, None);
+            found += approximate
+                .iter()
+                .filter(|(name, _)| truth.contains(name))
+                .count();
+        }
+        assert!(found >= 450, "recall {}/500", found);
+
+        // the removed nodes are unlinked and the others stay reachable
+        for i in (0..500).step_by(2) {
+            assert!(set.remove(i.to_string().as_bytes()));
+        }
+        assert_eq!(set.len(), 250);
+        let found = (1..500)
+            .step_by(2)
+            .filter(|i| {
+                let closest = set.search(&vectors[*i], 1, 50, &|_| true, None);
+                closest[0] == (&Bytes::from(i.to_string()), 1.0)
+            })
+            .count();
+        assert!(found >= 240, "found {}/250", found);
+
+        // the filter only lets through the elements with an attribute
+        set.set_attributes(b"7", Some(serde_json::json!({"kept": true})));
+        let filtered = set.search(&vectors[1], 5, 50, &|attributes| attributes.is_some(), None);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].0, &Bytes::from("7"));
+
+        let mut binary = VectorSet::new(3, Quantization::Binary, Metric::Cosine, 16, 200);
+        binary.add(Bytes::from("a"), &[1.0, -2.0, 3.0]);
+        binary.add(Bytes::from("b"), &[-1.0, 2.0, -3.0]);
+        let scores = binary.search(&[1.0, -1.0, 1.0], 2, 10, &|_| true, None);
+        assert_eq!(scores[0].0, &Bytes::from("a"));
+        assert!((scores[1].1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use super::Database;
+
+/// the keys watched by the clients. a watched key carries a version bumped
+/// whenever it is touched, a client compares it with the version it saw at
+/// WATCH time to know whether its transaction may go on
+#[derive(Default)]
+pub(crate) struct Watches {
+    keys: HashMap<String, Watched>,
+    /// the versions each client has seen of the keys it watches
+    clients: HashMap<u64, HashMap<String, u64>>,
+}
+
+struct Watched {
+    version: u64,
+    watchers: usize,
+}
+
+impl Database {
+    /// watch the keys for the current client, until its next EXEC, DISCARD or
+    /// UNWATCH. watching a key again keeps the version it was first seen with
+    pub(crate) fn watch(&mut self, keys: Vec<String>) {
+        let watches = &mut self.watches;
+        let seen = watches.clients.entry(self.client).or_default();
+        for key in keys {
+            if seen.contains_key(&key) {
+                continue;
+            }
+            let watched = watches.keys.entry(key.clone()).or_insert(Watched {
+                version: 0,
+                watchers: 0,
+            });
+            watched.watchers += 1;
+            seen.insert(key, watched.version);
+        }
+    }
+
+    /// forget the keys the client watches, returning whether one of them has
+    /// been touched since it was watched
+    pub(crate) fn unwatch(&mut self, id: u64) -> bool {
+        let watches = &mut self.watches;
+        let Some(seen) = watches.clients.remove(&id) else {
+            return false;
+        };
+        let mut touched = false;
+        for (key, version) in seen {
+            let Some(watched) = watches.keys.get_mut(&key) else {
+                continue;
+            };
+            touched |= watched.version != version;
+            watched.watchers -= 1;
+            if watched.watchers == 0 {
+                watches.keys.remove(&key);
+            }
+        }
+        touched
+    }
+
+    /// bump the version of the key, if it is watched
+    pub(super) fn bump_version(&mut self, key: &str) {
+        if let Some(watched) = self.watches.keys.get_mut(key) {
+            watched.version += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::{sync::Notify, time::Instant};
+
+    use super::*;
+    use crate::{Command, Frame};
+
+    #[test]
+    fn watch_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        db.client = 1;
+        db.watch(vec!["a".to_string(), "b".to_string()]);
+        db.client = 2;
+        db.watch(vec!["a".to_string()]);
+
+        db.set("b".to_string(), Bytes::from_static(b"1"), None);
+        db.set("c".to_string(), Bytes::from_static(b"1"), None);
+        assert!(db.unwatch(1));
+        assert!(!db.unwatch(2));
+        // nothing is left once nobody watches
+        assert!(db.watches.keys.is_empty() && db.watches.clients.is_empty());
+
+        db.watch(vec!["a".to_string()]);
+        Command::parse(&["SADD", "a", "m"]).apply(&mut db);
+        db.watch(vec!["a".to_string()]);
+        assert!(db.unwatch(2));
+        assert!(!db.unwatch(2));
+    }
+
+    #[test]
+    fn failed_writes_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        db.set("s".to_string(), Bytes::from_static(b"v"), None);
+        Command::parse(&["SADD", "set", "m"]).apply(&mut db);
+        let keys = ["s", "set", "missing"].map(String::from).to_vec();
+        db.client = 1;
+        db.watch(keys.clone());
+
+        // writes refused because of the type of the value, or changing nothing
+        let writes: [&[&str]; 7] = [
+            &["HSET", "s", "f", "v"],
+            &["SADD", "s", "m"],
+            &["ZADD", "s", "1", "m"],
+            &["XADD", "s", "*", "f", "v"],
+            &["SADD", "set", "m"],
+            &["SREM", "missing", "m"],
+            &["XADD", "missing", "0-0", "f", "v"],
+        ];
+        for words in writes {
+            let reply = Command::parse(words).apply(&mut db);
+            assert!(
+                matches!(reply, Frame::Error(_) | Frame::Integer(0)),
+                "{:?}",
+                words
+            );
+        }
+        assert!(db.get("missing").is_none());
+        assert!(!db.unwatch(1));
+
+        db.watch(keys);
+        Command::parse(&["SADD", "set", "n"]).apply(&mut db);
+        assert!(db.unwatch(1));
+    }
+
+    #[test]
+    fn write_families_test() {
+        // the commands preparing the key, then a write expected to touch it
+        let cases: &[(&[&[&str]], &[&str])] = &[
+            (&[], &["SET", "k", "v"]),
+            (&[], &["PFADD", "k", "a"]),
+            (&[], &["HSET", "k", "f", "v"]),
+            (&[&["HSET", "k", "f", "v"]], &["HDEL", "k", "f"]),
+            (
+                &[&["HSET", "k", "f", "v"]],
+                &["HEXPIRE", "k", "100", "FIELDS", "1", "f"],
+            ),
+            (&[], &["SADD", "k", "m"]),
+            (&[&["SADD", "k", "m"]], &["SPOP", "k"]),
+            (&[&["SADD", "s", "m"]], &["SMOVE", "s", "k", "m"]),
+            (&[], &["ZADD", "k", "1", "m"]),
+            (&[&["ZADD", "k", "1", "m"]], &["ZINCRBY", "k", "1", "m"]),
+            (&[&["ZADD", "k", "1", "m"]], &["ZPOPMIN", "k"]),
+            (
+                &[&["ZADD", "z", "1", "m"]],
+                &["ZRANGESTORE", "k", "z", "0", "-1"],
+            ),
+            (&[], &["GEOADD", "k", "13.36", "38.11", "m"]),
+            (&[], &["XADD", "k", "*", "f", "v"]),
+            (&[&["XADD", "k", "1-0", "f", "v"]], &["XDEL", "k", "1-0"]),
+            (
+                &[&["XADD", "k", "1-0", "f", "v"]],
+                &["XTRIM", "k", "MAXLEN", "0"],
+            ),
+            (
+                &[&["XADD", "k", "1-0", "f", "v"]],
+                &["XGROUP", "CREATE", "k", "g", "$"],
+            ),
+            (&[], &["BF.ADD", "k", "a"]),
+            (&[], &["CF.ADD", "k", "a"]),
+            (&[&["CF.ADD", "k", "a"]], &["CF.DEL", "k", "a"]),
+            (
+                &[&["CMS.INITBYDIM", "k", "10", "2"]],
+                &["CMS.INCRBY", "k", "a", "1"],
+            ),
+            (&[&["TOPK.RESERVE", "k", "3"]], &["TOPK.ADD", "k", "a"]),
+            (&[&["TDIGEST.CREATE", "k"]], &["TDIGEST.ADD", "k", "1"]),
+            (&[], &["JSON.SET", "k", "$", "{\"a\":1}"]),
+            (
+                &[&["JSON.SET", "k", "$", "{\"a\":1}"]],
+                &["JSON.NUMINCRBY", "k", "$.a", "1"],
+            ),
+            (
+                &[&["JSON.SET", "k", "$", "{\"a\":1}"]],
+                &["JSON.DEL", "k", "$"],
+            ),
+            (&[], &["TS.ADD", "k", "1", "1"]),
+            (&[], &["VADD", "k", "VALUES", "2", "1", "0", "e"]),
+            (
+                &[&["VADD", "k", "VALUES", "2", "1", "0", "e"]],
+                &["VREM", "k", "e"],
+            ),
+            (&[], &["R.ADD", "k", "1"]),
+            (&[&["R.ADD", "k", "1"]], &["R.REM", "k", "1"]),
+        ];
+        for (setup, write) in cases {
+            let mut db = Database::new(Arc::new(Notify::new()));
+            for words in *setup {
+                Command::parse(words).apply(&mut db);
+            }
+            db.watch(vec!["k".to_string()]);
+            let reply = Command::parse(write).apply(&mut db);
+            assert!(
+                !matches!(reply, Frame::Error(_)),
+                "{:?}: {:?}",
+                write,
+                reply
+            );
+            assert!(db.unwatch(db.client), "{:?}", write);
+        }
+    }
+
+    #[test]
+    fn expiry_test() {
+        let mut db = Database::new(Arc::new(Notify::new()));
+        db.set(
+            "k".to_string(),
+            Bytes::from_static(b"v"),
+            Some(Duration::from_secs(1)),
+        );
+        Command::parse(&["HSET", "h", "f", "v", "g", "v"]).apply(&mut db);
+        Command::parse(&["HEXPIRE", "h", "1", "FIELDS", "1", "f"]).apply(&mut db);
+        db.watch(vec!["k".to_string(), "h".to_string()]);
+
+        // neither the key nor the field is due yet
+        db.remove_expired(Instant::now());
+        assert!(!db.unwatch(db.client));
+
+        db.client = 1;
+        db.watch(vec!["k".to_string()]);
+        db.client = 2;
+        db.watch(vec!["h".to_string()]);
+        db.remove_expired(Instant::now() + Duration::from_secs(2));
+        assert!(db.get("k").is_none() && db.get("h").is_some());
+        assert!(db.unwatch(1) && db.unwatch(2));
+
+        // the key goes with the last field of the hash
+        Command::parse(&["HEXPIRE", "h", "1", "FIELDS", "1", "g"]).apply(&mut db);
+        db.watch(vec!["h".to_string()]);
+        db.remove_expired(Instant::now() + Duration::from_secs(2));
+        assert!(db.get("h").is_none());
+        assert!(db.unwatch(2));
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{
+    skiplist::{Cursor, SkipList},
+    Database, Value, WrongType,
+};
+use crate::config;
+
+/// a bound of a score range such as `1.5`, `(1.5` or `-inf`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScoreBound {
+    pub(crate) value: f64,
+    pub(crate) exclusive: bool,
+}
+
+/// a bound of a lexicographical range such as `[a`, `(a`, `-` or `+`
+#[derive(Debug, Clone)]
+pub(crate) enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// a set of members ordered by score. a small one is a listpack, a vector
+/// sorted by (score, member). a larger one is a skip list answering range and
+/// rank queries, and a map giving the score of a member in O(1)
+#[derive(Debug, Clone)]
+pub(crate) struct ZSet {
+    members: Members,
+}
+
+#[derive(Debug, Clone)]
+enum Members {
+    Listpack(Vec<(Bytes, f64)>),
+    SkipList {
+        scores: HashMap<Bytes, f64>,
+        list: SkipList,
+    },
+}
+
+/// where a walk over the members starts
+#[derive(Clone, Copy)]
+enum Position {
+    Index(usize),
+    Node(Cursor),
+}
+
+impl ScoreBound {
+    fn above_min(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.value
+        } else {
+            score >= self.value
+        }
+    }
+
+    fn below_max(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.value
+        } else {
+            score <= self.value
+        }
+    }
+}
+
+impl LexBound {
+    fn above_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(bound) => member >= &bound[..],
+            LexBound::Exclusive(bound) => member > &bound[..],
+        }
+    }
+
+    fn below_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(bound) => member <= &bound[..],
+            LexBound::Exclusive(bound) => member < &bound[..],
+        }
+    }
+}
+
+impl Default for ZSet {
+    fn default() -> Self {
+        ZSet {
+            members: Members::Listpack(vec![]),
+        }
+    }
+}
+
+impl ZSet {
+    pub(crate) fn len(&self) -> usize {
+        match &self.members {
+            Members::Listpack(entries) => entries.len(),
+            Members::SkipList { list, .. } => list.len(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        match &self.members {
+            Members::Listpack(entries) => entries
+                .iter()
+                .find(|(m, _)| m == member)
+                .map(|(_, score)| *score),
+            Members::SkipList { scores, .. } => scores.get(member).cloned(),
+        }
+    }
+
+    /// insert a member or update its score. return the previous score
+    pub(crate) fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        match &mut self.members {
+            Members::Listpack(entries) => {
+                let prev = entries
+                    .iter()
+                    .position(|(m, _)| *m == member)
+                    .map(|i| entries.remove(i).1);
+                let too_long = member.len() > config::get(&config::ZSET_MAX_LISTPACK_VALUE);
+                let i = entries.partition_point(|(m, s)| (*s, m) < (score, &member));
+                entries.insert(i, (member, score));
+                if too_long || entries.len() > config::get(&config::ZSET_MAX_LISTPACK_ENTRIES) {
+                    let mut list = SkipList::new();
+                    for (member, score) in entries.iter() {
+                        list.insert(*score, member.clone());
+                    }
+                    let scores = std::mem::take(entries).into_iter().collect();
+                    self.members = Members::SkipList { scores, list };
+                }
+                prev
+            }
+            Members::SkipList { scores, list } => {
+                let prev = scores.insert(member.clone(), score);
+                match prev {
+                    Some(prev) if prev == score => {}
+                    Some(prev) => {
+                        list.remove(prev, &member);
+                        list.insert(score, member);
+                    }
+                    None => list.insert(score, member),
+                }
+                prev
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.members {
+            Members::Listpack(entries) => match entries.iter().position(|(m, _)| m == member) {
+                Some(i) => {
+                    entries.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Members::SkipList { scores, list } => match scores.remove(member) {
+                Some(score) => list.remove(score, member),
+                None => false,
+            },
+        }
+    }
+
+    /// 0-based rank of a member, counted from the highest score if `reverse` is set
+    pub(crate) fn rank(&self, member: &[u8], reverse: bool) -> Option<usize> {
+        let rank = match &self.members {
+            Members::Listpack(entries) => entries.iter().position(|(m, _)| m == member)?,
+            Members::SkipList { list, .. } => list.rank(self.score(member)?, member)?,
+        };
+        Some(if reverse { self.len() - 1 - rank } else { rank })
+    }
+
+    /// every member in ascending order
+    pub(crate) fn iter(&self) -> Box<dyn Iterator<Item = (&Bytes, f64)> + '_> {
+        self.walk(self.by_rank(0), false)
+    }
+
+    /// the name OBJECT ENCODING reports
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.members {
+            Members::Listpack(_) => "listpack",
+            Members::SkipList { .. } => "skiplist",
+        }
+    }
+
+    /// members whose rank is within `start..=stop`, both are 0-based and in range
+    pub(crate) fn range_by_rank(
+        &self,
+        start: usize,
+        stop: usize,
+        reverse: bool,
+    ) -> Vec<(Bytes, f64)> {
+        if start > stop || start >= self.len() {
+            return vec![];
+        }
+        let first = if reverse {
+            self.by_rank(self.len() - 1 - start)
+        } else {
+            self.by_rank(start)
+        };
+        self.walk(first, reverse)
+            .take(stop - start + 1)
+            .map(|(member, score)| (member.clone(), score))
+            .collect()
+    }
+
+    /// members whose score is within `min` and `max`. `offset` and `count` apply
+    /// after ordering, so that a reversed range starts from the highest score
+    pub(crate) fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+        reverse: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        let first = if reverse {
+            self.last_by_score(max)
+        } else {
+            self.first_by_score(min)
+        };
+        self.walk(first, reverse)
+            .take_while(|(_, score)| {
+                if reverse {
+                    min.above_min(*score)
+                } else {
+                    max.below_max(*score)
+                }
+            })
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(member, score)| (member.clone(), score))
+            .collect()
+    }
+
+    /// members within `min` and `max` by lexicographical order,
+    /// which only makes sense when every member has the same score
+    pub(crate) fn range_by_lex(
+        &self,
+        min: &LexBound,
+        max: &LexBound,
+        reverse: bool,
+        offset: usize,
+        count: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        let first = if reverse {
+            self.last_by_lex(max)
+        } else {
+            self.first_by_lex(min)
+        };
+        self.walk(first, reverse)
+            .take_while(|(member, _)| {
+                if reverse {
+                    min.above_min(member)
+                } else {
+                    max.below_max(member)
+                }
+            })
+            .skip(offset)
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(member, score)| (member.clone(), score))
+            .collect()
+    }
+
+    /// number of members within a score range, computed from ranks in O(log n)
+    pub(crate) fn count_by_score(&self, min: ScoreBound, max: ScoreBound) -> usize {
+        self.count_between(self.first_by_score(min), self.last_by_score(max))
+    }
+
+    pub(crate) fn count_by_lex(&self, min: &LexBound, max: &LexBound) -> usize {
+        self.count_between(self.first_by_lex(min), self.last_by_lex(max))
+    }
+
+    /// remove and return up to `count` members with the lowest score, or the highest if `max` is set
+    pub(crate) fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        if count == 0 {
+            return vec![];
+        }
+        let popped = self.range_by_rank(0, count - 1, max);
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+
+    fn first_by_score(&self, min: ScoreBound) -> Option<Position> {
+        self.first_where_not(|score, _| !min.above_min(score))
+    }
+
+    fn last_by_score(&self, max: ScoreBound) -> Option<Position> {
+        self.last_where(|score, _| max.below_max(score))
+    }
+
+    fn first_by_lex(&self, min: &LexBound) -> Option<Position> {
+        self.first_where_not(|_, member| !min.above_min(member))
+    }
+
+    fn last_by_lex(&self, max: &LexBound) -> Option<Position> {
+        self.last_where(|_, member| max.below_max(member))
+    }
+
+    /// the first member for which `before` returns false.
+    /// `before` must hold for a prefix of the members
+    fn first_where_not(&self, before: impl Fn(f64, &Bytes) -> bool) -> Option<Position> {
+        match &self.members {
+            Members::Listpack(entries) => {
+                let i = entries.partition_point(|(member, score)| before(*score, member));
+                (i < entries.len()).then_some(Position::Index(i))
+            }
+            Members::SkipList { list, .. } => list.first_where_not(before).map(Position::Node),
+        }
+    }
+
+    /// the last member for which `within` returns true.
+    /// `within` must hold for a prefix of the members
+    fn last_where(&self, within: impl Fn(f64, &Bytes) -> bool) -> Option<Position> {
+        match &self.members {
+            Members::Listpack(entries) => {
+                let i = entries.partition_point(|(member, score)| within(*score, member));
+                i.checked_sub(1).map(Position::Index)
+            }
+            Members::SkipList { list, .. } => list.last_where(within).map(Position::Node),
+        }
+    }
+
+    fn by_rank(&self, rank: usize) -> Option<Position> {
+        match &self.members {
+            Members::Listpack(entries) => (rank < entries.len()).then_some(Position::Index(rank)),
+            Members::SkipList { list, .. } => list.by_rank(rank).map(Position::Node),
+        }
+    }
+
+    /// iterate from a member to the highest score, or to the lowest if `reverse` is set
+    fn walk(
+        &self,
+        from: Option<Position>,
+        reverse: bool,
+    ) -> Box<dyn Iterator<Item = (&Bytes, f64)> + '_> {
+        match (&self.members, from) {
+            (_, None) => Box::new(std::iter::empty()),
+            (Members::Listpack(entries), Some(Position::Index(i))) => {
+                let entries = entries.iter().map(|(member, score)| (member, *score));
+                match reverse {
+                    true => Box::new(entries.take(i + 1).rev()),
+                    false => Box::new(entries.skip(i)),
+                }
+            }
+            (Members::SkipList { list, .. }, Some(Position::Node(cursor))) => {
+                Box::new(list.walk(Some(cursor), reverse))
+            }
+            _ => unreachable!("a position of another encoding"),
+        }
+    }
+
+    fn rank_of(&self, position: Position) -> usize {
+        match (&self.members, position) {
+            (_, Position::Index(i)) => i,
+            (Members::SkipList { list, .. }, Position::Node(cursor)) => {
+                let (member, score) = list.get(cursor);
+                list.rank(score, member).unwrap_or_default()
+            }
+            _ => unreachable!("a position of another encoding"),
+        }
+    }
+
+    fn count_between(&self, first: Option<Position>, last: Option<Position>) -> usize {
+        let (Some(first), Some(last)) = (first, last) else {
+            return 0;
+        };
+        let (first, last) = (self.rank_of(first), self.rank_of(last));
+        if first > last {
+            0
+        } else {
+            last - first + 1
+        }
+    }
+}
+
+impl Database {
+    pub(crate) fn get_zset(&self, key: &str) -> Result<Option<&ZSet>, WrongType> {
+        match self.entries.get(key) {
+            Some(Value::ZSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// `remove_if_empty` must be invoked once the modification is done
+    pub(crate) fn get_zset_mut(&mut self, key: &str) -> Result<Option<&mut ZSet>, WrongType> {
+        match self.value_mut(key) {
+            Some(Value::ZSet(zset)) => Ok(Some(zset)),
+            Some(_) => Err(WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// get the sorted set stored at key, an empty one is created if the key doesn't exist.
+    /// `remove_if_empty` must be invoked once the modification is done
+    pub(crate) fn get_or_insert_zset(&mut self, key: &str) -> Result<&mut ZSet, WrongType> {
+        if !self.entries.contains_key(key) {
+            self.create(key);
+        }
+        let value = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Value::ZSet(ZSet::default()));
+        match value {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listpack_and_skiplist_agree_test() {
+        let mut zset = ZSet::default();
+        let check = |zset: &ZSet, encoding| {
+            assert_eq!(zset.encoding(), encoding);
+            let mut members: Vec<(Bytes, f64)> = zset
+                .iter()
+                .map(|(member, score)| (member.clone(), score))
+                .collect();
+            assert!(members
+                .windows(2)
+                .all(|w| (w[0].1, &w[0].0) < (w[1].1, &w[1].0)));
+            let min = ScoreBound {
+                value: 10.0,
+                exclusive: true,
+            };
+            let max = ScoreBound {
+                value: 30.0,
+                exclusive: false,
+            };
+            let within: Vec<_> = members
+                .iter()
+                .filter(|(_, score)| *score > 10.0 && *score <= 30.0)
+                .cloned()
+                .collect();
+            assert_eq!(zset.range_by_score(min, max, false, 0, None), within);
+            assert_eq!(zset.count_by_score(min, max), within.len());
+            let mut reversed = within.clone();
+            reversed.reverse();
+            assert_eq!(
+                zset.range_by_score(min, max, true, 1, Some(3)),
+                reversed[1..4]
+            );
+            let rank = zset.rank(&within[0].0, false).unwrap();
+            assert_eq!(members[rank], within[0]);
+            members.reverse();
+            assert_eq!(zset.range_by_rank(0, 4, true), members[..5]);
+        };
+        for i in 0..100 {
+            zset.insert(Bytes::from(format!("m{}", i)), (i % 40) as f64);
+        }
+        assert_eq!(zset.insert(Bytes::from_static(b"m5"), 50.0), Some(5.0));
+        check(&zset, "listpack");
+        for i in 100..300 {
+            zset.insert(Bytes::from(format!("m{}", i)), (i % 40) as f64);
+        }
+        check(&zset, "skiplist");
+        assert_eq!(zset.pop(2, false)[1], (Bytes::from_static(b"m120"), 0.0));
+    }
+}
@@ -0,0 +1,355 @@
+//! the FILTER expressions of VSIM, evaluated against the JSON attributes of the
+//! elements: `.year >= 1980 and (.genre == "drama" or "oscar" in .awards)`
+
+use serde_json::{Number, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    /// `.name`, a field of the attributes
+    Selector(String),
+    Array(Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, Op, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+/// the operators from the loosest to the tightest, `**` binds to the right
+const PRECEDENCE: [&[(&str, Op)]; 6] = [
+    &[("||", Op::Or), ("or", Op::Or)],
+    &[("&&", Op::And), ("and", Op::And)],
+    &[
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        ("<=", Op::Le),
+        (">=", Op::Ge),
+        ("<", Op::Lt),
+        (">", Op::Gt),
+        ("in", Op::In),
+    ],
+    &[("+", Op::Add), ("-", Op::Sub)],
+    &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Mod)],
+    &[("**", Op::Pow)],
+];
+
+impl Expr {
+    pub(crate) fn parse(text: &str) -> Result<Expr, String> {
+        let mut reader = Reader {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        let expr = reader.binary(0)?;
+        reader.skip_spaces();
+        if reader.pos < reader.chars.len() {
+            return reader.error();
+        }
+        Ok(expr)
+    }
+
+    /// whether the attributes pass the filter, an element without the selected
+    /// attributes never does
+    pub(crate) fn matches(&self, attributes: Option<&Value>) -> bool {
+        match attributes.and_then(|attributes| self.eval(attributes)) {
+            Some(value) => truthy(&value),
+            None => false,
+        }
+    }
+
+    fn eval(&self, attributes: &Value) -> Option<Value> {
+        match self {
+            Expr::Literal(value) => Some(value.clone()),
+            Expr::Selector(name) => attributes.get(name).cloned(),
+            Expr::Array(items) => items
+                .iter()
+                .map(|item| item.eval(attributes))
+                .collect::<Option<_>>()
+                .map(Value::Array),
+            Expr::Not(expr) => Some(Value::Bool(!truthy(&expr.eval(attributes)?))),
+            Expr::Negate(expr) => number(-expr.eval(attributes)?.as_f64()?),
+            Expr::Binary(left, Op::Or, right) => {
+                let left = left.eval(attributes).is_some_and(|value| truthy(&value));
+                Some(Value::Bool(left || right.matches(Some(attributes))))
+            }
+            Expr::Binary(left, Op::And, right) => {
+                let left = left.eval(attributes).is_some_and(|value| truthy(&value));
+                Some(Value::Bool(left && right.matches(Some(attributes))))
+            }
+            Expr::Binary(left, op, right) => {
+                let left = left.eval(attributes)?;
+                let right = right.eval(attributes)?;
+                apply(&left, *op, &right)
+            }
+        }
+    }
+}
+
+fn apply(left: &Value, op: Op, right: &Value) -> Option<Value> {
+    let compare = || match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    };
+    let arithmetic = |f: fn(f64, f64) -> f64| number(f(left.as_f64()?, right.as_f64()?));
+    match op {
+        Op::Eq => Some(Value::Bool(equal(left, right))),
+        Op::Ne => Some(Value::Bool(!equal(left, right))),
+        Op::Lt => Some(Value::Bool(compare()?.is_lt())),
+        Op::Le => Some(Value::Bool(compare()?.is_le())),
+        Op::Gt => Some(Value::Bool(compare()?.is_gt())),
+        Op::Ge => Some(Value::Bool(compare()?.is_ge())),
+        Op::In => match (left, right) {
+            (_, Value::Array(items)) => {
+                Some(Value::Bool(items.iter().any(|item| equal(left, item))))
+            }
+            (Value::String(needle), Value::String(haystack)) => {
+                Some(Value::Bool(haystack.contains(needle.as_str())))
+            }
+            _ => None,
+        },
+        Op::Add => arithmetic(|a, b| a + b),
+        Op::Sub => arithmetic(|a, b| a - b),
+        Op::Mul => arithmetic(|a, b| a * b),
+        Op::Div => arithmetic(|a, b| a / b),
+        Op::Mod => arithmetic(|a, b| a % b),
+        Op::Pow => arithmetic(f64::powf),
+        Op::Or | Op::And => unreachable!(),
+    }
+}
+
+/// numbers are equal whatever their representation, `1 == 1.0`
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => left == right,
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64().is_some_and(|number| number != 0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+/// infinities and NaN have no JSON representation and fail the filter
+fn number(value: f64) -> Option<Value> {
+    Number::from_f64(value).map(Value::Number)
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn error<T>(&self) -> Result<T, String> {
+        Err(format!("syntax error in FILTER at offset {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// consume the token if it comes next, words must not run into the text after them
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        let end = self.pos + token.chars().count();
+        if end > self.chars.len() || !self.chars[self.pos..end].iter().copied().eq(token.chars()) {
+            return false;
+        }
+        let is_word = token.chars().all(char::is_alphabetic);
+        if is_word
+            && self
+                .chars
+                .get(end)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        {
+            return false;
+        }
+        self.pos = end;
+        true
+    }
+
+    /// the operators of a precedence level, each operand binding tighter
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'operators: loop {
+            for (token, op) in PRECEDENCE[level] {
+                // `*` must not be mistaken for the start of `**`
+                if *op == Op::Mul && self.lookahead("**") {
+                    continue;
+                }
+                if self.eat(token) {
+                    let right = match op {
+                        Op::Pow => self.binary(level)?,
+                        _ => self.binary(level + 1)?,
+                    };
+                    left = Expr::Binary(Box::new(left), *op, Box::new(right));
+                    if *op == Op::Pow {
+                        return Ok(left);
+                    }
+                    continue 'operators;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn lookahead(&mut self, token: &str) -> bool {
+        let pos = self.pos;
+        let found = self.eat(token);
+        self.pos = pos;
+        found
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") || self.eat("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.binary(0)?;
+            if !self.eat(")") {
+                return self.error();
+            }
+            return Ok(expr);
+        }
+        if self.eat("[") {
+            let mut items = vec![];
+            if !self.eat("]") {
+                loop {
+                    items.push(self.binary(0)?);
+                    if self.eat("]") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return self.error();
+                    }
+                }
+            }
+            return Ok(Expr::Array(items));
+        }
+        for (word, value) in [
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+            ("null", Value::Null),
+        ] {
+            if self.eat(word) {
+                return Ok(Expr::Literal(value));
+            }
+        }
+        self.skip_spaces();
+        match self.peek() {
+            Some('.') => {
+                self.pos += 1;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return self.error();
+                }
+                Ok(Expr::Selector(self.chars[start..self.pos].iter().collect()))
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                let mut text = String::new();
+                loop {
+                    match self.peek() {
+                        None => return self.error(),
+                        Some('\\') if self.pos + 1 < self.chars.len() => {
+                            text.push(self.chars[self.pos + 1]);
+                            self.pos += 2;
+                        }
+                        Some(c) if c == quote => {
+                            self.pos += 1;
+                            return Ok(Expr::Literal(Value::String(text)));
+                        }
+                        Some(c) => {
+                            text.push(c);
+                            self.pos += 1;
+                        }
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E')
+                {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                match text.parse().ok().and_then(number) {
+                    Some(value) => Ok(Expr::Literal(value)),
+                    None => self.error(),
+                }
+            }
+            _ => self.error(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn filter_test() {
+        let movie = json!({"year": 1994, "genre": "drama", "rating": 8.9, "awards": ["oscar"]});
+        let matches = |filter: &str| Expr::parse(filter).unwrap().matches(Some(&movie));
+        assert!(matches(".year >= 1990 and .year < 2000"));
+        assert!(matches(".genre == 'comedy' || \"oscar\" in .awards"));
+        assert!(matches(
+            "not (.rating < 5) && .genre in [\"drama\", \"thriller\"]"
+        ));
+        assert!(matches(
+            ".year % 100 == 94 and 2 ** 3 ** 2 == 512 and -.rating < 0"
+        ));
+        assert!(matches("\"ram\" in .genre"));
+        assert!(!matches(".year > 2000 or .genre == 'horror'"));
+        // a missing attribute fails the comparison, but not the alternative
+        assert!(!matches(".director == 'nobody'"));
+        assert!(matches(".director == 'nobody' or .year == 1994"));
+        assert!(!Expr::parse(".year > 1").unwrap().matches(None));
+        assert!(Expr::parse(".year >").is_err());
+        assert!(Expr::parse(".year > 1 )").is_err());
+        assert!(Expr::parse("andy and 1").is_err());
+    }
+}
@@ -16,6 +16,10 @@ pub enum Frame {
     Error(String),
     Array(Vec<Frame>),
     Null,
+    /// a RESP3 map, its pairs keep their order
+    Map(Vec<(Frame, Frame)>),
+    /// a RESP3 push message, sent out of band of the replies
+    Push(Vec<Frame>),
 }
 
 #[derive(Debug)]
@@ -41,6 +45,10 @@ impl Frame {
                     src.advance(4);
                 } else {
                     let len = Self::get_number(src)? as usize;
+                    // the payload may not have been received entirely yet
+                    if src.remaining() < len + 2 {
+                        return Err(Error::Incomplete);
+                    }
                     src.advance(len + 2);
                 }
                 Ok(())
@@ -53,7 +61,7 @@ impl Frame {
                     Err(Error::Other("invalid frame type".to_string()))
                 }
             }
-            b'*' => {
+            b'*' | b'>' => {
                 let len = Self::get_line(src)?;
                 let len: u64 = atoi::<u64>(len).ok_or_else(|| Error::from("invalid frame type"))?;
                 for _ in 0..len {
@@ -61,6 +69,13 @@ impl Frame {
                 }
                 Ok(())
             }
+            b'%' => {
+                let len = Self::get_number(src)?;
+                for _ in 0..len * 2 {
+                    Self::check(src)?;
+                }
+                Ok(())
+            }
             b'_' => Ok(()),
             _ => Err(Error::Other("invalid frame type".to_string())),
         }
@@ -104,7 +119,7 @@ impl Frame {
                     Ok(Frame::Bulk(data))
                 }
             }
-            b'*' => {
+            sign @ (b'*' | b'>') => {
                 let line = Self::get_line(src)?;
                 let len: u64 =
                     atoi::<u64>(line).ok_or_else(|| Error::from("invalid frame type"))?;
@@ -113,7 +128,18 @@ impl Frame {
                     let frame = Self::parse(src)?;
                     arr.push(frame);
                 }
-                Ok(Frame::Array(arr))
+                match sign {
+                    b'>' => Ok(Frame::Push(arr)),
+                    _ => Ok(Frame::Array(arr)),
+                }
+            }
+            b'%' => {
+                let len = Self::get_number(src)?;
+                let mut pairs = vec![];
+                for _ in 0..len {
+                    pairs.push((Self::parse(src)?, Self::parse(src)?));
+                }
+                Ok(Frame::Map(pairs))
             }
             b'_' => Ok(Frame::Null),
             _ => Err(Error::Incomplete),
@@ -165,7 +191,22 @@ impl Frame {
                 }
                 bytes
             }
+            Frame::Push(arr) => {
+                let mut bytes = format!(">{}\r\n", arr.len()).into_bytes();
+                for frame in arr {
+                    bytes.append(&mut frame.into_bytes());
+                }
+                bytes
+            }
             Frame::Null => "_\r\n".as_bytes().to_vec(),
+            Frame::Map(pairs) => {
+                let mut bytes = format!("%{}\r\n", pairs.len()).into_bytes();
+                for (key, value) in pairs {
+                    bytes.append(&mut key.into_bytes());
+                    bytes.append(&mut value.into_bytes());
+                }
+                bytes
+            }
         }
     }
 
@@ -173,6 +214,14 @@ impl Frame {
         Frame::Simple(msg.to_string())
     }
 
+    /// floats are replied as bulk strings, spelled `inf`, `-inf` and `nan` like redis does
+    pub fn into_double(value: f64) -> Frame {
+        if value.is_nan() {
+            return Frame::Bulk(Bytes::from_static(b"nan"));
+        }
+        Frame::Bulk(Bytes::from(value.to_string()))
+    }
+
     fn get_sign(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
         if !src.has_remaining() {
             return Err(Error::Incomplete);
@@ -202,7 +251,7 @@ impl Display for Frame {
             Frame::Error(msg) => write!(f, "{}", msg),
             Frame::Integer(value) => write!(f, "{}", value),
             Frame::Bulk(data) => write!(f, "{}", String::from_utf8_lossy(data)),
-            Frame::Array(arr) => {
+            Frame::Array(arr) | Frame::Push(arr) => {
                 write!(f, "[")?;
                 for frame in arr {
                     write!(f, "{}", frame)?;
@@ -211,6 +260,13 @@ impl Display for Frame {
                 write!(f, "]")
             }
             Frame::Null => write!(f, "null"),
+            Frame::Map(pairs) => {
+                write!(f, "{{")?;
+                for (key, value) in pairs {
+                    write!(f, "{}:{},", key, value)?;
+                }
+                write!(f, "}}")
+            }
         }
     }
 }
@@ -225,3 +281,14 @@ fn check_integer_frame_test() {
     assert!(result.is_ok(), "error: {}", result.unwrap_err());
     assert_eq!(Frame::Integer(-123), result.unwrap());
 }
+
+#[test]
+fn incomplete_bulk_test() {
+    let frame = b"*2\r\n$3\r\nGET\r\n$5\r\nhello\r\n";
+    // a bulk string whose payload hasn't been received entirely
+    for end in [17, 22, 23] {
+        let result = Frame::check(&mut Cursor::new(&frame[..end]));
+        assert!(matches!(result, Err(Error::Incomplete)), "{}", end);
+    }
+    assert!(Frame::check(&mut Cursor::new(&frame[..])).is_ok());
+}
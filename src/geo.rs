@@ -0,0 +1,254 @@
+/// the latitudes covered by the web mercator projection, which are the ones accepted
+pub(crate) const LAT_MIN: f64 = -85.051_128_78;
+pub(crate) const LAT_MAX: f64 = 85.051_128_78;
+pub(crate) const LON_MIN: f64 = -180.0;
+pub(crate) const LON_MAX: f64 = 180.0;
+
+/// bits per coordinate, the scores stored in the sorted set are 52 bits long
+const STEP: u32 = 26;
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// the area searched by GEOSEARCH, in meters
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+pub(crate) fn is_valid(lon: f64, lat: f64) -> bool {
+    (LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+/// the 52-bit geohash of a position, used as its score
+pub(crate) fn encode(lon: f64, lat: f64) -> u64 {
+    let (lat_cell, lon_cell) = cells(lon, lat, STEP, (LAT_MIN, LAT_MAX));
+    interleave(lat_cell, lon_cell)
+}
+
+/// the center of the area a geohash stands for
+pub(crate) fn decode(hash: u64) -> (f64, f64) {
+    let (lat_cell, lon_cell) = deinterleave(hash);
+    let cell_center = |cell: u32, min: f64, max: f64| {
+        let size = (max - min) / (1u64 << STEP) as f64;
+        let low = min + cell as f64 * size;
+        (low + (low + size)) / 2.0
+    };
+    let lon = cell_center(lon_cell, LON_MIN, LON_MAX).clamp(LON_MIN, LON_MAX);
+    let lat = cell_center(lat_cell, LAT_MIN, LAT_MAX).clamp(LAT_MIN, LAT_MAX);
+    (lon, lat)
+}
+
+/// the standard 11 characters geohash, which uses the whole [-90, 90] latitude range
+pub(crate) fn to_geohash_string(hash: u64) -> String {
+    let (lon, lat) = decode(hash);
+    let (lat_cell, lon_cell) = cells(lon, lat, STEP, (-90.0, 90.0));
+    let bits = interleave(lat_cell, lon_cell);
+    (0..11)
+        .map(|i| {
+            // 52 bits only fill 10 characters and a half
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            BASE32[index as usize] as char
+        })
+        .collect()
+}
+
+/// the great-circle distance in meters, computed with the haversine formula
+pub(crate) fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+impl Shape {
+    /// the distance of a position from the center, if the shape contains it
+    pub(crate) fn distance_if_within(&self, center: (f64, f64), point: (f64, f64)) -> Option<f64> {
+        let ((lon1, lat1), (lon2, lat2)) = (center, point);
+        match *self {
+            Shape::Radius(radius) => {
+                let distance = distance(lon1, lat1, lon2, lat2);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Box { width, height } => {
+                let lat_distance = EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs();
+                if lat_distance > height / 2.0 {
+                    return None;
+                }
+                // measured along the latitude of the point, where the box is checked
+                if distance(lon2, lat2, lon1, lat2) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(lon1, lat1, lon2, lat2))
+            }
+        }
+    }
+
+    /// (min lon, min lat, max lon, max lat) enclosing the shape
+    fn bounding_box(&self, lon: f64, lat: f64) -> (f64, f64, f64, f64) {
+        let (half_width, half_height) = match *self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let lat_delta = (half_height / EARTH_RADIUS).to_degrees();
+        let (min_lat, max_lat) = (lat - lat_delta, lat + lat_delta);
+        // the box widens towards the pole
+        let widest = min_lat.abs().max(max_lat.abs()).min(90.0);
+        let lon_delta = (half_width / EARTH_RADIUS / widest.to_radians().cos()).to_degrees();
+        (lon - lon_delta, min_lat, lon + lon_delta, max_lat)
+    }
+
+    fn enclosing_radius(&self) -> f64 {
+        match *self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        }
+    }
+}
+
+/// the ranges of scores `[min, max)` which contain every position within the shape:
+/// the geohash cell of the center and its eight neighbors, with cells large enough
+/// for the shape to fit in them
+pub(crate) fn search_ranges(lon: f64, lat: f64, shape: &Shape) -> Vec<(u64, u64)> {
+    let (min_lon, min_lat, max_lon, max_lat) = shape.bounding_box(lon, lat);
+    let mut step = estimate_step(shape.enclosing_radius(), lat);
+    let (lat_cell, lon_cell) = loop {
+        let (lat_cell, lon_cell) = cells(lon, lat, step, (LAT_MIN, LAT_MAX));
+        let lat_size = (LAT_MAX - LAT_MIN) / (1u64 << step) as f64;
+        let lon_size = (LON_MAX - LON_MIN) / (1u64 << step) as f64;
+        let lat_low = LAT_MIN + (lat_cell as f64 - 1.0) * lat_size;
+        let lon_low = LON_MIN + (lon_cell as f64 - 1.0) * lon_size;
+        let covered = (lat_low <= min_lat || lat_cell == 0)
+            && (lat_low + 3.0 * lat_size >= max_lat || lat_cell + 1 == 1 << step)
+            && ((lon_low <= min_lon && lon_low + 3.0 * lon_size >= max_lon)
+                || 3.0 * lon_size >= LON_MAX - LON_MIN);
+        if covered || step == 1 {
+            break (lat_cell, lon_cell);
+        }
+        step -= 1;
+    };
+
+    let cells_per_axis = 1i64 << step;
+    let shift = 2 * (STEP - step);
+    let mut ranges = vec![];
+    for lat_delta in -1..=1 {
+        let lat_neighbor = lat_cell as i64 + lat_delta;
+        if lat_neighbor < 0 || lat_neighbor >= cells_per_axis {
+            continue;
+        }
+        for lon_delta in -1..=1 {
+            // longitudes wrap around the antimeridian
+            let lon_neighbor = (lon_cell as i64 + lon_delta).rem_euclid(cells_per_axis);
+            let hash = interleave(lat_neighbor as u32, lon_neighbor as u32);
+            let range = (hash << shift, (hash + 1) << shift);
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges
+}
+
+/// the largest cells which are still small enough for a search of this radius
+fn estimate_step(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP;
+    }
+    let mut step: i32 = 1;
+    let mut range = radius;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // make sure the range fits in most cases
+    step -= 2;
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP as i32) as u32
+}
+
+/// the cells a position belongs to at `step` bits per coordinate
+fn cells(lon: f64, lat: f64, step: u32, (lat_min, lat_max): (f64, f64)) -> (u32, u32) {
+    let cells = (1u64 << step) as f64;
+    let cell = |value: f64, min: f64, max: f64| {
+        let offset = ((value - min) / (max - min) * cells) as u64;
+        offset.min((1u64 << step) - 1) as u32
+    };
+    (cell(lat, lat_min, lat_max), cell(lon, LON_MIN, LON_MAX))
+}
+
+/// latitude bits go to the even positions and longitude bits to the odd ones
+fn interleave(lat: u32, lon: u32) -> u64 {
+    spread(lat) | spread(lon) << 1
+}
+
+fn deinterleave(hash: u64) -> (u32, u32) {
+    (squash(hash), squash(hash >> 1))
+}
+
+/// move the bit n of a 32 bit value to the bit 2n
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+fn squash(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    ((x | (x >> 16)) & 0x0000_0000_FFFF_FFFF) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geohash_test() {
+        // Palermo, as in the redis documentation
+        let hash = encode(13.361389, 38.115556);
+        assert_eq!(hash, 3479099956230698);
+        assert_eq!(to_geohash_string(hash), "sqc8b49rny0");
+        let (lon, lat) = decode(hash);
+        assert!((lon - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+
+        let catania = decode(encode(15.087269, 37.502669));
+        let d = distance(lon, lat, catania.0, catania.1);
+        assert!((d - 166274.1516).abs() < 0.01);
+
+        // every position within the radius lies in one of the ranges
+        let shape = Shape::Radius(200_000.0);
+        let ranges = search_ranges(15.0, 37.0, &shape);
+        for position in [(lon, lat), catania] {
+            let hash = encode(position.0, position.1);
+            assert!(ranges.iter().any(|(min, max)| (*min..*max).contains(&hash)));
+            assert!(shape.distance_if_within((15.0, 37.0), position).is_some());
+        }
+        let wide = Shape::Box {
+            width: 400_000.0,
+            height: 400_000.0,
+        };
+        assert!(wide
+            .distance_if_within((15.0, 37.0), (17.0, 37.0))
+            .is_some());
+        assert!(wide
+            .distance_if_within((15.0, 37.0), (15.0, 39.0))
+            .is_none());
+    }
+}
@@ -0,0 +1,112 @@
+/// match `text` against a glob-style pattern the way redis does:
+/// `*` matches any sequence, `?` matches one byte, `[abc]`, `[^a]` and `[a-z]`
+/// match a class of bytes and `\` escapes the next byte
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // where to resume after the most recent `*`
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    backtrack = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, next)) = match_class(pattern, p, text[t]) {
+                        if matched {
+                            p = next;
+                            t += 1;
+                            continue;
+                        }
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        // mismatch, let the last `*` swallow one more byte
+        match backtrack {
+            Some((star, consumed)) => {
+                p = star + 1;
+                t = consumed + 1;
+                backtrack = Some((star, consumed + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// match a byte against the class starting at `start`.
+/// return whether it matched and the position right after the class
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = pattern.get(i) == Some(&b'^');
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    loop {
+        match pattern.get(i)? {
+            b']' => break,
+            b'\\' => {
+                i += 1;
+                matched |= *pattern.get(i)? == c;
+                i += 1;
+            }
+            &low => {
+                if pattern.get(i + 1) == Some(&b'-')
+                    && pattern.get(i + 2).is_some_and(|b| *b != b']')
+                {
+                    let high = pattern[i + 2];
+                    let (low, high) = if low <= high {
+                        (low, high)
+                    } else {
+                        (high, low)
+                    };
+                    matched |= low <= c && c <= high;
+                    i += 3;
+                } else {
+                    matched |= low == c;
+                    i += 1;
+                }
+            }
+        }
+    }
+    Some((matched != negate, i + 1))
+}
+
+#[test]
+fn glob_match_test() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(glob_match(b"h*llo", b"heeeello"));
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+    assert!(glob_match(b"orders.*.created", b"orders.eu.42.created"));
+    assert!(!glob_match(b"orders.*.created", b"orders.eu.42.updated"));
+    assert!(glob_match(b"a\\*b", b"a*b"));
+    assert!(!glob_match(b"a\\*b", b"axb"));
+}
@@ -0,0 +1,358 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+/// number of bits of the hash used to select a register
+const P: u32 = 14;
+const REGISTERS: usize = 1 << P;
+/// number of bits of the hash left to count the run of zeros
+const Q: usize = 64 - P as usize;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_LEN: usize = 16;
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * REGISTER_BITS / 8;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+/// past this size a sparse representation is promoted to the dense one
+const SPARSE_MAX_BYTES: usize = 3000;
+/// the greatest register value a sparse VAL opcode can hold
+const SPARSE_VAL_MAX: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+/// set in the most significant byte of the cached cardinality when it is stale
+const CACHE_INVALID: u8 = 1 << 7;
+
+const HASH_SEED: u64 = 0xadc8_3b19;
+
+/// a HyperLogLog with 16384 six-bit registers, giving a standard error of 0.81%.
+/// it is stored as a string using the same layout as redis: a 16 bytes header
+/// followed by either the sparse run-length encoding or the dense packed registers
+#[derive(Debug, Clone)]
+pub(crate) struct HyperLogLog {
+    registers: Vec<u8>,
+    /// once dense, a HyperLogLog never goes back to the sparse encoding
+    dense: bool,
+    cached: Option<u64>,
+}
+
+/// returned when a string isn't a HyperLogLog
+#[derive(Debug)]
+pub(crate) enum InvalidHll {
+    NotHll,
+    Corrupted,
+}
+
+impl HyperLogLog {
+    pub(crate) fn new() -> HyperLogLog {
+        HyperLogLog {
+            registers: vec![0; REGISTERS],
+            dense: false,
+            cached: Some(0),
+        }
+    }
+
+    pub(crate) fn decode(bytes: &[u8]) -> Result<HyperLogLog, InvalidHll> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(InvalidHll::NotHll);
+        }
+        let cache: [u8; 8] = bytes[8..HEADER_LEN].try_into().unwrap_or_default();
+        let cached = (cache[7] & CACHE_INVALID == 0).then(|| u64::from_le_bytes(cache));
+        let payload = &bytes[HEADER_LEN..];
+
+        let registers = match bytes[4] {
+            DENSE if bytes.len() == DENSE_LEN => (0..REGISTERS)
+                .map(|index| dense_get(payload, index))
+                .collect(),
+            SPARSE => sparse_decode(payload).ok_or(InvalidHll::Corrupted)?,
+            _ => return Err(InvalidHll::NotHll),
+        };
+        Ok(HyperLogLog {
+            registers,
+            dense: bytes[4] == DENSE,
+            cached,
+        })
+    }
+
+    /// the sparse encoding is kept as long as it is small enough and can hold every register
+    pub(crate) fn encode(&mut self) -> Bytes {
+        let sparse = if self.dense {
+            None
+        } else {
+            sparse_encode(&self.registers).filter(|sparse| sparse.len() <= SPARSE_MAX_BYTES)
+        };
+        self.dense = sparse.is_none();
+
+        let mut bytes = BytesMut::with_capacity(DENSE_LEN);
+        bytes.put_slice(MAGIC);
+        bytes.put_u8(if self.dense { DENSE } else { SPARSE });
+        bytes.put_bytes(0, 3);
+        match self.cached {
+            Some(cardinality) => bytes.put_u64_le(cardinality),
+            None => {
+                bytes.put_bytes(0, 7);
+                bytes.put_u8(CACHE_INVALID);
+            }
+        }
+        match sparse {
+            Some(sparse) => bytes.put_slice(&sparse),
+            None => {
+                let start = bytes.len();
+                bytes.put_bytes(0, DENSE_LEN - HEADER_LEN);
+                for (index, value) in self.registers.iter().enumerate() {
+                    dense_set(&mut bytes[start..], index, *value);
+                }
+            }
+        }
+        bytes.freeze()
+    }
+
+    /// return true if a register has been updated
+    pub(crate) fn add(&mut self, element: &[u8]) -> bool {
+        let (index, count) = register_of(element);
+        if self.registers[index] >= count {
+            return false;
+        }
+        self.registers[index] = count;
+        self.cached = None;
+        true
+    }
+
+    /// make this HyperLogLog count the union of both
+    pub(crate) fn merge(&mut self, other: &HyperLogLog) {
+        for (register, value) in self.registers.iter_mut().zip(&other.registers) {
+            if *value > *register {
+                *register = *value;
+                self.cached = None;
+            }
+        }
+        self.dense |= other.dense;
+    }
+
+    pub(crate) fn is_cached(&self) -> bool {
+        self.cached.is_some()
+    }
+
+    /// estimated cardinality, cached until the next update
+    pub(crate) fn count(&mut self) -> u64 {
+        if let Some(cardinality) = self.cached {
+            return cardinality;
+        }
+        let cardinality = self.estimate();
+        self.cached = Some(cardinality);
+        cardinality
+    }
+
+    /// the estimator from "New cardinality estimation algorithms for HyperLogLog
+    /// sketches" by Otmar Ertl, which is also what redis uses
+    fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let mut histogram = [0u32; 64];
+        for register in &self.registers {
+            histogram[*register as usize] += 1;
+        }
+        let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+        for count in histogram[1..=Q].iter().rev() {
+            z += *count as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        (ALPHA_INF * m * m / z).round() as u64
+    }
+}
+
+/// the register an element belongs to, and the value it brings:
+/// the position of the first set bit in the rest of its hash
+fn register_of(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, HASH_SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // the sentinel bit makes sure the count fits into a register
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn dense_get(payload: &[u8], index: usize) -> u8 {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    let low = payload[byte] as u16;
+    let high = payload.get(byte + 1).copied().unwrap_or_default() as u16;
+    (((low | high << 8) >> shift) as u8) & REGISTER_MAX
+}
+
+fn dense_set(payload: &mut [u8], index: usize, value: u8) {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = index * REGISTER_BITS % 8;
+    let value = (value & REGISTER_MAX) as u16;
+    payload[byte] |= (value << shift) as u8;
+    if shift > 8 - REGISTER_BITS {
+        payload[byte + 1] |= (value >> (8 - shift)) as u8;
+    }
+}
+
+/// decode the opcodes of the sparse encoding:
+/// `00xxxxxx` is a run of up to 64 zeros, `01xxxxxx yyyyyyyy` a run of up to 16384 zeros
+/// and `1vvvvvxx` a run of up to 4 registers set to a value up to 32
+fn sparse_decode(payload: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut i = 0;
+    while i < payload.len() {
+        let opcode = payload[i];
+        let (value, len) = match opcode >> 6 {
+            0b00 => (0, (opcode & 0x3f) as usize + 1),
+            0b01 => {
+                let low = *payload.get(i + 1)? as usize;
+                i += 1;
+                (0, (((opcode & 0x3f) as usize) << 8 | low) + 1)
+            }
+            _ => (((opcode >> 2) & 0x1f) + 1, (opcode & 0x3) as usize + 1),
+        };
+        if registers.len() + len > REGISTERS {
+            return None;
+        }
+        registers.resize(registers.len() + len, value);
+        i += 1;
+    }
+    (registers.len() == REGISTERS).then_some(registers)
+}
+
+/// return `None` if a register is too large for the sparse encoding
+fn sparse_encode(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut payload = vec![];
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|r| **r == value).count();
+        if value > SPARSE_VAL_MAX {
+            return None;
+        }
+        let mut left = run;
+        while left > 0 {
+            if value == 0 && left > SPARSE_ZERO_MAX_LEN {
+                let len = left.min(SPARSE_XZERO_MAX_LEN);
+                payload.push(0x40 | ((len - 1) >> 8) as u8);
+                payload.push(((len - 1) & 0xff) as u8);
+                left -= len;
+            } else if value == 0 {
+                payload.push((left - 1) as u8);
+                left = 0;
+            } else {
+                let len = left.min(SPARSE_VAL_MAX_LEN);
+                payload.push(0x80 | (value - 1) << 2 | (len - 1) as u8);
+                left -= len;
+            }
+        }
+        i += run;
+    }
+    Some(payload)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A by Austin Appleby, which redis uses to hash the elements
+pub(crate) fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hyperloglog_test() {
+        let mut hll = HyperLogLog::new();
+        let empty = hll.encode();
+        // the same bytes as a key freshly created by redis' PFADD
+        assert_eq!(
+            &empty[..],
+            b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        );
+
+        let n = 100_000;
+        for i in 0..n {
+            hll.add(format!("user:{}", i).as_bytes());
+        }
+        assert!(!hll.add(b"user:0"));
+        let estimate = hll.count() as f64;
+        assert!((estimate - n as f64).abs() / (n as f64) < 0.02);
+
+        let encoded = hll.encode();
+        assert_eq!(encoded.len(), DENSE_LEN);
+        let decoded = HyperLogLog::decode(&encoded).unwrap();
+        assert_eq!(decoded.registers, hll.registers);
+        assert_eq!(decoded.cached, Some(estimate as u64));
+
+        let mut small = HyperLogLog::new();
+        for i in 0..100 {
+            small.add(format!("page:{}", i).as_bytes());
+        }
+        let encoded = small.encode();
+        assert_eq!(encoded[4], SPARSE);
+        let mut decoded = HyperLogLog::decode(&encoded).unwrap();
+        assert_eq!(decoded.registers, small.registers);
+        assert!(decoded.cached.is_none());
+        assert!((decoded.count() as i64 - 100).abs() <= 2);
+
+        decoded.merge(&hll);
+        let union = decoded.count() as f64;
+        assert!((union - (n + 100) as f64).abs() / ((n + 100) as f64) < 0.02);
+        assert!(matches!(
+            HyperLogLog::decode(b"not a hll"),
+            Err(InvalidHll::NotHll)
+        ));
+    }
+}
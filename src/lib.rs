@@ -8,12 +8,26 @@ mod frame;
 pub use frame::Frame;
 
 mod cmd;
-pub use cmd::{Command, Ping, Get, Set};
+pub use cmd::{Command, Get, Ping, Set};
 
 mod parser;
 
+mod glob;
+
+mod script;
+
+mod hyperloglog;
+
+mod geo;
+
+mod search;
+
+mod filter;
+
+mod config;
+
 mod db;
-pub use db::DbHolder;
+pub use db::{DbHolder, WrongType};
 
 mod client;
 pub use client::BlockingClient;
@@ -46,8 +46,59 @@ impl Parser {
     pub fn next_int(&mut self) -> Result<i64, ParseError> {
         match self.next()? {
             Frame::Integer(i) => Ok(i),
+            // most clients send every argument as a bulk string
+            Frame::Bulk(data) => std::str::from_utf8(&data)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| "value is not an integer or out of range".into()),
+            Frame::Simple(s) => s
+                .parse()
+                .map_err(|_| "value is not an integer or out of range".into()),
             frame => Err(format!("can't get an integer from {:?}", frame).into()),
-        } 
+        }
+    }
+
+    pub fn next_float(&mut self) -> Result<f64, ParseError> {
+        let value = match self.next()? {
+            Frame::Integer(i) => return Ok(i as f64),
+            Frame::Bulk(data) => String::from_utf8_lossy(&data).to_string(),
+            Frame::Simple(s) => s,
+            frame => return Err(format!("can't get a float from {:?}", frame).into()),
+        };
+        match value.parse::<f64>() {
+            Ok(value) if !value.is_nan() => Ok(value),
+            _ => Err("value is not a valid float".into()),
+        }
+    }
+
+    /// the number of arguments left, to check a count given by the client
+    /// before relying on it
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// collect every remaining argument, at least one is required
+    pub fn remaining_bytes(&mut self) -> Result<Vec<Bytes>, ParseError> {
+        let mut items = vec![self.next_bytes()?];
+        loop {
+            match self.next_bytes() {
+                Ok(item) => items.push(item),
+                Err(ParseError::EndOfStream) => return Ok(items),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// collect every remaining argument as a string, at least one is required
+    pub fn remaining_strings(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut items = vec![self.next_string()?];
+        loop {
+            match self.next_string() {
+                Ok(item) => items.push(item),
+                Err(ParseError::EndOfStream) => return Ok(items),
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     pub fn check_finished(&mut self) -> Result<(), ParseError> {
@@ -72,7 +123,10 @@ impl From<&str> for ParseError {
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            ParseError::EndOfStream => write!(f, "wrong number of arguments"),
+            ParseError::Other(msg) => write!(f, "{}", msg),
+        }
     }
 }
 
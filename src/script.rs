@@ -0,0 +1,334 @@
+//! the Lua 5.1 interpreter running EVAL scripts. every script gets a fresh
+//! sandboxed state holding only the base, table, string and math libraries, and
+//! reaches the database through `redis.call` and `redis.pcall`
+
+use std::{cell::RefCell, fmt, sync::Arc};
+
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value as LuaValue, Variadic};
+
+use crate::{
+    db::{sha1_hex, Database, ScriptControl},
+    Command, Frame,
+};
+
+/// the number of instructions run between two checks of SCRIPT KILL
+const KILL_CHECK_INTERVAL: u32 = 10_000;
+
+/// creating or reading an undeclared global is an error, so that scripts don't
+/// leak state through the globals nor silently read nil from a typo
+const PROTECT_GLOBALS: &str = r#"
+setmetatable(_G, {
+    __newindex = function(_, name)
+        error("Script attempted to create global variable '" .. tostring(name) .. "'", 2)
+    end,
+    __index = function(_, name)
+        error("Script attempted to access nonexistent global variable '" .. tostring(name) .. "'", 2)
+    end,
+})
+"#;
+
+/// an error reply raised by `redis.call`, which EVAL replies as is
+#[derive(Debug)]
+struct ReplyError(String);
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ReplyError {}
+
+/// run the script with KEYS and ARGV set, holding the database all along. a
+/// read-only script may only call commands which don't write
+pub(crate) fn run(
+    db: &mut Database,
+    body: &str,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    readonly: bool,
+) -> Frame {
+    let control = db.script_control();
+    control.start();
+    let reply = match eval(db, &control, body, keys, args, readonly) {
+        Ok(frame) => frame,
+        Err(e) => Frame::Error(error_message(&e)),
+    };
+    control.stop();
+    reply
+}
+
+fn eval(
+    db: &mut Database,
+    control: &Arc<ScriptControl>,
+    body: &str,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    readonly: bool,
+) -> mlua::Result<Frame> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    let killed = control.clone();
+    let triggers = HookTriggers::new().every_nth_instruction(KILL_CHECK_INTERVAL);
+    lua.set_hook(triggers, move |_, _| match killed.killed() {
+        true => Err(mlua::Error::external(ReplyError(
+            "ERR Script killed by user with SCRIPT KILL...".to_string(),
+        ))),
+        false => Ok(()),
+    });
+
+    let globals = lua.globals();
+    globals.set("KEYS", strings(&lua, &keys)?)?;
+    globals.set("ARGV", strings(&lua, &args)?)?;
+    // scripts may neither run files nor compile code of their own
+    for unsafe_function in ["loadfile", "dofile", "loadstring", "load"] {
+        globals.set(unsafe_function, LuaValue::Nil)?;
+    }
+
+    let db = RefCell::new(db);
+    lua.scope(|scope| {
+        let redis = redis_table(&lua)?;
+        let call = scope.create_function(|lua, args: Variadic<LuaValue>| {
+            match dispatch(&mut db.borrow_mut(), control, args, readonly) {
+                Frame::Error(e) => Err(mlua::Error::external(ReplyError(e))),
+                frame => to_lua(lua, frame),
+            }
+        })?;
+        let pcall = scope.create_function(|lua, args: Variadic<LuaValue>| {
+            to_lua(lua, dispatch(&mut db.borrow_mut(), control, args, readonly))
+        })?;
+        redis.set("call", call)?;
+        redis.set("pcall", pcall)?;
+        globals.set("redis", redis)?;
+        lua.load(PROTECT_GLOBALS).set_name("@sandbox").exec()?;
+
+        let value = lua.load(body).set_name("@user_script").eval()?;
+        Ok(from_lua(value))
+    })
+}
+
+/// the `redis` library, apart from the functions calling commands
+fn redis_table(lua: &Lua) -> mlua::Result<Table<'_>> {
+    let redis = lua.create_table()?;
+    redis.set(
+        "error_reply",
+        lua.create_function(|lua, message: String| reply_table(lua, "err", message))?,
+    )?;
+    redis.set(
+        "status_reply",
+        lua.create_function(|lua, message: String| reply_table(lua, "ok", message))?,
+    )?;
+    redis.set(
+        "sha1hex",
+        lua.create_function(|_, body: mlua::String| Ok(sha1_hex(body.as_bytes())))?,
+    )?;
+    // the server keeps no log, the messages are dropped
+    redis.set(
+        "log",
+        lua.create_function(|_, _: Variadic<LuaValue>| Ok(()))?,
+    )?;
+    for (i, level) in ["LOG_DEBUG", "LOG_VERBOSE", "LOG_NOTICE", "LOG_WARNING"]
+        .iter()
+        .enumerate()
+    {
+        redis.set(*level, i)?;
+    }
+    Ok(redis)
+}
+
+/// apply the command given to `redis.call` or `redis.pcall`. the invalid ones
+/// are replied errors, like the errors of the commands themselves
+fn dispatch(
+    db: &mut Database,
+    control: &ScriptControl,
+    args: Variadic<LuaValue>,
+    readonly: bool,
+) -> Frame {
+    if args.is_empty() {
+        return Frame::Error(
+            "ERR Please specify at least one argument for this redis lib call".to_string(),
+        );
+    }
+    let mut parts = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        let part = match arg {
+            LuaValue::String(s) => Bytes::copy_from_slice(s.as_bytes()),
+            LuaValue::Integer(i) => Bytes::from(i.to_string()),
+            LuaValue::Number(n) => Bytes::from(format_number(*n)),
+            _ => {
+                return Frame::Error(
+                    "ERR Lua redis lib command arguments must be strings or integers".to_string(),
+                )
+            }
+        };
+        parts.push(Frame::Bulk(part));
+    }
+
+    let cmd = match Command::from_frame(Frame::Array(parts)) {
+        Ok(cmd) => cmd,
+        Err(e) => return Frame::Error(format!("ERR {}", e)),
+    };
+    if !cmd.is_scriptable() {
+        return Frame::Error("ERR This Redis command is not allowed from script".to_string());
+    }
+    if !cmd.is_readonly() {
+        if readonly {
+            return Frame::Error(
+                "ERR Write commands are not allowed from read-only scripts.".to_string(),
+            );
+        }
+        control.write();
+    }
+    cmd.apply(db)
+}
+
+/// numbers are passed to commands the way Lua prints them, integers without
+/// a fractional part
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        (n as i64).to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+fn strings<'lua>(lua: &'lua Lua, items: &[Bytes]) -> mlua::Result<Table<'lua>> {
+    let strings = items
+        .iter()
+        .map(|item| lua.create_string(item))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    lua.create_sequence_from(strings)
+}
+
+/// the `{ok = ...}` and `{err = ...}` tables standing for status and error replies
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, message: String) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, message)?;
+    Ok(table)
+}
+
+/// a reply as the script sees it: a null is false, integers are numbers, and
+/// status and error replies are tables with a single `ok` or `err` field
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<LuaValue<'_>> {
+    let value = match frame {
+        Frame::Simple(s) => LuaValue::Table(reply_table(lua, "ok", s)?),
+        Frame::Error(e) => LuaValue::Table(reply_table(lua, "err", e)?),
+        Frame::Bulk(data) => LuaValue::String(lua.create_string(&data)?),
+        Frame::Integer(i) => LuaValue::Number(i as f64),
+        Frame::Null => LuaValue::Boolean(false),
+        Frame::Array(items) | Frame::Push(items) => {
+            let items = items
+                .into_iter()
+                .map(|item| to_lua(lua, item))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            LuaValue::Table(lua.create_sequence_from(items)?)
+        }
+        // maps are flattened into their keys and values
+        Frame::Map(pairs) => {
+            let items = pairs
+                .into_iter()
+                .flat_map(|(key, value)| [key, value])
+                .map(|item| to_lua(lua, item))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            LuaValue::Table(lua.create_sequence_from(items)?)
+        }
+    };
+    Ok(value)
+}
+
+/// the reply of the script: numbers are truncated to integers, true is 1, false
+/// and nil are null, and a table is an array up to its first nil unless it has
+/// an `ok` or `err` field
+fn from_lua(value: LuaValue) -> Frame {
+    match value {
+        LuaValue::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        LuaValue::Integer(i) => Frame::Integer(i),
+        LuaValue::Number(n) => Frame::Integer(n as i64),
+        LuaValue::Boolean(true) => Frame::Integer(1),
+        LuaValue::Table(table) => {
+            if let Ok(LuaValue::String(e)) = table.raw_get("err") {
+                return Frame::Error(e.to_string_lossy().into_owned());
+            }
+            if let Ok(LuaValue::String(s)) = table.raw_get("ok") {
+                return Frame::Simple(s.to_string_lossy().into_owned());
+            }
+            let mut items = vec![];
+            for i in 1.. {
+                match table.raw_get(i) {
+                    Ok(LuaValue::Nil) | Err(_) => break,
+                    Ok(item) => items.push(from_lua(item)),
+                }
+            }
+            Frame::Array(items)
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// an error reply raised by `redis.call` or SCRIPT KILL is replied as is
+fn error_message(e: &mlua::Error) -> String {
+    match e {
+        mlua::Error::CallbackError { cause, .. } => error_message(cause),
+        mlua::Error::ExternalError(external) => match external.downcast_ref::<ReplyError>() {
+            Some(ReplyError(message)) => message.clone(),
+            None => format!("ERR {}", external),
+        },
+        mlua::Error::SyntaxError { message, .. } => {
+            format!("ERR Error compiling script: {}", message)
+        }
+        // the stack traceback following the message doesn't fit in an error reply
+        mlua::Error::RuntimeError(message) => {
+            let message = message.lines().next().unwrap_or_default();
+            format!("ERR Error running script: {}", message)
+        }
+        e => format!("ERR Error running script: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::*;
+    use crate::DbHolder;
+
+    #[tokio::test]
+    async fn run_test() {
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let (shutdown_completed_tx, _) = mpsc::channel(1);
+        let holder = DbHolder::new(shutdown_tx.subscribe(), shutdown_completed_tx);
+        let mut db = holder.lock();
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from_static(s.as_bytes()));
+        let mut eval = |body: &str, readonly: bool| {
+            let keys = vec![Bytes::from_static(b"k")];
+            let args = vec![Bytes::from_static(b"v")];
+            run(&mut db, body, keys, args, readonly)
+        };
+
+        let set = "return redis.call('SET', KEYS[1], ARGV[1])";
+        assert_eq!(eval(set, false), Frame::into_simple("OK"));
+        assert!(matches!(eval(set, true), Frame::Error(_)));
+        let reply = eval("return {1, 'a', false, true, 2.7, nil, 3}", false);
+        let expected = vec![Frame::Integer(1), bulk("a"), Frame::Null, Frame::Integer(1)];
+        assert_eq!(
+            reply,
+            Frame::Array([expected, vec![Frame::Integer(2)]].concat())
+        );
+
+        // an error raised by redis.call is the reply, redis.pcall hands it to the script
+        let reply = eval("return redis.call('SADD', KEYS[1], 'm')", false);
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("WRONGTYPE")));
+        let reply = eval("return redis.pcall('SADD', KEYS[1], 'm').err ~= nil", false);
+        assert_eq!(reply, Frame::Integer(1));
+        assert!(matches!(eval("leaked = 1", false), Frame::Error(_)));
+        for body in [
+            "return loadstring('return 1')()",
+            "return load(function() end)",
+        ] {
+            assert!(matches!(eval(body, false), Frame::Error(_)));
+        }
+    }
+}
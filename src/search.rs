@@ -0,0 +1,311 @@
+//! the query language of FT.SEARCH and FT.AGGREGATE
+
+use std::ops::Bound;
+
+/// words which are too common to be worth indexing
+const STOPWORDS: [&str; 33] = [
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Query {
+    /// `*` matches every document
+    All,
+    /// the words next to each other, in any TEXT field unless `field` is given
+    Phrase {
+        field: Option<String>,
+        words: Vec<String>,
+    },
+    /// any word starting with the prefix, `hel*`
+    Prefix {
+        field: Option<String>,
+        prefix: String,
+    },
+    /// any of the tags, `@field:{a | b}`
+    Tags {
+        field: String,
+        tags: Vec<String>,
+    },
+    /// `@field:[min max]`, a bound preceded by `(` is exclusive
+    Range {
+        field: String,
+        min: Bound<f64>,
+        max: Bound<f64>,
+    },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    pub(crate) fn parse(text: &str) -> Result<Query, String> {
+        let mut reader = Reader {
+            chars: text.chars().collect(),
+            pos: 0,
+        };
+        let query = reader.union(None)?;
+        reader.skip_spaces();
+        if reader.pos < reader.chars.len() {
+            return reader.error();
+        }
+        Ok(query)
+    }
+
+    /// the words the query looks for, which weigh in the score of the documents
+    pub(crate) fn words(&self) -> Vec<&str> {
+        match self {
+            Query::Phrase { words, .. } => words.iter().map(String::as_str).collect(),
+            Query::And(queries) | Query::Or(queries) => {
+                queries.iter().flat_map(Query::words).collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// split a text into lowercase words, stopwords are left out
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Reader {
+    fn error<T>(&self) -> Result<T, String> {
+        Err(format!("Syntax error at offset {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// intersections separated by `|`
+    fn union(&mut self, field: Option<&str>) -> Result<Query, String> {
+        let mut queries = vec![self.intersection(field)?];
+        loop {
+            self.skip_spaces();
+            if !self.eat('|') {
+                break;
+            }
+            queries.push(self.intersection(field)?);
+        }
+        Ok(match queries.len() {
+            1 => queries.remove(0),
+            _ => Query::Or(queries),
+        })
+    }
+
+    /// queries next to each other, which must all match
+    fn intersection(&mut self, field: Option<&str>) -> Result<Query, String> {
+        let mut queries = vec![];
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None | Some('|') | Some(')') => break,
+                _ => queries.extend(self.unary(field)?),
+            }
+        }
+        Ok(match queries.len() {
+            0 => return self.error(),
+            1 => queries.remove(0),
+            _ => Query::And(queries),
+        })
+    }
+
+    /// a stopword alone is no query at all
+    fn unary(&mut self, field: Option<&str>) -> Result<Option<Query>, String> {
+        if self.eat('-') {
+            return match self.unary(field)? {
+                Some(query) => Ok(Some(Query::Not(Box::new(query)))),
+                None => self.error(),
+            };
+        }
+        if self.eat('(') {
+            let query = self.union(field)?;
+            self.skip_spaces();
+            if !self.eat(')') {
+                return self.error();
+            }
+            return Ok(Some(query));
+        }
+        if self.eat('@') {
+            let name = self.name();
+            if name.is_empty() || !self.eat(':') {
+                return self.error();
+            }
+            self.skip_spaces();
+            return match self.peek() {
+                Some('{') => self.tags(name).map(Some),
+                Some('[') => self.range(name).map(Some),
+                _ => self.unary(Some(&name)),
+            };
+        }
+        if self.eat('*') {
+            return Ok(Some(Query::All));
+        }
+        let field = field.map(str::to_string);
+        if self.eat('"') {
+            let start = self.pos;
+            while self.peek().is_some_and(|c| c != '"') {
+                self.pos += 1;
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            if !self.eat('"') {
+                return self.error();
+            }
+            let words = tokenize(&text);
+            return Ok((!words.is_empty()).then_some(Query::Phrase { field, words }));
+        }
+        let word = self.name();
+        if word.is_empty() {
+            return self.error();
+        }
+        if self.eat('*') {
+            let prefix = word.to_lowercase();
+            return Ok(Some(Query::Prefix { field, prefix }));
+        }
+        let words = tokenize(&word);
+        Ok((!words.is_empty()).then_some(Query::Phrase { field, words }))
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// `{a | b}`, a backslash escapes the character after it
+    fn tags(&mut self, field: String) -> Result<Query, String> {
+        self.pos += 1;
+        let mut tags = vec![];
+        let mut tag = String::new();
+        loop {
+            match self.peek() {
+                None => return self.error(),
+                Some('\\') if self.pos + 1 < self.chars.len() => {
+                    tag.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                    continue;
+                }
+                Some(c @ ('|' | '}')) => {
+                    tags.push(tag.trim().to_string());
+                    tag.clear();
+                    self.pos += 1;
+                    if c == '}' {
+                        return Ok(Query::Tags { field, tags });
+                    }
+                }
+                Some(c) => {
+                    tag.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// `[min max]`
+    fn range(&mut self, field: String) -> Result<Query, String> {
+        self.pos += 1;
+        let min = self.bound()?;
+        let max = self.bound()?;
+        self.skip_spaces();
+        if !self.eat(']') {
+            return self.error();
+        }
+        Ok(Query::Range { field, min, max })
+    }
+
+    fn bound(&mut self) -> Result<Bound<f64>, String> {
+        self.skip_spaces();
+        let exclusive = self.eat('(');
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && c != ']') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        let value = match &text.to_lowercase()[..] {
+            "-inf" => f64::NEG_INFINITY,
+            "inf" | "+inf" => f64::INFINITY,
+            number => match number.parse::<f64>() {
+                Ok(value) if !value.is_nan() => value,
+                _ => return self.error(),
+            },
+        };
+        Ok(match exclusive {
+            true => Bound::Excluded(value),
+            false => Bound::Included(value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_query_test() {
+        let phrase = |field: Option<&str>, words: &[&str]| Query::Phrase {
+            field: field.map(str::to_string),
+            words: words.iter().map(|w| w.to_string()).collect(),
+        };
+        assert_eq!(
+            Query::parse("Hello the world").unwrap(),
+            Query::And(vec![phrase(None, &["hello"]), phrase(None, &["world"])])
+        );
+        assert_eq!(
+            Query::parse("@title:(foo|\"bar baz\") -@tags:{a b | c} @price:[(10 +inf]").unwrap(),
+            Query::And(vec![
+                Query::Or(vec![
+                    phrase(Some("title"), &["foo"]),
+                    phrase(Some("title"), &["bar", "baz"]),
+                ]),
+                Query::Not(Box::new(Query::Tags {
+                    field: "tags".to_string(),
+                    tags: vec!["a b".to_string(), "c".to_string()],
+                })),
+                Query::Range {
+                    field: "price".to_string(),
+                    min: Bound::Excluded(10.0),
+                    max: Bound::Included(f64::INFINITY),
+                },
+            ])
+        );
+        assert_eq!(
+            Query::parse("hel* | *").unwrap(),
+            Query::Or(vec![
+                Query::Prefix {
+                    field: None,
+                    prefix: "hel".to_string()
+                },
+                Query::All
+            ])
+        );
+        assert!(Query::parse("(foo").is_err());
+        assert!(Query::parse("@price:[1]").is_err());
+    }
+}
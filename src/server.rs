@@ -1,6 +1,11 @@
-use std::{future::Future, sync::Arc};
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
 
-use crate::{cmd::Command, connection::Connection, DbHolder, Error, Frame, Result};
+use crate::{
+    cmd::{run_script, Command, Transaction},
+    config::{self, BUSY_REPLY_THRESHOLD},
+    connection::Connection,
+    DbHolder, Error, Frame, Result,
+};
 use tokio::{
     net::TcpListener,
     select, spawn,
@@ -20,6 +25,12 @@ pub struct Listener {
 pub struct Handler {
     connection: Connection,
     db: DbHolder,
+    /// the push messages of the client, such as invalidations of the keys it caches
+    pushes: mpsc::Receiver<Frame>,
+    /// whether the last command was CLIENT CACHING
+    caching: bool,
+    /// the commands queued since MULTI
+    transaction: Option<Transaction>,
     shutdown_receiver: broadcast::Receiver<()>,
     _shutdown_completed_tx: mpsc::Sender<()>,
 }
@@ -70,6 +81,11 @@ impl Listener {
         })
     }
 
+    /// the address the listener is bound to, such as the port picked for port 0
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         let db = DbHolder::new(
             self.shutdown_broadcast.subscribe(),
@@ -79,9 +95,13 @@ impl Listener {
             let permit = self.semaphore.clone().acquire_owned().await.unwrap();
 
             let (socket, _) = self.listener.accept().await?;
+            let (db, pushes) = db.connect();
             let mut handler = Handler {
                 connection: Connection::new(socket),
-                db: db.clone(),
+                db,
+                pushes,
+                caching: false,
+                transaction: None,
                 shutdown_receiver: self.shutdown_broadcast.subscribe(),
                 _shutdown_completed_tx: self.shutdown_completed_tx.clone(),
             };
@@ -104,14 +124,52 @@ impl Handler {
         loop {
             let frame = select! {
                 frame = self.connection.read_frame() => frame?,
+                push = self.pushes.recv() => match push {
+                    Some(push) => {
+                        self.connection.write_frame(push).await?;
+                        continue;
+                    }
+                    // the client has been disconnected by the server
+                    None => return Ok(()),
+                },
                 _ = self.shutdown_receiver.recv() => {
                     return Err("server has been closed".into());
                 },
             };
 
             if let Some(frame) = frame {
-                let cmd = Command::from_frame(frame)?;
-                cmd.execute(&mut self.connection, &self.db).await?;
+                let cmd = Command::from_frame(frame);
+                let killing = matches!(&cmd, Ok(Command::Script(cmd)) if cmd.is_kill());
+                if !killing && self.script_busy().await {
+                    let busy = "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.";
+                    self.connection
+                        .write_frame(Frame::Error(busy.to_string()))
+                        .await?;
+                    continue;
+                }
+                let cmd = match self.transaction.take() {
+                    Some(transaction) => match self.transact(transaction, cmd).await? {
+                        Some(cmd) => cmd,
+                        None => continue,
+                    },
+                    None => cmd?,
+                };
+                if let Command::Multi(_) = cmd {
+                    self.transaction = Some(Transaction::default());
+                }
+                let caching = matches!(&cmd, Command::Client(cmd) if cmd.is_caching());
+                // blocking commands may wait for a long time, don't let them delay the shutdown
+                select! {
+                    res = cmd.execute(&mut self.connection, &self.db, &mut self.pushes) => res?,
+                    _ = self.shutdown_receiver.recv() => {
+                        return Err("server has been closed".into());
+                    },
+                }
+                // CLIENT CACHING only applies to the command following it
+                if self.caching && !caching {
+                    self.db.lock().reset_caching(self.db.client());
+                }
+                self.caching = caching;
             } else {
                 // this means that the client has closed the connection
                 return Ok(());
@@ -119,9 +177,57 @@ impl Handler {
         }
     }
 
+    /// the commands following MULTI are queued until EXEC applies them or DISCARD
+    /// drops them. RESET drops them too, and is returned to be executed as usual
+    async fn transact(
+        &mut self,
+        mut transaction: Transaction,
+        cmd: Result<Command>,
+    ) -> Result<Option<Command>> {
+        let reply = match cmd {
+            // the queued commands may run scripts
+            Ok(Command::Exec(_)) => run_script(|| transaction.exec(&self.db)),
+            Ok(Command::Discard(_)) => transaction.discard(&self.db),
+            Ok(cmd @ Command::Reset(_)) => return Ok(Some(cmd)),
+            Ok(cmd) => {
+                let reply = transaction.queue(cmd);
+                self.transaction = Some(transaction);
+                reply
+            }
+            // a malformed command doesn't close the connection, it aborts the transaction
+            Err(e) => {
+                let reply = transaction.reject(format!("ERR {}", e));
+                self.transaction = Some(transaction);
+                reply
+            }
+        };
+        self.connection.write_frame(reply).await?;
+        Ok(None)
+    }
+
+    /// a running script holds the database. other commands wait for it to end,
+    /// until it has been running for longer than the busy reply threshold
+    async fn script_busy(&self) -> bool {
+        let control = self.db.script_control();
+        let threshold = config::get(&BUSY_REPLY_THRESHOLD) as u64;
+        let threshold = Duration::from_millis(threshold);
+        if let Some(elapsed) = control.elapsed() {
+            control.wait(threshold.saturating_sub(elapsed)).await;
+        }
+        control
+            .elapsed()
+            .is_some_and(|elapsed| elapsed >= threshold)
+    }
+
     async fn send_error_msg(&mut self, e: Error) -> Result<()> {
         self.connection
             .write_frame(Frame::into_simple(&format!("error: {}", e)))
             .await
     }
 }
+
+impl Drop for Handler {
+    fn drop(&mut self) {
+        self.db.disconnect();
+    }
+}